@@ -0,0 +1,233 @@
+use crate::{
+    logging, FilterSettings, GainTags, Loudness, LoudnessAnalyzer, MediaInfo, Normalizer, Options,
+    Tagger,
+};
+use serde::Serialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// One measured track of an album.
+#[derive(Debug, Clone)]
+pub struct AlbumTrack {
+    pub input_path: PathBuf,
+    pub loudness: Loudness,
+    /// Track duration in seconds, used to weight its loudness.
+    pub duration: Option<f64>,
+    /// Whether the selected audio stream is Opus.
+    pub opus: bool,
+}
+
+/// A file whose loudness sets the gain of a group instead of the album
+/// loudness, see [`Album::relative_to`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupReference {
+    /// The track with the highest integrated loudness.
+    Loudest,
+    /// This file, one of the tracks or measured just for reference.
+    File(PathBuf),
+}
+
+/// The track an [`Album`] is brought to the target by.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferenceTrack {
+    pub input_path: PathBuf,
+    /// Integrated loudness in LUFS.
+    pub integrated_loudness: f64,
+}
+
+/// Measurements of a group of files that are normalized together, so the
+/// level differences between tracks are preserved.
+#[derive(Debug, Clone, Serialize)]
+pub struct Album {
+    #[serde(skip)]
+    pub tracks: Vec<AlbumTrack>,
+    /// Album loudness in LUFS: the duration-weighted energy average of the
+    /// tracks' integrated loudness.
+    pub integrated_loudness: f64,
+    /// Highest true peak of any track, in dBTP.
+    pub true_peak: f64,
+    /// Track that hits the target instead of the album loudness.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<ReferenceTrack>,
+}
+
+impl Album {
+    /// Measures every file in `input_paths`.
+    pub fn measure(input_paths: &[PathBuf], options: &Options) -> io::Result<Self> {
+        let tracks = input_paths
+            .iter()
+            .map(|input_path| Self::measure_track(input_path, options))
+            .collect::<io::Result<Vec<_>>>()?;
+        Self::from_tracks(tracks)
+    }
+
+    /// Probes and measures a single track.
+    pub fn measure_track(input_path: &Path, options: &Options) -> io::Result<AlbumTrack> {
+        let info = MediaInfo::probe(input_path, options)?;
+        let loudness = LoudnessAnalyzer::measure_probed(input_path, options, &info)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", input_path.display(), e)))?;
+        Ok(AlbumTrack {
+            input_path: input_path.to_path_buf(),
+            loudness,
+            duration: info.duration_of(options.audio_stream),
+            opus: Tagger::is_opus(&info, options),
+        })
+    }
+
+    /// Combines already measured tracks into album values. Tracks whose
+    /// loudness is not finite (silence) don't contribute to the average.
+    pub fn from_tracks(tracks: Vec<AlbumTrack>) -> io::Result<Self> {
+        let mut energy = 0.0;
+        let mut weight = 0.0;
+        let mut true_peak = f64::NEG_INFINITY;
+        for track in &tracks {
+            let (input_i, input_tp) = (track.loudness.input_i, track.loudness.input_tp);
+            if input_i.is_finite() {
+                let duration = track.duration.filter(|d| *d > 0.0).unwrap_or(1.0);
+                energy += duration * 10f64.powf(input_i / 10.0);
+                weight += duration;
+            }
+            if input_tp.is_finite() {
+                true_peak = true_peak.max(input_tp);
+            }
+        }
+        if weight == 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "No track of the album has a finite loudness",
+            ));
+        }
+        Ok(Self {
+            tracks,
+            integrated_loudness: 10.0 * (energy / weight).log10(),
+            true_peak,
+            reference: None,
+        })
+    }
+
+    /// Combines `tracks` with [`Album::from_tracks`], relative to
+    /// `reference` when given.
+    pub fn group(
+        tracks: Vec<AlbumTrack>,
+        reference: Option<&GroupReference>,
+        options: &Options,
+    ) -> io::Result<Self> {
+        let album = Self::from_tracks(tracks)?;
+        match reference {
+            Some(reference) => album.relative_to(reference, options),
+            None => Ok(album),
+        }
+    }
+
+    /// Makes `reference` hit the target rather than the album loudness.
+    /// Every track still gets the same gain, keeping the balance between
+    /// them, e.g. of stems or the segments of an episode. A reference file
+    /// that is not one of the tracks is measured with `options`.
+    pub fn relative_to(
+        mut self,
+        reference: &GroupReference,
+        options: &Options,
+    ) -> io::Result<Self> {
+        let track = match reference {
+            GroupReference::Loudest => self
+                .tracks
+                .iter()
+                .filter(|track| track.loudness.input_i.is_finite())
+                .max_by(|a, b| a.loudness.input_i.total_cmp(&b.loudness.input_i))
+                .cloned()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "No track of the group has a finite loudness",
+                    )
+                })?,
+            GroupReference::File(path) => {
+                let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+                match self.tracks.iter().find(|track| {
+                    fs::canonicalize(&track.input_path).unwrap_or_else(|_| track.input_path.clone())
+                        == canonical
+                }) {
+                    Some(track) => track.clone(),
+                    None => Self::measure_track(path, options)?,
+                }
+            }
+        };
+        if !track.loudness.input_i.is_finite() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: the reference has no finite loudness",
+                    track.input_path.display()
+                ),
+            ));
+        }
+        logging::info(format_args!(
+            "{}: reference at {:.2} LUFS",
+            track.input_path.display(),
+            track.loudness.input_i
+        ));
+        self.reference = Some(ReferenceTrack {
+            input_path: track.input_path,
+            integrated_loudness: track.loudness.input_i,
+        });
+        Ok(self)
+    }
+
+    /// Gain in dB that brings the album, or its reference track, to the
+    /// integrated loudness target.
+    pub fn gain_db(&self, options: &Options) -> f64 {
+        let loudness = self
+            .reference
+            .as_ref()
+            .map_or(self.integrated_loudness, |reference| {
+                reference.integrated_loudness
+            });
+        options.integrated_loudness - loudness
+    }
+
+    /// Track tags for `track` extended with the album gain and peak.
+    pub fn tags_for(&self, track: &AlbumTrack, options: &Options) -> Option<GainTags> {
+        GainTags::compute(&track.loudness, options, track.opus).map(|tags| {
+            tags.with_album(
+                self.gain_db(options),
+                self.true_peak,
+                self.integrated_loudness,
+            )
+        })
+    }
+
+    /// Applies the album gain to `track` and writes the result to
+    /// `output_path`. Returns the filter used.
+    pub fn normalize_track(
+        &self,
+        track: &AlbumTrack,
+        output_path: &Path,
+        options: &Options,
+    ) -> io::Result<String> {
+        let filter = FilterSettings::construct_gain(options, self.gain_db(options));
+        Normalizer::encode(&track.input_path, output_path, &filter, options)?;
+        Ok(filter)
+    }
+
+    /// Writes track and album gain tags for `track`, to `output_path` or in
+    /// place.
+    pub fn tag_track(
+        &self,
+        track: &AlbumTrack,
+        output_path: Option<&Path>,
+        options: &Options,
+    ) -> io::Result<GainTags> {
+        Tagger::check_format(track.opus, options)?;
+        let tags = self.tags_for(track, options).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Measured loudness is not finite; refusing to tag",
+            )
+        })?;
+        let metadata = tags.to_metadata(options.tag_format);
+        Tagger::write(&track.input_path, output_path, &metadata, options)?;
+        Ok(tags)
+    }
+}
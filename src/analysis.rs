@@ -0,0 +1,54 @@
+//! Measurements handed between runs with `--save-analysis` and
+//! `--from-analysis`, e.g. from a node that only measures to one that
+//! encodes.
+
+use crate::Loudness;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The measurements of one input, in the shape `--format json` prints them,
+/// so either output can be loaded again.
+#[derive(Serialize, Deserialize)]
+struct SavedAnalysis {
+    input: PathBuf,
+    #[serde(flatten)]
+    loudness: Loudness,
+}
+
+/// Writes the measurements of `input_path` to `path` as JSON.
+pub fn save(path: &Path, input_path: &Path, loudness: &Loudness) -> io::Result<()> {
+    let saved = SavedAnalysis {
+        input: input_path.to_path_buf(),
+        loudness: loudness.clone(),
+    };
+    fs::write(path, serde_json::to_string_pretty(&saved)? + "\n")
+}
+
+/// Reads the measurements saved in `path`. A file with several objects,
+/// such as the output of a batch with `--format json`, is searched for the
+/// one of `input_path`.
+pub fn load(path: &Path, input_path: Option<&Path>) -> io::Result<Loudness> {
+    let contents = fs::read_to_string(path)?;
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), message),
+        )
+    };
+    let mut saved = serde_json::Deserializer::from_str(&contents)
+        .into_iter::<SavedAnalysis>()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(e.to_string()))?;
+    if saved.len() == 1 {
+        return Ok(saved.remove(0).loudness);
+    }
+    let input_path = input_path.unwrap_or(Path::new(""));
+    saved
+        .into_iter()
+        .find(|saved| saved.input == input_path)
+        .map(|saved| saved.loudness)
+        .ok_or_else(|| invalid(format!("no measurements of {}", input_path.display())))
+}
@@ -0,0 +1,703 @@
+use crate::{
+    ffmpeg, layout_channels, logging, AnalysisCache, AudioStreamInfo, Backend, Compressor, Downmix,
+    Error, FilterSettings, Loudness, LoudnessHistory, MediaInfo, Mode, Options, PeakMode,
+    ProgressSpinner,
+};
+use std::{ffi::OsStr, io, path::Path};
+
+/// Shortest input in seconds that loudnorm measures reliably. Its loudness
+/// range needs 3s short-term blocks, and shorter inputs leave the integrated
+/// measurement with too few gating blocks.
+const MIN_MEASURE_DURATION: f64 = 3.0;
+
+/// How far in LU the loudness range may exceed its target before
+/// `--enforce-lra` compresses it.
+const LRA_ENFORCE_MARGIN: f64 = 1.0;
+
+/// Shortest chunk in seconds `--analysis-jobs` splits an input into; for
+/// shorter inputs, starting the processes costs more than it saves.
+const MIN_CHUNK_DURATION: f64 = 60.0;
+
+/// What ebur128 reports as the integrated loudness of silence.
+const EBUR128_SILENCE_LUFS: f64 = -70.0;
+
+/// Sample peak in dBFS from which samples count as clipped; astats prints
+/// 0.000000 for full scale.
+const FULL_SCALE_DBFS: f64 = -0.001;
+
+/// Runs the loudnorm measurement pass.
+pub struct LoudnessAnalyzer;
+
+impl LoudnessAnalyzer {
+    /// Measures the loudness of `input_path` with the targets in `options`.
+    pub fn measure(input_path: &Path, options: &Options) -> io::Result<Loudness> {
+        if let Some(measured) = &options.measured {
+            return Ok(measured.clone());
+        }
+        if options.backend == Backend::Native {
+            return Self::cached(input_path, options, || {
+                Self::measure_native(input_path, options)
+            });
+        }
+        let info = MediaInfo::probe(input_path, options)?;
+        Self::measure_probed(input_path, options, &info)
+    }
+
+    /// Measures `input_path` using stream details already gathered by
+    /// [`MediaInfo::probe`].
+    pub fn measure_probed(
+        input_path: &Path,
+        options: &Options,
+        info: &MediaInfo,
+    ) -> io::Result<Loudness> {
+        let stream = info.audio_stream(options.audio_stream)?;
+        Self::warn_if_downmixed(input_path, options, stream);
+        let sample_rate = stream.sample_rate;
+        let dialnorm = stream.dialnorm;
+        if let Some(measured) = &options.measured {
+            return Ok(Loudness {
+                sample_rate,
+                dialnorm,
+                ..measured.clone()
+            });
+        }
+        let loudness = Self::measure_stream(input_path, options, info)?;
+        let loudness = Self::enforce_lra(
+            input_path,
+            options,
+            info,
+            Loudness {
+                sample_rate,
+                dialnorm,
+                ..loudness
+            },
+        )?;
+        let loudness = Self::measure_dialogue(input_path, options, info, loudness)?;
+        Self::warn_about_dialnorm(input_path, options, &loudness);
+        Ok(loudness)
+    }
+
+    /// Measures the selected stream of `input_path` through the filters of
+    /// `options`, or returns its cached measurements.
+    fn measure_stream(
+        input_path: &Path,
+        options: &Options,
+        info: &MediaInfo,
+    ) -> io::Result<Loudness> {
+        Self::cached(input_path, options, || {
+            if options.backend == Backend::Native {
+                return Self::measure_native(input_path, options);
+            }
+            let duration = options.segment_duration(info.duration_of(options.audio_stream));
+            if let Some(duration) = duration.filter(|&d| {
+                options.analysis_jobs > 1 && d >= options.analysis_jobs as f64 * MIN_CHUNK_DURATION
+            }) {
+                return Self::measure_chunked(input_path, options, duration);
+            }
+            let filter_settings = FilterSettings::construct(options, None);
+            let sampling = options
+                .fast_analysis
+                .filter(|sampling| duration.is_some_and(|d| sampling.applies_to(d)));
+            let (filter_settings, duration) = match sampling {
+                Some(sampling) => {
+                    logging::info(format_args!(
+                        "{}: measuring {}s of every {}s only; the results are approximate",
+                        input_path.display(),
+                        sampling.chunk,
+                        sampling.interval
+                    ));
+                    (
+                        format!("{},{}", sampling.filter(), filter_settings),
+                        duration.map(|d| sampling.sampled_duration(d)),
+                    )
+                }
+                None => (filter_settings, duration),
+            };
+            let loops = Self::loops_needed(input_path, options, duration);
+            let output = Self::analyze_loudness(
+                input_path,
+                &filter_settings,
+                options,
+                duration.map(|d| d * f64::from(loops + 1)),
+                loops,
+            )?;
+
+            let sample_peak = Self::extract_overall(&output, "Peak level dB:");
+            let tail = || {
+                let lines: Vec<&str> = output.lines().collect();
+                lines[lines.len().saturating_sub(10)..].join("\n")
+            };
+            let mut loudness =
+                match options.peak_mode {
+                    PeakMode::True => {
+                        let json = Self::extract_json(&output);
+                        json.parse::<Loudness>().map_err(|e| {
+                            io::Error::from(Error::InvalidOutput {
+                                message: format!("Failed to parse loudnorm JSON: {}", e),
+                                text: if json.is_empty() {
+                                    tail()
+                                } else {
+                                    json.clone()
+                                },
+                            })
+                        })?
+                    }
+                    PeakMode::Sample => Self::parse_ebur128_summary(&output, sample_peak)
+                        .ok_or_else(|| Error::InvalidOutput {
+                            message: "Failed to parse the ebur128 summary".to_string(),
+                            text: tail(),
+                        })?,
+                };
+            if options.mode == Mode::Rms {
+                loudness.input_rms = Some(
+                    Self::extract_overall(&output, "RMS level dB:").ok_or_else(|| {
+                        Error::InvalidOutput {
+                            message: "astats reported no RMS level".to_string(),
+                            text: String::new(),
+                        }
+                    })?,
+                );
+            }
+            loudness.sample_peak = sample_peak;
+            // astats counts how often the peak was reached, which only means
+            // clipping when that peak is full scale.
+            loudness.clipped_samples = loudness.sample_peak.map(|peak| {
+                if peak >= FULL_SCALE_DBFS {
+                    Self::extract_overall(&output, "Peak count:").map_or(0, |count| count as u64)
+                } else {
+                    0
+                }
+            });
+            if let Some(clipped) = loudness.clipped_samples.filter(|&n| n > 0) {
+                logging::warn(format_args!(
+                    "{}: the source is already clipped: {} samples at full scale",
+                    input_path.display(),
+                    clipped
+                ));
+            }
+            loudness.approximate = sampling.is_some();
+            Ok(loudness)
+        })
+    }
+
+    /// With `options.dialogue_gated`, measures the dialogue of the input
+    /// again through [`FilterSettings::dialogue_selection`] and records its
+    /// loudness in `loudness`, the program measurement. Inputs without
+    /// measurable dialogue keep the program loudness.
+    fn measure_dialogue(
+        input_path: &Path,
+        options: &Options,
+        info: &MediaInfo,
+        loudness: Loudness,
+    ) -> io::Result<Loudness> {
+        if !options.dialogue_gated || loudness.is_silent() {
+            return Ok(loudness);
+        }
+        let stream = info.audio_stream(options.audio_stream)?;
+        let selection = FilterSettings::dialogue_selection(stream);
+        // The dialogue is taken from the source channels, ahead of any
+        // conversion of the layout.
+        let dialogue_options = Options {
+            dialogue_gated: false,
+            downmix: None,
+            channel_layout: None,
+            pre_filter: Some(match &options.pre_filter {
+                Some(filter) => format!("{},{}", filter, selection),
+                None => selection,
+            }),
+            ..options.clone()
+        };
+        let dialogue = Self::measure_stream(input_path, &dialogue_options, info)?;
+        if dialogue.is_silent() {
+            logging::warn(format_args!(
+                "{}: no dialogue measured; normalizing the program loudness",
+                input_path.display()
+            ));
+            return Ok(loudness);
+        }
+        logging::info(format_args!(
+            "{}: dialogue at {:.1} LUFS, program at {:.1} LUFS",
+            input_path.display(),
+            dialogue.input_i,
+            loudness.input_i
+        ));
+        Ok(Loudness {
+            dialogue_i: Some(dialogue.input_i),
+            ..loudness
+        })
+    }
+
+    /// With `options.enforce_lra`, measures again through a [`Compressor`]
+    /// when the loudness range of `loudness` is too far above its target for
+    /// loudnorm to normalize linearly, and returns those measurements with
+    /// the compressor recorded for the second pass.
+    fn enforce_lra(
+        input_path: &Path,
+        options: &Options,
+        info: &MediaInfo,
+        loudness: Loudness,
+    ) -> io::Result<Loudness> {
+        if !options.enforce_lra
+            || options.compressor.is_some()
+            || loudness.is_silent()
+            || loudness.input_lra <= options.loudness_range + LRA_ENFORCE_MARGIN
+        {
+            return Ok(loudness);
+        }
+        let compressor = Compressor::for_range(&loudness, options);
+        let compressed = Options {
+            compressor: Some(compressor),
+            ..options.clone()
+        };
+        let measured = Self::measure_probed(input_path, &compressed, info)?;
+        logging::info(format_args!(
+            "{}: compressing {:.1}:1 above {:.1} dBFS to bring the loudness range from {:.1} to {:.1} LU",
+            input_path.display(),
+            compressor.ratio,
+            compressor.threshold,
+            loudness.input_lra,
+            measured.input_lra
+        ));
+        // Clipping is a property of the source, not of the compressed audio.
+        Ok(Loudness {
+            compressor: Some(compressor),
+            sample_peak: loudness.sample_peak,
+            clipped_samples: loudness.clipped_samples,
+            ..measured
+        })
+    }
+
+    /// Returns the cached measurements of `input_path` if there are any, in
+    /// the cache or the history database, and otherwise runs `measure` and
+    /// caches its result. The history only gains rows once the caller knows
+    /// the applied gain.
+    fn cached(
+        input_path: &Path,
+        options: &Options,
+        measure: impl FnOnce() -> io::Result<Loudness>,
+    ) -> io::Result<Loudness> {
+        let cache = options.cache_dir.as_ref().map(AnalysisCache::new);
+        // Entries measured outside RMS mode lack the RMS level.
+        let cached = cache
+            .as_ref()
+            .and_then(|c| c.load(input_path, options))
+            .or_else(|| {
+                let history = options.history_db.as_ref().map(LoudnessHistory::new)?;
+                history.load(input_path, options)
+            })
+            .filter(|l| options.mode != Mode::Rms || l.input_rms.is_some());
+        if let Some(loudness) = cached {
+            logging::debug(format_args!(
+                "{}: using cached measurements",
+                input_path.display()
+            ));
+            return Ok(loudness);
+        }
+        logging::info(format_args!("{}: measuring loudness", input_path.display()));
+        let loudness = measure()?;
+        if let Some(cache) = &cache {
+            // A cache that can't be written only costs a future re-measure.
+            if let Err(e) = cache.store(input_path, options, &loudness) {
+                logging::debug(format_args!("{}: not cached: {}", input_path.display(), e));
+            }
+        }
+        Ok(loudness)
+    }
+
+    /// Measures each audio stream of `input_path` in its own pass.
+    pub fn measure_all_streams(input_path: &Path, options: &Options) -> io::Result<Vec<Loudness>> {
+        let info = MediaInfo::probe(input_path, options)?;
+        info.audio_stream(None)?;
+        (0..info.audio_streams.len())
+            .map(|index| {
+                let options = Options {
+                    audio_stream: Some(index),
+                    ..options.clone()
+                };
+                Self::measure_probed(input_path, &options, &info)
+            })
+            .collect()
+    }
+
+    /// Measures `input_path` with the native meter, which reads the file as
+    /// it is. Options that would change the signal loudnorm measures, or
+    /// that [`LoudnessAnalyzer::measure_probed`] applies afterwards, are
+    /// refused rather than silently left out.
+    #[cfg(feature = "native")]
+    fn measure_native(input_path: &Path, options: &Options) -> io::Result<Loudness> {
+        let unsupported = [
+            (options.mode == Mode::Rms, "RMS mode"),
+            (options.trim_silence.is_some(), "Silence trimming"),
+            (options.fast_analysis.is_some(), "Fast analysis"),
+            (options.peak_mode == PeakMode::Sample, "Sample peak mode"),
+            (options.dialogue_gated, "Dialogue gating"),
+            (options.enforce_lra, "Enforcing the loudness range"),
+            (options.downmix.is_some(), "Downmixing"),
+            (options.channel_layout.is_some(), "A channel layout"),
+            (
+                options.encoding.sample_rate.is_some() || options.encoding.sample_fmt.is_some(),
+                "Converting the sample rate or format",
+            ),
+            (options.pre_filter.is_some(), "A pre-filter"),
+            (options.compressor.is_some(), "Compression"),
+            // Whatever else comes to filter the input before loudnorm.
+            (!options.aformat_prefix().is_empty(), "Filtering the input"),
+        ];
+        if let Some((_, feature)) = unsupported.iter().find(|(used, _)| *used) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} needs the ffmpeg backend", feature),
+            ));
+        }
+        crate::native::measure(input_path, options)
+    }
+
+    #[cfg(not(feature = "native"))]
+    fn measure_native(_input_path: &Path, _options: &Options) -> io::Result<Loudness> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The native backend is not available; rebuild with `--features native`",
+        ))
+    }
+
+    /// Measures `duration` seconds of `input_path` in `options.analysis_jobs`
+    /// chunks at once, for the native meter to join.
+    #[cfg(feature = "native")]
+    fn measure_chunked(
+        input_path: &Path,
+        options: &Options,
+        duration: f64,
+    ) -> io::Result<Loudness> {
+        if options.mode == Mode::Rms {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "RMS mode can't be measured in chunks",
+            ));
+        }
+        if options.trim_silence.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Silence trimming can't be measured in chunks",
+            ));
+        }
+        if options.peak_mode == PeakMode::Sample {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Sample peak mode can't be measured in chunks",
+            ));
+        }
+        let start = options
+            .start
+            .as_deref()
+            .and_then(ffmpeg::parse_timestamp)
+            .unwrap_or(0.0);
+        logging::info(format_args!(
+            "{}: measuring {:.0}s in {} chunks at once",
+            input_path.display(),
+            duration,
+            options.analysis_jobs
+        ));
+        crate::native::measure_chunked(input_path, options, start, duration, options.analysis_jobs)
+    }
+
+    #[cfg(not(feature = "native"))]
+    fn measure_chunked(
+        _input_path: &Path,
+        _options: &Options,
+        _duration: f64,
+    ) -> io::Result<Loudness> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Chunked analysis needs the native meter; rebuild with `--features native`",
+        ))
+    }
+
+    /// Warns when the source has more channels than the layout it is
+    /// converted to, naming the matrix that folds them down.
+    fn warn_if_downmixed(input_path: &Path, options: &Options, stream: &AudioStreamInfo) {
+        let Some(layout) = options.target_layout() else {
+            return;
+        };
+        let (Some(channels), Some(target)) = (stream.channels, layout_channels(layout)) else {
+            return;
+        };
+        if channels <= target {
+            return;
+        }
+        let matrix = match &options.downmix {
+            Some(Downmix::LoRo) => "the lo-ro matrix",
+            Some(Downmix::LtRt) => "the lt-rt matrix",
+            Some(Downmix::DialogueBoost) => "the dialogue-boost matrix",
+            Some(Downmix::Pan(_)) => "the given pan matrix",
+            None => "ffmpeg's default matrix, which drops the LFE; --downmix picks another",
+        };
+        logging::warn(format_args!(
+            "{}: downmixing {} to {} with {}",
+            input_path.display(),
+            stream
+                .channel_layout
+                .clone()
+                .unwrap_or_else(|| format!("{} channels", channels)),
+            layout,
+            matrix
+        ));
+    }
+
+    /// Warns when the input declares a dialnorm, which decoders apply on
+    /// top of the level measured here, and which the output doesn't inherit.
+    fn warn_about_dialnorm(input_path: &Path, options: &Options, loudness: &Loudness) {
+        let Some(dialnorm) = loudness.dialnorm else {
+            return;
+        };
+        let output = match options.encoding.dialnorm {
+            Some(output) => format!(
+                "is marked with {} dB",
+                output.value(options.integrated_loudness)
+            ),
+            None => "carries no dialnorm; see --dialnorm".to_string(),
+        };
+        logging::warn(format_args!(
+            "{}: the input declares a dialnorm of {} dB, so decoders honoring it play it {} dB below the measured {:.1} LUFS; the output {}",
+            input_path.display(),
+            dialnorm,
+            31 + dialnorm,
+            loudness.input_i,
+            output
+        ));
+    }
+
+    /// How many extra times to play an input shorter than [`MIN_MEASURE_DURATION`] so
+    /// the measurement sees enough audio. Looping keeps the integrated
+    /// loudness and true peak of the original.
+    fn loops_needed(input_path: &Path, options: &Options, duration: Option<f64>) -> u32 {
+        let Some(duration) = duration.filter(|d| *d > 0.0 && *d < MIN_MEASURE_DURATION) else {
+            return 0;
+        };
+        if options.duration.is_some() {
+            logging::warn(format_args!(
+                "{}: the measured segment is only {:.2}s; loudnorm needs {:.0}s for reliable results",
+                input_path.display(),
+                duration,
+                MIN_MEASURE_DURATION
+            ));
+            return 0;
+        }
+        logging::warn(format_args!(
+            "{}: input is only {:.2}s long; looping it to measure at least {:.0}s",
+            input_path.display(),
+            duration,
+            MIN_MEASURE_DURATION
+        ));
+        (MIN_MEASURE_DURATION / duration).ceil() as u32 - 1
+    }
+
+    fn analyze_loudness(
+        input_path: &Path,
+        filter_settings: &str,
+        options: &Options,
+        duration: Option<f64>,
+        loops: u32,
+    ) -> io::Result<String> {
+        let loops = (loops > 0).then(|| loops.to_string());
+        let segment = options.segment_args();
+        let mut args: Vec<&OsStr> = Vec::new();
+        if let Some(loops) = &loops {
+            args.extend(["-stream_loop", loops.as_str()].map(OsStr::new));
+        }
+        args.extend(segment.iter().map(OsStr::new));
+        args.extend(ffmpeg::protocol_args(input_path).iter().map(OsStr::new));
+        let input = ffmpeg::path_arg(input_path);
+        args.extend([
+            "-i".as_ref(),
+            &*input,
+            "-hide_banner".as_ref(),
+            "-vn".as_ref(),
+        ]);
+        let stream = options.stream_specifier();
+        if let Some(stream) = &stream {
+            args.extend(["-map", stream.as_str()].map(OsStr::new));
+        }
+        args.extend(["-af", filter_settings, "-f", "null", "-"].map(OsStr::new));
+
+        let spinner = ProgressSpinner::labeled("Measuring");
+        let output = ffmpeg::run_with_progress(options, args, duration, &spinner, |line| {
+            ["RMS level dB:", "Peak level dB:", "Peak count:"]
+                .iter()
+                .any(|label| line.contains(label))
+        });
+        spinner.stop();
+        output
+    }
+
+    /// The overall value of the astats statistic `label`, which it prints
+    /// last, after the per-channel ones, e.g. as `RMS level dB: -20.123456`.
+    fn extract_overall(output: &str, label: &str) -> Option<f64> {
+        output
+            .lines()
+            .rev()
+            .find_map(|line| line.split_once(label))
+            .and_then(|(_, value)| value.trim().parse().ok())
+    }
+
+    /// Measurements from the summary ebur128 prints at the end, e.g.
+    /// `I: -19.2 LUFS` followed by the gating `Threshold: -29.5 LUFS` and
+    /// `LRA: 6.9 LU`, with `sample_peak` from astats as the peak. ebur128
+    /// reports silence as its -70 LUFS gate, which becomes `-inf` like
+    /// loudnorm's.
+    fn parse_ebur128_summary(output: &str, sample_peak: Option<f64>) -> Option<Loudness> {
+        let summary = &output[output.rfind("Summary:")?..];
+        let value = |label: &str| {
+            summary
+                .lines()
+                .find_map(|line| line.trim().strip_prefix(label))
+                .and_then(|rest| rest.split_whitespace().next()?.parse::<f64>().ok())
+        };
+        let input_i = value("I:")?;
+        let input_i = if input_i <= EBUR128_SILENCE_LUFS {
+            f64::NEG_INFINITY
+        } else {
+            input_i
+        };
+        Some(Loudness::new(
+            input_i,
+            sample_peak?,
+            value("LRA:")?,
+            value("Threshold:")?,
+        ))
+    }
+
+    /// Finds the summary loudnorm prints after its `[Parsed_loudnorm_N @ ...]`
+    /// line. Anchoring on the last such marker and matching braces keeps
+    /// other braces on stderr, e.g. in metadata or later warnings, out of
+    /// the JSON. Without a marker, the last object mentioning `input_i` is
+    /// used.
+    pub(crate) fn extract_json(output: &str) -> String {
+        let is_summary = |object: &&str| object.contains("\"input_i\"");
+        let from_marker = output
+            .rfind("[Parsed_loudnorm")
+            .and_then(|marker| Self::balanced_object(&output[marker..]))
+            .filter(is_summary);
+        from_marker
+            .or_else(|| {
+                output
+                    .match_indices('{')
+                    .rev()
+                    .filter_map(|(start, _)| Self::balanced_object(&output[start..]))
+                    .find(is_summary)
+            })
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// The first `{...}` object in `text` with balanced braces, ignoring
+    /// braces inside JSON strings.
+    fn balanced_object(text: &str) -> Option<&str> {
+        let start = text.find('{')?;
+        let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+        for (offset, c) in text[start..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '{' if !in_string => depth += 1,
+                '}' if !in_string => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(&text[start..=start + offset]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LoudnessAnalyzer;
+    use crate::Loudness;
+
+    /// The loudnorm summary found in the ffmpeg stderr `output`.
+    fn summary(output: &str) -> Loudness {
+        LoudnessAnalyzer::extract_json(output)
+            .parse()
+            .expect("loudnorm summary")
+    }
+
+    #[test]
+    fn braces_in_metadata_are_skipped() {
+        let loudness = summary(include_str!("../tests/data/loudnorm/metadata_braces.txt"));
+        assert_eq!(loudness.input_i, -27.61);
+        assert_eq!(loudness.input_tp, -4.47);
+        assert_eq!(loudness.input_lra, 18.06);
+        assert_eq!(loudness.input_thresh, -39.20);
+        assert_eq!(loudness.target_offset, 0.58);
+    }
+
+    #[test]
+    fn warnings_after_the_summary_are_ignored() {
+        let loudness = summary(include_str!("../tests/data/loudnorm/trailing_warnings.txt"));
+        assert_eq!(loudness.input_i, -19.02);
+        assert_eq!(loudness.input_tp, -0.31);
+        assert_eq!(loudness.target_offset, 0.03);
+    }
+
+    #[test]
+    fn the_last_of_several_summaries_is_used() {
+        let loudness = summary(include_str!("../tests/data/loudnorm/multiple_blocks.txt"));
+        assert_eq!(loudness.input_i, -22.87);
+        assert_eq!(loudness.input_lra, 9.80);
+    }
+
+    #[test]
+    fn truncated_output_has_no_summary() {
+        let output = include_str!("../tests/data/loudnorm/truncated.txt");
+        assert_eq!(LoudnessAnalyzer::extract_json(output), "");
+        assert!(LoudnessAnalyzer::extract_json(output)
+            .parse::<Loudness>()
+            .is_err());
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn native_backend_refuses_options_changing_the_measured_signal() {
+        use crate::{Backend, Options};
+        use std::{io, path::Path};
+
+        for (options, feature) in [
+            (
+                Options {
+                    pre_filter: Some("highpass=f=80".into()),
+                    ..Options::default()
+                },
+                "A pre-filter",
+            ),
+            (
+                Options {
+                    enforce_lra: true,
+                    ..Options::default()
+                },
+                "Enforcing the loudness range",
+            ),
+            (
+                Options {
+                    channel_layout: Some("mono".into()),
+                    ..Options::default()
+                },
+                "A channel layout",
+            ),
+        ] {
+            let options = Options {
+                backend: Backend::Native,
+                ..options
+            };
+            let e = LoudnessAnalyzer::measure(Path::new("missing.wav"), &options).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+            assert_eq!(
+                e.to_string(),
+                format!("{} needs the ffmpeg backend", feature)
+            );
+        }
+    }
+}
@@ -0,0 +1,1002 @@
+//! Turning the parsed command line into the [`RunConfig`] of the run, one
+//! group of settings at a time, over the defaults of the config file.
+
+use crate::{cli, ENV_PREFIX, OUTPUT_EXTENSIONS};
+use clap::{builder::Command, parser::ValueSource, ArgMatches};
+use ffmpeg_normalize::{
+    analysis, logging, serve, throttle, AnalysisCache, Backend, ConfigFile, ConfigValue, CueSheet,
+    Dialnorm, Downmix, Dynaudnorm, EncodeOptions, Engine, FilterTarget, GroupReference,
+    ImportedStats, Limiter, Loudness, Manifest, Mode, Notifier, Options, OutputFormat, PeakMode,
+    PostHook, Preset, PrintValue, ProgressFormat, Resampler, RunConfig, Sampling, Shell,
+    SilenceTrim, SpecProfile, Speechnorm, StatsExport, Strategy, StreamFormat, TagFormat,
+    STDIN_PATH, STDOUT_PATH,
+};
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// The arguments of one invocation, with its subcommand split off.
+struct Args<'a> {
+    matches: &'a ArgMatches,
+    subcommand: Option<&'a str>,
+    /// The flag the subcommand stands for: `tag` for `--tag-only` and
+    /// `verify` for `--verify`.
+    implied: Option<&'static str>,
+    /// The flag asking for group-relative normalization, which is album
+    /// mode with a reference.
+    group_flag: Option<&'static str>,
+}
+
+impl<'a> Args<'a> {
+    fn new(matches: &'a ArgMatches) -> Self {
+        let (matches, subcommand) = match matches.subcommand() {
+            Some((name, matches)) => (matches, Some(name)),
+            None => (matches, None),
+        };
+        Self {
+            matches,
+            subcommand,
+            implied: match subcommand {
+                Some("tag") => Some("tag_only"),
+                Some("verify") => Some("verify"),
+                _ => None,
+            },
+            group_flag: ["group_relative", "match_loudest", "reference"]
+                .into_iter()
+                .find(|id| is_explicit(matches, id)),
+        }
+    }
+
+    /// Whether the flag `id` is set, by itself or by what stands for it.
+    fn flag(&self, id: &str) -> bool {
+        self.matches.get_flag(id)
+            || self.implied == Some(id)
+            || (id == "album" && self.group_flag.is_some())
+    }
+
+    fn is_explicit(&self, id: &str) -> bool {
+        is_explicit(self.matches, id)
+    }
+
+    /// Whether the inputs are only measured and reported on (`analyze` and
+    /// `compare`).
+    fn report(&self) -> bool {
+        matches!(self.subcommand, Some("analyze" | "compare"))
+    }
+}
+
+/// Builds the configuration of the run from `matches`, failing on options
+/// that don't go together.
+pub(crate) fn run_config(matches: &ArgMatches) -> io::Result<RunConfig> {
+    let args = Args::new(matches);
+    check_subcommand(&args)?;
+    let config = RunConfig::new(options(&args)?);
+    [
+        input_settings,
+        output_settings,
+        run_settings,
+        batch_settings,
+        print_settings,
+    ]
+    .into_iter()
+    .try_fold(config, |config, apply| apply(config, &args))
+}
+
+/// Refuses the options the subcommand has no use for, and requires the
+/// ones it can't do without.
+fn check_subcommand(args: &Args) -> io::Result<()> {
+    let matches = args.matches;
+    if let (Some(group_flag), false) = (args.group_flag, matches.get_flag("album")) {
+        if let Some(id) = conflicts_of("album")
+            .iter()
+            .find(|id| is_explicit(matches, id))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "--{} can't be used with --{}",
+                    id.replace('_', "-"),
+                    group_flag.replace('_', "-")
+                ),
+            ));
+        }
+    }
+    if let Some(subcommand) = args.subcommand {
+        let excluded: Vec<String> = match subcommand {
+            "analyze" | "compare" | "check" => [
+                "output",
+                "output_template",
+                "output_dir",
+                "output_ext",
+                "tag_only",
+                "album",
+                "group_relative",
+                "match_loudest",
+                "reference",
+                "print_command",
+                "verify",
+                "cue",
+                "targets",
+            ]
+            .map(String::from)
+            .to_vec(),
+            "batch" => ["output", "cue"].map(String::from).to_vec(),
+            // Jobs name their inputs and outputs, one at a time.
+            "serve" => [
+                "output",
+                "watch",
+                "cue",
+                "concat",
+                "stems",
+                "album",
+                "manifest",
+                "files_from",
+                "print_command",
+                "interactive",
+                "tui",
+                "save_analysis",
+                "measured_i",
+                "from_analysis",
+                "import_stats",
+                "export_stats",
+            ]
+            .map(String::from)
+            .to_vec(),
+            _ => args.implied.map(conflicts_of).unwrap_or_default(),
+        };
+        if let Some(id) = excluded.iter().find(|id| is_explicit(matches, id)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "--{} can't be used with {}",
+                    id.replace('_', "-"),
+                    subcommand
+                ),
+            ));
+        }
+    }
+    if args.is_explicit("spec") && args.subcommand != Some("check") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--spec only applies to check",
+        ));
+    }
+    if let Some(id) = ["socket", "listen"]
+        .iter()
+        .find(|id| is_explicit(matches, id))
+        .filter(|_| args.subcommand != Some("serve"))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("--{} only applies to serve", id),
+        ));
+    }
+    if args.subcommand == Some("serve")
+        && !matches.contains_id("socket")
+        && !matches.contains_id("listen")
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "serve needs --socket or --listen",
+        ));
+    }
+    if args.subcommand == Some("batch")
+        && !matches.contains_id("output_template")
+        && !matches.contains_id("output_dir")
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "batch needs --output-template or --output-dir",
+        ));
+    }
+    Ok(())
+}
+
+/// The options of measuring and normalizing each input.
+fn options(args: &Args) -> io::Result<Options> {
+    let matches = args.matches;
+    if matches.contains_id("nice") && !cfg!(unix) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--nice needs the nice command of Unix systems",
+        ));
+    }
+    if matches.get_flag("idle_io") && !cfg!(target_os = "linux") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--idle-io is only available on Linux",
+        ));
+    }
+    let engine = engine(args)?;
+    let mode = mode(args, &engine)?;
+    let preset = matches
+        .get_one::<String>("preset")
+        .and_then(|name| Preset::find(name));
+    // A preset replaces the defaults, but explicitly passed flags win.
+    let target = |id: &str, from_preset: fn(&Preset) -> f64| match preset {
+        Some(preset) if !args.is_explicit(id) => from_preset(&preset),
+        _ => *matches.get_one::<f64>(id).unwrap(),
+    };
+    let downmix = matches
+        .get_one::<String>("downmix")
+        .map(|downmix| downmix.parse::<Downmix>())
+        .transpose()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(Options {
+        integrated_loudness: target("integrated_loudness", |p| p.integrated_loudness),
+        loudness_range: target("loudness_range", |p| p.loudness_range),
+        true_peak: target("true_peak", |p| p.true_peak),
+        channel_layout: matches
+            .get_one::<String>("channel_layout")
+            .cloned()
+            .or_else(|| {
+                // The downmix presets fold down to stereo.
+                let preset = downmix.as_ref().is_some_and(|d| d.pan_layout().is_none());
+                (matches.get_flag("down_mix") || preset).then(|| "stereo".to_string())
+            }),
+        downmix,
+        dual_mono: matches.get_flag("dual_mono"),
+        offset: matches.get_one::<f64>("offset").copied(),
+        measured: measured(args)?,
+        pass_silent: matches.get_flag("pass_silent"),
+        encoding: encoding(args)?,
+        cache_dir: cache_dir(args)?,
+        history_db: matches.get_one::<PathBuf>("db").cloned(),
+        ffmpeg_path: matches.get_one::<PathBuf>("ffmpeg_path").cloned(),
+        audio_stream: matches
+            .get_one::<u64>("audio_stream")
+            .map(|&index| index as usize),
+        start: matches.get_one::<String>("start").cloned(),
+        duration: matches.get_one::<String>("duration").cloned(),
+        cut: false,
+        trim_silence: matches.get_one::<SilenceTrim>("trim_silence").copied(),
+        fast_analysis: matches.get_one::<Sampling>("fast_analysis").copied(),
+        analysis_jobs: usize::from(*matches.get_one::<u16>("analysis_jobs").unwrap()),
+        compressor: None,
+        pre_filter: matches.get_one::<String>("pre_filter").cloned(),
+        post_filter: matches.get_one::<String>("post_filter").cloned(),
+        enforce_lra: matches.get_flag("enforce_lra"),
+        dialogue_gated: matches.get_flag("dialogue_gated"),
+        timeout: matches
+            .get_one::<f64>("timeout")
+            .map(|&seconds| Duration::from_secs_f64(seconds)),
+        nice: matches.get_one::<i64>("nice").map(|&level| level as i32),
+        idle_io: matches.get_flag("idle_io"),
+        backend: matches
+            .get_one::<String>("backend")
+            .map_or(Ok(Backend::Ffmpeg), |s| s.parse())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        mode,
+        target_rms: *matches.get_one::<f64>("target_rms").unwrap(),
+        engine,
+        limiter: matches.get_flag("limiter").then(|| Limiter {
+            attack: *matches.get_one::<f64>("limiter_attack").unwrap(),
+            release: *matches.get_one::<f64>("limiter_release").unwrap(),
+            ceiling: matches.get_one::<f64>("limiter_ceiling").copied(),
+        }),
+        peak_mode: matches
+            .get_one::<String>("peak_mode")
+            .map_or(Ok(PeakMode::True), |s| s.parse())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        tag_format: matches
+            .get_one::<String>("tag_format")
+            .map_or(Ok(TagFormat::Auto), |s| s.parse())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        strategy: if matches.get_flag("no_linear") {
+            Strategy::Dynamic
+        } else {
+            matches
+                .get_one::<String>("strategy")
+                .map_or(Ok(Strategy::Auto), |s| s.parse())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        },
+    })
+}
+
+/// The `--filter-engine`, refusing what only loudnorm can do with the
+/// others.
+fn engine(args: &Args) -> io::Result<Engine> {
+    let matches = args.matches;
+    let engine = match matches
+        .get_one::<String>("filter_engine")
+        .map(String::as_str)
+    {
+        Some("dynaudnorm") => Engine::Dynaudnorm(Dynaudnorm {
+            frame_len: *matches.get_one::<u32>("dynaudnorm_frame_len").unwrap(),
+            gauss_size: *matches.get_one::<u32>("dynaudnorm_gauss_size").unwrap(),
+            peak: *matches.get_one::<f64>("dynaudnorm_peak").unwrap(),
+            max_gain: *matches.get_one::<f64>("dynaudnorm_max_gain").unwrap(),
+        }),
+        Some("speechnorm") => Engine::Speechnorm(Speechnorm {
+            peak: *matches.get_one::<f64>("speechnorm_peak").unwrap(),
+            expansion: *matches.get_one::<f64>("speechnorm_expansion").unwrap(),
+            compression: *matches.get_one::<f64>("speechnorm_compression").unwrap(),
+        }),
+        _ => Engine::Loudnorm,
+    };
+    if engine == Engine::Loudnorm {
+        return Ok(engine);
+    }
+    if matches.contains_id("targets") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--targets only works with --filter-engine loudnorm",
+        ));
+    }
+    if args.report() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "analyze only works with --filter-engine loudnorm",
+        ));
+    }
+    // These all rely on loudnorm's measurements.
+    let measuring = ["tag_only", "album", "all_audio_streams", "verify"];
+    if let Some(id) = measuring.iter().find(|id| args.flag(id)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "--{} only works with --filter-engine loudnorm",
+                id.replace('_', "-")
+            ),
+        ));
+    }
+    Ok(engine)
+}
+
+/// The `--mode`, refusing what only integrated loudness with loudnorm can
+/// do with the others.
+fn mode(args: &Args, engine: &Engine) -> io::Result<Mode> {
+    let matches = args.matches;
+    let mode = matches
+        .get_one::<String>("mode")
+        .map_or(Ok(Mode::Ebu), |s| s.parse())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if mode != Mode::Ebu && *engine != Engine::Loudnorm {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--mode only works with --filter-engine loudnorm",
+        ));
+    }
+    if matches.get_flag("enforce_lra") && (mode != Mode::Ebu || *engine != Engine::Loudnorm) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--enforce-lra only works with --mode ebu and --filter-engine loudnorm",
+        ));
+    }
+    if matches.get_flag("dialogue_gated") && (mode != Mode::Ebu || *engine != Engine::Loudnorm) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--dialogue-gated only works with --mode ebu and --filter-engine loudnorm",
+        ));
+    }
+    if mode != Mode::Ebu {
+        // These target integrated loudness whatever the mode.
+        if let Some(id) = ["tag_only", "album", "verify"]
+            .iter()
+            .find(|id| args.flag(id))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--{} only works with --mode ebu", id.replace('_', "-")),
+            ));
+        }
+    }
+    Ok(mode)
+}
+
+/// How the outputs are encoded.
+fn encoding(args: &Args) -> io::Result<EncodeOptions> {
+    let matches = args.matches;
+    // --down_mix stands for the stereo 16bit 48kHz it always meant,
+    // unless any of them is set explicitly.
+    let down_mix = matches.get_flag("down_mix");
+    let output_ext = matches.get_one::<String>("output_ext").map(String::as_str);
+    let codec = matches.get_one::<String>("codec").cloned().or_else(|| {
+        OUTPUT_EXTENSIONS
+            .iter()
+            .find(|(ext, _)| Some(*ext) == output_ext)
+            .and_then(|(_, codec)| codec.map(str::to_string))
+    });
+    let dialnorm = matches.get_one::<Dialnorm>("dialnorm").copied();
+    if let (Some(_), Some(codec)) = (dialnorm, &codec) {
+        if !["ac3", "ac3_fixed", "eac3"].contains(&codec.as_str()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--dialnorm needs an AC-3 or E-AC-3 output, not {}", codec),
+            ));
+        }
+    }
+    Ok(EncodeOptions {
+        codec,
+        bitrate: matches.get_one::<String>("bitrate").cloned(),
+        sample_rate: matches
+            .get_one::<u32>("sample_rate")
+            .copied()
+            .or(down_mix.then_some(48000)),
+        sample_fmt: matches
+            .get_one::<String>("sample_fmt")
+            .cloned()
+            .or_else(|| down_mix.then(|| "s16".to_string())),
+        resampler: matches
+            .get_one::<String>("resampler")
+            .map(|engine| Resampler {
+                engine: engine.clone(),
+                precision: matches.get_one::<u32>("resampler_precision").copied(),
+            }),
+        copy_video: matches.get_flag("copy_video"),
+        map_all: matches.get_flag("map_all"),
+        strip_metadata: matches.get_flag("strip_metadata"),
+        metadata: Vec::new(),
+        keep_sample_rate: matches.get_flag("keep_sample_rate"),
+        keep_bit_depth: true,
+        reproducible: matches.get_flag("reproducible"),
+        dialnorm,
+        stream_format: matches
+            .get_one::<String>("output_format")
+            .map(|format| format.parse::<StreamFormat>())
+            .transpose()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+    })
+}
+
+/// Measurements replacing the first pass, from `--from-analysis` or the
+/// `--measured-*` values.
+fn measured(args: &Args) -> io::Result<Option<Loudness>> {
+    let matches = args.matches;
+    if let Some(path) = matches.get_one::<PathBuf>("from_analysis") {
+        let input_path = matches
+            .try_get_many::<PathBuf>("input")
+            .ok()
+            .flatten()
+            .and_then(|mut inputs| inputs.next());
+        return analysis::load(path, input_path.map(PathBuf::as_path)).map(Some);
+    }
+    let Some(&input_i) = matches.get_one::<f64>("measured_i") else {
+        return Ok(None);
+    };
+    let value = |id| *matches.get_one::<f64>(id).unwrap();
+    Ok(Some(Loudness::new(
+        input_i,
+        value("measured_tp"),
+        value("measured_lra"),
+        value("measured_thresh"),
+    )))
+}
+
+/// The directory of the measurement cache, with `--cache`.
+fn cache_dir(args: &Args) -> io::Result<Option<PathBuf>> {
+    let matches = args.matches;
+    if !matches.get_flag("cache") || matches.get_flag("no_cache") {
+        return Ok(None);
+    }
+    matches
+        .get_one::<PathBuf>("cache_dir")
+        .cloned()
+        .or_else(AnalysisCache::default_dir)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No cache directory found; pass --cache-dir",
+            )
+        })
+        .map(Some)
+}
+
+/// The inputs: given as arguments, listed in `--files-from` or the
+/// `--manifest`, named by the `--cue` sheet, or the `--watch` directory.
+fn input_settings(config: RunConfig, args: &Args) -> io::Result<RunConfig> {
+    let matches = args.matches;
+    let cue = matches
+        .get_one::<PathBuf>("cue")
+        .map(|path| CueSheet::read(path))
+        .transpose()?;
+    // `selftest` and `doctor` have no inputs to define.
+    let inputs = matches.try_get_many::<PathBuf>("input").ok().flatten();
+    let files_from = matches.get_one::<PathBuf>("files_from");
+    if files_from.is_some_and(|path| path == Path::new(STDIN_PATH))
+        && inputs
+            .clone()
+            .is_some_and(|mut inputs| inputs.any(|path| path == Path::new(STDIN_PATH)))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Standard input can't hold both the file list and an input",
+        ));
+    }
+    let files_from = files_from
+        .map(|path| ffmpeg_normalize::read_file_list(path))
+        .transpose()?;
+    let manifest = matches
+        .get_one::<PathBuf>("manifest")
+        .map(|path| Manifest::read(path).map(Arc::new))
+        .transpose()?;
+    // Inputs listed in files rather than given as arguments.
+    let listed = (files_from.is_some() || manifest.is_some()).then(|| {
+        let manifest_inputs = manifest.iter().flat_map(|manifest| manifest.inputs());
+        files_from
+            .into_iter()
+            .flatten()
+            .chain(manifest_inputs.cloned())
+            .collect::<Vec<_>>()
+    });
+    let input_paths = match inputs {
+        Some(_) if matches.contains_id("watch") => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--watch takes no input files",
+            ))
+        }
+        Some(inputs) => ffmpeg_normalize::expand_inputs(&inputs.cloned().collect::<Vec<_>>())
+            .into_iter()
+            .chain(listed.into_iter().flatten())
+            .collect(),
+        None if listed.is_some() => listed.unwrap_or_default(),
+        None if matches.contains_id("watch")
+            || matches!(args.subcommand, Some("selftest" | "doctor" | "serve")) =>
+        {
+            Vec::new()
+        }
+        None if cue.is_some() => {
+            let image = cue.as_ref().and_then(|sheet| sheet.file.clone());
+            vec![image.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "The CUE sheet names no FILE; pass the image as input",
+                )
+            })?]
+        }
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Missing input file path",
+            ))
+        }
+    };
+    Ok(RunConfig {
+        input_paths,
+        watch_dir: matches.get_one::<PathBuf>("watch").cloned(),
+        input_format: matches.get_one::<String>("input_format").cloned(),
+        download_inputs: matches.get_flag("download_inputs"),
+        manifest,
+        cue,
+        recursive: matches.get_flag("recursive") || args.subcommand == Some("batch"),
+        include_ext: matches
+            .get_many::<String>("include_ext")
+            .map(|exts| {
+                exts.map(|ext| ext.trim_start_matches('.').to_string())
+                    .collect()
+            })
+            .unwrap_or(config.include_ext),
+        ..config
+    })
+}
+
+/// Where the outputs go, the `--targets` variants written and the files
+/// written beside them.
+fn output_settings(config: RunConfig, args: &Args) -> io::Result<RunConfig> {
+    let matches = args.matches;
+    let report = args.report();
+    let to_stdout = matches
+        .get_one::<PathBuf>("output")
+        .is_some_and(|path| path == Path::new(STDOUT_PATH));
+    if to_stdout != config.options.encoding.stream_format.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--output - and --output-format go together",
+        ));
+    }
+    if to_stdout {
+        let streaming_conflicts = [
+            "verify",
+            "tag_only",
+            "all_audio_streams",
+            "targets",
+            "post_hook",
+        ];
+        if let Some(id) = streaming_conflicts
+            .iter()
+            .find(|id| is_explicit(matches, id))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--{} can't be used with --output -", id.replace('_', "-")),
+            ));
+        }
+        if matches
+            .get_one::<String>("progress_format")
+            .map(String::as_str)
+            == Some("jsonl")
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--progress-format jsonl writes to standard output, which --output - takes",
+            ));
+        }
+    }
+    let output_ext = matches.get_one::<String>("output_ext");
+    let names_outputs = matches.contains_id("output_template") || matches.contains_id("output_dir");
+    if output_ext.is_some() && !names_outputs {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--output-ext needs --output-template or --output-dir",
+        ));
+    }
+    let variants: Vec<(String, Preset)> = matches
+        .get_many::<String>("targets")
+        .into_iter()
+        .flatten()
+        .filter_map(|name| Some((name.clone(), Preset::find(name)?)))
+        .collect();
+    if !variants.is_empty() && !report {
+        if !names_outputs {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--targets needs --output-template or --output-dir",
+            ));
+        }
+        let distinct = matches
+            .get_one::<String>("output_template")
+            .is_none_or(|t| t.contains("{variant}") || t.contains("{lufs}"));
+        if !distinct {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--output-template needs {variant} or {lufs} to keep the outputs of --targets apart",
+            ));
+        }
+    }
+    Ok(RunConfig {
+        output_path: matches
+            .get_one::<PathBuf>("output")
+            .filter(|_| !report)
+            .cloned(),
+        output_template: matches
+            .get_one::<String>("output_template")
+            .filter(|_| !report)
+            .cloned(),
+        output_dir: matches
+            .get_one::<PathBuf>("output_dir")
+            .filter(|_| !report)
+            .cloned(),
+        output_ext: output_ext.filter(|_| !report).cloned(),
+        in_place: matches.get_flag("in_place") && !report,
+        backup_suffix: matches.get_one::<String>("backup_suffix").cloned(),
+        keep_mtime: matches.get_flag("keep_mtime"),
+        variants: if report { Vec::new() } else { variants },
+        force: matches.get_flag("force"),
+        skip_existing: matches.get_flag("skip_existing"),
+        marker: !matches.get_flag("no_marker"),
+        timeline_path: matches.get_one::<PathBuf>("timeline").cloned(),
+        save_analysis_path: matches.get_one::<PathBuf>("save_analysis").cloned(),
+        plot_path: matches.get_one::<PathBuf>("plot").cloned(),
+        filter_script_path: matches.get_one::<PathBuf>("filter_script").cloned(),
+        output_playlist: matches.get_one::<PathBuf>("output_playlist").cloned(),
+        post_hook: match matches.get_one::<String>("post_hook") {
+            Some(template) if !report => Some(PostHook::new(template)?),
+            _ => None,
+        },
+        ..config
+    })
+}
+
+/// What is done with the inputs: the subcommand, and the modes of
+/// normalizing them.
+fn run_settings(config: RunConfig, args: &Args) -> io::Result<RunConfig> {
+    let matches = args.matches;
+    let report = args.report();
+    Ok(RunConfig {
+        all_audio_streams: matches.get_flag("all_audio_streams"),
+        tag_only: args.flag("tag_only") && !report,
+        album: args.flag("album") && !report,
+        group_reference: match matches.get_one::<PathBuf>("reference") {
+            Some(path) => Some(GroupReference::File(path.clone())),
+            None if args.group_flag.is_some() => Some(GroupReference::Loudest),
+            None => None,
+        },
+        verify_tolerance: args
+            .flag("verify")
+            .then(|| *matches.get_one::<f64>("verify_tolerance").unwrap()),
+        skip_within: matches.get_one::<f64>("skip_within").copied(),
+        concat: matches.get_flag("concat"),
+        stems: matches.get_flag("stems"),
+        audiobook: matches
+            .get_one::<String>("audiobook")
+            .map(|mode| mode.parse())
+            .transpose()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        auto_download_ffmpeg: matches.get_flag("auto_download_ffmpeg"),
+        report,
+        compare: args.subcommand == Some("compare"),
+        check: (args.subcommand == Some("check"))
+            .then(|| matches.get_one::<String>("spec"))
+            .flatten()
+            .and_then(|name| SpecProfile::find(name)),
+        selftest: args.subcommand == Some("selftest"),
+        doctor: args.subcommand == Some("doctor"),
+        serve: if let Some(path) = matches.get_one::<PathBuf>("socket") {
+            Some(serve::Endpoint::Socket(path.clone()))
+        } else {
+            matches
+                .get_one::<String>("listen")
+                .map(|address| serve::Endpoint::Tcp(address.clone()))
+        },
+        ..config
+    })
+}
+
+/// How a batch goes: which inputs are skipped, how many run at once, and
+/// what is recorded about them.
+fn batch_settings(config: RunConfig, args: &Args) -> io::Result<RunConfig> {
+    let matches = args.matches;
+    let report = args.report();
+    if matches.contains_id("max_load") && throttle::load_average().is_none() {
+        logging::warn(format_args!(
+            "--max-load: the load average can't be read on this system; not throttling"
+        ));
+    }
+    Ok(RunConfig {
+        state_path: matches.get_one::<PathBuf>("state").cloned(),
+        resume: matches.get_flag("resume"),
+        skip_tagged: matches.get_flag("skip_tagged") && !matches.get_flag("retag"),
+        skip_normalized: matches.get_flag("skip_normalized"),
+        max_load: matches.get_one::<f64>("max_load").copied(),
+        jobs: match matches.get_one::<u64>("jobs") {
+            // Questions are asked one input at a time.
+            _ if matches.get_flag("interactive") => 1,
+            Some(&n) => n as usize,
+            None => thread::available_parallelism().map_or(1, |n| n.get()),
+        },
+        noop_exit_code: matches.get_flag("noop_exit_code"),
+        fail_fast: matches.get_flag("fail_fast"),
+        interactive: (matches.get_flag("interactive") && !report).then(Arc::default),
+        tui: matches.get_flag("tui") && !report,
+        report_path: matches.get_one::<PathBuf>("report_path").cloned(),
+        notifier: matches
+            .get_one::<String>("notify_url")
+            .map(|url| Arc::new(Notifier::new(url))),
+        imported_stats: matches
+            .get_one::<PathBuf>("import_stats")
+            .map(|path| ImportedStats::read(path).map(Arc::new))
+            .transpose()?,
+        stats_export: matches
+            .get_one::<PathBuf>("export_stats")
+            .map(|path| Arc::new(StatsExport::new(path))),
+        ..config
+    })
+}
+
+/// What is printed for each input, and how progress is shown.
+fn print_settings(config: RunConfig, args: &Args) -> io::Result<RunConfig> {
+    let matches = args.matches;
+    if matches
+        .get_one::<String>("print")
+        .is_some_and(|print| print == "all")
+        && matches
+            .get_one::<String>("format")
+            .is_some_and(|format| format != "json")
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--print all only works with --format json",
+        ));
+    }
+    Ok(RunConfig {
+        print_command: matches.get_flag("print_command") && !args.report(),
+        shell: matches
+            .get_one::<String>("shell")
+            .map_or(Ok(Shell::default()), |s| s.parse())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        progress: !matches.get_flag("no_progress"),
+        progress_format: match matches
+            .get_one::<String>("progress_format")
+            .map(String::as_str)
+        {
+            Some("jsonl") => ProgressFormat::Jsonl,
+            _ => ProgressFormat::Text,
+        },
+        format: match matches.get_one::<String>("format").map(String::as_str) {
+            Some("json") => OutputFormat::Json,
+            _ => OutputFormat::Text,
+        },
+        print0: matches.get_flag("print0"),
+        escape: matches
+            .get_one::<String>("escape")
+            .filter(|shell| *shell != "none")
+            .map(|shell| shell.parse())
+            .transpose()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        target: match matches.get_one::<String>("target").map(String::as_str) {
+            Some("mpv") => FilterTarget::Mpv,
+            _ => FilterTarget::Ffmpeg,
+        },
+        print: match matches.get_one::<String>("print").map(String::as_str) {
+            Some("gain") => PrintValue::Gain,
+            Some("volume") => PrintValue::Volume,
+            _ if matches.get_flag("summary") => PrintValue::Summary,
+            Some("summary") => PrintValue::Summary,
+            Some("all") => PrintValue::All,
+            _ => PrintValue::Filter,
+        },
+        ..config
+    })
+}
+
+/// Ids of the arguments that conflict with `id` either way round.
+fn conflicts_of(id: &str) -> Vec<String> {
+    let command = cli::command();
+    command
+        .get_arguments()
+        .filter(|arg| {
+            arg.get_id() != id
+                && command
+                    .get_arg_conflicts_with(arg)
+                    .iter()
+                    .any(|other| other.get_id() == id)
+        })
+        .chain(
+            command
+                .get_arguments()
+                .filter(|arg| arg.get_id() == id)
+                .flat_map(|arg| command.get_arg_conflicts_with(arg)),
+        )
+        .map(|arg| arg.get_id().to_string())
+        .collect()
+}
+
+/// Parses the command line over the defaults of the config file.
+pub(crate) fn setup_cli() -> io::Result<ArgMatches> {
+    let mut command = cli::command();
+    if let Some(config_file) = load_config_file()? {
+        command = apply_config_file(command, &config_file)?;
+    }
+    Ok(command.get_matches())
+}
+
+/// Loads the file named by `--config`, or the first discovered config
+/// file. This runs before clap so the file can supply argument defaults.
+fn load_config_file() -> io::Result<Option<ConfigFile>> {
+    let mut args = env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            return args.next().map_or_else(
+                || Ok(None),
+                |path| ConfigFile::load(Path::new(&path)).map(Some),
+            );
+        }
+        if let Some(path) = arg.to_str().and_then(|a| a.strip_prefix("--config=")) {
+            return ConfigFile::load(Path::new(path)).map(Some);
+        }
+    }
+    if let Some(path) = env::var_os(format!("{}CONFIG", ENV_PREFIX)) {
+        return ConfigFile::load(Path::new(&path)).map(Some);
+    }
+    ConfigFile::discover()
+}
+
+/// Turns config file settings into argument defaults so that flags given
+/// on the command line or in the environment still take precedence.
+///
+/// `[preset.NAME]` tables declare custom presets, selectable with
+/// `--preset NAME` like the built-in ones. The settings of the selected
+/// one are applied over the file's top-level settings.
+fn apply_config_file(mut command: Command, config_file: &ConfigFile) -> io::Result<Command> {
+    command = apply_settings(command, config_file, config_file.settings(), &[])?;
+    let custom: Vec<&str> = config_file.preset_names().collect();
+    if custom.is_empty() {
+        return Ok(command);
+    }
+    if let Some(name) = custom.iter().find(|name| Preset::find(name).is_some()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}: preset '{}' is built in and can't be redefined",
+                config_file.path.display(),
+                name
+            ),
+        ));
+    }
+    let names: Vec<String> = Preset::names()
+        .map(String::from)
+        .chain(custom.iter().map(|name| name.to_string()))
+        .collect();
+    let help = custom.iter().fold(cli::preset_help(), |help, name| {
+        let description = config_file
+            .preset(name)
+            .find(|(key, _)| *key == "description")
+            .and_then(|(_, value)| value.to_arg_values().pop())
+            .unwrap_or_else(|| format!("from {}", config_file.path.display()));
+        format!("{}\n  {:<16}{}", help, name, description)
+    });
+    command = command.mut_arg("preset", |arg| arg.value_parser(names).long_help(help));
+    match selected_preset(config_file) {
+        Some(name) if custom.contains(&name.as_str()) => apply_settings(
+            command,
+            config_file,
+            config_file.preset(&name),
+            &["description"],
+        ),
+        _ => Ok(command),
+    }
+}
+
+/// Makes `settings` from `config_file` the defaults of their arguments,
+/// ignoring the keys in `skip`.
+fn apply_settings<'a>(
+    mut command: Command,
+    config_file: &ConfigFile,
+    settings: impl Iterator<Item = (&'a str, &'a ConfigValue)>,
+    skip: &[&str],
+) -> io::Result<Command> {
+    for (key, value) in settings.filter(|(key, _)| !skip.contains(key)) {
+        let known = !["input", "config", "preset"].contains(&key)
+            && command.get_arguments().any(|arg| arg.get_id() == key);
+        if !known {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: unknown setting '{}'", config_file.path.display(), key),
+            ));
+        }
+        let values = value.to_arg_values();
+        command = command.mut_arg(key, |arg| arg.default_values(values));
+    }
+    Ok(command)
+}
+
+/// The preset chosen with `--preset`, in the environment or at the top
+/// of `config_file`, looked up before clap parses the arguments.
+fn selected_preset(config_file: &ConfigFile) -> Option<String> {
+    let mut args = env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--preset" || arg == "-p" {
+            return args.next().map(|name| name.to_string_lossy().into_owned());
+        }
+        let arg = arg.to_string_lossy();
+        if let Some(name) = arg
+            .strip_prefix("--preset=")
+            .or_else(|| arg.strip_prefix("-p").filter(|name| !name.is_empty()))
+        {
+            return Some(name.to_string());
+        }
+    }
+    if let Some(name) = env::var_os(format!("{}PRESET", ENV_PREFIX)) {
+        return Some(name.to_string_lossy().into_owned());
+    }
+    config_file
+        .settings()
+        .find(|(key, _)| *key == "preset")
+        .and_then(|(_, value)| value.to_arg_values().pop())
+}
+
+/// Whether `id` was set on the command line or through its environment
+/// variable, rather than by a default or the config file.
+fn is_explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// The log level chosen by `--verbose`, `--quiet` or `--log-level`, looked
+/// up on the subcommand when there is one.
+pub(crate) fn log_level(matches: &ArgMatches) -> logging::Level {
+    let matches = matches.subcommand().map_or(matches, |(_, matches)| matches);
+    if matches.get_flag("verbose") {
+        logging::Level::Debug
+    } else if matches.get_flag("quiet") {
+        logging::Level::Error
+    } else {
+        matches
+            .get_one::<String>("log_level")
+            .and_then(|level| level.parse().ok())
+            .unwrap_or_default()
+    }
+}
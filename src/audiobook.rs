@@ -0,0 +1,188 @@
+//! Audiobooks with chapters, such as M4B files, normalized chapter by
+//! chapter or as a whole book with the loudness of each chapter verified.
+
+use crate::{
+    Chapter, Error, FilterSettings, Loudness, LoudnessAnalyzer, MediaInfo, Options,
+    ProgressSpinner, Verification,
+};
+use serde::Serialize;
+use std::{io, path::Path, str::FromStr};
+
+/// How `--audiobook` normalizes a book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudiobookMode {
+    /// Each chapter gets the gain reaching the target from its own
+    /// measurements.
+    Chapters,
+    /// The book is normalized as a whole, and each chapter of the output is
+    /// verified.
+    Book,
+}
+
+impl FromStr for AudiobookMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chapters" => Ok(AudiobookMode::Chapters),
+            "book" => Ok(AudiobookMode::Book),
+            _ => Err(format!(
+                "unknown audiobook mode '{}'; expected chapters or book",
+                s
+            )),
+        }
+    }
+}
+
+/// Measurements, gain and verification of one chapter.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChapterResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub start: f64,
+    pub end: f64,
+    /// Measurements of the chapter in the input, when chapters are
+    /// normalized on their own.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<Loudness>,
+    /// Gain in dB applied to the chapter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gain_db: Option<f64>,
+    /// The chapter of the output compared with the targets; silent
+    /// chapters aren't verified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<Verification>,
+}
+
+impl ChapterResult {
+    /// A result for `chapter` with nothing measured yet.
+    pub fn new(chapter: &Chapter) -> Self {
+        Self {
+            title: chapter.title.clone(),
+            start: chapter.start,
+            end: chapter.end,
+            loudness: None,
+            gain_db: None,
+            verification: None,
+        }
+    }
+}
+
+/// The chapters of an audiobook.
+#[derive(Debug, Clone)]
+pub struct Audiobook {
+    pub chapters: Vec<Chapter>,
+}
+
+impl Audiobook {
+    /// Reads the chapters of `input_path`, failing when it has none.
+    pub fn probe(input_path: &Path, options: &Options) -> io::Result<Self> {
+        let info = MediaInfo::probe(input_path, options)?;
+        if info.chapters.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Input has no chapters",
+            ));
+        }
+        Ok(Self {
+            chapters: info.chapters,
+        })
+    }
+
+    /// Measures each chapter of `input_path`.
+    pub fn measure(&self, input_path: &Path, options: &Options) -> io::Result<Vec<Loudness>> {
+        self.chapters
+            .iter()
+            .map(|chapter| {
+                LoudnessAnalyzer::measure(input_path, &chapter_options(options, chapter))
+            })
+            .collect()
+    }
+
+    /// Measures each chapter of `input_path` and builds the filter applying
+    /// the gain of every chapter, returned with the result of each chapter.
+    /// Only a book silent throughout is refused; silent chapters keep their
+    /// level.
+    pub fn chapter_filter(
+        &self,
+        input_path: &Path,
+        options: &Options,
+    ) -> io::Result<(String, Vec<ChapterResult>)> {
+        let loudness = self.measure(input_path, options)?;
+        if loudness.iter().all(Loudness::is_silent) {
+            loudness[0].ensure_audible(options)?;
+        }
+        let filter = FilterSettings::construct_chapters(options, &self.chapters, &loudness);
+        let results = self
+            .chapters
+            .iter()
+            .zip(loudness)
+            .map(|(chapter, loudness)| ChapterResult {
+                gain_db: FilterSettings::gain_db(options, &loudness),
+                loudness: Some(loudness),
+                ..ChapterResult::new(chapter)
+            })
+            .collect();
+        Ok((filter, results))
+    }
+
+    /// Measures each chapter of `output_path` and compares it with the
+    /// targets in `options`, bypassing the cache and supplied measurements
+    /// like [`Verification::check`]. Silent chapters get no verification.
+    pub fn verify(
+        &self,
+        output_path: &Path,
+        options: &Options,
+        tolerance: f64,
+    ) -> io::Result<Vec<Option<Verification>>> {
+        self.chapters
+            .iter()
+            .map(|chapter| {
+                let options = Options {
+                    audio_stream: None,
+                    cache_dir: None,
+                    history_db: None,
+                    measured: None,
+                    compressor: None,
+                    enforce_lra: false,
+                    ..chapter_options(options, chapter)
+                };
+                let loudness = ProgressSpinner::in_stage("Verifying", || {
+                    LoudnessAnalyzer::measure(output_path, &options)
+                })?;
+                Ok((!loudness.is_silent())
+                    .then(|| Verification::compare(&loudness, &options, tolerance)))
+            })
+            .collect()
+    }
+
+    /// Fails with [`Error::NotCompliant`] naming the chapters in `results`
+    /// that didn't pass verification.
+    pub fn ensure_passed(results: &[ChapterResult]) -> io::Result<()> {
+        let failed: Vec<String> = results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| result.verification.as_ref().is_some_and(|v| !v.passed))
+            .map(|(index, result)| {
+                result
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| format!("chapter {}", index + 1))
+            })
+            .collect();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::NotCompliant(format!("chapters {}", failed.join(", "))).into())
+        }
+    }
+}
+
+/// `options` measuring only `chapter`.
+fn chapter_options(options: &Options, chapter: &Chapter) -> Options {
+    Options {
+        start: Some(format!("{:.6}", chapter.start)),
+        duration: Some(format!("{:.6}", chapter.duration())),
+        ..options.clone()
+    }
+}
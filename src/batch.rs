@@ -0,0 +1,1018 @@
+//! Normalizing the inputs of a run: in parallel, as an album, joined with
+//! `--concat`, as `--stems` or split by `--cue`, with the bookkeeping of
+//! their results, the exit code, the `--report` and the `--state`.
+
+use crate::{
+    dashboard::Dashboard,
+    events, interrupt, logging,
+    print::report_result,
+    process::{self, marked, process_cue_track, process_file, AlbumSummary, FileResult},
+    report::{BatchReport, ReportRow},
+    state::RunState,
+    stats, throttle, Album, ConcatBuffer, CueSheet, Error, FilterSettings, Loudness, MediaInfo,
+    MultiProgress, NormalizationType, Options, Playlist, PlaylistEntry, ProgressFormat,
+    ProgressSpinner, RunConfig, Stems, DEFAULT_TOLERANCE, NOOP_EXIT_CODE, STDIN_PATH,
+};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+/// Normalizes `inputs` one by one, or `--jobs` of them at a time, or as an
+/// album, and returns the exit code of the batch.
+pub(crate) fn run(config: &RunConfig, inputs: Vec<io::Result<PathBuf>>) -> ExitCode {
+    if let Err(message) = check_inputs(config, &inputs) {
+        eprintln!("{}", message);
+        return ExitCode::from(2);
+    }
+    let batch = inputs.len() > 1;
+    let playlist_inputs: Vec<PathBuf> = inputs
+        .iter()
+        .filter_map(|input| input.as_ref().ok().cloned())
+        .collect();
+    let inputs = match check_existing_outputs(config, inputs) {
+        Ok(inputs) => inputs,
+        Err(code) => return code,
+    };
+    let failures = Failures::for_run(config);
+    if config.album {
+        let mut input_paths = Vec::new();
+        for input in inputs {
+            match input {
+                Ok(input_path) => input_paths.push(input_path),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        let report = config.report_path.as_ref().map(|_| BatchReport::default());
+        process_album(config, &input_paths, batch, &failures, report.as_ref());
+        write_report(config, report, &failures);
+        write_playlist(config, &playlist_inputs, &failures);
+        return failures.exit_code();
+    }
+
+    let report = match Batch::start(config, &inputs, &failures) {
+        Ok(batch) => batch.run(),
+        Err(code) => return code,
+    };
+    write_report(config, report, &failures);
+    write_playlist(config, &playlist_inputs, &failures);
+    failures.exit_code()
+}
+
+/// Checks that the options given suit the number and kind of `inputs`,
+/// returning the usage error otherwise.
+fn check_inputs(config: &RunConfig, inputs: &[io::Result<PathBuf>]) -> Result<(), String> {
+    let single = |flag: &str| format!("{} can only be used with a single input file", flag);
+    if config.output_path.is_some() && inputs.len() > 1 {
+        return Err(single("--output"));
+    }
+    if config.options.measured.is_some() && inputs.len() > 1 {
+        return Err(single("--measured-* values and --from-analysis"));
+    }
+    if config.save_analysis_path.is_some() && inputs.len() > 1 {
+        return Err(single("--save-analysis"));
+    }
+    let stdin_inputs = inputs
+        .iter()
+        .filter(|input| matches!(input, Ok(path) if path == Path::new(STDIN_PATH)))
+        .count();
+    if stdin_inputs == 1 && config.interactive.is_some() {
+        return Err(
+            "--interactive reads its answers from standard input, so it can't read audio from it"
+                .to_string(),
+        );
+    }
+    if stdin_inputs > 1 || (stdin_inputs == 1 && config.album) {
+        return Err("Standard input can only be read once and not as part of an album".to_string());
+    }
+    let names_output = config.output_dir.is_some()
+        || config.output_template.is_some()
+        || config.manifest.as_ref().is_some_and(|m| m.names_outputs());
+    for (enabled, flag) in [
+        (config.print_command, "--print-command"),
+        (config.verify_tolerance.is_some(), "--verify"),
+        (config.output_playlist.is_some(), "--output-playlist"),
+    ] {
+        if enabled && config.output_path.is_none() && !names_output {
+            return Err(format!(
+                "{} needs --output, --output-template or --output-dir",
+                flag
+            ));
+        }
+    }
+    if stdin_inputs == 1 && config.output_path.is_none() && (names_output || config.tag_only) {
+        return Err("Reading from standard input needs --output to write a file".to_string());
+    }
+    for (path, flag) in [
+        (&config.timeline_path, "--timeline"),
+        (&config.plot_path, "--plot"),
+        (&config.filter_script_path, "--filter-script"),
+    ] {
+        if path.is_some() && inputs.len() > 1 {
+            return Err(single(flag));
+        }
+    }
+    Ok(())
+}
+
+/// The inputs of a batch outside album mode and what its workers share.
+struct Batch<'a> {
+    config: &'a RunConfig,
+    inputs: &'a [io::Result<PathBuf>],
+    failures: &'a Failures,
+    jobs: usize,
+    /// A bar per input being processed with several `--jobs`.
+    bars: Option<MultiProgress>,
+    state: Option<RunState>,
+    report: Option<BatchReport>,
+    dashboard: Option<Dashboard>,
+    /// Index of the next input to take without the dashboard.
+    next: AtomicUsize,
+}
+
+impl<'a> Batch<'a> {
+    /// Sets up the progress display, the `--state` and the `--report` for
+    /// `inputs`, or returns the exit code when one can't be.
+    fn start(
+        config: &'a RunConfig,
+        inputs: &'a [io::Result<PathBuf>],
+        failures: &'a Failures,
+    ) -> Result<Self, ExitCode> {
+        let jobs = config.jobs.min(inputs.len()).max(1);
+        // Concurrent files get a bar each instead of one spinner.
+        let bars = (jobs > 1 && !config.tui && config.progress_format == ProgressFormat::Text)
+            .then(|| MultiProgress::start(inputs.len()))
+            .flatten();
+        if jobs > 1 || config.tui {
+            ProgressSpinner::set_enabled(false);
+        }
+        let state = config
+            .state_path
+            .as_deref()
+            .map(|path| RunState::open(path, config.resume))
+            .transpose()
+            .map_err(|e| {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            })?;
+        let dashboard = if config.tui {
+            let names = inputs
+                .iter()
+                .map(|input| match input {
+                    Ok(input_path) => input_path.to_string_lossy().into_owned(),
+                    Err(e) => e.to_string(),
+                })
+                .collect();
+            let dashboard = Dashboard::start(names, jobs).map_err(|e| {
+                eprintln!("{}", e);
+                ExitCode::from(2)
+            })?;
+            Some(dashboard)
+        } else {
+            None
+        };
+        Ok(Self {
+            config,
+            inputs,
+            failures,
+            jobs,
+            bars,
+            state,
+            report: config.report_path.as_ref().map(|_| BatchReport::default()),
+            dashboard,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Runs the workers until the inputs are done or the batch stops, and
+    /// returns the rows of the `--report`.
+    fn run(self) -> Option<BatchReport> {
+        thread::scope(|scope| {
+            for _ in 0..self.jobs {
+                scope.spawn(|| self.work());
+            }
+        });
+        // Restores the terminal before the summary is printed.
+        let Self {
+            report,
+            dashboard,
+            bars,
+            ..
+        } = self;
+        drop(dashboard);
+        drop(bars);
+        report
+    }
+
+    /// Takes one input after the other until none is left or the batch
+    /// stops.
+    fn work(&self) {
+        loop {
+            let index = match &self.dashboard {
+                Some(dashboard) => match dashboard.next() {
+                    Some(index) => index,
+                    None => break,
+                },
+                None => self.next.fetch_add(1, Ordering::Relaxed),
+            };
+            let Some(input) = self.inputs.get(index) else {
+                break;
+            };
+            let quit = self
+                .config
+                .interactive
+                .as_ref()
+                .is_some_and(|confirmation| confirmation.quit_requested());
+            if self.failures.should_stop() || quit {
+                if let Some(dashboard) = &self.dashboard {
+                    dashboard.quit();
+                }
+                break;
+            }
+            match input {
+                Ok(input_path) => {
+                    if self.skips(index, input_path) {
+                        continue;
+                    }
+                    if let Some(max_load) = self.config.max_load {
+                        throttle::wait_for_load(max_load);
+                        if self.failures.should_stop() {
+                            break;
+                        }
+                    }
+                    self.process(index, input_path);
+                }
+                Err(e) => {
+                    MultiProgress::suspend(|| eprintln!("{}", e));
+                    self.failures.record(None);
+                    if let Some(dashboard) = &self.dashboard {
+                        dashboard.fail(index, &e.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the input at `index` is left alone: done in an earlier run
+    /// with `--resume`, or tagged or normalized already with
+    /// `--skip-tagged` or `--skip-normalized`.
+    fn skips(&self, index: usize, input_path: &Path) -> bool {
+        let config = self.config;
+        // What the log says, and the shorter status on the dashboard.
+        let (reason, status) = if self
+            .state
+            .as_ref()
+            .is_some_and(|state| state.is_done(input_path))
+        {
+            ("done in an earlier run", "done in an earlier run")
+        } else if config.skip_tagged && process::is_tagged(config, input_path) {
+            ("already tagged", "already tagged")
+        } else if config.skip_normalized && process::is_normalized(config, input_path) {
+            ("already normalized to the target", "already normalized")
+        } else {
+            return false;
+        };
+        logging::info(format_args!(
+            "{}: {}; skipping",
+            input_path.display(),
+            reason
+        ));
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.skip(index, status);
+        }
+        true
+    }
+
+    /// Processes the input at `index`, then records its outcome.
+    fn process(&self, index: usize, input_path: &Path) {
+        let config = self.config;
+        let started = Instant::now();
+        let outcome = track_progress(config, input_path, || match (&self.dashboard, &self.bars) {
+            (Some(dashboard), _) => dashboard.track(index, || process::process(config, input_path)),
+            (None, Some(bars)) => bars.track(&input_path.to_string_lossy(), || {
+                process::process(config, input_path)
+            }),
+            (None, None) => ProgressSpinner::for_file(
+                &input_path.to_string_lossy(),
+                (self.inputs.len() > 1).then_some((index + 1, self.inputs.len())),
+                || process::process(config, input_path),
+            ),
+        });
+        if let Some(dashboard) = &self.dashboard {
+            if dashboard.was_skipped(index) {
+                return;
+            }
+            // A retried input only counts with its last outcome.
+            self.failures.forget_input(input_path);
+        }
+        let batch = self.inputs.len() > 1;
+        let row =
+            MultiProgress::suspend(|| finish(config, input_path, outcome, batch, self.failures));
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.finish(index, &row);
+        }
+        if let Some(state) = &self.state {
+            let error = (!matches!(row.status.as_str(), "ok" | "already normalized"))
+                .then_some(row.status.as_str());
+            // Interrupted inputs stay unprocessed for --resume.
+            if !interrupt::is_interrupted() {
+                if let Err(e) = state.record(input_path, error) {
+                    MultiProgress::suspend(|| eprintln!("{}: {}", input_path.display(), e));
+                    self.failures.record(Some(&e));
+                }
+            }
+        }
+        record_row(config, self.report.as_ref(), index, row, started);
+    }
+}
+
+/// Exit codes of failed inputs, combined into one: the shared code when all
+/// of them failed alike, 1 when they failed for different reasons.
+#[derive(Default)]
+pub(crate) struct Failures {
+    code: AtomicU8,
+    /// Exit with [`NOOP_EXIT_CODE`] when every input was already at target.
+    noop_exit: bool,
+    /// Stop once an input has failed.
+    fail_fast: bool,
+    /// Inputs that failed with the category and exit code of their error,
+    /// for the summary at the end of a batch.
+    failed: Mutex<Vec<(PathBuf, String, u8)>>,
+    succeeded: AtomicUsize,
+    unchanged: AtomicUsize,
+}
+
+impl Failures {
+    pub(crate) fn for_run(config: &RunConfig) -> Self {
+        Self {
+            noop_exit: config.noop_exit_code,
+            fail_fast: config.fail_fast,
+            ..Self::default()
+        }
+    }
+
+    /// Records that `input_path` failed with `error`.
+    pub(crate) fn record_input(&self, input_path: &Path, error: &io::Error) {
+        let category =
+            Error::of(error).map_or_else(|| error.kind().to_string(), |e| e.category().to_string());
+        let code = Error::of(error).map_or(1, Error::exit_code);
+        if let Ok(mut failed) = self.failed.lock() {
+            failed.push((input_path.to_path_buf(), category, code));
+        }
+    }
+
+    /// Forgets the failure of `input_path` when it is tried again.
+    pub(crate) fn forget_input(&self, input_path: &Path) {
+        if let Ok(mut failed) = self.failed.lock() {
+            failed.retain(|(path, _, _)| path != input_path);
+        }
+    }
+
+    /// Whether no further inputs should be started: after an interruption,
+    /// or a failure with `--fail-fast`.
+    pub(crate) fn should_stop(&self) -> bool {
+        interrupt::is_interrupted()
+            || (self.fail_fast && self.failed.lock().is_ok_and(|failed| !failed.is_empty()))
+    }
+
+    /// Records a failure, categorized by `error` when there is one.
+    pub(crate) fn record(&self, error: Option<&io::Error>) {
+        let code = error.and_then(Error::of).map_or(1, Error::exit_code);
+        let _ = self
+            .code
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                Some(combine_exit_codes(current, code))
+            });
+    }
+
+    /// Records an input that succeeded, `unchanged` when it was already at
+    /// the target.
+    pub(crate) fn record_success(&self, unchanged: bool) {
+        self.succeeded.fetch_add(1, Ordering::Relaxed);
+        if unchanged {
+            self.unchanged.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Lists the failed inputs of a batch on stderr.
+    fn print_summary(&self) {
+        let Ok(failed) = self.failed.lock() else {
+            return;
+        };
+        let total = failed.len() + self.succeeded.load(Ordering::Relaxed);
+        if failed.is_empty() || total < 2 {
+            return;
+        }
+        eprintln!("{} of {} inputs failed:", failed.len(), total);
+        for (input_path, category, _) in failed.iter() {
+            eprintln!("  {} ({})", input_path.display(), category);
+        }
+    }
+
+    pub(crate) fn exit_code(self) -> ExitCode {
+        self.print_summary();
+        if interrupt::is_interrupted() {
+            return ExitCode::from(Error::Interrupted.exit_code());
+        }
+        let failed = self.failed.into_inner().unwrap_or_default();
+        let code = failed
+            .iter()
+            .fold(self.code.into_inner(), |current, (_, _, code)| {
+                combine_exit_codes(current, *code)
+            });
+        let succeeded = self.succeeded.into_inner();
+        if code == 0 && self.noop_exit && succeeded > 0 && self.unchanged.into_inner() == succeeded
+        {
+            return ExitCode::from(NOOP_EXIT_CODE);
+        }
+        ExitCode::from(code)
+    }
+}
+
+/// The exit code for failures with `current` and `code`: the shared one, or
+/// 1 when they differ.
+fn combine_exit_codes(current: u8, code: u8) -> u8 {
+    if current == 0 || current == code {
+        code
+    } else {
+        1
+    }
+}
+
+/// Measures all inputs as one album, then applies the album gain to each
+/// track (or tags it).
+fn process_album(
+    config: &RunConfig,
+    input_paths: &[PathBuf],
+    batch: bool,
+    failures: &Failures,
+    report: Option<&BatchReport>,
+) {
+    let mut tracks = Vec::new();
+    for input_path in input_paths {
+        match track_progress(config, input_path, || {
+            Album::measure_track(input_path, &config.options)
+        }) {
+            Ok(track) => tracks.push(track),
+            Err(e) => {
+                eprintln!("{}: {}", input_path.display(), e);
+                failures.record_input(input_path, &e);
+                return;
+            }
+        }
+    }
+    let album = match Album::group(tracks, config.group_reference.as_ref(), &config.options) {
+        Ok(album) => album,
+        Err(e) => {
+            eprintln!("{}", e);
+            failures.record(Some(&e));
+            return;
+        }
+    };
+    let summary = AlbumSummary {
+        integrated_loudness: album.integrated_loudness,
+        true_peak: album.true_peak,
+        gain_db: album.gain_db(&config.options),
+        reference_loudness: album
+            .reference
+            .as_ref()
+            .map(|reference| reference.integrated_loudness),
+    };
+    for (index, track) in album.tracks.iter().enumerate() {
+        if failures.should_stop() {
+            break;
+        }
+        let started = Instant::now();
+        if config.skip_existing && !config.tag_only {
+            if let Ok(Some(output_path)) = config.output_for(&track.input_path) {
+                if output_path.exists() {
+                    logging::info(format_args!("{}: exists; skipping", output_path.display()));
+                    continue;
+                }
+            }
+        }
+        let skip = config.skip_within.is_some_and(|tolerance| {
+            summary.gain_db.abs() <= tolerance
+                && track.loudness.input_tp <= config.options.true_peak
+        });
+        if skip {
+            logging::info(format_args!(
+                "{}: album within tolerance of the target; skipping the second pass",
+                track.input_path.display()
+            ));
+        }
+        let outcome = config
+            .output_for(&track.input_path)
+            .map(|output_path| output_path.filter(|_| !skip))
+            .and_then(|output_path| {
+                let mut result = FileResult::new(&track.input_path, output_path.clone());
+                if config.tag_only {
+                    result.tags =
+                        Some(album.tag_track(track, output_path.as_deref(), &config.options)?);
+                } else if let Some(output_path) = &output_path {
+                    result.filter = Some(track_progress(config, &track.input_path, || {
+                        album.normalize_track(track, output_path, &config.options)
+                    })?);
+                } else {
+                    result.filter = Some(FilterSettings::construct_gain(
+                        &config.options,
+                        summary.gain_db,
+                    ));
+                }
+                result.loudness = Some(track.loudness.clone());
+                result.album = Some(summary);
+                Ok(result)
+            });
+        let row = finish(config, &track.input_path, outcome, batch, failures);
+        record_row(config, report, index, row, started);
+    }
+}
+
+/// Prints the result of one input, or its error, and returns its row for
+/// the `--report`.
+fn finish(
+    config: &RunConfig,
+    input_path: &Path,
+    outcome: io::Result<FileResult>,
+    batch: bool,
+    failures: &Failures,
+) -> ReportRow {
+    let mut row = ReportRow {
+        input: input_path.to_string_lossy().into_owned(),
+        status: "ok".to_string(),
+        ..ReportRow::default()
+    };
+    let outcome = outcome.and_then(|mut result| {
+        result.already_normalized = is_unchanged(config, &result);
+        if result.declined {
+            row.status = "declined".to_string();
+        } else if result.already_normalized {
+            logging::info(format_args!("{}: already normalized", input_path.display()));
+            row.status = "already normalized".to_string();
+        }
+        row.output = result
+            .output
+            .as_ref()
+            .map(|output| output.to_string_lossy().into_owned());
+        let first_stream = result.streams.first().map(|stream| &stream.loudness);
+        if let Some(loudness) = result.loudness.as_ref().or(first_stream) {
+            row.input_i = Some(loudness.input_i).filter(|i| i.is_finite());
+            row.input_tp = Some(loudness.input_tp).filter(|tp| tp.is_finite());
+            row.input_lra = Some(loudness.input_lra);
+            row.gain_db = match result.album {
+                Some(album) => Some(album.gain_db),
+                None => FilterSettings::gain_db(&config.options, loudness),
+            };
+        }
+        if let Some(stats) = &result.second_pass {
+            row.output_i = Some(stats.output_i).filter(|i| i.is_finite());
+            row.output_tp = Some(stats.output_tp).filter(|tp| tp.is_finite());
+            row.output_lra = Some(stats.output_lra);
+            row.normalization_type = stats.normalization_type.map(|kind| {
+                match kind {
+                    NormalizationType::Linear => "linear",
+                    NormalizationType::Dynamic => "dynamic",
+                }
+                .to_string()
+            });
+        }
+        // A verification measures the written file, so it wins.
+        if let Some(verification) = &result.verification {
+            row.output_i = Some(verification.integrated_loudness);
+            row.output_tp = Some(verification.true_peak);
+        }
+        if let Err(e) = record_history(config, input_path, &result) {
+            eprintln!("{}: {}", input_path.display(), e);
+            failures.record(Some(&e));
+        }
+        if let Err(e) = export_stats(config, input_path, &result) {
+            eprintln!("{}", e);
+            failures.record(Some(&e));
+        }
+        if config.progress_format == ProgressFormat::Jsonl {
+            events::file_done(input_path, &row.status, &result);
+        }
+        report_result(config, &result, batch)
+    });
+    match outcome {
+        Ok(()) => failures.record_success(row.status != "ok"),
+        Err(e) => {
+            if config.progress_format == ProgressFormat::Jsonl {
+                events::error(input_path, &e.to_string());
+            }
+            eprintln!("{}: {}", input_path.display(), e);
+            failures.record_input(input_path, &e);
+            row.status = match Error::of(&e) {
+                Some(Error::NotCompliant(_)) => "failed verification".to_string(),
+                _ => e.to_string(),
+            };
+        }
+    }
+    row
+}
+
+/// Adds the streams measured for `result` to the `--export-stats` file,
+/// numbered among all streams of the input as Python's `stream_id` is.
+fn export_stats(config: &RunConfig, input_path: &Path, result: &FileResult) -> io::Result<()> {
+    let Some(export) = &config.stats_export else {
+        return Ok(());
+    };
+    let info = MediaInfo::probe(input_path, &config.options).ok();
+    // Inputs that can't be probed again, like standard input, keep their
+    // index among the audio streams.
+    let stream_id = |audio_stream: usize| {
+        info.as_ref()
+            .and_then(|info| info.audio_streams.get(audio_stream))
+            .map_or(audio_stream, |stream| stream.index)
+    };
+    let output = result.output.as_deref();
+    let streams = match &result.loudness {
+        Some(loudness) => vec![stats::StreamStats::new(
+            &result.input,
+            output,
+            stream_id(config.options.audio_stream.unwrap_or(0)),
+            loudness,
+            result.second_pass.as_ref(),
+        )],
+        None => result
+            .streams
+            .iter()
+            .map(|stream| {
+                stats::StreamStats::new(
+                    &result.input,
+                    output,
+                    stream_id(stream.audio_stream),
+                    &stream.loudness,
+                    None,
+                )
+            })
+            .collect(),
+    };
+    export.record(streams)
+}
+
+/// Adds the measurements of `result` to the `--db` history, with the gain
+/// applied when an output or tags were written.
+pub(crate) fn record_history(
+    config: &RunConfig,
+    input_path: &Path,
+    result: &FileResult,
+) -> io::Result<()> {
+    let Some(history) = &config.history else {
+        return Ok(());
+    };
+    // The joined program of --concat is no file to be looked up again.
+    if input_path == Path::new(STDIN_PATH) || crate::is_url(input_path) || config.concat {
+        return Ok(());
+    }
+    let applied = result.command.is_none() && (result.output.is_some() || result.tags.is_some());
+    let measurements: Vec<(Option<usize>, &Loudness)> = match &result.loudness {
+        Some(loudness) => vec![(config.options.audio_stream, loudness)],
+        None => result
+            .streams
+            .iter()
+            .map(|stream| (Some(stream.audio_stream), &stream.loudness))
+            .collect(),
+    };
+    for (audio_stream, loudness) in measurements {
+        let options = Options {
+            audio_stream,
+            ..config.options.clone()
+        };
+        let gain = match result.album {
+            Some(album) => Some(album.gain_db),
+            None => FilterSettings::gain_db(&options, loudness),
+        };
+        history.record(input_path, &options, loudness, gain.filter(|_| applied))?;
+    }
+    Ok(())
+}
+
+/// Whether the input of `result` was at the target before normalizing, by
+/// `--skip-within` or else the same tolerance `--verify` defaults to. Album
+/// tracks are judged by the album gain.
+pub(crate) fn is_unchanged(config: &RunConfig, result: &FileResult) -> bool {
+    let Some(loudness) = &result.loudness else {
+        return false;
+    };
+    let tolerance = config.skip_within.unwrap_or(DEFAULT_TOLERANCE);
+    match result.album {
+        Some(album) => {
+            album.gain_db.abs() <= tolerance && loudness.input_tp <= config.options.true_peak
+        }
+        None => loudness.is_at_target(&config.options, tolerance),
+    }
+}
+
+/// Applies `--force` and `--skip-existing` to inputs whose output already
+/// exists. Without either flag, lists the conflicting outputs and fails
+/// before any ffmpeg process is started.
+fn check_existing_outputs(
+    config: &RunConfig,
+    mut inputs: Vec<io::Result<PathBuf>>,
+) -> Result<Vec<io::Result<PathBuf>>, ExitCode> {
+    let writes_outputs = config.output_path.is_some()
+        || config.output_template.is_some()
+        || config.output_dir.is_some();
+    if !writes_outputs || config.print_command || config.report || config.force {
+        return Ok(inputs);
+    }
+    let existing_output = |input: &io::Result<PathBuf>| match input {
+        Ok(input_path) => config
+            .outputs_for(input_path)
+            .ok()?
+            .into_iter()
+            .find(|output_path| output_path.exists()),
+        Err(_) => None,
+    };
+    if config.skip_existing {
+        // Album tracks are still measured for the album gain, and skipped
+        // when encoding.
+        if !config.album {
+            inputs.retain(|input| match existing_output(input) {
+                Some(output_path) => {
+                    logging::info(format_args!("{}: exists; skipping", output_path.display()));
+                    false
+                }
+                None => true,
+            });
+        }
+        return Ok(inputs);
+    }
+    let conflicts: Vec<PathBuf> = inputs.iter().filter_map(existing_output).collect();
+    if conflicts.is_empty() {
+        return Ok(inputs);
+    }
+    eprintln!("These outputs already exist; pass --force to overwrite or --skip-existing to skip:");
+    for output_path in conflicts {
+        eprintln!("  {}", output_path.display());
+    }
+    Err(ExitCode::FAILURE)
+}
+
+/// Runs `job` for `input_path`, reporting its progress as events with
+/// `--progress-format jsonl`.
+pub(crate) fn track_progress<T>(
+    config: &RunConfig,
+    input_path: &Path,
+    job: impl FnOnce() -> io::Result<T>,
+) -> io::Result<T> {
+    match config.progress_format {
+        ProgressFormat::Jsonl => events::track(input_path, job),
+        ProgressFormat::Text => job(),
+    }
+}
+
+/// Adds the `row` of the input at `index`, started at `started`, to the
+/// `--report` and sends it to the `--notify-url`.
+fn record_row(
+    config: &RunConfig,
+    report: Option<&BatchReport>,
+    index: usize,
+    row: ReportRow,
+    started: Instant,
+) {
+    let row = ReportRow {
+        elapsed: started.elapsed().as_secs_f64(),
+        ..row
+    };
+    if let Some(notifier) = &config.notifier {
+        notifier.file_done(&row);
+    }
+    if let Some(report) = report {
+        report.add(index, row);
+    }
+}
+
+/// Writes the `--report` and sends the end of the run to the
+/// `--notify-url`.
+fn write_report(config: &RunConfig, report: Option<BatchReport>, failures: &Failures) {
+    if let (Some(path), Some(report)) = (&config.report_path, report) {
+        if let Err(e) = report.write(path) {
+            eprintln!("{}: {}", path.display(), e);
+            failures.record(Some(&e));
+        }
+    }
+    if let Some(notifier) = &config.notifier {
+        notifier.batch_done(interrupt::is_interrupted());
+    }
+}
+
+/// Writes the `--output-playlist`, listing the outputs of `input_paths` that
+/// exist. Tracks read from input playlists keep their `#EXTINF` line.
+fn write_playlist(config: &RunConfig, input_paths: &[PathBuf], failures: &Failures) {
+    let Some(path) = &config.output_playlist else {
+        return;
+    };
+    let track_info: HashMap<PathBuf, String> = config
+        .input_paths
+        .iter()
+        .filter(|input_path| Playlist::is_playlist(input_path))
+        .filter_map(|input_path| Playlist::read(input_path).ok())
+        .flat_map(|playlist| playlist.entries)
+        .filter_map(|entry| Some((entry.path, entry.info?)))
+        .collect();
+    let entries = input_paths
+        .iter()
+        .filter_map(|input_path| {
+            let output_path = config.output_for(input_path).ok().flatten()?;
+            output_path.exists().then(|| PlaylistEntry {
+                path: output_path,
+                info: track_info.get(input_path).cloned(),
+            })
+        })
+        .collect();
+    if let Err(e) = (Playlist { entries }).write(path) {
+        eprintln!("{}: {}", path.display(), e);
+        failures.record(Some(&e));
+    }
+}
+
+/// Normalizes `inputs` joined into one program with `--concat`.
+pub(crate) fn concat(config: &RunConfig, inputs: Vec<io::Result<PathBuf>>) -> ExitCode {
+    let input_paths = match inputs.into_iter().collect::<io::Result<Vec<_>>>() {
+        Ok(input_paths) => input_paths,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    if input_paths.len() < 2 {
+        eprintln!("--concat needs at least two inputs");
+        return ExitCode::from(2);
+    }
+    if let Some(output_path) = config.output_path.as_ref().filter(|path| path.exists()) {
+        if !config.force {
+            eprintln!(
+                "{}: exists; pass --force to overwrite it",
+                output_path.display()
+            );
+            return ExitCode::from(2);
+        }
+    }
+    let failures = Failures::for_run(config);
+    let program = ConcatBuffer::program_name(&input_paths);
+    let program_config = RunConfig {
+        options: ConcatBuffer::program_options(&config.options),
+        ..config.clone()
+    };
+    let started = Instant::now();
+    let outcome = track_progress(config, &program, || {
+        let joined = ConcatBuffer::join(&input_paths, &config.options)?;
+        let mut result = process_file(&program_config, joined.path())?;
+        result.input = program.clone();
+        Ok(result)
+    });
+    let row = finish(config, &program, outcome, false, &failures);
+    let report = config.report_path.as_ref().map(|_| BatchReport::default());
+    record_row(config, report.as_ref(), 0, row, started);
+    write_report(config, report, &failures);
+    failures.exit_code()
+}
+
+/// Normalizes the mix, the first of `inputs`, and applies its gain to the
+/// stems following it with `--stems`.
+pub(crate) fn stems(config: &RunConfig, inputs: Vec<io::Result<PathBuf>>) -> ExitCode {
+    let input_paths = match inputs.into_iter().collect::<io::Result<Vec<_>>>() {
+        Ok(input_paths) => input_paths,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let [mix, _, ..] = input_paths.as_slice() else {
+        eprintln!("--stems needs the mix and at least one stem");
+        return ExitCode::from(2);
+    };
+    if config.output_dir.is_none() && config.output_template.is_none() {
+        eprintln!("--stems needs --output-dir or --output-template");
+        return ExitCode::from(2);
+    }
+    let failures = Failures::for_run(config);
+    let stems = match track_progress(config, mix, || Stems::measure(mix, &config.options)) {
+        Ok(stems) => stems,
+        Err(e) => {
+            eprintln!("{}: {}", mix.display(), e);
+            failures.record_input(mix, &e);
+            return failures.exit_code();
+        }
+    };
+    let options = marked(config, &Stems::options(&config.options), None);
+    let report = config.report_path.as_ref().map(|_| BatchReport::default());
+    for (index, input_path) in input_paths.iter().enumerate() {
+        if failures.should_stop() {
+            break;
+        }
+        let started = Instant::now();
+        let outcome = track_progress(config, input_path, || {
+            let output_path = config.output_for(input_path)?;
+            let mut result = FileResult::new(input_path, output_path.clone());
+            if let Some(output_path) = &output_path {
+                stems.encode(input_path, output_path, &options)?;
+            }
+            if index == 0 {
+                result.loudness = Some(stems.loudness.clone());
+            }
+            result.filter = Some(stems.filter.clone());
+            result.gain_db = Some(stems.gain_db);
+            Ok(result)
+        });
+        let row = finish(config, input_path, outcome, true, &failures);
+        record_row(config, report.as_ref(), index, row, started);
+    }
+    write_report(config, report, &failures);
+    failures.exit_code()
+}
+
+/// Splits the image of `--cue` into its tracks, normalizing each on its own
+/// or, with `--album`, all by the gain of the whole image.
+pub(crate) fn split_cue(config: &RunConfig, sheet: &CueSheet) -> ExitCode {
+    let [image] = config.input_paths.as_slice() else {
+        eprintln!("--cue takes a single image as input");
+        return ExitCode::from(2);
+    };
+    let failures = Failures::for_run(config);
+    let output_dir = config
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| image.parent().unwrap_or(Path::new("")).to_path_buf());
+    if let Err(e) = fs::create_dir_all(&output_dir).or_else(|e| {
+        // An empty path stands for the current directory.
+        if output_dir.as_os_str().is_empty() {
+            Ok(())
+        } else {
+            Err(e)
+        }
+    }) {
+        eprintln!("{}: {}", output_dir.display(), e);
+        failures.record(Some(&e));
+        return failures.exit_code();
+    }
+    let album = if config.album {
+        let measured = crate::analyze(image, &config.options).and_then(|loudness| {
+            loudness.ensure_audible(&config.options)?;
+            let summary = AlbumSummary {
+                integrated_loudness: loudness.input_i,
+                true_peak: loudness.input_tp,
+                gain_db: config.options.integrated_loudness - loudness.input_i,
+                reference_loudness: None,
+            };
+            Ok((loudness, summary))
+        });
+        match measured {
+            Ok(album) => Some(album),
+            Err(e) => {
+                eprintln!("{}: {}", image.display(), e);
+                failures.record(Some(&e));
+                return failures.exit_code();
+            }
+        }
+    } else {
+        None
+    };
+    let extension = match &config.output_ext {
+        Some(ext) => ext.into(),
+        None => image
+            .extension()
+            .map_or_else(|| "flac".into(), |ext| ext.to_string_lossy()),
+    };
+    let report = config.report_path.as_ref().map(|_| BatchReport::default());
+    for (index, track) in sheet.tracks.iter().enumerate() {
+        if failures.should_stop() {
+            break;
+        }
+        let output_path = output_dir.join(track.file_name(&extension));
+        if output_path.exists() && !config.force {
+            if config.skip_existing {
+                logging::info(format_args!("{}: exists; skipping", output_path.display()));
+            } else {
+                eprintln!(
+                    "{}: exists; pass --force to overwrite or --skip-existing to skip",
+                    output_path.display()
+                );
+                failures.record(None);
+            }
+            continue;
+        }
+        let started = Instant::now();
+        let options = marked(config, &sheet.track_options(track, &config.options), None);
+        let outcome = track_progress(config, image, || {
+            process_cue_track(image, &output_path, &options, album.as_ref())
+        });
+        let row = finish(config, image, outcome, true, &failures);
+        record_row(config, report.as_ref(), index, row, started);
+    }
+    write_report(config, report, &failures);
+    failures.exit_code()
+}
@@ -0,0 +1,124 @@
+use crate::{Loudness, Options, PeakMode};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Stores first-pass measurements on disk so that re-running with different
+/// targets doesn't require measuring the input again.
+///
+/// Entries are keyed by the input's canonical path, size and modification
+/// time together with the settings that influence the measurement itself
+/// (stream selection, downmix, dual mono). Target values are not part of the
+/// key; see [`AnalysisCache::load`].
+pub struct AnalysisCache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    input: String,
+    integrated_loudness: f64,
+    loudness_range: f64,
+    true_peak: f64,
+    loudness: Loudness,
+}
+
+impl AnalysisCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The per-user cache directory, e.g. `~/.cache/ffmpeg-loudnorm-helper`.
+    pub fn default_dir() -> Option<PathBuf> {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|dir| dir.join("ffmpeg-loudnorm-helper"))
+    }
+
+    /// Returns the cached measurement for `input_path`, if any.
+    ///
+    /// loudnorm's `target_offset` depends on the integrated loudness target,
+    /// so it is reset to zero when the cached entry was measured with
+    /// different targets.
+    pub fn load(&self, input_path: &Path, options: &Options) -> Option<Loudness> {
+        let path = self.entry_path(input_path, options).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+        let mut loudness = entry.loudness;
+        let same_targets = entry.integrated_loudness == options.integrated_loudness
+            && entry.loudness_range == options.loudness_range
+            && entry.true_peak == options.true_peak;
+        if !same_targets {
+            loudness.target_offset = 0.0;
+        }
+        Some(loudness)
+    }
+
+    /// Writes the measurement for `input_path` to the cache.
+    pub fn store(
+        &self,
+        input_path: &Path,
+        options: &Options,
+        loudness: &Loudness,
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = CacheEntry {
+            input: input_path.to_string_lossy().into_owned(),
+            integrated_loudness: options.integrated_loudness,
+            loudness_range: options.loudness_range,
+            true_peak: options.true_peak,
+            loudness: loudness.clone(),
+        };
+        fs::write(
+            self.entry_path(input_path, options)?,
+            serde_json::to_vec_pretty(&entry)?,
+        )
+    }
+
+    fn entry_path(&self, input_path: &Path, options: &Options) -> io::Result<PathBuf> {
+        let canonical = fs::canonicalize(input_path)?;
+        let metadata = fs::metadata(&canonical)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        // Unconverted input keeps the key entries had when the only
+        // conversion was a downmix flag.
+        let format = options.aformat_prefix();
+        let mut key = format!(
+            "{}\0{}\0{}\0{:?}\0{}\0{}",
+            canonical.to_string_lossy(),
+            metadata.len(),
+            modified,
+            options.audio_stream,
+            if format.is_empty() { "false" } else { &format },
+            options.dual_mono
+        );
+        // Only segment measurements extend the key, so whole-file entries
+        // written before segments existed stay valid.
+        if options.start.is_some() || options.duration.is_some() {
+            key.push_str(&format!("\0{:?}\0{:?}", options.start, options.duration));
+        }
+        if options.peak_mode == PeakMode::Sample {
+            key.push_str("\0sample_peak");
+        }
+        if let Some(sampling) = &options.fast_analysis {
+            key.push_str(&format!("\0{:?}", sampling));
+        }
+        Ok(self
+            .dir
+            .join(format!("{:016x}.json", fnv1a(key.as_bytes()))))
+    }
+}
+
+/// FNV-1a, used instead of `DefaultHasher` because its output must stay
+/// stable across Rust releases for cache keys to remain valid.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
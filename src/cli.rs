@@ -0,0 +1,1292 @@
+//! The command line definition: the subcommands and every option, in the
+//! order `--help` lists them, with the parsers validating their values.
+
+use crate::{completions, ENV_PREFIX, OUTPUT_EXTENSIONS};
+use clap::{builder::Command, value_parser, Arg, ArgAction};
+use ffmpeg_normalize::{
+    parse_in_range, Dialnorm, Error, Preset, Sampling, SilenceTrim, SpecProfile,
+    INTEGRATED_LOUDNESS_RANGE, LOUDNESS_RANGE_RANGE, MARKER_TAG, NOOP_EXIT_CODE, OFFSET_RANGE,
+    PRESETS, RMS_RANGE, TRUE_PEAK_RANGE,
+};
+use std::path::PathBuf;
+
+/// The exit codes listed after `--help`.
+fn exit_codes_help() -> String {
+    let codes = [
+        (0, "success"),
+        (
+            1,
+            "failure not listed below, or inputs failing for different reasons",
+        ),
+        (2, "invalid command line"),
+        (3, "ffmpeg or ffprobe not found"),
+        (4, "ffmpeg or ffprobe failed"),
+        (5, "ffmpeg or ffprobe output could not be parsed"),
+        (6, "input has no audio stream, or not the requested one"),
+        (7, "input is silent (see --pass-silent)"),
+        (8, "output failed --verify, or input failed check"),
+        (
+            NOOP_EXIT_CODE,
+            "every input was already at its target, with --noop-exit-code",
+        ),
+        (
+            Error::Interrupted.exit_code(),
+            "interrupted by Ctrl+C or SIGTERM",
+        ),
+    ];
+    let mut help = "Exit codes:".to_string();
+    for (code, meaning) in codes {
+        help.push_str(&format!("\n  {:>3}  {}", code, meaning));
+    }
+    help
+}
+
+/// The command line parser, without the settings of a config file.
+pub(crate) fn command() -> Command {
+    let command = Command::new("ffmpeg-loudnorm-helper")
+        .about("Helps normalize loudness of audio files.")
+        .after_help(exit_codes_help())
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("completions")
+                .about("Print a shell completion script.")
+                .hide(true)
+                .arg(
+                    Arg::new("shell")
+                        .value_parser(completions::SHELLS)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("analyze")
+                .about("Print a loudness report for each input instead of a filter.")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            Command::new("normalize")
+                .about("Measure the inputs and print or apply the second pass; the same as giving no subcommand.")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            Command::new("tag")
+                .about("Write ReplayGain gain tags instead of re-encoding, like --tag-only.")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Normalize, then measure each output again and fail outside tolerance, like --verify.")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            Command::new("batch")
+                .about("Normalize many inputs, walking directories recursively, into --output-dir or --output-template.")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            Command::new("compare")
+                .about("Measure two inputs and print their loudness side by side.")
+                .arg(
+                    Arg::new("input")
+                        .value_parser(value_parser!(PathBuf))
+                        .value_names(["A", "B"])
+                        .help("The two files to compare.")
+                        .num_args(2)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Measure momentary, short-term and integrated loudness, loudness range and true peak with ebur128 and check each against --spec.")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            Command::new("selftest")
+                .about("Measure and normalize generated signals of known loudness to check the ffmpeg build."),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check that ffmpeg and ffprobe are installed and have the filters and encoders needed, and that the temporary, output, cache and history directories are writable, with hints for fixing what isn't."),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Run as a daemon taking jobs over HTTP on --socket or --listen: POST /jobs with {\"input\": ...} and any of output, preset, i, tp and lra queues one, GET /jobs/ID shows its progress and result, DELETE /jobs/ID cancels it, GET /status counts the jobs. Requests need the token printed at startup as Authorization: Bearer TOKEN. Inputs must be local files, and a job's output is a relative path below --output-dir, or the working directory without it. Up to --jobs run at once; the other options apply to every job."),
+        )
+        .arg(input_arg());
+    // The options in groups, in the order of `--help`.
+    let command = [
+        target_args,
+        analysis_args,
+        normalization_args,
+        input_args,
+        mode_args,
+        verify_args,
+        output_args,
+        encoding_args,
+        placement_args,
+        report_args,
+        batch_args,
+        print_args,
+        runtime_args,
+    ]
+    .into_iter()
+    .fold(command, |command, add_args| add_args(command));
+    // Options apply to the subcommands too, e.g. `analyze -i -16 file.wav`,
+    // and can be set from the environment, e.g. `FFLH_PRESET=podcast`,
+    // which the command line overrides and which overrides the config
+    // file.
+    command.mut_args(|arg| {
+        if arg.get_id() == "input" {
+            arg
+        } else {
+            let var = format!("{}{}", ENV_PREFIX, arg.get_id().as_str().to_uppercase());
+            arg.global(true).env(var)
+        }
+    })
+}
+
+/// The loudness targets, and measurements replacing the first pass.
+fn target_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("integrated_loudness")
+                .short('i')
+                .long("integrated_loudness")
+                .default_value("-23.0")
+                .allow_hyphen_values(true)
+                .value_parser(|value: &str| {
+                    parse_in_range(value, INTEGRATED_LOUDNESS_RANGE, "LUFS")
+                })
+                .help("Integrated loudness target"),
+        )
+        .arg(
+            Arg::new("loudness_range")
+                .short('l')
+                .long("loudness_range")
+                .default_value("7.0")
+                .value_parser(|value: &str| parse_in_range(value, LOUDNESS_RANGE_RANGE, "LU"))
+                .help("Loudness range target."),
+        )
+        .arg(
+            Arg::new("true_peak")
+                .short('t')
+                .long("true_peak")
+                .default_value("-2.0")
+                .allow_hyphen_values(true)
+                .value_parser(|value: &str| parse_in_range(value, TRUE_PEAK_RANGE, "dBTP"))
+                .help("Maximum true peak."),
+        )
+        .arg(
+            Arg::new("preset")
+                .short('p')
+                .long("preset")
+                .value_parser(Preset::names().collect::<Vec<_>>())
+                .help("Use the targets of a common delivery specification.")
+                .long_help(preset_help()),
+        )
+        .arg(
+            Arg::new("spec")
+                .long("spec")
+                .default_value("ebu-r128")
+                .value_parser(SpecProfile::names().collect::<Vec<_>>())
+                .help("Delivery specification that check compares the inputs with."),
+        )
+        .arg(
+            Arg::new("down_mix")
+                .short('d')
+                .long("down_mix")
+                .action(ArgAction::SetTrue)
+                .help("Downmix to 16bit 48kHz stereo. Same as --channel-layout stereo --sample-rate 48000 --sample-fmt s16."),
+        )
+        .arg(
+            Arg::new("channel_layout")
+                .long("channel-layout")
+                .value_parser(["mono", "stereo", "2.1", "quad", "5.0", "5.1", "7.1"])
+                .help("Convert to this channel layout before measuring and normalizing."),
+        )
+        .arg(
+            Arg::new("downmix")
+                .long("downmix")
+                .value_name("MATRIX")
+                .help("How to fold multichannel sources down: lo-ro (centre and surrounds at -3 dB, no LFE), lt-rt (matrix surround for Pro Logic decoders), dialogue-boost (centre at full level, surrounds at -6 dB, LFE at -10 dB), or the arguments of a pan filter such as 'stereo|FL=FL+0.7*FC+0.5*LFE|FR=FR+0.7*FC+0.5*LFE'. The presets downmix to stereo unless --channel-layout says otherwise."),
+        )
+        .arg(
+            Arg::new("dual_mono")
+                .long("dual-mono")
+                .action(ArgAction::SetTrue)
+                .help("Treat mono input as dual-mono."),
+        )
+        .arg(
+            Arg::new("offset")
+                .long("offset")
+                .allow_hyphen_values(true)
+                .value_parser(|value: &str| parse_in_range(value, OFFSET_RANGE, "LU"))
+                .help("Gain offset in LU to use instead of the measured target offset."),
+        )
+        .args(measured_args())
+}
+
+/// Measurements saved by earlier runs or by the Python ffmpeg-normalize.
+fn analysis_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("from_analysis")
+                .long("from-analysis")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["measured_i", "all_audio_streams", "album", "tag_only"])
+                .help("Skip the first pass and use the measurements saved in this JSON file by --save-analysis or --format json. When it holds several, the input's are used."),
+        )
+        .arg(
+            Arg::new("save_analysis")
+                .long("save-analysis")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["from_analysis", "all_audio_streams", "album"])
+                .help("Save the measurements of the input to this JSON file, for --from-analysis in a later run, possibly on another machine."),
+        )
+        .arg(
+            Arg::new("import_stats")
+                .long("import-stats")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["from_analysis", "measured_i", "all_audio_streams", "album", "tag_only"])
+                .help("Skip the first pass for the inputs listed in this stats file of the Python ffmpeg-normalize, as its --print-stats writes it, using their ebu_pass1 measurements. With --audio-stream N the Nth stream listed for an input is used. Inputs not listed are measured as usual."),
+        )
+        .arg(
+            Arg::new("export_stats")
+                .long("export-stats")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .help("Write the measurements of the inputs to this file in the stats format of the Python ffmpeg-normalize, readable with --import-stats. The file is rewritten as each input finishes."),
+        )
+}
+
+/// The normalization filter and its tuning.
+fn normalization_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("strategy")
+                .long("strategy")
+                .value_parser(["linear", "dynamic", "auto"])
+                .default_value("auto")
+                .help("Second-pass mode. auto uses linear normalization when the measurements allow it."),
+        )
+        .arg(
+            Arg::new("no_linear")
+                .long("no-linear")
+                .action(ArgAction::SetTrue)
+                .help("Normalize dynamically instead of linearly in the second pass. Same as --strategy dynamic."),
+        )
+        .arg(
+            Arg::new("mode")
+                .long("mode")
+                .value_parser(["ebu", "peak", "rms"])
+                .default_value("ebu")
+                .help("Normalize integrated loudness with loudnorm, or apply a plain gain bringing the true peak to --true_peak or the RMS level to --target-rms."),
+        )
+        .arg(
+            Arg::new("enforce_lra")
+                .long("enforce-lra")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("tag_only")
+                .help("When the measured loudness range is more than 1 LU above --loudness_range, compress it ahead of loudnorm, with a ratio and threshold derived from the measurement, and measure again, so the output meets the target with linear normalization where possible."),
+        )
+        .arg(
+            Arg::new("dialogue_gated")
+                .long("dialogue-gated")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["enforce_lra", "album"])
+                .help("Normalize the dialogue to the target instead of the whole program: measure the center channel, or the mid signal of layouts without one, limited to the speech band, and apply the gain that brings it to --integrated_loudness. A limiter holds --true_peak where the gain lifts the program peaks past it."),
+        )
+        .arg(
+            Arg::new("peak_mode")
+                .long("peak-mode")
+                .value_parser(["true", "sample"])
+                .default_value("true")
+                .help("Peak that --true_peak caps. sample measures the highest sample with astats and the loudness with ebur128 at the input's rate, skipping loudnorm's 192 kHz oversampling in the first pass."),
+        )
+        .arg(
+            Arg::new("target_rms")
+                .long("target-rms")
+                .allow_hyphen_values(true)
+                .value_parser(parse_rms)
+                .default_value("-20")
+                .help("RMS level target for --mode rms, e.g. -20dB."),
+        )
+        .arg(
+            Arg::new("filter_engine")
+                .long("filter-engine")
+                .value_parser(["loudnorm", "dynaudnorm", "speechnorm"])
+                .default_value("loudnorm")
+                .help("Filter that normalizes. dynaudnorm and speechnorm compress dynamically in a single pass, without measuring."),
+        )
+        .arg(
+            Arg::new("dynaudnorm_frame_len")
+                .long("dynaudnorm-frame-len")
+                .value_parser(value_parser!(u32).range(10..=8000))
+                .default_value("500")
+                .help("Frame length of dynaudnorm in milliseconds."),
+        )
+        .arg(
+            Arg::new("dynaudnorm_gauss_size")
+                .long("dynaudnorm-gauss-size")
+                .value_parser(parse_gauss_size)
+                .default_value("31")
+                .help("Gaussian smoothing window of dynaudnorm in frames, an odd number from 3 to 301."),
+        )
+        .arg(
+            Arg::new("dynaudnorm_peak")
+                .long("dynaudnorm-peak")
+                .value_parser(|value: &str| parse_in_range(value, 0.0..=1.0, ""))
+                .default_value("0.95")
+                .help("Target peak of dynaudnorm."),
+        )
+        .arg(
+            Arg::new("dynaudnorm_max_gain")
+                .long("dynaudnorm-max-gain")
+                .value_parser(|value: &str| parse_in_range(value, 1.0..=100.0, ""))
+                .default_value("10")
+                .help("Maximum gain factor of dynaudnorm."),
+        )
+        .arg(
+            Arg::new("speechnorm_peak")
+                .long("speechnorm-peak")
+                .value_parser(|value: &str| parse_in_range(value, 0.0..=1.0, ""))
+                .default_value("0.95")
+                .help("Target peak of speechnorm."),
+        )
+        .arg(
+            Arg::new("speechnorm_expansion")
+                .long("speechnorm-expansion")
+                .value_parser(|value: &str| parse_in_range(value, 1.0..=50.0, ""))
+                .default_value("2")
+                .help("Maximum expansion factor of speechnorm."),
+        )
+        .arg(
+            Arg::new("speechnorm_compression")
+                .long("speechnorm-compression")
+                .value_parser(|value: &str| parse_in_range(value, 1.0..=50.0, ""))
+                .default_value("2")
+                .help("Maximum compression factor of speechnorm."),
+        )
+        .arg(
+            Arg::new("limiter")
+                .long("limiter")
+                .action(ArgAction::SetTrue)
+                .help("Append a limiter after loudnorm to catch inter-sample overs."),
+        )
+        .arg(
+            Arg::new("limiter_attack")
+                .long("limiter-attack")
+                .value_parser(|value: &str| parse_in_range(value, 0.1..=80.0, "ms"))
+                .default_value("5")
+                .help("Attack time of the --limiter in milliseconds."),
+        )
+        .arg(
+            Arg::new("limiter_release")
+                .long("limiter-release")
+                .value_parser(|value: &str| parse_in_range(value, 1.0..=8000.0, "ms"))
+                .default_value("50")
+                .help("Release time of the --limiter in milliseconds."),
+        )
+        .arg(
+            Arg::new("limiter_ceiling")
+                .long("limiter-ceiling")
+                .allow_hyphen_values(true)
+                .value_parser(|value: &str| parse_in_range(value, TRUE_PEAK_RANGE, "dBTP"))
+                .help("Ceiling of the --limiter in dBTP. Defaults to the true peak target."),
+        )
+}
+
+/// What of each input is measured, and how it is prepared.
+fn input_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("pass_silent")
+                .long("pass-silent")
+                .action(ArgAction::SetTrue)
+                .help("Pass silent inputs through without gain instead of skipping them."),
+        )
+        .arg(
+            Arg::new("audio_stream")
+                .long("audio-stream")
+                .value_parser(value_parser!(u64))
+                .help("Index of the audio stream to normalize, counting audio streams only."),
+        )
+        .arg(
+            Arg::new("start")
+                .long("start")
+                .value_parser(parse_time)
+                .help("Measure from this position, in seconds or [HH:]MM:SS[.m]."),
+        )
+        .arg(
+            Arg::new("trim_silence")
+                .long("trim-silence")
+                .value_name("THRESHOLD_DB,MIN_DURATION")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("-60,0.1")
+                .value_parser(parse_silence_trim)
+                .help("Trim silence below THRESHOLD_DB (default -60) off the start and end in both passes, after downmixing and before normalizing. Silence ends once audio lasts MIN_DURATION seconds (default 0.1). Needs memory for the whole decoded input to find the end."),
+        )
+        .arg(
+            Arg::new("pre_filter")
+                .long("pre-filter")
+                .value_name("FILTERS")
+                .help("Filters to run ahead of loudnorm in both passes, so they are measured too, e.g. highpass=f=60. They follow the channel layout and sample format conversion."),
+        )
+        .arg(
+            Arg::new("post_filter")
+                .long("post-filter")
+                .value_name("FILTERS")
+                .help("Filters to append to the second pass after loudnorm and the limiter, e.g. aresample=48000. They are not measured, so they shouldn't change the loudness."),
+        )
+        .arg(
+            Arg::new("fast_analysis")
+                .long("fast-analysis")
+                .value_name("CHUNK,INTERVAL")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("30,300")
+                .value_parser(parse_sampling)
+                .help("Measure only the first CHUNK seconds (default 30) of every INTERVAL (default 300) of inputs at least two intervals long, and estimate the loudness of the whole from them. Results are marked approximate; the true peak between chunks is missed."),
+        )
+        .arg(
+            Arg::new("analysis_jobs")
+                .long("analysis-jobs")
+                .value_name("N")
+                .default_value("1")
+                .value_parser(value_parser!(u16).range(1..=64))
+                .conflicts_with("fast_analysis")
+                .help("Measure inputs of at least N minutes in N chunks decoded by concurrent ffmpeg processes, whose 100ms blocks are gated together by the native meter, as in a single pass. Needs a build with --features native."),
+        )
+        .arg(
+            Arg::new("duration")
+                .long("duration")
+                .value_parser(parse_time)
+                .help("Measure only this long a segment, in seconds or [HH:]MM:SS[.m]."),
+        )
+        .arg(
+            Arg::new("all_audio_streams")
+                .long("all-audio-streams")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("audio_stream")
+                .help("Normalize every audio stream separately and copy the video. With analyze, print a table of the streams with their language, layout, measurements and compliance."),
+        )
+}
+
+/// Tagging instead of re-encoding, and the album, cue sheet and audiobook
+/// modes.
+fn mode_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("tag_only")
+                .long("tag-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("all_audio_streams")
+                .help("Write ReplayGain (and Opus R128) gain tags instead of re-encoding the audio."),
+        )
+        .arg(
+            Arg::new("tag_format")
+                .long("tag-format")
+                .value_parser(["auto", "replaygain", "r128"])
+                .default_value("auto")
+                .help("Gain tags written with --tag-only. auto writes ReplayGain tags, plus R128_TRACK_GAIN/R128_ALBUM_GAIN for Opus; r128 writes only the latter and refuses other inputs."),
+        )
+        .arg(
+            Arg::new("album")
+                .long("album")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("all_audio_streams")
+                .help("Treat all inputs as one album: apply the same gain to every track, and write album tags with --tag-only."),
+        )
+        .arg(
+            Arg::new("group_relative")
+                .long("group-relative")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("cue")
+                .help("Apply the same gain to every input, chosen so the reference input hits the target: the loudest one, or --reference. Keeps the balance between stems or segments."),
+        )
+        .arg(
+            Arg::new("match_loudest")
+                .long("match-loudest")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["cue", "reference"])
+                .help("--group-relative with the loudest input as the reference."),
+        )
+        .arg(
+            Arg::new("reference")
+                .long("reference")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("cue")
+                .help("Input, or other file, that hits the target in --group-relative mode. Implies --group-relative."),
+        )
+        .arg(
+            Arg::new("cue")
+                .long("cue")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all([
+                    "watch",
+                    "tag_only",
+                    "output",
+                    "output_template",
+                    "all_audio_streams",
+                    "recursive",
+                    "start",
+                    "duration",
+                ])
+                .help("Split the input image into the tracks of this CUE sheet and normalize each, written to --output-dir. With --album, every track gets the gain of the whole image."),
+        )
+        .arg(
+            Arg::new("audiobook")
+                .long("audiobook")
+                .value_name("MODE")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("chapters")
+                .value_parser(["chapters", "book"])
+                .conflicts_with_all([
+                    "album",
+                    "all_audio_streams",
+                    "tag_only",
+                    "targets",
+                    "cue",
+                    "concat",
+                    "stems",
+                    "start",
+                    "duration",
+                    "interactive",
+                ])
+                .help("Normalize inputs with chapters, such as M4B audiobooks, into one output keeping the chapters, metadata and cover. chapters (the default) gives each chapter the gain reaching the target from its own measurements; book normalizes the whole book and verifies each chapter of the output. Chapters are verified in chapters mode too with --verify."),
+        )
+}
+
+/// Printing the ffmpeg commands and verifying the outputs.
+fn verify_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("print_command")
+                .long("print-command")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["tag_only", "album", "watch"])
+                .help("Measure, then print the quoted second-pass ffmpeg command instead of running it."),
+        )
+        .arg(
+            Arg::new("shell")
+                .long("shell")
+                .value_parser(["posix", "cmd", "powershell"])
+                .requires("print_command")
+                .help("Quote --print-command output for this shell. Defaults to cmd on Windows and posix elsewhere."),
+        )
+        .arg(
+            Arg::new("verify")
+                .long("verify")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["tag_only", "album", "all_audio_streams", "print_command"])
+                .help("Measure each output again and fail unless it is within tolerance of the target and under the true peak ceiling."),
+        )
+        .arg(
+            Arg::new("verify_tolerance")
+                .long("verify-tolerance")
+                .value_parser(|value: &str| parse_in_range(value, 0.0..=10.0, "LU"))
+                .default_value("0.5")
+                .help("Accepted deviation from the integrated loudness target for --verify."),
+        )
+}
+
+/// The output file, and when writing it is skipped.
+fn output_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("output")
+                .value_parser(value_parser!(PathBuf))
+                .short('o')
+                .long("output")
+                .help("Run the second pass and write the normalized audio to this path, or to standard output for - with --output-format."),
+        )
+        .arg(
+            Arg::new("output_format")
+                .long("output-format")
+                .value_parser(["wav", "flac", "s16le"])
+                .help("Encoding of the audio written to standard output with --output -. s16le is headerless 16-bit PCM at the output sample rate."),
+        )
+        .arg(
+            Arg::new("force")
+                .short('f')
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Overwrite outputs that already exist."),
+        )
+        .arg(
+            Arg::new("skip_existing")
+                .long("skip-existing")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("force")
+                .help("Skip inputs whose output already exists."),
+        )
+        .arg(
+            Arg::new("skip_tagged")
+                .long("skip-tagged")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("album")
+                .help("Skip inputs that already carry ReplayGain or R128 track gain tags."),
+        )
+        .arg(
+            Arg::new("retag")
+                .long("retag")
+                .action(ArgAction::SetTrue)
+                .help("Measure and tag inputs again even with --skip-tagged, e.g. from the config file."),
+        )
+        .arg(
+            Arg::new("skip_normalized")
+                .long("skip-normalized")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("album")
+                .help(format!("Skip inputs whose {} tag shows they were normalized to the same mode and target already, without measuring them.", MARKER_TAG)),
+        )
+        .arg(
+            Arg::new("no_marker")
+                .long("no-marker")
+                .action(ArgAction::SetTrue)
+                .help(format!("Don't write the {} tag recording the targets, the measured input loudness and the date into outputs.", MARKER_TAG)),
+        )
+}
+
+/// Encoder settings of the outputs.
+fn encoding_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("codec")
+                .long("codec")
+                .help("Audio codec for the output, e.g. aac, libopus or flac."),
+        )
+        .arg(
+            Arg::new("dialnorm")
+                .long("dialnorm")
+                .value_name("DB")
+                .allow_hyphen_values(true)
+                .value_parser(|value: &str| value.parse::<Dialnorm>())
+                .help("Dialogue level an AC-3 or E-AC-3 output declares, from -31 to -1 dB, or target for the rounded integrated loudness target."),
+        )
+        .arg(
+            Arg::new("bitrate")
+                .long("bitrate")
+                .help("Audio bitrate for the output, e.g. 192k."),
+        )
+        .arg(
+            Arg::new("sample_rate")
+                .long("sample-rate")
+                .value_parser(value_parser!(u32))
+                .help("Convert to this sample rate in Hz before measuring, and encode the output with it."),
+        )
+        .arg(
+            Arg::new("keep_sample_rate")
+                .long("keep-sample-rate")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["sample_rate", "down_mix"])
+                .help("Resample loudnorm's 192kHz output back to the sample rate of the input."),
+        )
+        .arg(
+            Arg::new("sample_fmt")
+                .long("sample-fmt")
+                .help("Convert to this sample format before measuring, and encode the output with it, e.g. s16 or s32. Without it, FLAC, WAV and AIFF outputs keep the bit depth of the input."),
+        )
+        .arg(
+            Arg::new("resampler")
+                .long("resampler")
+                .value_parser(["swr", "soxr"])
+                .help("Resampler for sample rate conversions. soxr gives higher quality if ffmpeg was built with it."),
+        )
+        .arg(
+            Arg::new("resampler_precision")
+                .long("resampler-precision")
+                .value_parser(value_parser!(u32).range(15..=33))
+                .requires("resampler")
+                .help("Precision of the soxr resampler in bits."),
+        )
+        .arg(
+            Arg::new("copy_video")
+                .long("copy-video")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("tag_only")
+                .help("Stream-copy the video of the input into the output and only re-encode the audio."),
+        )
+        .arg(
+            Arg::new("map_all")
+                .long("map-all")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("tag_only")
+                .help("Keep every stream of the input, stream-copying subtitles, attachments, data, video and the other audio streams, so that only the normalized audio stream is re-encoded."),
+        )
+        .arg(
+            Arg::new("strip_metadata")
+                .long("strip-metadata")
+                .action(ArgAction::SetTrue)
+                .help("Leave tags, chapters and cover art of the input out of the output."),
+        )
+        .arg(
+            Arg::new("reproducible")
+                .long("reproducible")
+                .action(ArgAction::SetTrue)
+                .help("Write byte-identical outputs when normalizing the same input twice: bitexact muxing and encoding, without version strings or creation times."),
+        )
+}
+
+/// Where outputs are written, and what happens to the inputs.
+fn placement_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("output_template")
+                .long("output-template")
+                .conflicts_with("output")
+                .help("Output path for each input, with {dir}, {stem}, {ext} and {lufs} placeholders."),
+        )
+        .arg(
+            Arg::new("output_dir")
+                .long("output-dir")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("output")
+                .help("Directory for outputs of batch runs. Relative templates are resolved against it."),
+        )
+        .arg(
+            Arg::new("targets")
+                .long("targets")
+                .value_name("PRESETS")
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .value_parser(Preset::names().collect::<Vec<_>>())
+                .conflicts_with_all(["output", "tag_only", "all_audio_streams", "album", "print_command", "cue"])
+                .help("Write an output for each of these presets from a single measurement, e.g. streaming,podcast,ebu-r128. Outputs are named by --output-template, {stem}.{variant}.{ext} by default, where {variant} is the preset."),
+        )
+        .arg(
+            Arg::new("output_ext")
+                .long("output-ext")
+                .value_parser(OUTPUT_EXTENSIONS.map(|(ext, _)| ext))
+                .conflicts_with_all(["output", "tag_only"])
+                .help("Container of outputs named by --output-template or --output-dir, in place of the input's: {ext} becomes this extension, and unless --codec is given the usual codec for it is used."),
+        )
+        .arg(
+            Arg::new("in_place")
+                .long("in-place")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "output",
+                    "output_template",
+                    "output_dir",
+                    "output_ext",
+                    "targets",
+                    "tag_only",
+                    "concat",
+                    "stems",
+                    "download_inputs",
+                    "verify",
+                ])
+                .help("Replace each input with its normalized audio, written to a temporary file next to it and renamed over it once complete. The permissions and, where allowed, the owner of the input carry over."),
+        )
+        .arg(
+            Arg::new("backup_suffix")
+                .long("backup-suffix")
+                .value_name("SUFFIX")
+                .requires("in_place")
+                .help("Keep each input replaced by --in-place under its name with SUFFIX appended, e.g. .bak. An existing backup is left alone."),
+        )
+        .arg(
+            Arg::new("keep_mtime")
+                .long("keep-mtime")
+                .action(ArgAction::SetTrue)
+                .requires("in_place")
+                .help("Give each input replaced by --in-place its previous modification time."),
+        )
+}
+
+/// Reports, hooks and notifications of a run.
+fn report_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("timeline")
+                .long("timeline")
+                .value_parser(value_parser!(PathBuf))
+                .help("Export momentary and short-term loudness over time to this CSV or JSON file."),
+        )
+        .arg(
+            Arg::new("plot")
+                .long("plot")
+                .value_parser(value_parser!(PathBuf))
+                .help("Draw loudness over time and the true peak ceiling to this SVG file."),
+        )
+        .arg(
+            Arg::new("filter_script")
+                .long("filter-script")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["tag_only", "album"])
+                .help("Also write the filtergraph to this file, for ffmpeg's -filter_script or -filter_complex_script."),
+        )
+        .arg(
+            Arg::new("report_path")
+                .long("report")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("watch")
+                .help("Write a summary with one row per input to this CSV or JSON file."),
+        )
+        .arg(
+            Arg::new("output_playlist")
+                .long("output-playlist")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["watch", "tag_only"])
+                .help("Write an M3U playlist pointing at the outputs, in input order."),
+        )
+        .arg(
+            Arg::new("post_hook")
+                .long("post-hook")
+                .value_name("COMMAND")
+                .conflicts_with("print_command")
+                .help("Run this shell command after each written output. {input}, {output}, {input_i}, {input_tp}, {input_lra}, {input_thresh}, {gain} and {target_i} are replaced with quoted values; a failing command fails the file."),
+        )
+        .arg(
+            Arg::new("notify_url")
+                .long("notify-url")
+                .value_name("URL")
+                .conflicts_with("watch")
+                .help("POST a JSON event to this URL as each input completes, with its status, measurements and elapsed time, and a summary when the run completes. Needs curl."),
+        )
+        .arg(
+            Arg::new("skip_within")
+                .long("skip-within")
+                .value_parser(|value: &str| parse_in_range(value, 0.0..=10.0, "LU"))
+                .conflicts_with_all(["tag_only", "all_audio_streams", "print_command"])
+                .help("Skip the second pass for inputs within this many LU of the target whose true peak is under the ceiling."),
+        )
+}
+
+/// Runs over many inputs: where they come from, how they are scheduled
+/// and what the exit code says.
+fn batch_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("noop_exit_code")
+                .long("noop-exit-code")
+                .action(ArgAction::SetTrue)
+                .help(format!("Exit with {} when every input was already at its target and none failed.", NOOP_EXIT_CODE)),
+        )
+        .arg(
+            Arg::new("fail_fast")
+                .long("fail-fast")
+                .action(ArgAction::SetTrue)
+                .help("Stop after the first input that fails instead of carrying on with the rest of the batch."),
+        )
+        .arg(
+            Arg::new("interactive")
+                .long("interactive")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["print_command", "album", "tag_only", "watch", "cue", "all_audio_streams"])
+                .help("After measuring each input, show the measurements and the proposed filter and ask whether to encode it: yes, no, all or quit. Runs one input at a time."),
+        )
+        .arg(
+            Arg::new("tui")
+                .long("tui")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["interactive", "print_command", "album", "watch", "cue"])
+                .help("Show the batch on a full-screen dashboard: every input with its state and measurements, throughput and an overall ETA. Keys pause the batch (p), skip (s) or retry (r) the selected input and quit (q); the dashboard stays open for retries until q."),
+        )
+        .arg(
+            Arg::new("state")
+                .long("state")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["watch", "album"])
+                .help("Record the outcome of every input in this JSON file as the run progresses."),
+        )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .action(ArgAction::SetTrue)
+                .requires("state")
+                .help("Skip inputs the --state file lists as done, retrying failed and unprocessed ones."),
+        )
+        .arg(
+            Arg::new("input_format")
+                .long("input-format")
+                .help("Container format of audio read from stdin, e.g. wav or flac."),
+        )
+        .arg(
+            Arg::new("download_inputs")
+                .long("download-inputs")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("album")
+                .help("Download http(s) URL inputs to a temporary file with curl, so they are fetched once instead of in every pass."),
+        )
+        .arg(
+            Arg::new("files_from")
+                .long("files-from")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["watch", "cue"])
+                .help("Read input paths from FILE, or - for standard input, one per line or NUL-separated as printed by find -print0. Paths are taken literally, without glob expansion."),
+        )
+        .arg(
+            Arg::new("concat")
+                .long("concat")
+                .action(ArgAction::SetTrue)
+                .requires("output")
+                .conflicts_with_all(["album", "watch", "cue", "manifest", "tag_only", "all_audio_streams", "targets", "save_analysis", "interactive", "tui"])
+                .help("Join the inputs in the order given, e.g. intro, episode and outro, and measure and normalize them as one program into --output. Only their audio is kept."),
+        )
+        .arg(
+            Arg::new("stems")
+                .long("stems")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["output", "album", "concat", "watch", "cue", "manifest", "tag_only", "all_audio_streams", "targets", "interactive", "tui", "trim_silence", "enforce_lra", "pre_filter"])
+                .help("Treat the first input as the mix and the others as its stems: measure only the mix and apply its gain as a plain volume change to every input, so the stems still sum to the normalized mix. Needs --output-dir or --output-template. Silence trimming, pre-filters, compression and limiting are left out, as they would act on each stem differently."),
+        )
+        .arg(
+            Arg::new("manifest")
+                .long("manifest")
+                .value_name("CSV")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["watch", "cue", "album", "output", "targets"])
+                .help("Process the inputs listed in this CSV. Its header names the columns: input, and optionally output, preset, i, tp and lra, which override the output and the targets for that input. Empty cells keep the values of the command line."),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("album")
+                .help("Keep watching this directory and normalize audio files into --output-dir once they stop growing."),
+        )
+        .arg(
+            Arg::new("recursive")
+                .short('r')
+                .long("recursive")
+                .action(ArgAction::SetTrue)
+                .help("Walk input directories and process every audio file inside."),
+        )
+        .arg(
+            Arg::new("include_ext")
+                .long("include-ext")
+                .value_delimiter(',')
+                .help("Comma separated extensions to pick up with --recursive."),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .value_name("SECS")
+                .value_parser(|value: &str| parse_in_range(value, 0.1..=86400.0, "seconds"))
+                .help("Stop ffmpeg when its position hasn't advanced for SECS seconds and fail that file, continuing with the rest."),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_parser(value_parser!(u64).range(1..))
+                .help(
+                    "Number of files to process concurrently. Defaults to the number of cores.",
+                ),
+        )
+        .arg(
+            Arg::new("socket")
+                .long("socket")
+                .value_name("PATH")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("listen")
+                .help("Unix socket serve takes jobs on, e.g. /run/fflh.sock. Only its owner may connect. A socket left behind by a server that is gone is replaced."),
+        )
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .value_name("ADDRESS")
+                .help("Loopback address and port serve takes jobs on, e.g. 127.0.0.1:8765."),
+        )
+        .arg(
+            Arg::new("max_load")
+                .long("max-load")
+                .value_name("LOAD")
+                .value_parser(|value: &str| parse_in_range(value, 0.1..=1024.0, ""))
+                .help("Wait with starting the next file while the one-minute load average is above LOAD."),
+        )
+        .arg(
+            Arg::new("nice")
+                .long("nice")
+                .value_name("LEVEL")
+                .value_parser(value_parser!(i64).range(0..=19))
+                .help("Run ffmpeg with this niceness, from 0 to 19, through nice."),
+        )
+        .arg(
+            Arg::new("idle_io")
+                .long("idle-io")
+                .action(ArgAction::SetTrue)
+                .help("Run ffmpeg in the idle I/O scheduling class through ionice, so it only reads and writes when nothing else does. Linux only."),
+        )
+}
+
+/// How measurements and filters are printed.
+fn print_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .help("Print the filter string, or a JSON object with the measurements and filter."),
+        )
+        .arg(
+            Arg::new("print0")
+                .short('z')
+                .long("print0")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("format")
+                .help("End the printed filter, gain or tags with a NUL instead of a newline, and in batches separate the input path from it with a NUL too, for xargs -0."),
+        )
+        .arg(
+            Arg::new("escape")
+                .long("escape")
+                .value_parser(["sh", "fish", "powershell", "none"])
+                .default_value("none")
+                .conflicts_with_all(["format", "print0", "print_command"])
+                .help("Quote the printed filter as one word for this shell, for eval or Invoke-Expression. Plain command substitution doesn't remove quotes: write -af \"$(...)\" in sh instead, or -af (...) in fish and PowerShell."),
+        )
+        .arg(
+            Arg::new("target")
+                .long("target")
+                .value_parser(["ffmpeg", "mpv"])
+                .default_value("ffmpeg")
+                .conflicts_with_all(["all_audio_streams", "print_command"])
+                .help("Print the filter for ffmpeg's -af, or as an mpv --af=lavfi=[...] option."),
+        )
+        .arg(
+            Arg::new("print")
+                .long("print")
+                .value_parser(["filter", "gain", "volume", "summary", "all"])
+                .default_value("filter")
+                .conflicts_with_all(["all_audio_streams", "tag_only", "print_command"])
+                .help("Print the second-pass filter, just the gain in dB reaching the target, a volume filter applying it, or a summary of the measurements in aligned columns. With --format json, all prints one object with the measurements, the filter, the gain and the full second-pass ffmpeg command, writing to OUTPUT when no output is given."),
+        )
+        .arg(
+            Arg::new("summary")
+                .long("summary")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["print", "all_audio_streams", "tag_only", "print_command"])
+                .help("Print the measurements as loudnorm's human-readable summary. Same as --print summary."),
+        )
+}
+
+/// Logging, the ffmpeg used, and the caches.
+fn runtime_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("backend")
+                .long("backend")
+                .value_parser(["ffmpeg", "native"])
+                .help("Measure with ffmpeg's loudnorm, or natively in-process. The native meter reads WAV files only and fails on any other input, unless --analysis-jobs has ffmpeg decode the chunks; it needs a build with the `native` feature."),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["quiet", "log_level"])
+                .help("Show ffmpeg command lines, timings and the full stderr of failed runs."),
+        )
+        .arg(
+            Arg::new("no_progress")
+                .long("no-progress")
+                .action(ArgAction::SetTrue)
+                .help("Show no progress, neither the spinner on a terminal nor progress lines otherwise."),
+        )
+        .arg(
+            Arg::new("progress_format")
+                .long("progress-format")
+                .value_parser(["text", "jsonl"])
+                .default_value("text")
+                .conflicts_with_all(["watch", "print_command"])
+                .help("How progress is reported: a spinner, or with jsonl one JSON event per line on stdout (pass1_progress, pass2_progress, pass_complete, file_done, error)."),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("log_level")
+                .help("Only print errors."),
+        )
+        .arg(
+            Arg::new("log_level")
+                .long("log-level")
+                .value_parser(["error", "warn", "info", "debug"])
+                .help("How much to log on stderr [default: warn]."),
+        )
+        .arg(
+            Arg::new("auto_download_ffmpeg")
+                .long("auto-download-ffmpeg")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("ffmpeg_path")
+                .help("When ffmpeg is not installed, download the static ffmpeg build pinned for this platform into the cache directory and use that, after checking it against the SHA-256 recorded for it. Platforms without a pinned build are refused."),
+        )
+        .arg(
+            Arg::new("ffmpeg_path")
+                .long("ffmpeg-path")
+                .value_parser(value_parser!(PathBuf))
+                .help("Path to the ffmpeg binary or its directory. Defaults to FFMPEG_PATH, FFMPEG_BINARY or PATH."),
+        )
+        .arg(
+            Arg::new("cache")
+                .long("cache")
+                .action(ArgAction::SetTrue)
+                .help("Reuse first-pass measurements from earlier runs and store new ones."),
+        )
+        .arg(
+            Arg::new("no_cache")
+                .long("no-cache")
+                .action(ArgAction::SetTrue)
+                .help("Always measure, even when caching is enabled in the config file."),
+        )
+        .arg(
+            Arg::new("cache_dir")
+                .long("cache-dir")
+                .value_parser(value_parser!(PathBuf))
+                .help("Directory for cached measurements. Defaults to the user cache directory."),
+        )
+        .arg(
+            Arg::new("db")
+                .long("db")
+                .value_parser(value_parser!(PathBuf))
+                .help("Record every measurement with its targets and applied gain in this SQLite database, which is also used like --cache. Needs the sqlite3 shell."),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .help("Read default settings from this TOML file instead of the discovered one."),
+        )
+}
+
+/// The help of `--preset`, listing the built-in presets.
+pub(crate) fn preset_help() -> String {
+    PRESETS.iter().fold(
+        "Use the targets of a common delivery specification. Explicit target flags override the preset.\n".to_string(),
+        |help, p| {
+            format!(
+                "{}\n  {:<16}{} (I={:.1} LRA={:.1} TP={:.1})",
+                help, p.name, p.description, p.integrated_loudness, p.loudness_range, p.true_peak
+            )
+        },
+    )
+}
+
+/// `--measured-i`, `--measured-tp`, `--measured-lra` and
+/// `--measured-thresh`, which replace the first pass and so only come
+/// together. The ranges are the ones loudnorm accepts.
+fn measured_args() -> Vec<Arg> {
+    let ids = [
+        "measured_i",
+        "measured_tp",
+        "measured_lra",
+        "measured_thresh",
+    ];
+    [
+        (
+            "measured_i",
+            -99.0..=0.0,
+            "LUFS",
+            "Measured integrated loudness",
+        ),
+        ("measured_tp", -99.0..=99.0, "dBTP", "Measured true peak"),
+        ("measured_lra", 0.0..=99.0, "LU", "Measured loudness range"),
+        (
+            "measured_thresh",
+            -99.0..=0.0,
+            "LUFS",
+            "Measured gating threshold",
+        ),
+    ]
+    .into_iter()
+    .map(|(id, range, unit, noun)| {
+        Arg::new(id)
+            .long(id.replace('_', "-"))
+            .allow_hyphen_values(true)
+            .value_parser(move |value: &str| parse_in_range(value, range.clone(), unit))
+            .requires_all(
+                ids.into_iter()
+                    .filter(|other| *other != id)
+                    .collect::<Vec<_>>(),
+            )
+            .conflicts_with_all(["all_audio_streams", "album", "tag_only"])
+            .help(format!(
+                "{} in {}, to skip the first pass. Needs all four --measured-* values.",
+                noun, unit
+            ))
+    })
+    .collect()
+}
+
+fn input_arg() -> Arg {
+    Arg::new("input")
+        .value_parser(value_parser!(PathBuf))
+        .help("Paths or glob patterns of the input files, http(s) URLs, or - to read from stdin.")
+        .num_args(1..)
+        .required_unless_present_any(["watch", "cue", "files_from", "manifest"])
+}
+
+/// Parses an RMS level target, with or without a `dB` suffix.
+fn parse_rms(value: &str) -> Result<f64, String> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("dBFS"))
+        .unwrap_or(value);
+    parse_in_range(number, RMS_RANGE, "dBFS")
+}
+
+/// Parses the dynaudnorm smoothing window, which has to be odd.
+fn parse_gauss_size(value: &str) -> Result<u32, String> {
+    match value.trim().parse::<u32>() {
+        Ok(size) if (3..=301).contains(&size) && size % 2 == 1 => Ok(size),
+        _ => Err("expected an odd number from 3 to 301".to_string()),
+    }
+}
+
+/// Parses `--fast-analysis` as `CHUNK[,INTERVAL]` in seconds.
+fn parse_sampling(value: &str) -> Result<Sampling, String> {
+    let (chunk, interval) = value.split_once(',').unwrap_or((value, ""));
+    let chunk = parse_in_range(chunk, 3.0..=3600.0, "seconds")?;
+    let interval = match interval.trim() {
+        "" => Sampling::default().interval,
+        interval => parse_in_range(interval, 6.0..=86400.0, "seconds")?,
+    };
+    if chunk >= interval {
+        return Err("the chunk has to be shorter than the interval".to_string());
+    }
+    Ok(Sampling { chunk, interval })
+}
+
+/// Parses `--trim-silence` as `THRESHOLD_DB[,MIN_DURATION]`.
+fn parse_silence_trim(value: &str) -> Result<SilenceTrim, String> {
+    let (threshold, min_duration) = value.split_once(',').unwrap_or((value, ""));
+    let threshold = threshold.trim();
+    let threshold = parse_in_range(
+        threshold.strip_suffix("dB").unwrap_or(threshold),
+        -120.0..=0.0,
+        "dB",
+    )?;
+    let min_duration = match min_duration.trim() {
+        "" => SilenceTrim::default().min_duration,
+        seconds => seconds
+            .parse::<f64>()
+            .ok()
+            .filter(|s| (0.0..=60.0).contains(s))
+            .ok_or("expected a minimum duration from 0 to 60 seconds")?,
+    };
+    Ok(SilenceTrim {
+        threshold,
+        min_duration,
+    })
+}
+
+/// Validates a `--start`/`--duration` value in a form ffmpeg accepts.
+fn parse_time(value: &str) -> Result<String, String> {
+    let valid = value.split(':').count() <= 3
+        && value
+            .split(':')
+            .all(|part| part.parse::<f64>().is_ok_and(|n| n >= 0.0));
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err("expected seconds or [HH:]MM:SS[.m]".to_string())
+    }
+}
@@ -0,0 +1,218 @@
+//! The subcommands that don't normalize a batch of inputs: `selftest`,
+//! `doctor`, `serve`, `compare` and `check`.
+
+use crate::{
+    batch::{is_unchanged, record_history, Failures},
+    compare::Comparison,
+    print::run_post_hook,
+    process::{process, serialize_path},
+    serve, Audiobook, Compliance, Doctor, Error, OutputFormat, ProgressSpinner, RunConfig,
+    SelfTest, SpecProfile, Verification,
+};
+use serde::Serialize;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// Runs the checks of `selftest`, failing when any of them does.
+pub(crate) fn selftest(config: &RunConfig) -> ExitCode {
+    let checks = match SelfTest::run(&config.options) {
+        Ok(checks) => checks,
+        Err(e) => {
+            eprintln!("Could not generate the test signals: {}", e);
+            let failures = Failures::default();
+            failures.record(Some(&e));
+            return failures.exit_code();
+        }
+    };
+    match config.format {
+        OutputFormat::Json => match serde_json::to_string(&checks) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("{}", e),
+        },
+        OutputFormat::Text => {
+            for check in &checks {
+                let status = if check.passed { "PASS" } else { "FAIL" };
+                println!("{} {}: {}", status, check.name, check.detail);
+            }
+        }
+    }
+    if checks.iter().all(|check| check.passed) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Runs the checks of `doctor`, failing when any of them does.
+pub(crate) fn doctor(config: &RunConfig) -> ExitCode {
+    let checks = Doctor::run(&config.options, config.output_dir.as_deref());
+    match config.format {
+        OutputFormat::Json => match serde_json::to_string(&checks) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("{}", e),
+        },
+        OutputFormat::Text => {
+            for check in &checks {
+                let status = if check.passed { "PASS" } else { "FAIL" };
+                println!("{} {}: {}", status, check.name, check.detail);
+                if let Some(hint) = &check.hint {
+                    println!("  hint: {}", hint);
+                }
+            }
+        }
+    }
+    if checks.iter().all(|check| check.passed) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Runs the `serve` daemon on `endpoint`, normalizing the jobs posted to
+/// it until interrupted.
+pub(crate) fn serve(config: &RunConfig, endpoint: &serve::Endpoint) -> ExitCode {
+    let listener = match serve::Listener::bind(endpoint) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Jobs report their progress through the API.
+    ProgressSpinner::set_enabled(false);
+    let server = match serve::Server::new(config.jobs) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("serve: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!(
+        "serve: send requests with the header Authorization: Bearer {}",
+        server.token()
+    );
+    // Outputs named by jobs are relative to the output directory.
+    let output_root = config.output_dir.clone().unwrap_or_default();
+    server.run(&listener, |input_path, job| {
+        let mut job_config = config.for_job(job);
+        if let Some(output_path) = &job.output {
+            job_config.output_path = Some(output_root.join(output_path));
+        }
+        serve_job(&job_config, input_path)
+    });
+    ExitCode::from(Error::Interrupted.exit_code())
+}
+
+/// Processes one job of `serve` like an input of a batch, returning its
+/// result as `--format json` prints it.
+fn serve_job(config: &RunConfig, input_path: &Path) -> io::Result<serde_json::Value> {
+    if let Some(output_path) = config.output_for(input_path)? {
+        if output_path.exists() && !config.force && !config.in_place {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{}: exists; run the server with --force to overwrite",
+                    output_path.display()
+                ),
+            ));
+        }
+    }
+    let mut result = process(config, input_path)?;
+    result.already_normalized = is_unchanged(config, &result);
+    record_history(config, input_path, &result)?;
+    let variants = result.variants.iter().map(|v| &v.verification);
+    std::iter::once(&result.verification)
+        .chain(variants)
+        .flatten()
+        .try_for_each(Verification::ensure_passed)?;
+    if config.verify_tolerance.is_some() {
+        Audiobook::ensure_passed(&result.chapters)?;
+    }
+    run_post_hook(config, &result)?;
+    Ok(serde_json::to_value(&result)?)
+}
+
+/// Measures the two inputs of `compare` and prints them side by side.
+pub(crate) fn compare(config: &RunConfig) -> ExitCode {
+    let [a, b] = [0, 1].map(|index| {
+        let path = &config.input_paths[index];
+        crate::analyze(path, &config.options).map_err(|e| (path, e))
+    });
+    let printed = a.and_then(|a| b.map(|b| (a, b))).and_then(|(a, b)| {
+        let comparison = Comparison::new(&config.input_paths[0], a, &config.input_paths[1], b);
+        match config.format {
+            OutputFormat::Json => serde_json::to_string(&comparison)
+                .map(|json| println!("{}", json))
+                .map_err(|e| (&config.input_paths[0], e.into())),
+            OutputFormat::Text => {
+                println!("{}", comparison);
+                Ok(())
+            }
+        }
+    });
+    match printed {
+        Ok(()) => ExitCode::SUCCESS,
+        Err((path, e)) => {
+            eprintln!("{}: {}", path.display(), e);
+            let failures = Failures::default();
+            failures.record(Some(&e));
+            failures.exit_code()
+        }
+    }
+}
+
+/// Checks each input of `check` against `spec`, printing every criterion.
+pub(crate) fn check(
+    config: &RunConfig,
+    spec: &SpecProfile,
+    inputs: Vec<io::Result<PathBuf>>,
+) -> ExitCode {
+    #[derive(Serialize)]
+    struct Checked<'a> {
+        #[serde(serialize_with = "serialize_path")]
+        input: &'a Path,
+        #[serde(flatten)]
+        compliance: &'a Compliance,
+    }
+
+    let failures = Failures::for_run(config);
+    for input in inputs {
+        if failures.should_stop() {
+            break;
+        }
+        let input_path = match input {
+            Ok(input_path) => input_path,
+            Err(e) => {
+                eprintln!("{}", e);
+                failures.record(Some(&e));
+                continue;
+            }
+        };
+        let checked = crate::check(&input_path, &config.options, spec).and_then(|compliance| {
+            match config.format {
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string(&Checked {
+                        input: &input_path,
+                        compliance: &compliance,
+                    })?
+                ),
+                OutputFormat::Text => println!("{}\n{}", input_path.display(), compliance),
+            }
+            compliance.ensure_passed()
+        });
+        match checked {
+            Ok(()) => failures.record_success(false),
+            Err(e) => {
+                if !matches!(Error::of(&e), Some(Error::NotCompliant(_))) {
+                    eprintln!("{}: {}", input_path.display(), e);
+                }
+                failures.record_input(&input_path, &e);
+            }
+        }
+    }
+    failures.exit_code()
+}
@@ -0,0 +1,102 @@
+//! Side-by-side loudness of two inputs, printed by `compare`.
+
+use crate::Loudness;
+use serde::Serialize;
+use std::{fmt, path::Path};
+
+/// Measurements of two inputs and how far the second is from the first.
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub a: Side,
+    pub b: Side,
+    pub delta: Delta,
+}
+
+/// One of the compared inputs.
+#[derive(Debug, Serialize)]
+pub struct Side {
+    pub input: String,
+    #[serde(flatten)]
+    pub loudness: Loudness,
+}
+
+/// Differences `b - a`, `None` where either value is not finite.
+#[derive(Debug, Serialize)]
+pub struct Delta {
+    /// Integrated loudness difference in LU.
+    pub integrated_loudness: Option<f64>,
+    /// True peak difference in dB.
+    pub true_peak: Option<f64>,
+    /// Loudness range difference in LU.
+    pub loudness_range: Option<f64>,
+}
+
+impl Comparison {
+    pub fn new(a_path: &Path, a: Loudness, b_path: &Path, b: Loudness) -> Self {
+        let difference = |a: f64, b: f64| Some(b - a).filter(|d| d.is_finite());
+        Self {
+            delta: Delta {
+                integrated_loudness: difference(a.input_i, b.input_i),
+                true_peak: difference(a.input_tp, b.input_tp),
+                loudness_range: difference(a.input_lra, b.input_lra),
+            },
+            a: Side {
+                input: a_path.to_string_lossy().into_owned(),
+                loudness: a,
+            },
+            b: Side {
+                input: b_path.to_string_lossy().into_owned(),
+                loudness: b,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let delta = |value: Option<f64>, unit: &str| {
+            value.map_or_else(|| "n/a".to_string(), |d| format!("{:+.2} {}", d, unit))
+        };
+        writeln!(f, "A: {}", self.a.input)?;
+        writeln!(f, "B: {}", self.b.input)?;
+        writeln!(
+            f,
+            "  {:<21}{:>7}      {:>7}      {:>12}",
+            "", "A", "B", "B - A"
+        )?;
+        let rows = [
+            (
+                "Integrated loudness",
+                self.a.loudness.input_i,
+                self.b.loudness.input_i,
+                "LUFS",
+                delta(self.delta.integrated_loudness, "LU"),
+            ),
+            (
+                "True peak",
+                self.a.loudness.input_tp,
+                self.b.loudness.input_tp,
+                "dBTP",
+                delta(self.delta.true_peak, "dB"),
+            ),
+            (
+                "Loudness range",
+                self.a.loudness.input_lra,
+                self.b.loudness.input_lra,
+                "LU",
+                delta(self.delta.loudness_range, "LU"),
+            ),
+        ];
+        for (index, (name, a, b, unit, delta)) in rows.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "  {:<21}{:>7.2} {:<4} {:>7.2} {:<4} {:>12}",
+                name, a, unit, b, unit, delta
+            )?;
+        }
+        Ok(())
+    }
+}
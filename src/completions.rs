@@ -0,0 +1,240 @@
+//! Shell completion scripts generated from the clap command definition.
+
+use clap::{builder::Command, Arg, ValueHint};
+use std::fmt::Write as _;
+
+/// Shells that completion scripts can be generated for.
+pub const SHELLS: [&str; 4] = ["bash", "zsh", "fish", "powershell"];
+
+/// The completion script for `shell`, completing `bin_name` invocations of
+/// `command`.
+pub fn generate(shell: &str, command: &Command, bin_name: &str) -> String {
+    let options = options(command);
+    let subcommands: Vec<&Command> = command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .collect();
+    match shell {
+        "bash" => bash(bin_name, &options, &subcommands),
+        "zsh" => zsh(bin_name, &options, &subcommands),
+        "fish" => fish(bin_name, &options, &subcommands),
+        _ => powershell(bin_name, &options, &subcommands),
+    }
+}
+
+/// A visible option with the details completions need.
+struct Completable {
+    short: Option<char>,
+    long: Option<String>,
+    help: String,
+    takes_value: bool,
+    values: Vec<String>,
+    path: bool,
+}
+
+fn options(command: &Command) -> Vec<Completable> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .map(|arg: &Arg| Completable {
+            short: arg.get_short(),
+            long: arg.get_long().map(str::to_string),
+            help: arg
+                .get_help()
+                .map(|help| help.to_string())
+                .unwrap_or_default(),
+            takes_value: arg.get_action().takes_values(),
+            values: arg
+                .get_possible_values()
+                .iter()
+                .map(|value| value.get_name().to_string())
+                .collect(),
+            path: matches!(
+                arg.get_value_hint(),
+                ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath
+            ),
+        })
+        .collect()
+}
+
+fn flags(option: &Completable) -> Vec<String> {
+    let mut flags = Vec::new();
+    if let Some(short) = option.short {
+        flags.push(format!("-{}", short));
+    }
+    if let Some(long) = &option.long {
+        flags.push(format!("--{}", long));
+    }
+    flags
+}
+
+/// Identifier-safe form of `bin_name` for shell function names.
+fn function_name(bin_name: &str) -> String {
+    bin_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn bash(bin_name: &str, options: &[Completable], subcommands: &[&Command]) -> String {
+    let function = format!("_{}", function_name(bin_name));
+    let words: Vec<String> = options
+        .iter()
+        .flat_map(flags)
+        .chain(subcommands.iter().map(|s| s.get_name().to_string()))
+        .collect();
+    let mut script = String::new();
+    let _ = writeln!(script, "{}() {{", function);
+    let _ = writeln!(script, "    local cur prev");
+    let _ = writeln!(script, "    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"");
+    let _ = writeln!(script, "    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"");
+    let _ = writeln!(script, "    case \"$prev\" in");
+    for option in options.iter().filter(|o| o.takes_value) {
+        let reply = if !option.values.is_empty() {
+            format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                option.values.join(" ")
+            )
+        } else if option.path {
+            "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string()
+        } else {
+            "COMPREPLY=()".to_string()
+        };
+        let _ = writeln!(
+            script,
+            "        {})\n            {}\n            return 0\n            ;;",
+            flags(option).join("|"),
+            reply
+        );
+    }
+    let _ = writeln!(script, "    esac");
+    let _ = writeln!(script, "    if [[ \"$cur\" == -* ]]; then");
+    let _ = writeln!(
+        script,
+        "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        words.join(" ")
+    );
+    let _ = writeln!(script, "    else");
+    let _ = writeln!(script, "        COMPREPLY=($(compgen -f -- \"$cur\"))");
+    let _ = writeln!(script, "    fi");
+    let _ = writeln!(script, "}}");
+    let _ = writeln!(script, "complete -o filenames -F {} {}", function, bin_name);
+    script
+}
+
+fn zsh(bin_name: &str, options: &[Completable], subcommands: &[&Command]) -> String {
+    let escape = |text: &str| {
+        text.replace('\\', "\\\\")
+            .replace('\'', "'\\''")
+            .replace('[', "\\[")
+            .replace(']', "\\]")
+            .replace(':', "\\:")
+    };
+    let mut script = format!("#compdef {}\n\n_arguments -s \\\n", bin_name);
+    for option in options {
+        let action = if !option.takes_value {
+            String::new()
+        } else if !option.values.is_empty() {
+            format!(":value:({})", option.values.join(" "))
+        } else if option.path {
+            ":path:_files".to_string()
+        } else {
+            ":value:".to_string()
+        };
+        for flag in flags(option) {
+            let _ = writeln!(
+                script,
+                "    '{}[{}]{}' \\",
+                flag,
+                escape(&option.help),
+                action
+            );
+        }
+    }
+    let names: Vec<String> = subcommands
+        .iter()
+        .map(|s| {
+            format!(
+                "{}\\:{}",
+                s.get_name(),
+                escape(&s.get_about().map(|a| a.to_string()).unwrap_or_default())
+                    .replace(' ', "\\ ")
+            )
+        })
+        .collect();
+    if !names.is_empty() {
+        let _ = writeln!(script, "    '1::command:(({}))' \\", names.join(" "));
+    }
+    script.push_str("    '*:input:_files'\n");
+    script
+}
+
+fn fish(bin_name: &str, options: &[Completable], subcommands: &[&Command]) -> String {
+    let quote = |text: &str| format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"));
+    let mut script = String::new();
+    for subcommand in subcommands {
+        let _ = writeln!(
+            script,
+            "complete -c {} -n '__fish_use_subcommand' -a {} -d {}",
+            bin_name,
+            subcommand.get_name(),
+            quote(
+                &subcommand
+                    .get_about()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default()
+            )
+        );
+    }
+    for option in options {
+        let mut line = format!("complete -c {}", bin_name);
+        if let Some(short) = option.short {
+            let _ = write!(line, " -s {}", short);
+        }
+        if let Some(long) = &option.long {
+            let _ = write!(line, " -l {}", long);
+        }
+        if option.takes_value {
+            line.push_str(if option.path { " -r -F" } else { " -r" });
+        }
+        if !option.values.is_empty() {
+            let _ = write!(line, " -a {}", quote(&option.values.join(" ")));
+        }
+        let _ = write!(line, " -d {}", quote(&option.help));
+        script.push_str(&line);
+        script.push('\n');
+    }
+    script
+}
+
+fn powershell(bin_name: &str, options: &[Completable], subcommands: &[&Command]) -> String {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let mut script = format!(
+        "Register-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{\n    param($wordToComplete, $commandAst, $cursorPosition)\n    @(\n",
+        quote(bin_name)
+    );
+    let entries: Vec<(String, String)> = options
+        .iter()
+        .flat_map(|option| {
+            flags(option)
+                .into_iter()
+                .map(|flag| (flag, option.help.clone()))
+        })
+        .chain(subcommands.iter().map(|s| {
+            let about = s.get_about().map(|a| a.to_string()).unwrap_or_default();
+            (s.get_name().to_string(), about)
+        }))
+        .collect();
+    for (word, help) in entries {
+        let help = if help.is_empty() { &word } else { &help };
+        let _ = writeln!(
+            script,
+            "        [System.Management.Automation.CompletionResult]::new({}, {}, 'ParameterName', {})",
+            quote(&word),
+            quote(&word),
+            quote(help)
+        );
+    }
+    script.push_str("    ) | Where-Object { $_.CompletionText -like \"$wordToComplete*\" }\n}\n");
+    script
+}
@@ -0,0 +1,253 @@
+//! Compliance of an input with a broadcast delivery specification, checked
+//! by `check` over the ebur128 measurements rather than loudnorm's.
+
+use crate::{Error, Options, ProgressSpinner, Timeline};
+use serde::Serialize;
+use std::{fmt, io, path::Path};
+
+/// The limits of a delivery specification. Criteria a specification doesn't
+/// constrain are `None`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpecProfile {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub description: &'static str,
+    /// Integrated loudness target in LUFS.
+    pub integrated_loudness: f64,
+    /// Accepted deviation from `integrated_loudness` in LU.
+    pub tolerance: f64,
+    /// Maximum true peak in dBTP.
+    pub true_peak: f64,
+    /// Maximum momentary loudness in LUFS.
+    pub max_momentary: Option<f64>,
+    /// Maximum short-term loudness in LUFS.
+    pub max_short_term: Option<f64>,
+    /// Maximum loudness range in LU.
+    pub max_loudness_range: Option<f64>,
+}
+
+/// Built-in specifications, selectable with `--spec`.
+pub const SPEC_PROFILES: [SpecProfile; 4] = [
+    SpecProfile {
+        name: "ebu-r128",
+        aliases: &["r128"],
+        description: "EBU R128 programmes, -23 LUFS ±0.5 LU, -1 dBTP",
+        integrated_loudness: -23.0,
+        tolerance: 0.5,
+        true_peak: -1.0,
+        max_momentary: None,
+        max_short_term: None,
+        max_loudness_range: None,
+    },
+    SpecProfile {
+        name: "ebu-r128-s1",
+        aliases: &["r128-s1"],
+        description: "EBU R128 s1 advertisements and short-form, Max S -18 LUFS",
+        integrated_loudness: -23.0,
+        tolerance: 0.5,
+        true_peak: -1.0,
+        max_momentary: None,
+        max_short_term: Some(-18.0),
+        max_loudness_range: None,
+    },
+    SpecProfile {
+        name: "atsc-a85",
+        aliases: &["atsc"],
+        description: "ATSC A/85, -24 LKFS ±2 LU, -2 dBTP",
+        integrated_loudness: -24.0,
+        tolerance: 2.0,
+        true_peak: -2.0,
+        max_momentary: None,
+        max_short_term: None,
+        max_loudness_range: None,
+    },
+    SpecProfile {
+        name: "arib-tr-b32",
+        aliases: &["arib"],
+        description: "ARIB TR-B32, -24 LKFS ±1 LU, -1 dBTP",
+        integrated_loudness: -24.0,
+        tolerance: 1.0,
+        true_peak: -1.0,
+        max_momentary: None,
+        max_short_term: None,
+        max_loudness_range: None,
+    },
+];
+
+impl SpecProfile {
+    /// Looks up a built-in specification by name or alias.
+    pub fn find(name: &str) -> Option<SpecProfile> {
+        SPEC_PROFILES.into_iter().find(|p| {
+            p.name.eq_ignore_ascii_case(name)
+                || p.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// All names accepted by [`SpecProfile::find`].
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        SPEC_PROFILES
+            .iter()
+            .flat_map(|p| std::iter::once(p.name).chain(p.aliases.iter().copied()))
+    }
+}
+
+/// One measurement and the range a specification accepts for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Criterion {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub measured: f64,
+    /// Lowest accepted value, if any.
+    pub min: Option<f64>,
+    /// Highest accepted value, if any.
+    pub max: Option<f64>,
+    pub passed: bool,
+}
+
+impl Criterion {
+    fn new(
+        name: &'static str,
+        unit: &'static str,
+        measured: f64,
+        min: Option<f64>,
+        max: Option<f64>,
+    ) -> Self {
+        let passed = min.is_none_or(|min| measured >= min) && max.is_none_or(|max| measured <= max);
+        Self {
+            name,
+            unit,
+            measured,
+            min,
+            max,
+            passed,
+        }
+    }
+}
+
+/// The criteria of a specification, measured for one input.
+#[derive(Debug, Clone, Serialize)]
+pub struct Compliance {
+    pub spec: &'static str,
+    pub criteria: Vec<Criterion>,
+    /// Whether every criterion passed.
+    pub passed: bool,
+}
+
+impl Compliance {
+    /// Measures `input_path` with the ebur128 filter and compares it with
+    /// `spec`.
+    pub fn check(input_path: &Path, options: &Options, spec: &SpecProfile) -> io::Result<Self> {
+        let timeline =
+            ProgressSpinner::in_stage("Checking", || Timeline::measure(input_path, options))?;
+        Self::compare(&timeline, spec)
+    }
+
+    /// Compares the measurements of `timeline` with `spec`.
+    pub fn compare(timeline: &Timeline, spec: &SpecProfile) -> io::Result<Self> {
+        let missing = |what: &str| {
+            io::Error::from(Error::InvalidOutput {
+                message: format!("ebur128 reported no {}", what),
+                text: String::new(),
+            })
+        };
+        let integrated = timeline
+            .integrated
+            .or_else(|| timeline.points.last().map(|point| point.integrated))
+            .ok_or_else(|| missing("integrated loudness"))?;
+        let criteria = vec![
+            Criterion::new(
+                "Integrated loudness",
+                "LUFS",
+                integrated,
+                Some(spec.integrated_loudness - spec.tolerance),
+                Some(spec.integrated_loudness + spec.tolerance),
+            ),
+            Criterion::new(
+                "True peak",
+                "dBTP",
+                timeline
+                    .max_true_peak()
+                    .ok_or_else(|| missing("true peak"))?,
+                None,
+                Some(spec.true_peak),
+            ),
+            Criterion::new(
+                "Loudness range",
+                "LU",
+                timeline
+                    .loudness_range
+                    .ok_or_else(|| missing("loudness range"))?,
+                None,
+                spec.max_loudness_range,
+            ),
+            Criterion::new(
+                "Max momentary",
+                "LUFS",
+                timeline
+                    .max_momentary()
+                    .ok_or_else(|| missing("momentary loudness"))?,
+                None,
+                spec.max_momentary,
+            ),
+            Criterion::new(
+                "Max short-term",
+                "LUFS",
+                timeline
+                    .max_short_term()
+                    .ok_or_else(|| missing("short-term loudness"))?,
+                None,
+                spec.max_short_term,
+            ),
+        ];
+        Ok(Self {
+            spec: spec.name,
+            passed: criteria.iter().all(|criterion| criterion.passed),
+            criteria,
+        })
+    }
+
+    /// Fails with [`Error::NotCompliant`] naming the failed criteria when
+    /// the input didn't pass.
+    pub fn ensure_passed(&self) -> io::Result<()> {
+        if self.passed {
+            return Ok(());
+        }
+        let failed: Vec<_> = self
+            .criteria
+            .iter()
+            .filter(|criterion| !criterion.passed)
+            .map(|criterion| criterion.name.to_lowercase())
+            .collect();
+        Err(Error::NotCompliant(format!("{} ({})", self.spec, failed.join(", "))).into())
+    }
+}
+
+impl fmt::Display for Compliance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            self.spec,
+            if self.passed { "PASS" } else { "FAIL" }
+        )?;
+        for criterion in &self.criteria {
+            let limit = match (criterion.min, criterion.max) {
+                (Some(min), Some(max)) => format!("{:.1} to {:.1}", min, max),
+                (None, Some(max)) => format!("max {:.1}", max),
+                (Some(min), None) => format!("min {:.1}", min),
+                (None, None) => "-".to_string(),
+            };
+            let verdict = match (criterion.min, criterion.max, criterion.passed) {
+                (None, None, _) => "",
+                (_, _, true) => "PASS",
+                (_, _, false) => "FAIL",
+            };
+            let row = format!(
+                "  {:<21}{:>8.2} {:<5} {:>16}  {}",
+                criterion.name, criterion.measured, criterion.unit, limit, verdict
+            );
+            write!(f, "\n{}", row.trim_end())?;
+        }
+        Ok(())
+    }
+}
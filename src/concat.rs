@@ -0,0 +1,115 @@
+//! Inputs joined into one program with `--concat`, such as intro, episode
+//! and outro, so they are measured and normalized as a whole.
+
+use crate::{ffmpeg, MediaInfo, Options, ProgressSpinner};
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// The audio of several inputs decoded one after another into a temporary
+/// file, which is removed when this is dropped.
+///
+/// The parts are joined with ffmpeg's concat filter, which converts them to
+/// a common sample rate and channel layout, and kept as 32-bit float PCM so
+/// that nothing is lost before the loudness passes.
+pub struct ConcatBuffer {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl ConcatBuffer {
+    /// Joins the audio stream `options.audio_stream` of each of
+    /// `input_paths`, in order.
+    pub fn join(input_paths: &[PathBuf], options: &Options) -> io::Result<Self> {
+        let dir =
+            std::env::temp_dir().join(format!("ffmpeg-loudnorm-helper-{}-concat", process::id()));
+        fs::create_dir_all(&dir)?;
+        let buffer = Self {
+            path: dir.join("program.mka"),
+            dir,
+        };
+
+        // The progress of the join is over the length of all parts.
+        let mut duration = Some(0.0);
+        for input_path in input_paths {
+            let info = MediaInfo::probe(input_path, options)?;
+            info.audio_stream(options.audio_stream)?;
+            duration = duration
+                .zip(info.duration_of(options.audio_stream))
+                .map(|(a, b)| a + b);
+        }
+
+        let stream = options.audio_stream.unwrap_or(0);
+        let inputs: Vec<_> = input_paths
+            .iter()
+            .map(|input_path| ffmpeg::path_arg(input_path))
+            .collect();
+        let mut args: Vec<&OsStr> = Vec::new();
+        for (input_path, input) in input_paths.iter().zip(&inputs) {
+            args.extend(ffmpeg::protocol_args(input_path).iter().map(OsStr::new));
+            args.extend(["-i".as_ref(), &**input]);
+        }
+        let graph = format!(
+            "{}concat=n={}:v=0:a=1[program]",
+            (0..input_paths.len())
+                .map(|index| format!("[{}:a:{}]", index, stream))
+                .collect::<String>(),
+            input_paths.len()
+        );
+        args.extend(
+            [
+                "-hide_banner",
+                "-y",
+                "-filter_complex",
+                &graph,
+                "-map",
+                "[program]",
+                "-c:a",
+                "pcm_f32le",
+            ]
+            .map(OsStr::new),
+        );
+        args.push(buffer.path.as_os_str());
+
+        let spinner = ProgressSpinner::labeled("Joining");
+        let output = ffmpeg::run_with_progress(options, args, duration, &spinner, |_| false);
+        spinner.stop();
+        output?;
+        Ok(buffer)
+    }
+
+    /// Location of the joined program.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stands for the program joined from `input_paths` in messages and
+    /// reports: their paths joined with ` + `.
+    pub fn program_name(input_paths: &[PathBuf]) -> PathBuf {
+        PathBuf::from(
+            input_paths
+                .iter()
+                .map(|path| path.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" + "),
+        )
+    }
+
+    /// `options` for the joined program, which has the selected stream as
+    /// its only one.
+    pub fn program_options(options: &Options) -> Options {
+        Options {
+            audio_stream: None,
+            ..options.clone()
+        }
+    }
+}
+
+impl Drop for ConcatBuffer {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
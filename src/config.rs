@@ -0,0 +1,305 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// File name looked up in the working directory and the user config dir.
+pub const CONFIG_FILE_NAME: &str = "ffmpeg-loudnorm-helper.toml";
+
+/// A scalar or array value from the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    String(String),
+    Number(String),
+    Bool(bool),
+    Array(Vec<ConfigValue>),
+}
+
+impl ConfigValue {
+    /// Flattens the value into the strings a command line flag would take.
+    pub fn to_arg_values(&self) -> Vec<String> {
+        match self {
+            ConfigValue::String(s) | ConfigValue::Number(s) => vec![s.clone()],
+            ConfigValue::Bool(b) => vec![b.to_string()],
+            ConfigValue::Array(values) => values.iter().flat_map(Self::to_arg_values).collect(),
+        }
+    }
+}
+
+/// A parsed configuration file.
+///
+/// Only the subset of TOML needed for flat settings and simple tables is
+/// supported: `[table]` headers, `key = value` pairs, strings, numbers,
+/// booleans and single-line arrays.
+#[derive(Debug, Default, Clone)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    tables: BTreeMap<String, BTreeMap<String, ConfigValue>>,
+}
+
+impl ConfigFile {
+    /// Loads and parses the config file at `path`.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read config {}: {}", path.display(), e),
+            )
+        })?;
+        Self::parse(&contents)
+            .map(|tables| Self {
+                path: path.to_path_buf(),
+                tables,
+            })
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })
+    }
+
+    /// Finds the config file in the working directory or, failing that, the
+    /// user config directory.
+    pub fn discover() -> io::Result<Option<Self>> {
+        let candidates = [Some(PathBuf::from(CONFIG_FILE_NAME)), user_config_dir()]
+            .into_iter()
+            .flatten()
+            .map(|dir| {
+                if dir.ends_with(CONFIG_FILE_NAME) {
+                    dir
+                } else {
+                    dir.join(CONFIG_FILE_NAME)
+                }
+            });
+        for candidate in candidates {
+            if candidate.is_file() {
+                return Self::load(&candidate).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Key/value pairs outside of any `[table]`.
+    pub fn settings(&self) -> impl Iterator<Item = (&str, &ConfigValue)> {
+        self.table("")
+    }
+
+    /// Names of the custom presets declared as `[preset.NAME]` tables.
+    pub fn preset_names(&self) -> impl Iterator<Item = &str> {
+        self.tables
+            .keys()
+            .filter_map(|name| name.strip_prefix("preset."))
+    }
+
+    /// Settings of the custom preset `name`.
+    pub fn preset(&self, name: &str) -> impl Iterator<Item = (&str, &ConfigValue)> {
+        self.tables
+            .get(&format!("preset.{}", name))
+            .into_iter()
+            .flatten()
+            .map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Key/value pairs of the `[name]` table.
+    pub fn table(&self, name: &str) -> impl Iterator<Item = (&str, &ConfigValue)> {
+        self.tables
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|(k, v)| (k.as_str(), v))
+    }
+
+    fn parse(contents: &str) -> Result<BTreeMap<String, BTreeMap<String, ConfigValue>>, String> {
+        let mut tables = BTreeMap::new();
+        let mut current = String::new();
+        for (number, raw_line) in contents.lines().enumerate() {
+            let line = strip_comment(raw_line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: &str| format!("line {}: {}", number + 1, msg);
+            if let Some(header) = line.strip_prefix('[') {
+                current = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| err("unterminated table header"))?
+                    .trim()
+                    .to_string();
+                tables.entry(current.clone()).or_insert_with(BTreeMap::new);
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err("expected `key = value`"))?;
+            let key = key.trim().trim_matches('"').replace('-', "_");
+            let value = parse_value(value.trim()).map_err(|e| err(&e))?;
+            tables
+                .entry(current.clone())
+                .or_insert_with(BTreeMap::new)
+                .insert(key, value);
+        }
+        Ok(tables)
+    }
+}
+
+fn user_config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+fn strip_comment(line: &str) -> &str {
+    match unquoted(line).find(|&(_, c)| c == '#') {
+        Some((i, _)) => &line[..i],
+        None => line,
+    }
+}
+
+/// The characters of `s` outside quoted strings, quotes excluded, with
+/// their byte offsets. Basic strings may escape a quote with a backslash.
+fn unquoted(s: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quote = None;
+    let mut escaped = false;
+    s.char_indices().filter(move |&(_, c)| match quote {
+        Some(q) => {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && q == '"' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            false
+        }
+        None if c == '"' || c == '\'' => {
+            quote = Some(c);
+            false
+        }
+        None => true,
+    })
+}
+
+fn parse_value(value: &str) -> Result<ConfigValue, String> {
+    if let Some(inner) = value.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or("arrays must be closed on the same line")?;
+        return split_array(inner)
+            .into_iter()
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| parse_value(item.trim()))
+            .collect::<Result<_, _>>()
+            .map(ConfigValue::Array);
+    }
+    if let Some(inner) = value.strip_prefix('"') {
+        let mut escaped = false;
+        let end = inner.char_indices().find(|&(_, c)| {
+            let closes = c == '"' && !escaped;
+            escaped = c == '\\' && !escaped;
+            closes
+        });
+        return match end {
+            Some((i, _)) if i + 1 == inner.len() => Ok(ConfigValue::String(unescape(&inner[..i]))),
+            Some(_) => Err(format!("unexpected text after the string in `{}`", value)),
+            None => Err("unterminated string".to_string()),
+        };
+    }
+    if let Some(inner) = value.strip_prefix('\'') {
+        return inner
+            .strip_suffix('\'')
+            .map(|s| ConfigValue::String(s.to_string()))
+            .ok_or_else(|| "unterminated string".to_string());
+    }
+    match value {
+        "true" => Ok(ConfigValue::Bool(true)),
+        "false" => Ok(ConfigValue::Bool(false)),
+        _ => number(value)
+            .map(ConfigValue::Number)
+            .ok_or_else(|| format!("unsupported value `{}`", value)),
+    }
+}
+
+/// `value` as a number without its digit separators. As in TOML, each
+/// underscore must sit between two digits.
+fn number(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let separated = bytes.iter().enumerate().all(|(i, &b)| {
+        b != b'_'
+            || (i > 0
+                && bytes[i - 1].is_ascii_digit()
+                && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+    });
+    let number = value.replace('_', "");
+    (separated && number.parse::<f64>().is_ok()).then_some(number)
+}
+
+fn split_array(inner: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    for (i, _) in unquoted(inner).filter(|&(_, c)| c == ',') {
+        items.push(&inner[start..i]);
+        start = i + 1;
+    }
+    items.push(&inner[start..]);
+    items
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(line: &str) -> Result<ConfigValue, String> {
+        ConfigFile::parse(line).map(|mut tables| tables.remove("").unwrap().remove("key").unwrap())
+    }
+
+    #[test]
+    fn escaped_quotes_keep_comments_in_strings() {
+        assert_eq!(
+            value(r##"key = "a\"#b" # comment"##),
+            Ok(ConfigValue::String("a\"#b".to_string()))
+        );
+        assert_eq!(
+            value(r##"key = ["a\",#", 'b\'] # comment"##),
+            Ok(ConfigValue::Array(vec![
+                ConfigValue::String("a\",#".to_string()),
+                ConfigValue::String("b\\".to_string()),
+            ]))
+        );
+        assert!(value(r#"key = "a\""#).is_err());
+    }
+
+    #[test]
+    fn underscores_separate_digits() {
+        assert_eq!(
+            value("key = 1_000"),
+            Ok(ConfigValue::Number("1000".to_string()))
+        );
+        assert_eq!(
+            value("key = -2_3.5"),
+            Ok(ConfigValue::Number("-23.5".to_string()))
+        );
+        for number in ["1__0", "10_", "_10", "1_.5", "-_1"] {
+            assert!(value(&format!("key = {}", number)).is_err(), "{}", number);
+        }
+    }
+}
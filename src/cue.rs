@@ -0,0 +1,192 @@
+//! CUE sheets describing the tracks of a single-file disc image.
+
+use crate::Options;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// CUE timestamps count frames of 1/75 second.
+const FRAMES_PER_SECOND: f64 = 75.0;
+
+/// A track of a [`CueSheet`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// Position of `INDEX 01` in seconds.
+    pub start: f64,
+    /// Start of the next track in seconds, `None` for the last track.
+    pub end: Option<f64>,
+}
+
+impl CueTrack {
+    /// Length in seconds, `None` for the last track, which runs to the end
+    /// of the image.
+    pub fn duration(&self) -> Option<f64> {
+        self.end.map(|end| end - self.start)
+    }
+
+    /// `NN - Title.ext`, with characters that aren't allowed in file names
+    /// replaced.
+    pub fn file_name(&self, extension: &str) -> String {
+        let title = self
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("Track {:02}", self.number));
+        let title: String = title
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c => c,
+            })
+            .collect();
+        format!("{:02} - {}.{}", self.number, title.trim(), extension)
+    }
+}
+
+/// The disc described by a CUE sheet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// The image named by the `FILE` command, resolved against the
+    /// directory of the sheet.
+    pub file: Option<PathBuf>,
+    pub tracks: Vec<CueTrack>,
+}
+
+impl CueSheet {
+    /// Reads the sheet at `path`. Only the first `FILE` of the sheet is
+    /// considered; images split over several files are not supported.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        };
+        let contents = fs::read(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let contents = String::from_utf8_lossy(&contents);
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut sheet = CueSheet::default();
+        let mut files = 0;
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match command.to_ascii_uppercase().as_str() {
+                "FILE" => {
+                    files += 1;
+                    if files > 1 {
+                        return Err(invalid(
+                            "sheets with several FILEs are not supported".into(),
+                        ));
+                    }
+                    // The file type follows the possibly quoted name.
+                    let name = match rest.strip_prefix('"') {
+                        Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+                        None => rest.rsplit_once(' ').map_or(rest, |(name, _)| name),
+                    };
+                    sheet.file = Some(base.join(name));
+                }
+                "TRACK" => {
+                    let number = rest
+                        .split_whitespace()
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or_else(|| invalid(format!("bad TRACK on line {}", line_number + 1)))?;
+                    sheet.tracks.push(CueTrack {
+                        number,
+                        title: None,
+                        performer: None,
+                        start: f64::NAN,
+                        end: None,
+                    });
+                }
+                "TITLE" | "PERFORMER" => {
+                    let value = Some(unquote(rest));
+                    let is_title = command.eq_ignore_ascii_case("TITLE");
+                    match (sheet.tracks.last_mut(), is_title) {
+                        (Some(track), true) => track.title = value,
+                        (Some(track), false) => track.performer = value,
+                        (None, true) => sheet.title = value,
+                        (None, false) => sheet.performer = value,
+                    }
+                }
+                "INDEX" => {
+                    let mut parts = rest.split_whitespace();
+                    if parts.next().and_then(|n| n.parse::<u32>().ok()) != Some(1) {
+                        continue;
+                    }
+                    let start = parts
+                        .next()
+                        .and_then(parse_cue_time)
+                        .ok_or_else(|| invalid(format!("bad INDEX on line {}", line_number + 1)))?;
+                    if let Some(track) = sheet.tracks.last_mut() {
+                        track.start = start;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(track) = sheet.tracks.iter().find(|track| track.start.is_nan()) {
+            return Err(invalid(format!("track {} has no INDEX 01", track.number)));
+        }
+        if sheet.tracks.is_empty() {
+            return Err(invalid("no tracks".into()));
+        }
+        let starts: Vec<f64> = sheet.tracks.iter().map(|track| track.start).collect();
+        for (track, next) in sheet.tracks.iter_mut().zip(starts.iter().skip(1)) {
+            track.end = Some(*next);
+        }
+        Ok(sheet)
+    }
+
+    /// `options` cutting `track` out of the image and tagging it from the
+    /// sheet.
+    pub fn track_options(&self, track: &CueTrack, options: &Options) -> Options {
+        let mut options = Options {
+            start: Some(format!("{:.6}", track.start)),
+            duration: track.duration().map(|duration| format!("{:.6}", duration)),
+            cut: true,
+            ..options.clone()
+        };
+        let tags = [
+            ("title", track.title.clone()),
+            ("artist", track.performer.clone().or(self.performer.clone())),
+            ("album", self.title.clone()),
+            ("album_artist", self.performer.clone()),
+            (
+                "track",
+                Some(format!("{}/{}", track.number, self.tracks.len())),
+            ),
+        ];
+        options.encoding.metadata.extend(
+            tags.into_iter()
+                .filter_map(|(key, value)| Some((key.to_string(), value?))),
+        );
+        options
+    }
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+/// Parses a `MM:SS:FF` CUE timestamp into seconds.
+fn parse_cue_time(value: &str) -> Option<f64> {
+    let mut parts = value.split(':').map(|part| part.parse::<u32>().ok());
+    let (Some(Some(minutes)), Some(Some(seconds)), Some(Some(frames)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    Some(minutes as f64 * 60.0 + seconds as f64 + frames as f64 / FRAMES_PER_SECOND)
+}
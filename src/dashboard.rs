@@ -0,0 +1,575 @@
+//! The full-screen `--tui` dashboard of a batch: a table of the inputs with
+//! their state and measurements, throughput and an overall ETA, and keys to
+//! pause the batch and to skip or retry inputs.
+//!
+//! It is drawn with plain escape sequences on the alternate screen of
+//! stderr. Keys are read from the terminal, which `stty` puts into
+//! non-canonical mode on Unix; elsewhere the dashboard only shows progress.
+
+use crate::{interrupt, report::ReportRow, CancellationToken, TaskContext};
+use std::{
+    collections::VecDeque,
+    env,
+    fmt::Write as _,
+    io::{self, IsTerminal, Write as _},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How often the screen is redrawn.
+const FRAME_INTERVAL: Duration = Duration::from_millis(200);
+/// How often the terminal size is looked up again.
+const SIZE_INTERVAL: Duration = Duration::from_secs(1);
+/// Lines above and below the table: header, column titles and key help.
+const CHROME_LINES: usize = 4;
+
+/// State of an input of the batch.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Skipped,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Running => "running",
+            Status::Done => "done",
+            Status::Failed => "failed",
+            Status::Skipped => "skipped",
+        }
+    }
+}
+
+struct Row {
+    name: String,
+    status: Status,
+    stage: String,
+    fraction: f64,
+    started: Option<Instant>,
+    elapsed: Option<Duration>,
+    input_i: Option<f64>,
+    input_tp: Option<f64>,
+    input_lra: Option<f64>,
+    gain_db: Option<f64>,
+    /// The status of a finished input as the report has it, e.g. its error.
+    message: String,
+    /// Stops the running input when it is skipped.
+    cancel: Option<CancellationToken>,
+    skip_requested: bool,
+}
+
+struct State {
+    rows: Vec<Row>,
+    queue: VecDeque<usize>,
+    paused: bool,
+    quit: bool,
+    selected: usize,
+    scroll: usize,
+    jobs: usize,
+    started: Instant,
+}
+
+/// The dashboard and the queue of inputs it lets the workers take from.
+pub struct Dashboard {
+    state: Arc<(Mutex<State>, Condvar)>,
+    finished: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    terminal: TerminalMode,
+}
+
+impl Dashboard {
+    /// Takes over the terminal for a batch of the inputs `names`, processed
+    /// by `jobs` workers.
+    pub fn start(names: Vec<String>, jobs: usize) -> io::Result<Self> {
+        if !io::stderr().is_terminal() || env::var("TERM").is_ok_and(|t| t == "dumb") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "--tui needs a terminal on stderr",
+            ));
+        }
+        let rows = names
+            .into_iter()
+            .map(|name| Row {
+                name,
+                status: Status::Queued,
+                stage: String::new(),
+                fraction: 0.0,
+                started: None,
+                elapsed: None,
+                input_i: None,
+                input_tp: None,
+                input_lra: None,
+                gain_db: None,
+                message: String::new(),
+                cancel: None,
+                skip_requested: false,
+            })
+            .collect::<Vec<_>>();
+        let state = Arc::new((
+            Mutex::new(State {
+                queue: (0..rows.len()).collect(),
+                rows,
+                paused: false,
+                quit: false,
+                selected: 0,
+                scroll: 0,
+                jobs: jobs.max(1),
+                started: Instant::now(),
+            }),
+            Condvar::new(),
+        ));
+        let terminal = TerminalMode::enter();
+        if terminal.keys {
+            let state = Arc::clone(&state);
+            // Blocked on the terminal until the process exits.
+            thread::spawn(move || read_keys(&state));
+        }
+        let finished = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let state = Arc::clone(&state);
+            let finished = Arc::clone(&finished);
+            thread::spawn(move || {
+                let mut size = terminal_size();
+                let mut measured = Instant::now();
+                while !finished.load(Ordering::Acquire) {
+                    if measured.elapsed() >= SIZE_INTERVAL {
+                        size = terminal_size();
+                        measured = Instant::now();
+                    }
+                    if let Ok(mut state) = state.0.lock() {
+                        let frame = state.frame(size);
+                        let mut stderr = io::stderr().lock();
+                        let _ = write!(stderr, "\x1b[H\x1b[2J{}", frame);
+                        let _ = stderr.flush();
+                    }
+                    thread::sleep(FRAME_INTERVAL);
+                }
+            })
+        };
+        Ok(Self {
+            state,
+            finished,
+            handle: Some(handle),
+            terminal,
+        })
+    }
+
+    /// The next input for a worker to process, waiting while the batch is
+    /// paused, and once all are done for retries. `None` after quitting or
+    /// an interruption.
+    pub fn next(&self) -> Option<usize> {
+        let (lock, wake) = &*self.state;
+        let mut state = lock.lock().ok()?;
+        loop {
+            if state.quit || interrupt::is_interrupted() {
+                return None;
+            }
+            if !state.paused {
+                if let Some(index) = state.queue.pop_front() {
+                    let row = &mut state.rows[index];
+                    row.status = Status::Running;
+                    row.started = Some(Instant::now());
+                    row.stage.clear();
+                    row.fraction = 0.0;
+                    row.skip_requested = false;
+                    return Some(index);
+                }
+            }
+            // Interruptions don't notify, so they are polled for.
+            state = wake.wait_timeout(state, FRAME_INTERVAL).ok()?.0;
+        }
+    }
+
+    /// Runs `job` for the input at `index`, showing the progress ffmpeg
+    /// reports. Skipping the input cancels it.
+    pub fn track<T>(&self, index: usize, job: impl FnOnce() -> T) -> T {
+        let token = CancellationToken::new();
+        if let Ok(mut state) = self.state.0.lock() {
+            state.rows[index].cancel = Some(token.clone());
+        }
+        let state = Arc::clone(&self.state);
+        TaskContext::new()
+            .cancel_with(&token)
+            .on_progress(move |event| {
+                if let Ok(mut state) = state.0.lock() {
+                    let row = &mut state.rows[index];
+                    row.stage = event.stage;
+                    row.fraction = event.fraction.clamp(0.0, 1.0);
+                }
+            })
+            .run(job)
+    }
+
+    /// Whether the input at `index` was skipped while it ran, so its
+    /// outcome should be disregarded.
+    pub fn was_skipped(&self, index: usize) -> bool {
+        let (lock, wake) = &*self.state;
+        let Ok(mut state) = lock.lock() else {
+            return false;
+        };
+        let row = &mut state.rows[index];
+        row.cancel = None;
+        if !row.skip_requested {
+            return false;
+        }
+        row.status = Status::Skipped;
+        row.elapsed = row.started.map(|started| started.elapsed());
+        wake.notify_all();
+        true
+    }
+
+    /// Shows the outcome of the input at `index` from its report `row`.
+    pub fn finish(&self, index: usize, report: &ReportRow) {
+        let (lock, wake) = &*self.state;
+        if let Ok(mut state) = lock.lock() {
+            let row = &mut state.rows[index];
+            row.status = match report.status.as_str() {
+                "ok" | "already normalized" => Status::Done,
+                "declined" => Status::Skipped,
+                _ => Status::Failed,
+            };
+            row.elapsed = row.started.map(|started| started.elapsed());
+            row.input_i = report.input_i;
+            row.input_tp = report.input_tp;
+            row.input_lra = report.input_lra;
+            row.gain_db = report.gain_db;
+            row.message = match row.status {
+                Status::Done => String::new(),
+                _ => report.status.clone(),
+            };
+            row.cancel = None;
+            wake.notify_all();
+        }
+    }
+
+    /// Marks the input at `index` as skipped without processing it.
+    pub fn skip(&self, index: usize, reason: &str) {
+        let (lock, wake) = &*self.state;
+        if let Ok(mut state) = lock.lock() {
+            let row = &mut state.rows[index];
+            row.status = Status::Skipped;
+            row.message = reason.to_string();
+            wake.notify_all();
+        }
+    }
+
+    /// Marks the input at `index` as failed before it could be processed.
+    pub fn fail(&self, index: usize, message: &str) {
+        let (lock, wake) = &*self.state;
+        if let Ok(mut state) = lock.lock() {
+            let row = &mut state.rows[index];
+            row.status = Status::Failed;
+            row.message = message.to_string();
+            wake.notify_all();
+        }
+    }
+
+    /// Stops handing out inputs, e.g. after a failure with `--fail-fast`.
+    pub fn quit(&self) {
+        let (lock, wake) = &*self.state;
+        if let Ok(mut state) = lock.lock() {
+            state.quit = true;
+            wake.notify_all();
+        }
+    }
+}
+
+impl Drop for Dashboard {
+    /// Stops drawing and gives the terminal back.
+    fn drop(&mut self) {
+        self.finished.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        self.terminal.leave();
+    }
+}
+
+impl State {
+    /// The whole screen for a terminal of `(rows, columns)`.
+    fn frame(&mut self, (height, width): (usize, usize)) -> String {
+        let count = |status: Status| self.rows.iter().filter(|r| r.status == status).count();
+        let (queued, running, done, failed, skipped) = (
+            count(Status::Queued),
+            count(Status::Running),
+            count(Status::Done),
+            count(Status::Failed),
+            count(Status::Skipped),
+        );
+        let elapsed = self.started.elapsed();
+        let finished: Vec<Duration> = self.rows.iter().filter_map(|r| r.elapsed).collect();
+        let per_minute = finished.len() as f64 / (elapsed.as_secs_f64() / 60.0).max(1.0 / 60.0);
+        let eta = (!finished.is_empty() && queued + running > 0).then(|| {
+            let average = finished.iter().sum::<Duration>() / finished.len() as u32;
+            average * (queued + running) as u32 / self.jobs as u32
+        });
+        let state = if self.quit {
+            "QUITTING"
+        } else if self.paused {
+            "PAUSED"
+        } else if queued + running == 0 {
+            "FINISHED"
+        } else {
+            "RUNNING"
+        };
+
+        let mut frame = String::new();
+        let header = format!(
+            "{}  {}/{} done  {} running  {} failed  {} skipped  {:.1} files/min  elapsed {}  ETA {}",
+            state,
+            done,
+            self.rows.len(),
+            running,
+            failed,
+            skipped,
+            per_minute,
+            format_clock(elapsed),
+            eta.map_or_else(|| "--:--:--".to_string(), format_clock)
+        );
+        let _ = writeln!(frame, "\x1b[1m{}\x1b[0m\r", clip(&header, width));
+        let name_width = width.saturating_sub(68).clamp(16, 60);
+        let titles = format!(
+            "  {:<8} {:<name_width$} {:<18} {:>7} {:>7} {:>6} {:>7}  {}",
+            "STATUS", "FILE", "STAGE", "I", "TP", "LRA", "GAIN", "TIME"
+        );
+        let _ = writeln!(frame, "\x1b[7m{:<width$}\x1b[0m\r", clip(&titles, width));
+
+        let visible = height.saturating_sub(CHROME_LINES).max(1);
+        self.selected = self.selected.min(self.rows.len().saturating_sub(1));
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + visible {
+            self.scroll = self.selected + 1 - visible;
+        }
+        let number = |value: Option<f64>, sign: bool| match value {
+            Some(value) if sign => format!("{:+.1}", value),
+            Some(value) => format!("{:.1}", value),
+            None => String::new(),
+        };
+        for (index, row) in self.rows.iter().enumerate().skip(self.scroll).take(visible) {
+            let stage = match row.status {
+                Status::Running => format!("{} {:5.1}%", row.stage, row.fraction * 100.0),
+                Status::Failed | Status::Skipped => row.message.clone(),
+                _ => String::new(),
+            };
+            let time = row
+                .elapsed
+                .or_else(|| row.started.map(|started| started.elapsed()))
+                .map_or_else(String::new, format_clock);
+            let line = format!(
+                "{} {:<8} {:<name_width$} {:<18} {:>7} {:>7} {:>6} {:>7}  {}",
+                if index == self.selected { ">" } else { " " },
+                row.status.label(),
+                shorten(&row.name, name_width),
+                clip(&stage, 18),
+                number(row.input_i, false),
+                number(row.input_tp, false),
+                number(row.input_lra, false),
+                number(row.gain_db, true),
+                time
+            );
+            let line = clip(&line, width);
+            if index == self.selected {
+                let _ = writeln!(frame, "\x1b[1m{}\x1b[0m\r", line);
+            } else {
+                let _ = writeln!(frame, "{}\r", line);
+            }
+        }
+        let help = "p pause/resume  s skip  r retry  up/down/PgUp/PgDn select  q quit";
+        let _ = write!(
+            frame,
+            "\x1b[{};1H\x1b[2m{}\x1b[0m",
+            height,
+            clip(help, width)
+        );
+        frame
+    }
+
+    /// Handles one key press.
+    fn key(&mut self, key: Key) {
+        let last = self.rows.len().saturating_sub(1);
+        match key {
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => self.selected = (self.selected + 1).min(last),
+            Key::PageUp => self.selected = self.selected.saturating_sub(10),
+            Key::PageDown => self.selected = (self.selected + 10).min(last),
+            Key::Char('p') => self.paused = !self.paused,
+            Key::Char('q') => self.quit = true,
+            Key::Char('s') => {
+                let index = self.selected;
+                let Some(row) = self.rows.get_mut(index) else {
+                    return;
+                };
+                match row.status {
+                    Status::Queued => {
+                        row.status = Status::Skipped;
+                        self.queue.retain(|&queued| queued != index);
+                    }
+                    Status::Running => {
+                        row.skip_requested = true;
+                        if let Some(cancel) = &row.cancel {
+                            cancel.cancel();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Key::Char('r') => {
+                let index = self.selected;
+                let Some(row) = self.rows.get_mut(index) else {
+                    return;
+                };
+                if matches!(row.status, Status::Failed | Status::Skipped) {
+                    row.status = Status::Queued;
+                    row.elapsed = None;
+                    row.started = None;
+                    self.queue.push_back(index);
+                }
+            }
+            Key::Char(_) => {}
+        }
+    }
+}
+
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Char(char),
+}
+
+/// Reads key presses from the terminal until it closes, handing them to
+/// `state` and waking the workers.
+fn read_keys(state: &(Mutex<State>, Condvar)) {
+    use std::io::Read;
+    let Ok(mut tty) = std::fs::File::open("/dev/tty") else {
+        return;
+    };
+    let mut byte = [0u8; 1];
+    let mut next = |tty: &mut std::fs::File| {
+        tty.read(&mut byte)
+            .ok()
+            .filter(|&n| n == 1)
+            .map(|_| byte[0])
+    };
+    while let Some(first) = next(&mut tty) {
+        let key = match first {
+            0x1b => match (next(&mut tty), next(&mut tty)) {
+                (Some(b'['), Some(b'A')) => Key::Up,
+                (Some(b'['), Some(b'B')) => Key::Down,
+                (Some(b'['), Some(b'5')) => {
+                    next(&mut tty);
+                    Key::PageUp
+                }
+                (Some(b'['), Some(b'6')) => {
+                    next(&mut tty);
+                    Key::PageDown
+                }
+                _ => continue,
+            },
+            b'k' => Key::Up,
+            b'j' => Key::Down,
+            other => Key::Char(char::from(other).to_ascii_lowercase()),
+        };
+        let (lock, wake) = state;
+        if let Ok(mut state) = lock.lock() {
+            state.key(key);
+            wake.notify_all();
+        }
+    }
+}
+
+/// The terminal switched to the alternate screen, and on Unix to
+/// unbuffered, unechoed input, as it was before [`TerminalMode::enter`].
+struct TerminalMode {
+    /// The `stty -g` settings to restore.
+    saved: Option<String>,
+    /// Whether key presses can be read.
+    keys: bool,
+}
+
+impl TerminalMode {
+    fn enter() -> Self {
+        let saved = stty(&["-g"]).filter(|_| stty(&["-icanon", "-echo", "min", "1"]).is_some());
+        eprint!("\x1b[?1049h\x1b[?25l");
+        Self {
+            keys: saved.is_some(),
+            saved,
+        }
+    }
+
+    fn leave(&mut self) {
+        eprint!("\x1b[?25h\x1b[?1049l");
+        if let Some(saved) = self.saved.take() {
+            stty(&[saved.as_str()]);
+        }
+    }
+}
+
+/// Runs `stty` on the controlling terminal, returning what it printed.
+fn stty(args: &[&str]) -> Option<String> {
+    if !cfg!(unix) {
+        return None;
+    }
+    let tty = std::fs::File::open("/dev/tty").ok()?;
+    let output = std::process::Command::new("stty")
+        .args(args)
+        .stdin(tty)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Rows and columns of the terminal, from `stty size` or else `LINES` and
+/// `COLUMNS`.
+fn terminal_size() -> (usize, usize) {
+    let from_stty = stty(&["size"]).and_then(|size| {
+        let (rows, columns) = size.split_once(' ')?;
+        Some((rows.parse().ok()?, columns.parse().ok()?))
+            .filter(|&(rows, columns)| rows > 0 && columns > 0)
+    });
+    from_stty.unwrap_or_else(|| {
+        let read = |name: &str, default: usize| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        (read("LINES", 24), read("COLUMNS", 80))
+    })
+}
+
+/// `duration` as `HH:MM:SS`.
+fn format_clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// `text` cut to `width` characters.
+fn clip(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+/// `name` shortened from the front to `width` characters.
+fn shorten(name: &str, width: usize) -> String {
+    let length = name.chars().count();
+    if length <= width {
+        return name.to_string();
+    }
+    let tail: String = name.chars().skip(length + 1 - width).collect();
+    format!("…{}", tail)
+}
@@ -0,0 +1,236 @@
+//! Checks of the environment a run depends on, run by `doctor`: the ffmpeg
+//! and ffprobe binaries, the filters and encoders of the ffmpeg build, and
+//! the directories results are written to.
+
+use crate::{ffmpeg, Options};
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    env, fs, io,
+    path::Path,
+    process::{self, Command as ProcessCommand},
+};
+
+/// Filters the measurement and normalization passes can't do without.
+const REQUIRED_FILTERS: [&str; 4] = ["loudnorm", "ebur128", "alimiter", "astats"];
+
+/// Encoders of the default WAV outputs, at 16 and 24 bits.
+const REQUIRED_ENCODERS: [&str; 2] = ["pcm_s16le", "pcm_s24le"];
+
+/// Where to get an ffmpeg build that has everything.
+const INSTALL_HINT: &str = "install ffmpeg with your package manager (apt install ffmpeg, brew install ffmpeg, winget install ffmpeg) or from https://ffmpeg.org/download.html, or run with --auto-download-ffmpeg";
+
+/// Outcome of one check of [`Doctor::run`].
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub passed: bool,
+    /// What was found, or what is wrong.
+    pub detail: String,
+    /// What to do about a failed check.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl DoctorCheck {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed: true,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed: false,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Looks for the problems with the installation that make runs fail before
+/// any input is read.
+pub struct Doctor;
+
+impl Doctor {
+    /// Runs every check for the ffmpeg, cache and history selected by
+    /// `options`, and for `output_dir` if there is one.
+    pub fn run(options: &Options, output_dir: Option<&Path>) -> Vec<DoctorCheck> {
+        let mut checks = Vec::new();
+        let ffmpeg_found = match ffmpeg::ffmpeg_command(options) {
+            Ok(mut command) => {
+                let check = Self::version("ffmpeg", command.arg("-version"));
+                let found = check.passed;
+                checks.push(check);
+                found
+            }
+            Err(e) => {
+                checks.push(DoctorCheck::fail("ffmpeg", e.to_string(), INSTALL_HINT));
+                false
+            }
+        };
+        checks.push(match ffmpeg::ffprobe_command(options) {
+            Ok(mut command) => Self::version("ffprobe", command.arg("-version")),
+            Err(e) => DoctorCheck::fail(
+                "ffprobe",
+                e.to_string(),
+                "ffprobe ships with ffmpeg; install a full ffmpeg package or put ffprobe next to the ffmpeg binary",
+            ),
+        });
+        if ffmpeg_found {
+            checks.push(Self::components(
+                options,
+                "filters",
+                "-filters",
+                &REQUIRED_FILTERS,
+            ));
+            let mut encoders: Vec<&str> = REQUIRED_ENCODERS.to_vec();
+            if let Some(codec) = options
+                .encoding
+                .codec
+                .as_deref()
+                .filter(|&codec| codec != "copy")
+            {
+                encoders.push(codec);
+            }
+            checks.push(Self::components(
+                options,
+                "encoders",
+                "-encoders",
+                &encoders,
+            ));
+        }
+
+        let mut dirs = vec![("temporary directory", env::temp_dir())];
+        if let Some(dir) = output_dir {
+            dirs.push(("output directory", dir.to_path_buf()));
+        }
+        if let Some(dir) = &options.cache_dir {
+            dirs.push(("cache directory", dir.clone()));
+        }
+        if let Some(dir) = options
+            .history_db
+            .as_deref()
+            .and_then(Path::parent)
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            dirs.push(("history directory", dir.to_path_buf()));
+        }
+        checks.extend(
+            dirs.into_iter()
+                .map(|(name, dir)| Self::writable(name, &dir)),
+        );
+        checks
+    }
+
+    /// Runs `command`, a binary with `-version`, and reports the version
+    /// from the first line of its output.
+    fn version(name: &str, command: &mut ProcessCommand) -> DoctorCheck {
+        let output = match ffmpeg::output(command) {
+            Ok(output) if output.status.success() => output,
+            Ok(output) => {
+                return DoctorCheck::fail(
+                    name,
+                    format!("`{} -version` failed with {}", name, output.status),
+                    format!("the {} binary seems broken; {}", name, INSTALL_HINT),
+                )
+            }
+            Err(e) => {
+                return DoctorCheck::fail(
+                    name,
+                    e.to_string(),
+                    format!("check that {} is executable; {}", name, INSTALL_HINT),
+                )
+            }
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let line = stdout.lines().next().unwrap_or_default();
+        let version = line.split(" Copyright").next().unwrap_or(line).trim();
+        DoctorCheck::pass(
+            name,
+            format!("{} ({})", version, command.get_program().to_string_lossy()),
+        )
+    }
+
+    /// Lists the components ffmpeg prints for `flag`, `-filters` or
+    /// `-encoders`, and checks that each of `required` is among them.
+    fn components(options: &Options, name: &str, flag: &str, required: &[&str]) -> DoctorCheck {
+        let listed = ffmpeg::ffmpeg_command(options)
+            .and_then(|mut command| ffmpeg::output(command.args(["-hide_banner", flag])));
+        let listed = match listed {
+            Ok(output) => compiled_in(&String::from_utf8_lossy(&output.stdout)),
+            Err(e) => {
+                return DoctorCheck::fail(
+                    name,
+                    format!("`ffmpeg {}` failed: {}", flag, e),
+                    INSTALL_HINT,
+                )
+            }
+        };
+        let missing: Vec<&str> = required
+            .iter()
+            .copied()
+            .filter(|component| !listed.contains(*component))
+            .collect();
+        if missing.is_empty() {
+            DoctorCheck::pass(name, required.join(", "))
+        } else {
+            DoctorCheck::fail(
+                name,
+                format!("missing {}", missing.join(", ")),
+                format!(
+                    "this ffmpeg build was configured without them; {}",
+                    INSTALL_HINT
+                ),
+            )
+        }
+    }
+
+    /// Checks that a file can be created in `dir`, or in the directory it
+    /// would be created in when it doesn't exist yet.
+    fn writable(name: &str, dir: &Path) -> DoctorCheck {
+        // A relative path without an existing ancestor is created in the
+        // working directory.
+        let existing = dir
+            .ancestors()
+            .find(|ancestor| ancestor.is_dir())
+            .unwrap_or(Path::new("."));
+        let probe = existing.join(format!(".ffmpeg-normalize-doctor-{}", process::id()));
+        let written = fs::write(&probe, b"").and_then(|()| fs::remove_file(&probe));
+        let detail = if existing == dir {
+            dir.display().to_string()
+        } else {
+            format!("{} (created in {})", dir.display(), existing.display())
+        };
+        match written {
+            Ok(()) => DoctorCheck::pass(name, detail),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => DoctorCheck::fail(
+                name,
+                format!("{}: not writable", detail),
+                format!(
+                    "grant write access to {} or choose another directory",
+                    existing.display()
+                ),
+            ),
+            Err(e) => DoctorCheck::fail(
+                name,
+                format!("{}: {}", detail, e),
+                "choose another directory",
+            ),
+        }
+    }
+}
+
+/// The names ffmpeg lists in `-filters` or `-encoders` output, the word
+/// after the capability flags on each line.
+fn compiled_in(listing: &str) -> BTreeSet<String> {
+    listing
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect()
+}
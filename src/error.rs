@@ -0,0 +1,155 @@
+use crate::interrupt;
+use std::{fmt, io, process::Output, time::Duration};
+
+/// Lines of ffmpeg's stderr kept in [`Error::ProcessFailed`].
+const STDERR_TAIL_LINES: usize = 10;
+
+/// Categories of failure that callers may want to tell apart.
+///
+/// The library keeps returning `io::Result`; these travel inside the
+/// `io::Error` and can be recovered with [`Error::of`].
+#[derive(Debug)]
+pub enum Error {
+    /// ffmpeg or ffprobe could not be located.
+    BinaryNotFound(String),
+    /// ffmpeg or ffprobe exited unsuccessfully.
+    ProcessFailed {
+        program: &'static str,
+        status: Option<i32>,
+        /// The last lines ffmpeg printed to stderr.
+        stderr: String,
+    },
+    /// ffmpeg made no progress for the given time and was killed.
+    Stalled(Duration),
+    /// ffmpeg or ffprobe printed something that could not be parsed.
+    InvalidOutput { message: String, text: String },
+    /// The input has no audio stream, or not the requested one.
+    NoAudioStream(String),
+    /// The input is silent, or too quiet for loudnorm to measure.
+    Silent,
+    /// The normalized output missed the targets when measured again.
+    NotCompliant(String),
+    /// Stopped by Ctrl+C or a termination request.
+    Interrupted,
+}
+
+impl Error {
+    /// The category carried by `error`, if it has one.
+    pub fn of(error: &io::Error) -> Option<&Error> {
+        error.get_ref()?.downcast_ref()
+    }
+
+    /// Short name of this category for failure summaries.
+    pub fn category(&self) -> &'static str {
+        match self {
+            Error::BinaryNotFound(_) => "missing binary",
+            Error::ProcessFailed { .. } => "ffmpeg failed",
+            Error::Stalled(_) => "stalled",
+            Error::InvalidOutput { .. } => "unparsable output",
+            Error::NoAudioStream(_) => "no audio stream",
+            Error::Silent => "silent",
+            Error::NotCompliant(_) => "failed verification",
+            Error::Interrupted => "interrupted",
+        }
+    }
+
+    /// Process exit code for this category. 1 is left for other failures
+    /// and 2 for usage errors.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::BinaryNotFound(_) => 3,
+            Error::ProcessFailed { .. } | Error::Stalled(_) => 4,
+            Error::InvalidOutput { .. } => 5,
+            Error::NoAudioStream(_) => 6,
+            Error::Silent => 7,
+            Error::NotCompliant(_) => 8,
+            Error::Interrupted => 130,
+        }
+    }
+
+    /// A [`Error::ProcessFailed`] for a finished `program`, or
+    /// [`Error::Interrupted`] when it failed because the user stopped it.
+    pub(crate) fn process_failed(program: &'static str, output: &Output) -> Self {
+        Self::process_failed_with(
+            program,
+            output.status.code(),
+            &String::from_utf8_lossy(&output.stderr),
+        )
+    }
+
+    /// A [`Error::ProcessFailed`] keeping the tail of `stderr`.
+    pub(crate) fn process_failed_with(
+        program: &'static str,
+        status: Option<i32>,
+        stderr: &str,
+    ) -> Self {
+        if interrupt::is_interrupted() {
+            return Error::Interrupted;
+        }
+        let lines: Vec<&str> = stderr.lines().filter(|l| !l.trim().is_empty()).collect();
+        let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+        Error::ProcessFailed {
+            program,
+            status,
+            stderr: tail,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotCompliant(message) => write!(f, "Output failed verification: {}", message),
+            Error::BinaryNotFound(message) | Error::NoAudioStream(message) => {
+                write!(f, "{}", message)
+            }
+            Error::ProcessFailed {
+                program,
+                status,
+                stderr,
+            } => {
+                match status {
+                    Some(code) => write!(f, "{} exited with status {}", program, code)?,
+                    None => write!(f, "{} was terminated by a signal", program)?,
+                }
+                if !stderr.is_empty() {
+                    write!(f, ":\n{}", stderr)?;
+                }
+                Ok(())
+            }
+            Error::Silent => write!(
+                f,
+                "Input is silent or below the -70 LUFS measurement gate; skipped"
+            ),
+            Error::Interrupted => write!(f, "Interrupted"),
+            Error::Stalled(timeout) => write!(
+                f,
+                "ffmpeg made no progress for {}s and was stopped",
+                timeout.as_secs_f64()
+            ),
+            Error::InvalidOutput { message, text } => {
+                write!(f, "{}", message)?;
+                if !text.trim().is_empty() {
+                    write!(f, "; the output was:\n{}", text.trim())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        let kind = match error {
+            Error::BinaryNotFound(_) => io::ErrorKind::NotFound,
+            Error::ProcessFailed { .. } | Error::NotCompliant(_) => io::ErrorKind::Other,
+            Error::InvalidOutput { .. } => io::ErrorKind::InvalidData,
+            Error::NoAudioStream(_) | Error::Silent => io::ErrorKind::InvalidInput,
+            Error::Interrupted => io::ErrorKind::Interrupted,
+            Error::Stalled(_) => io::ErrorKind::TimedOut,
+        };
+        io::Error::new(kind, error)
+    }
+}
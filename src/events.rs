@@ -0,0 +1,138 @@
+//! JSON lines on stdout describing the progress of a run, printed with
+//! `--progress-format jsonl` for frontends that draw their own progress.
+
+use crate::{ProgressEvent, TaskContext};
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Serialize)]
+struct Progress<'a> {
+    event: String,
+    file: &'a str,
+    percent: f64,
+    /// Estimated seconds until the pass finishes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eta: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct PassComplete<'a> {
+    event: &'static str,
+    file: &'a str,
+    pass: Cow<'a, str>,
+}
+
+#[derive(Serialize)]
+struct FileDone<'a, T> {
+    event: &'static str,
+    file: Cow<'a, str>,
+    status: &'a str,
+    result: &'a T,
+}
+
+#[derive(Serialize)]
+struct Failed<'a> {
+    event: &'static str,
+    file: Cow<'a, str>,
+    message: &'a str,
+}
+
+/// Runs `job` for `input_path`, printing its progress and the end of each
+/// pass as events.
+pub fn track<T>(input_path: &Path, job: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    let tracker = Arc::new(Tracker {
+        file: input_path.to_string_lossy().into_owned(),
+        stage: Mutex::new(None),
+    });
+    let callback = Arc::clone(&tracker);
+    let outcome = TaskContext::new()
+        .on_progress(move |event| callback.progress(event))
+        .run(job);
+    if outcome.is_ok() {
+        tracker.complete();
+    }
+    outcome
+}
+
+/// Prints that `input_path` is done, with its `status` and `result`.
+pub fn file_done(input_path: &Path, status: &str, result: &impl Serialize) {
+    emit(&FileDone {
+        event: "file_done",
+        file: input_path.to_string_lossy(),
+        status,
+        result,
+    });
+}
+
+/// Prints that `input_path` failed with `message`.
+pub fn error(input_path: &Path, message: &str) {
+    emit(&Failed {
+        event: "error",
+        file: input_path.to_string_lossy(),
+        message,
+    });
+}
+
+/// The passes of one input, completed whenever the next one starts.
+struct Tracker {
+    file: String,
+    stage: Mutex<Option<String>>,
+}
+
+impl Tracker {
+    fn progress(&self, event: ProgressEvent) {
+        let Ok(mut stage) = self.stage.lock() else {
+            return;
+        };
+        if stage.as_deref() != Some(event.stage.as_str()) {
+            if let Some(previous) = stage.take() {
+                self.emit_complete(&previous);
+            }
+            *stage = Some(event.stage.clone());
+        }
+        emit(&Progress {
+            event: format!("{}_progress", pass_name(&event.stage)),
+            file: &self.file,
+            percent: (event.fraction * 100.0).clamp(0.0, 100.0),
+            eta: event.eta.map(|eta| eta.as_secs_f64()),
+            speed: event.speed,
+        });
+    }
+
+    fn complete(&self) {
+        if let Some(stage) = self.stage.lock().ok().and_then(|mut stage| stage.take()) {
+            self.emit_complete(&stage);
+        }
+    }
+
+    fn emit_complete(&self, stage: &str) {
+        emit(&PassComplete {
+            event: "pass_complete",
+            file: &self.file,
+            pass: pass_name(stage),
+        });
+    }
+}
+
+/// `pass1` for measuring, `pass2` for encoding, and the stage in snake case
+/// for anything else.
+fn pass_name(stage: &str) -> Cow<'_, str> {
+    match stage {
+        "Measuring" => "pass1".into(),
+        "Encoding" => "pass2".into(),
+        _ => stage.to_lowercase().replace(' ', "_").into(),
+    }
+}
+
+fn emit(event: &impl Serialize) {
+    if let Ok(line) = serde_json::to_string(event) {
+        println!("{}", line);
+    }
+}
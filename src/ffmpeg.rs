@@ -0,0 +1,591 @@
+use crate::{
+    interrupt::ChildGuard,
+    logging::{self, Level},
+    remote, Error, Options, ProgressSpinner, Shell, TaskContext,
+};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    env,
+    ffi::{OsStr, OsString},
+    io::{self, BufRead, BufReader},
+    path::{Component, Path, PathBuf},
+    process::{Child, Command as ProcessCommand, ExitStatus, Output, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Lines kept from the end of a pass's stderr, for error messages.
+const CAPTURED_TAIL_LINES: usize = 100;
+
+/// Most lines kept of a loudnorm summary, whose JSON object takes 13.
+const CAPTURED_SUMMARY_LINES: usize = 64;
+
+/// How often a pass with a timeout checks on ffmpeg.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Programs [`ffmpeg_command`] runs ffmpeg through to lower its priority.
+const PRIORITY_WRAPPERS: [&str; 2] = ["nice", "ionice"];
+
+/// Builds a command for the ffmpeg binary selected by `options`, run
+/// through `nice` and `ionice` for `options.nice` and `options.idle_io`.
+pub(crate) fn ffmpeg_command(options: &Options) -> io::Result<ProcessCommand> {
+    let ffmpeg = resolve_binary(
+        "ffmpeg",
+        options.ffmpeg_path.as_deref(),
+        &["FFMPEG_PATH", "FFMPEG_BINARY"],
+    )?;
+    let mut words: Vec<OsString> = Vec::new();
+    if let Some(level) = options.nice {
+        words.extend(["nice".into(), "-n".into(), level.to_string().into()]);
+    }
+    if options.idle_io {
+        words.extend(["ionice", "-c", "3"].map(OsString::from));
+    }
+    words.push(ffmpeg.into_os_string());
+    let mut command = ProcessCommand::new(&words[0]);
+    command.args(&words[1..]);
+    Ok(command)
+}
+
+/// Builds a command for ffprobe, preferring the one installed next to the
+/// selected ffmpeg binary.
+pub(crate) fn ffprobe_command(options: &Options) -> io::Result<ProcessCommand> {
+    let sibling = resolve_binary(
+        "ffmpeg",
+        options.ffmpeg_path.as_deref(),
+        &["FFMPEG_PATH", "FFMPEG_BINARY"],
+    )
+    .ok()
+    .and_then(|ffmpeg| {
+        binary_names("ffprobe")
+            .into_iter()
+            .map(|name| ffmpeg.with_file_name(name))
+            .find(|ffprobe| ffprobe.is_file())
+    });
+    sibling
+        .map_or_else(|| resolve_binary("ffprobe", None, &["FFPROBE_PATH"]), Ok)
+        .map(ProcessCommand::new)
+}
+
+/// Runs `command` to completion like [`ProcessCommand::output`], while
+/// letting an interruption reach the child.
+pub(crate) fn output(command: &mut ProcessCommand) -> io::Result<Output> {
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    log_command(command);
+    let started = Instant::now();
+    let child = command.spawn()?;
+    let _guard = ChildGuard::register(child.id());
+    let output = child.wait_with_output()?;
+    log_finished(command, started, output.status);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && !stderr.trim().is_empty() {
+        logging::debug(format_args!(
+            "{} stderr:\n{}",
+            program_name(command),
+            stderr.trim_end()
+        ));
+    }
+    Ok(output)
+}
+
+/// Logs the command line of `command` at debug level.
+fn log_command(command: &ProcessCommand) {
+    if logging::enabled(Level::Debug) {
+        logging::debug(format_args!(
+            "$ {}",
+            command_line(command, Shell::default())
+        ));
+    }
+}
+
+/// The program and arguments of `command`, quoted for `shell`.
+pub(crate) fn command_line(command: &ProcessCommand, shell: Shell) -> String {
+    shell.command_line(command.get_program(), command.get_args())
+}
+
+/// Logs how long `command` ran.
+fn log_finished(command: &ProcessCommand, started: Instant, status: ExitStatus) {
+    logging::debug(format_args!(
+        "{} finished in {:.2}s ({})",
+        program_name(command),
+        started.elapsed().as_secs_f64(),
+        status
+    ));
+}
+
+/// The name of the program `command` runs, looking past the
+/// [`PRIORITY_WRAPPERS`].
+fn program_name(command: &ProcessCommand) -> Cow<'_, str> {
+    match wrapped_program(command) {
+        Some(position) => command
+            .get_args()
+            .nth(position)
+            .map(stem)
+            .unwrap_or_default(),
+        None => stem(command.get_program()),
+    }
+}
+
+/// The arguments of a command from [`ffmpeg_command`] that go to ffmpeg,
+/// after the [`PRIORITY_WRAPPERS`] and their options.
+pub(crate) fn ffmpeg_args(command: &ProcessCommand) -> impl Iterator<Item = &OsStr> {
+    let skipped = wrapped_program(command).map_or(0, |position| position + 1);
+    command.get_args().skip(skipped)
+}
+
+/// The position among the arguments of `command` of the program the
+/// [`PRIORITY_WRAPPERS`] run, or `None` when it runs no wrapper.
+fn wrapped_program(command: &ProcessCommand) -> Option<usize> {
+    if !PRIORITY_WRAPPERS.contains(&&*stem(command.get_program())) {
+        return None;
+    }
+    command.get_args().map(stem).position(|word| {
+        !word.starts_with('-')
+            && word.parse::<i32>().is_err()
+            && !PRIORITY_WRAPPERS.contains(&&*word)
+    })
+}
+
+fn stem(word: &OsStr) -> Cow<'_, str> {
+    Path::new(word)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+}
+
+/// Locates `name`, checking in order an explicit path, the environment
+/// variables in `env_vars` and finally `PATH`. Explicit locations may name
+/// either the binary itself or the directory containing it.
+fn resolve_binary(name: &str, explicit: Option<&Path>, env_vars: &[&str]) -> io::Result<PathBuf> {
+    let configured = explicit
+        .map(|path| (path.to_path_buf(), "--ffmpeg-path".to_string()))
+        .or_else(|| {
+            env_vars.iter().find_map(|var| {
+                env::var_os(var)
+                    .filter(|value| !value.is_empty())
+                    .map(|value| (PathBuf::from(value), var.to_string()))
+            })
+        });
+
+    match configured {
+        Some((path, source)) => {
+            let path = if path.is_dir() {
+                let candidates = binary_names(name).into_iter().map(|name| path.join(name));
+                let mut candidates = candidates.peekable();
+                let first = candidates
+                    .peek()
+                    .cloned()
+                    .unwrap_or_else(|| path.join(name));
+                candidates
+                    .find(|candidate| check_executable(candidate).is_ok())
+                    .unwrap_or(first)
+            } else {
+                path
+            };
+            check_executable(&path).map_err(|reason| {
+                Error::BinaryNotFound(format!("{} from {} {}", path.display(), source, reason))
+            })?;
+            Ok(path)
+        }
+        None => find_in_path(name).ok_or_else(|| {
+            Error::BinaryNotFound(format!(
+                "{} was not found in PATH; install it or point --ffmpeg-path or FFMPEG_PATH at it",
+                name
+            ))
+            .into()
+        }),
+    }
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    let names = binary_names(name);
+    env::split_paths(&path)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| check_executable(candidate).is_ok())
+}
+
+/// The file names the program `name` may have: with each extension of
+/// `PATHEXT` on Windows, as is elsewhere.
+fn binary_names(name: &str) -> Vec<OsString> {
+    if !cfg!(windows) {
+        return vec![OsString::from(name)];
+    }
+    let extensions = env::var("PATHEXT")
+        .ok()
+        .filter(|extensions| !extensions.is_empty())
+        .unwrap_or_else(|| ".COM;.EXE;.BAT;.CMD".to_string());
+    extensions
+        .split(';')
+        .filter(|extension| !extension.is_empty())
+        .map(|extension| OsString::from(format!("{}{}", name, extension.to_ascii_lowercase())))
+        .collect()
+}
+
+/// `path` as an argument for ffmpeg or ffprobe. On Windows, paths longer
+/// than `MAX_PATH` are passed in their `\\?\` form, which lifts the limit,
+/// UNC paths as `\\?\UNC\server\share\...`. Relative paths ffmpeg would
+/// misread get a `./` prefix: a name with a `:` in its first component,
+/// such as `Intro:Live.flac`, is taken for `protocol:rest`, and one
+/// starting with `-` for an option. Otherwise, and for standard input and
+/// URLs, the path is passed as is. URLs with a [`crate::RemoteDownload`]
+/// are replaced with the downloaded copy.
+pub(crate) fn path_arg(path: &Path) -> Cow<'_, OsStr> {
+    if let Some(local) = remote::local_copy(path) {
+        return Cow::Owned(path_arg(&local).into_owned());
+    }
+    #[cfg(windows)]
+    {
+        const MAX_PATH: usize = 260;
+        let text = path.to_string_lossy();
+        let special = text == "-" || text.contains("://") || text.starts_with(r"\\?\");
+        if !special {
+            if let Ok(absolute) = std::path::absolute(path) {
+                let absolute = absolute.to_string_lossy().into_owned();
+                if absolute.len() >= MAX_PATH {
+                    let verbatim = match absolute.strip_prefix(r"\\") {
+                        Some(unc) => format!(r"\\?\UNC\{}", unc),
+                        None => format!(r"\\?\{}", absolute),
+                    };
+                    return Cow::Owned(OsString::from(verbatim));
+                }
+            }
+        }
+    }
+    if is_misread(path) {
+        let mut prefixed = OsString::from("./");
+        prefixed.push(path);
+        return Cow::Owned(prefixed);
+    }
+    Cow::Borrowed(path.as_os_str())
+}
+
+/// Whether ffmpeg would take the relative `path` for a protocol or an
+/// option rather than a file.
+fn is_misread(path: &Path) -> bool {
+    let Some(Component::Normal(first)) = path.components().next() else {
+        return false;
+    };
+    let text = path.as_os_str().as_encoded_bytes();
+    let first = first.as_encoded_bytes();
+    text != b"-"
+        && !text.windows(3).any(|window| window == b"://")
+        && (first.starts_with(b"-") || first.contains(&b':'))
+}
+
+/// Input arguments letting ffmpeg or ffprobe open `path` over the network
+/// when it is a URL that is read directly. They go before `-i`.
+pub(crate) fn protocol_args(path: &Path) -> &'static [&'static str] {
+    if remote::is_url(path) && remote::local_copy(path).is_none() {
+        &["-protocol_whitelist", remote::PROTOCOL_WHITELIST]
+    } else {
+        &[]
+    }
+}
+
+fn check_executable(path: &Path) -> Result<(), &'static str> {
+    let metadata = path.metadata().map_err(|_| "does not exist")?;
+    if !metadata.is_file() {
+        return Err("is not a file");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err("is not executable");
+        }
+    }
+    Ok(())
+}
+
+/// Runs ffmpeg with `args`, feeding its `-progress` output into `spinner`.
+///
+/// `duration` is the length of the input in seconds, usually known from
+/// ffprobe; without it the `Duration:` line ffmpeg prints for the input is
+/// used instead, so a percentage and ETA can be shown while the pass runs.
+///
+/// stderr is read as it arrives, logged at debug level, and only partly
+/// kept: every line `keep` accepts, the last loudnorm summary, and the
+/// tail. Returns those on success.
+pub(crate) fn run_with_progress<I, S>(
+    options: &Options,
+    args: I,
+    duration: Option<f64>,
+    spinner: &ProgressSpinner,
+    keep: fn(&str) -> bool,
+) -> io::Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = ffmpeg_command(options)?;
+    command
+        .args(["-nostats", "-progress", "pipe:1"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    log_command(&command);
+    let mut process = command.spawn()?;
+    let _guard = ChildGuard::register(process.id());
+
+    let duration = Arc::new(Mutex::new(duration));
+    let stderr = process.stderr.take().map(|stderr| {
+        let duration = Arc::clone(&duration);
+        thread::spawn(move || {
+            let mut captured = StderrCapture::new(keep);
+            let mut reader = BufReader::new(stderr);
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+                let text = String::from_utf8_lossy(&line);
+                if let Some(total) = parse_duration_line(&text) {
+                    if let Ok(mut duration) = duration.lock() {
+                        duration.get_or_insert(total);
+                    }
+                }
+                captured.push(text.trim_end_matches(['\r', '\n']));
+                line.clear();
+            }
+            captured.into_string()
+        })
+    });
+
+    let started = Instant::now();
+    let last_progress = Mutex::new(started);
+    let stdout = process.stdout.take();
+    // Progress goes to the callback of the task running this pass.
+    let context = TaskContext::current();
+    let waited = thread::scope(|scope| {
+        let reader = stdout.map(|stdout| {
+            scope.spawn(|| {
+                context.run(|| {
+                    let (mut speed, mut last_position) = (None, None);
+                    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                        if let Some(value) = parse_speed(&line) {
+                            speed = Some(value);
+                            continue;
+                        }
+                        let Some(position) = parse_out_time(&line) else {
+                            continue;
+                        };
+                        if last_position.replace(position) != Some(position) {
+                            if let Ok(mut last) = last_progress.lock() {
+                                *last = Instant::now();
+                            }
+                        }
+                        let total = duration.lock().ok().and_then(|d| *d);
+                        if let Some(total) = total.filter(|t| *t > 0.0) {
+                            let fraction = (position / total).clamp(0.0, 1.0);
+                            let eta = (fraction > 0.0)
+                                .then(|| started.elapsed().mul_f64((1.0 - fraction) / fraction));
+                            spinner.set_progress(fraction, eta, speed);
+                        }
+                    }
+                })
+            })
+        });
+        let waited = wait_or_kill(&mut process, options.timeout, &last_progress);
+        if let Some(reader) = reader {
+            let _ = reader.join();
+        }
+        waited
+    });
+
+    let (status, stalled) = waited?;
+    let stderr = stderr
+        .map(|handle| handle.join().unwrap_or_default())
+        .unwrap_or_default();
+    log_finished(&command, started, status);
+
+    if let (true, Some(timeout)) = (stalled, options.timeout) {
+        return Err(Error::Stalled(timeout).into());
+    }
+    if status.success() {
+        Ok(stderr)
+    } else {
+        Err(Error::process_failed_with("ffmpeg", status.code(), &stderr).into())
+    }
+}
+
+/// Runs ffmpeg with `args`, which make it write its output to standard
+/// output, with ours passed through to it. ffmpeg can't report its progress
+/// there as well, so there is none, and `options.timeout` doesn't apply.
+/// Returns ffmpeg's stderr, as far as [`run_with_progress`] keeps it.
+pub(crate) fn run_to_stdout<I, S>(options: &Options, args: I) -> io::Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = ffmpeg_command(options)?;
+    command
+        .arg("-nostats")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::piped());
+    log_command(&command);
+    let started = Instant::now();
+    let mut process = command.spawn()?;
+    let _guard = ChildGuard::register(process.id());
+    let mut captured = StderrCapture::new(|_| false);
+    if let Some(stderr) = process.stderr.take() {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            captured.push(&line);
+        }
+    }
+    let status = process.wait()?;
+    let stderr = captured.into_string();
+    log_finished(&command, started, status);
+    if status.success() {
+        Ok(stderr)
+    } else {
+        Err(Error::process_failed_with("ffmpeg", status.code(), &stderr).into())
+    }
+}
+
+/// The lines of ffmpeg's stderr a pass needs afterwards, so that a long run
+/// with chatty warnings doesn't hold everything it printed.
+struct StderrCapture {
+    keep: fn(&str) -> bool,
+    kept: Vec<String>,
+    /// The last loudnorm summary, from its `[Parsed_loudnorm` line to the
+    /// end of the JSON object.
+    summary: Vec<String>,
+    /// Braces opened and not yet closed in `summary`, or `None` once it is
+    /// complete.
+    summary_depth: Option<usize>,
+    tail: VecDeque<String>,
+}
+
+impl StderrCapture {
+    fn new(keep: fn(&str) -> bool) -> Self {
+        Self {
+            keep,
+            kept: Vec::new(),
+            summary: Vec::new(),
+            summary_depth: None,
+            tail: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, line: &str) {
+        if (self.keep)(line) {
+            self.kept.push(line.to_string());
+            return;
+        }
+        if line.contains("[Parsed_loudnorm") {
+            self.summary.clear();
+            self.summary_depth = Some(0);
+        }
+        if let Some(depth) = self.summary_depth {
+            let opened = depth + line.matches('{').count();
+            let depth = opened.saturating_sub(line.matches('}').count());
+            let closed = opened > 0 && depth == 0;
+            self.summary.push(line.to_string());
+            self.summary_depth =
+                (!closed && self.summary.len() < CAPTURED_SUMMARY_LINES).then_some(depth);
+            return;
+        }
+        logging::debug(format_args!("ffmpeg: {}", line));
+        if self.tail.len() == CAPTURED_TAIL_LINES {
+            self.tail.pop_front();
+        }
+        self.tail.push_back(line.to_string());
+    }
+
+    fn into_string(self) -> String {
+        let mut text = String::new();
+        for line in self.kept.iter().chain(&self.summary).chain(&self.tail) {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Waits for `process` to exit, killing it once `timeout` passes without
+/// `last_progress` being renewed. Returns its status and whether it was
+/// killed for stalling.
+fn wait_or_kill(
+    process: &mut Child,
+    timeout: Option<Duration>,
+    last_progress: &Mutex<Instant>,
+) -> io::Result<(ExitStatus, bool)> {
+    let Some(timeout) = timeout else {
+        return Ok((process.wait()?, false));
+    };
+    loop {
+        if let Some(status) = process.try_wait()? {
+            return Ok((status, false));
+        }
+        let idle = last_progress
+            .lock()
+            .map_or(Duration::ZERO, |last| last.elapsed());
+        if idle >= timeout {
+            // It may have exited in the meantime, which `wait` reports.
+            let _ = process.kill();
+            return Ok((process.wait()?, true));
+        }
+        thread::sleep(WATCHDOG_INTERVAL.min(timeout - idle));
+    }
+}
+
+/// Parses `out_time_us=` (and the misnamed `out_time_ms=`, which is also in
+/// microseconds) from ffmpeg's `-progress` output into seconds.
+fn parse_out_time(line: &str) -> Option<f64> {
+    line.strip_prefix("out_time_us=")
+        .or_else(|| line.strip_prefix("out_time_ms="))
+        .and_then(|us| us.trim().parse::<f64>().ok())
+        .map(|us| us / 1_000_000.0)
+}
+
+/// Parses `speed=1.23x` from ffmpeg's `-progress` output. ffmpeg reports
+/// `N/A` before it has an estimate.
+fn parse_speed(line: &str) -> Option<f64> {
+    line.strip_prefix("speed=")?
+        .trim()
+        .trim_end_matches('x')
+        .parse()
+        .ok()
+}
+
+/// Parses `  Duration: 01:02:03.45, start: ...` into seconds.
+fn parse_duration_line(line: &str) -> Option<f64> {
+    let rest = line.trim_start().strip_prefix("Duration:")?;
+    parse_timestamp(rest.split(',').next()?.trim())
+}
+
+/// Parses an `HH:MM:SS.ss` timestamp into seconds.
+pub(crate) fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    for part in timestamp.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_arg_keeps_colons_and_dashes_in_file_names() {
+        for (path, arg) in [
+            ("Intro:Live.flac", "./Intro:Live.flac"),
+            ("-loud.wav", "./-loud.wav"),
+            ("-", "-"),
+            ("live/Intro:Live.flac", "live/Intro:Live.flac"),
+            ("./Intro:Live.flac", "./Intro:Live.flac"),
+            (
+                "https://example.com/a:b.flac",
+                "https://example.com/a:b.flac",
+            ),
+            ("track.flac", "track.flac"),
+            ("/music/Intro:Live.flac", "/music/Intro:Live.flac"),
+        ] {
+            assert_eq!(path_arg(Path::new(path)), OsStr::new(arg), "{}", path);
+        }
+    }
+}
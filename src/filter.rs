@@ -0,0 +1,463 @@
+use crate::{
+    layout_has_center, loudness::format_loudnorm_value, AudioStreamInfo, Chapter, Engine, Loudness,
+    Mode, Options, PeakMode, Strategy,
+};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Length above which the second pass hands its filtergraph to ffmpeg in a
+/// script file rather than on the command line, which Windows caps at 32K.
+pub(crate) const FILTER_SCRIPT_THRESHOLD: usize = 4096;
+
+/// The band in Hz the dialogue measurement keeps, where speech carries
+/// nearly all of its loudness.
+const SPEECH_BAND: (u32, u32) = (100, 8000);
+
+/// Builds loudnorm filter strings for the measurement and normalization passes.
+pub struct FilterSettings;
+
+impl FilterSettings {
+    /// Constructs the measurement filter when `loudness` is `None`, and the
+    /// second-pass filter otherwise. Silent inputs get no gain at all, and
+    /// modes other than EBU and dialogue-gated measurements get a plain
+    /// gain.
+    pub fn construct(options: &Options, loudness: Option<&Loudness>) -> String {
+        // The compressor the measurements were taken through stays in place.
+        let with_compressor;
+        let options = match loudness.and_then(|l| l.compressor) {
+            Some(compressor) if options.compressor.is_none() => {
+                with_compressor = Options {
+                    compressor: Some(compressor),
+                    ..options.clone()
+                };
+                &with_compressor
+            }
+            _ => options,
+        };
+        let base = options.aformat_prefix();
+        if loudness.is_some_and(Loudness::is_silent) {
+            return format!("{}anull{}", base, Self::post_filter(options));
+        }
+        if let Some(loudness) = loudness.filter(|l| l.dialogue_i.is_some()) {
+            let gain = Self::gain_db(options, loudness).unwrap_or(0.0);
+            // Without loudnorm to hold the ceiling, a limiter catches the
+            // program peaks the dialogue gain lifts past it.
+            if options.limiter.is_none() && loudness.input_tp + gain > options.true_peak {
+                let limited = Options {
+                    limiter: Some(Default::default()),
+                    ..options.clone()
+                };
+                return Self::construct_gain(&limited, gain);
+            }
+            return Self::construct_gain(options, gain);
+        }
+        if let Some(loudness) = loudness.filter(|_| options.mode != Mode::Ebu) {
+            return Self::construct_gain(options, Self::gain_db(options, loudness).unwrap_or(0.0));
+        }
+        if loudness.is_none() && options.peak_mode == PeakMode::Sample {
+            return Self::construct_sample_peak_measurement(options);
+        }
+        let dual_mono = if options.dual_mono {
+            ":dual_mono=true"
+        } else {
+            ""
+        };
+        // astats prints its overall RMS and peak levels when the
+        // measurement ends.
+        let astats = if loudness.is_none() { "astats," } else { "" };
+        let loudness_params = loudness.map_or_else(
+            || ":print_format=json".to_string(),
+            |l| {
+                format!(
+                    ":linear={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}",
+                    Self::use_linear(options, l),
+                    format_loudnorm_value(l.input_i),
+                    format_loudnorm_value(l.input_tp),
+                    format_loudnorm_value(l.input_lra),
+                    format_loudnorm_value(l.input_thresh),
+                    options
+                        .offset
+                        .map_or_else(|| format_loudnorm_value(l.target_offset), format_value)
+                )
+            },
+        );
+        let (limiter, resample, post_filter) = match loudness {
+            Some(loudness) => (
+                Self::limiter(options),
+                Self::restore_sample_rate(options, loudness),
+                Self::post_filter(options),
+            ),
+            None => (String::new(), String::new(), String::new()),
+        };
+        format!(
+            "{}{}loudnorm=I={}:LRA={}:TP={}{}{}{}{}{}",
+            base,
+            astats,
+            format_value(options.integrated_loudness),
+            format_value(options.loudness_range),
+            format_value(options.true_peak),
+            dual_mono,
+            loudness_params,
+            limiter,
+            resample,
+            post_filter
+        )
+    }
+
+    /// The gain in dB that brings `loudness` to the target of the mode in
+    /// `options`, or `None` for silent input or a missing RMS level.
+    pub fn gain_db(options: &Options, loudness: &Loudness) -> Option<f64> {
+        if loudness.is_silent() {
+            return None;
+        }
+        match options.mode {
+            Mode::Ebu => {
+                Some(options.integrated_loudness - loudness.dialogue_i.unwrap_or(loudness.input_i))
+            }
+            Mode::Peak => Some(options.true_peak - loudness.input_tp),
+            Mode::Rms => loudness
+                .input_rms
+                .filter(|rms| rms.is_finite())
+                .map(|rms| options.target_rms - rms),
+        }
+    }
+
+    /// The filters `--dialogue-gated` measures the dialogue of `stream`
+    /// through: the center channel where its layout has one, and the mid
+    /// signal otherwise, both limited to the speech band. The mid signal
+    /// keeps two channels, so phantom-center dialogue measures as loud as
+    /// it plays in the program.
+    pub fn dialogue_selection(stream: &AudioStreamInfo) -> String {
+        let channels = if stream
+            .channel_layout
+            .as_deref()
+            .is_some_and(layout_has_center)
+        {
+            "pan=mono|c0=FC,"
+        } else if stream.channels.is_none_or(|channels| channels < 2) {
+            ""
+        } else {
+            "pan=stereo|c0=0.5*c0+0.5*c1|c1=0.5*c0+0.5*c1,"
+        };
+        format!(
+            "{}highpass=f={},lowpass=f={}",
+            channels, SPEECH_BAND.0, SPEECH_BAND.1
+        )
+    }
+
+    /// Constructs a plain gain filter applying `gain_db`, used where a fixed
+    /// correction is wanted instead of loudnorm, e.g. for album mode.
+    pub fn construct_gain(options: &Options, gain_db: f64) -> String {
+        let base = options.aformat_prefix();
+        format!(
+            "{}volume={:.2}dB{}{}",
+            base,
+            gain_db,
+            Self::limiter(options),
+            Self::post_filter(options)
+        )
+    }
+
+    /// Constructs a gain filter applying its own gain to each of
+    /// `chapters`, measured as `loudness`, switching at the chapter starts.
+    /// Silent chapters keep their level, and a default limiter is added
+    /// when a chapter's gain would lift its peak past the ceiling.
+    pub fn construct_chapters(
+        options: &Options,
+        chapters: &[Chapter],
+        loudness: &[Loudness],
+    ) -> String {
+        let gains: Vec<f64> = loudness
+            .iter()
+            .map(|l| Self::gain_db(options, l).unwrap_or(0.0))
+            .collect();
+        let overshoots = loudness
+            .iter()
+            .zip(&gains)
+            .any(|(l, gain)| !l.is_silent() && l.input_tp + gain > options.true_peak);
+        let limited;
+        let options = if overshoots && options.limiter.is_none() {
+            limited = Options {
+                limiter: Some(Default::default()),
+                ..options.clone()
+            };
+            &limited
+        } else {
+            options
+        };
+        // A sum of steps rather than nested ifs, which ffmpeg's expression
+        // parser would have to recurse into for every chapter.
+        let factors: Vec<f64> = gains.iter().map(|gain| 10f64.powf(gain / 20.0)).collect();
+        let mut expression = format!("{:.6}", factors.first().copied().unwrap_or(1.0));
+        for (chapter, pair) in chapters.iter().skip(1).zip(factors.windows(2)) {
+            if pair[1] == pair[0] {
+                continue;
+            }
+            expression.push_str(&format!(
+                "{:+.6}*gte(t,{:.3})",
+                pair[1] - pair[0],
+                chapter.start
+            ));
+        }
+        format!(
+            "{}volume='{}':eval=frame{}{}",
+            options.aformat_prefix(),
+            expression,
+            Self::limiter(options),
+            Self::post_filter(options)
+        )
+    }
+
+    /// Constructs the single-pass filter of a non-loudnorm engine, or `None`
+    /// for loudnorm, which needs measurements first.
+    pub fn construct_engine(options: &Options) -> Option<String> {
+        let base = options.aformat_prefix();
+        let filter = match &options.engine {
+            Engine::Loudnorm => return None,
+            Engine::Dynaudnorm(settings) => format!(
+                "dynaudnorm=f={}:g={}:p={}:m={}",
+                settings.frame_len,
+                settings.gauss_size,
+                format_value(settings.peak),
+                format_value(settings.max_gain)
+            ),
+            Engine::Speechnorm(settings) => format!(
+                "speechnorm=p={}:e={}:c={}",
+                format_value(settings.peak),
+                format_value(settings.expansion),
+                format_value(settings.compression)
+            ),
+        };
+        Some(format!(
+            "{}{}{}{}",
+            base,
+            filter,
+            Self::limiter(options),
+            Self::post_filter(options)
+        ))
+    }
+
+    /// Constructs the second-pass filter followed by a limiter holding the
+    /// true peak ceiling, for material where loudnorm alone overshoots it.
+    /// The configured limiter is used if there is one, and a default one
+    /// otherwise.
+    pub fn construct_limited(options: &Options, loudness: &Loudness) -> String {
+        let options = Options {
+            limiter: Some(options.limiter.clone().unwrap_or_default()),
+            ..options.clone()
+        };
+        Self::construct(&options, Some(loudness))
+    }
+
+    /// The `,alimiter=...` suffix for the limiter in `options`, or nothing.
+    /// loudnorm resamples to 192kHz, where sample peaks come close to true
+    /// peaks, so a sample peak limiter holds the true peak ceiling well.
+    fn limiter(options: &Options) -> String {
+        options
+            .limiter
+            .as_ref()
+            .map_or_else(String::new, |limiter| {
+                format!(
+                    ",alimiter=limit={:.4}:attack={}:release={}:level=false",
+                    10f64.powf(limiter.ceiling.unwrap_or(options.true_peak) / 20.0),
+                    format_value(limiter.attack),
+                    format_value(limiter.release)
+                )
+            })
+    }
+
+    /// The `,...` suffix of the configured `post_filter`, or nothing.
+    fn post_filter(options: &Options) -> String {
+        options
+            .post_filter
+            .as_ref()
+            .map_or_else(String::new, |filter| format!(",{}", filter))
+    }
+
+    /// The `,aresample=...` suffix taking loudnorm's 192kHz output back to
+    /// the measured sample rate with `keep_sample_rate`, or nothing.
+    fn restore_sample_rate(options: &Options, loudness: &Loudness) -> String {
+        let encoding = &options.encoding;
+        let rate = loudness
+            .sample_rate
+            .filter(|_| encoding.keep_sample_rate && encoding.sample_rate.is_none());
+        rate.map_or_else(String::new, |rate| {
+            let resampler: String = encoding
+                .resampler
+                .iter()
+                .flat_map(|resampler| resampler.options())
+                .map(|(name, value)| format!(":{}={}", name, value))
+                .collect();
+            format!(",aresample={}{}", rate, resampler)
+        })
+    }
+
+    /// `filter` with loudnorm asked to print its JSON report, so a second
+    /// pass tells what it achieved. Filters without loudnorm, or that print
+    /// already, stay as they are.
+    pub(crate) fn with_print_format(filter: &str) -> String {
+        let Some(start) = filter.find("loudnorm=") else {
+            return filter.to_string();
+        };
+        let end = filter[start..]
+            .find([',', ';', '['])
+            .map_or(filter.len(), |end| start + end);
+        if filter[start..end].contains("print_format=") {
+            return filter.to_string();
+        }
+        format!("{}:print_format=json{}", &filter[..end], &filter[end..])
+    }
+
+    /// Wraps a single-input filter chain as an mpv `--af` option, e.g. for a
+    /// profile or a shell alias. mpv's `[...]` quoting keeps the `,` and `:`
+    /// of the chain intact.
+    pub fn to_mpv_option(filter: &str) -> String {
+        format!("--af=lavfi=[{}]", filter)
+    }
+
+    /// Constructs the measurement filter of [`PeakMode::Sample`]: astats for
+    /// the sample peak and ebur128, which measures at the input's rate, for
+    /// the loudness, printing only its summary.
+    fn construct_sample_peak_measurement(options: &Options) -> String {
+        let dual_mono = if options.dual_mono {
+            ":dualmono=true"
+        } else {
+            ""
+        };
+        format!(
+            "{}astats,ebur128=framelog=verbose{}",
+            options.aformat_prefix(),
+            dual_mono
+        )
+    }
+
+    /// Constructs the ebur128 filter that logs momentary, short-term and
+    /// integrated loudness and the true peak for every frame.
+    pub fn construct_ebur128(options: &Options) -> String {
+        let base = options.aformat_prefix();
+        let dual_mono = if options.dual_mono {
+            ":dualmono=true"
+        } else {
+            ""
+        };
+        format!("{}ebur128=peak=true{}", base, dual_mono)
+    }
+
+    /// Constructs a `-filter_complex` graph that normalizes each audio stream
+    /// with its own measurements. Stream `n` is read from `[0:a:n]` and
+    /// written to `[an]`.
+    pub fn construct_streams(options: &Options, streams: &[Loudness]) -> String {
+        streams
+            .iter()
+            .enumerate()
+            .map(|(index, loudness)| {
+                format!(
+                    "[0:a:{}]{}[a{}]",
+                    index,
+                    Self::construct(options, Some(loudness)),
+                    index
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    /// Whether the second pass will ask loudnorm for linear normalization.
+    pub fn use_linear(options: &Options, loudness: &Loudness) -> bool {
+        match options.strategy {
+            Strategy::Linear => true,
+            Strategy::Dynamic => false,
+            Strategy::Auto => Self::linear_obstacle(options, loudness).is_none(),
+        }
+    }
+
+    /// Explains why loudnorm can't normalize `loudness` linearly, mirroring
+    /// the checks loudnorm itself performs before falling back to dynamic
+    /// mode. Returns `None` when linear normalization is possible.
+    pub fn linear_obstacle(options: &Options, loudness: &Loudness) -> Option<String> {
+        if loudness.is_silent() {
+            return None;
+        }
+        let (target_i, target_lra, target_tp) = (
+            options.integrated_loudness,
+            options.loudness_range,
+            options.true_peak,
+        );
+        let (input_i, input_lra, input_tp) =
+            (loudness.input_i, loudness.input_lra, loudness.input_tp);
+        if !(input_i.is_finite() && input_lra.is_finite() && input_tp.is_finite()) {
+            return Some("the measured values are not finite".to_string());
+        }
+
+        let output_tp = input_tp + (target_i - input_i);
+        if input_lra > target_lra {
+            Some(format!(
+                "measured LRA {:.1} LU exceeds the target of {:.1} LU",
+                input_lra, target_lra
+            ))
+        } else if output_tp > target_tp {
+            Some(format!(
+                "the required gain would raise true peak to {:.1} dBTP, above the {:.1} dBTP ceiling",
+                output_tp, target_tp
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Formats a target for a filter option, keeping a decimal point on whole
+/// numbers (`-23.0`) and all given digits otherwise.
+fn format_value(value: f64) -> String {
+    format!("{:?}", value)
+}
+
+/// A filtergraph written to a file for ffmpeg's `-filter_script` or
+/// `-filter_complex_script`, which sidesteps command-line length and quoting
+/// limits.
+pub struct FilterScript {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl FilterScript {
+    /// Writes `graph` to `path`, which is kept.
+    pub fn write(path: &Path, graph: &str) -> io::Result<Self> {
+        fs::write(path, graph)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            temporary: false,
+        })
+    }
+
+    /// Writes `graph` to a temporary file, removed when the script is
+    /// dropped.
+    pub(crate) fn temporary(graph: &str) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "ffmpeg-loudnorm-helper-{}-{}.filter",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut script = Self::write(&path, graph)?;
+        script.temporary = true;
+        Ok(script)
+    }
+
+    /// Location of the script.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for FilterScript {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
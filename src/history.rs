@@ -0,0 +1,224 @@
+//! A SQLite database of every measurement, kept with `--db`.
+//!
+//! The database is written through the `sqlite3` command line shell, so
+//! other tools can query it with plain SQL, e.g. to find the files that were
+//! normalized to an older target.
+
+use crate::{Error, Loudness, Options, PeakMode};
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Command as ProcessCommand, Stdio},
+    time::UNIX_EPOCH,
+};
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS measurements (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    modified INTEGER NOT NULL,
+    settings TEXT NOT NULL,
+    measured_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    input_i REAL,
+    input_tp REAL,
+    input_lra REAL,
+    input_thresh REAL,
+    target_i REAL NOT NULL,
+    target_lra REAL NOT NULL,
+    target_tp REAL NOT NULL,
+    applied_gain REAL,
+    loudness TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS measurements_path ON measurements (path);
+CREATE INDEX IF NOT EXISTS measurements_hash ON measurements (hash);";
+
+/// How long a write waits for another process holding the database, in
+/// milliseconds.
+const BUSY_TIMEOUT_MS: u32 = 10_000;
+
+/// Records measurements with their targets and applied gain in a SQLite
+/// database, which also serves as an analysis cache like
+/// [`crate::AnalysisCache`].
+#[derive(Debug, Clone)]
+pub struct LoudnessHistory {
+    path: PathBuf,
+}
+
+/// The file identity and measurement settings a row is stored under.
+struct Key {
+    path: String,
+    size: u64,
+    modified: u128,
+    settings: String,
+}
+
+impl LoudnessHistory {
+    /// The database at `path`, assumed to exist; see [`Self::open`].
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Opens the database at `path`, creating it and its table as needed.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let history = Self::new(path);
+        history.execute(SCHEMA)?;
+        Ok(history)
+    }
+
+    /// The most recent measurement of `input_path` with the settings of
+    /// `options`, if the file hasn't changed since. As with the analysis
+    /// cache, `target_offset` is reset when the targets differ.
+    pub fn load(&self, input_path: &Path, options: &Options) -> Option<Loudness> {
+        let key = Key::of(input_path, options).ok()?;
+        let output = self
+            .execute(&format!(
+                "SELECT target_i, target_lra, target_tp, loudness FROM measurements \
+                 WHERE path = {} AND size = {} AND modified = {} AND settings = {} \
+                 ORDER BY id DESC LIMIT 1;",
+                text(&key.path),
+                key.size,
+                key.modified,
+                text(&key.settings)
+            ))
+            .ok()?;
+        let mut columns = output.lines().next()?.splitn(4, '|');
+        let mut target = || columns.next()?.parse::<f64>().ok();
+        let same_targets = target() == Some(options.integrated_loudness)
+            && target() == Some(options.loudness_range)
+            && target() == Some(options.true_peak);
+        let mut loudness: Loudness = serde_json::from_str(columns.next()?).ok()?;
+        if !same_targets {
+            loudness.target_offset = 0.0;
+        }
+        Some(loudness)
+    }
+
+    /// Adds a row for the measurement of `input_path` with the targets of
+    /// `options`, and the gain in dB that was applied to it, if any.
+    pub fn record(
+        &self,
+        input_path: &Path,
+        options: &Options,
+        loudness: &Loudness,
+        applied_gain: Option<f64>,
+    ) -> io::Result<()> {
+        let key = Key::of(input_path, options)?;
+        let hash = content_hash(input_path)?;
+        self.execute(&format!(
+            "INSERT INTO measurements (path, hash, size, modified, settings, input_i, \
+             input_tp, input_lra, input_thresh, target_i, target_lra, target_tp, applied_gain, \
+             loudness) VALUES ({}, '{:016x}', {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {});",
+            text(&key.path),
+            hash,
+            key.size,
+            key.modified,
+            text(&key.settings),
+            real(loudness.input_i),
+            real(loudness.input_tp),
+            real(loudness.input_lra),
+            real(loudness.input_thresh),
+            real(options.integrated_loudness),
+            real(options.loudness_range),
+            real(options.true_peak),
+            applied_gain.map_or("NULL".to_string(), real),
+            text(&serde_json::to_string(loudness)?)
+        ))
+        .map(|_| ())
+    }
+
+    /// Runs `sql` and returns what `sqlite3` printed, one row per line with
+    /// columns separated by `|`.
+    fn execute(&self, sql: &str) -> io::Result<String> {
+        let output = ProcessCommand::new("sqlite3")
+            .arg("-batch")
+            .args(["-cmd", &format!(".timeout {}", BUSY_TIMEOUT_MS)])
+            .arg(&self.path)
+            .arg(sql)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => Error::BinaryNotFound(
+                    "sqlite3 is needed for --db but was not found".to_string(),
+                )
+                .into(),
+                _ => e,
+            })?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "{}: {}",
+                self.path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl Key {
+    fn of(input_path: &Path, options: &Options) -> io::Result<Self> {
+        let canonical = fs::canonicalize(input_path)?;
+        let metadata = fs::metadata(&canonical)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        Ok(Self {
+            path: canonical.to_string_lossy().into_owned(),
+            size: metadata.len(),
+            modified,
+            settings: format!(
+                "stream={:?} format={} dual_mono={} start={:?} duration={:?}{}{}",
+                options.audio_stream,
+                options.aformat_prefix(),
+                options.dual_mono,
+                options.start,
+                options.duration,
+                options
+                    .fast_analysis
+                    .map(|s| format!(" sampling={}/{}", s.chunk, s.interval))
+                    .unwrap_or_default(),
+                if options.peak_mode == PeakMode::Sample {
+                    " peak=sample"
+                } else {
+                    ""
+                }
+            ),
+        })
+    }
+}
+
+/// FNV-1a of the contents of `path`, to find a file again after it was
+/// moved or renamed.
+fn content_hash(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 1 << 16];
+    let mut hash: u64 = 0xcbf29ce484222325;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hash);
+        }
+        hash = buffer[..read].iter().fold(hash, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+        });
+    }
+}
+
+/// `value` as an SQL string literal.
+fn text(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// `value` as an SQL number; infinities, like the `-inf` loudness of
+/// silence, become values out of range, which SQLite reads as infinite.
+fn real(value: f64) -> String {
+    if value.is_nan() {
+        "NULL".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "9e999" } else { "-9e999" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,119 @@
+//! The `--post-hook` command run after each written output.
+
+use crate::{logging, Loudness, Shell};
+use std::{
+    ffi::OsStr,
+    io,
+    path::Path,
+    process::{Command as ProcessCommand, Stdio},
+};
+
+/// A shell command line with placeholders, run once per written output.
+///
+/// Supported placeholders, each quoted as one word for the shell:
+/// - `{input}`, `{output}`: paths of the input and the written output
+/// - `{input_i}`, `{input_tp}`, `{input_lra}`, `{input_thresh}`: the
+///   measurements of the input
+/// - `{gain}`: the gain in dB reaching the target, empty for silent input
+/// - `{target_i}`: the integrated loudness target
+#[derive(Clone)]
+pub struct PostHook {
+    template: String,
+}
+
+/// What a [`PostHook`] fills its placeholders with.
+pub struct HookValues<'a> {
+    pub input: &'a Path,
+    pub output: &'a Path,
+    pub loudness: Option<&'a Loudness>,
+    pub gain_db: Option<f64>,
+    pub target_i: f64,
+}
+
+impl PostHook {
+    /// Checks the placeholders of `template` up front, so a typo fails the
+    /// run before anything is encoded.
+    pub fn new(template: &str) -> io::Result<Self> {
+        let hook = Self {
+            template: template.to_string(),
+        };
+        hook.render(&HookValues {
+            input: Path::new(""),
+            output: Path::new(""),
+            loudness: None,
+            gain_db: None,
+            target_i: 0.0,
+        })?;
+        Ok(hook)
+    }
+
+    /// Runs the command for `values` through the platform shell, failing
+    /// when it exits unsuccessfully.
+    pub fn run(&self, values: &HookValues) -> io::Result<()> {
+        let command_line = self.render(values)?;
+        logging::debug(format_args!("post-hook: {}", command_line));
+        let mut command = if cfg!(windows) {
+            let mut command = ProcessCommand::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = ProcessCommand::new("sh");
+            command.arg("-c");
+            command
+        };
+        let status = command.arg(&command_line).stdin(Stdio::null()).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "post-hook '{}' failed with {}",
+                command_line, status
+            )));
+        }
+        Ok(())
+    }
+
+    fn render(&self, values: &HookValues) -> io::Result<String> {
+        let shell = Shell::default();
+        let quote = |value: &str| shell.quote(OsStr::new(value));
+        let measured = |value: fn(&Loudness) -> f64| {
+            values
+                .loudness
+                .map(|loudness| format!("{:.2}", value(loudness)))
+                .unwrap_or_default()
+        };
+
+        let mut rendered = String::new();
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            let end = rest[start..].find('}').ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unclosed placeholder in post-hook '{}'", self.template),
+                )
+            })? + start;
+            let value = match &rest[start + 1..end] {
+                "input" => values.input.to_string_lossy().into_owned(),
+                "output" => values.output.to_string_lossy().into_owned(),
+                "input_i" => measured(|l| l.input_i),
+                "input_tp" => measured(|l| l.input_tp),
+                "input_lra" => measured(|l| l.input_lra),
+                "input_thresh" => measured(|l| l.input_thresh),
+                "gain" => values
+                    .gain_db
+                    .map(|gain| format!("{:.2}", gain))
+                    .unwrap_or_default(),
+                "target_i" => values.target_i.to_string(),
+                other => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown placeholder '{{{}}}' in post-hook", other),
+                    ))
+                }
+            };
+            rendered.push_str(&quote(&value));
+            rest = &rest[end + 1..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
@@ -0,0 +1,142 @@
+//! Replacing inputs with their normalized audio, with `--in-place`.
+//!
+//! The normalizer writes next to the output and renames the finished file
+//! over it, so the input is replaced atomically; what's left to do here is
+//! the backup and the attributes the new file doesn't inherit.
+
+use crate::logging;
+use std::{
+    fs::{self, File, Metadata},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// An input about to be replaced, with the attributes of the original file
+/// and the backup made of it, if any.
+pub struct Original {
+    path: PathBuf,
+    metadata: Metadata,
+    /// The backup made for this run; one left by an earlier run isn't ours
+    /// to remove.
+    backup: Option<PathBuf>,
+}
+
+impl Original {
+    /// Records the attributes of `path` and, with a `backup_suffix`, links
+    /// or copies it to its name with the suffix appended. An existing
+    /// backup is kept, since it holds an older state of the same input.
+    pub fn prepare(path: &Path, backup_suffix: Option<&str>) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: --in-place needs a regular file", path.display()),
+            ));
+        }
+        let backup = match backup_suffix {
+            Some(suffix) => {
+                let mut name = path.as_os_str().to_owned();
+                name.push(suffix);
+                let backup = PathBuf::from(name);
+                if backup.exists() {
+                    logging::info(format_args!(
+                        "{}: keeping the existing backup {}",
+                        path.display(),
+                        backup.display()
+                    ));
+                    None
+                } else {
+                    // A hard link costs no space until the input is
+                    // replaced; filesystems without links get a copy.
+                    if fs::hard_link(path, &backup).is_err() {
+                        fs::copy(path, &backup)?;
+                    }
+                    Some(backup)
+                }
+            }
+            None => None,
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            metadata,
+            backup,
+        })
+    }
+
+    /// Gives the file that replaced the original its permissions, its owner
+    /// where the process may change it, and with `keep_mtime` its
+    /// modification time.
+    pub fn restore(self, keep_mtime: bool) -> io::Result<()> {
+        if keep_mtime {
+            File::options()
+                .write(true)
+                .open(&self.path)?
+                .set_modified(self.metadata.modified()?)?;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // Only root may give files away, so this is expected to fail
+            // for inputs owned by someone else.
+            if let Err(e) = std::os::unix::fs::chown(
+                &self.path,
+                Some(self.metadata.uid()),
+                Some(self.metadata.gid()),
+            ) {
+                logging::debug(format_args!(
+                    "{}: owner not restored: {}",
+                    self.path.display(),
+                    e
+                ));
+            }
+        }
+        // Permissions come last, after the changes a read-only original
+        // would forbid, and because changing the owner clears setuid bits.
+        fs::set_permissions(&self.path, self.metadata.permissions())
+    }
+
+    /// Whether the file at the input's path is no longer the original, i.e.
+    /// the normalized audio was renamed over it.
+    pub fn replaced(&self) -> bool {
+        let Ok(current) = fs::metadata(&self.path) else {
+            return false;
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            (current.dev(), current.ino()) != (self.metadata.dev(), self.metadata.ino())
+        }
+        #[cfg(not(unix))]
+        {
+            current.len() != self.metadata.len()
+                || current.modified().ok() != self.metadata.modified().ok()
+        }
+    }
+
+    /// Restores the attributes of an input replaced before a later step
+    /// failed, keeping the backup as the only copy of the original.
+    pub fn keep(self, keep_mtime: bool) {
+        if let Some(backup) = &self.backup {
+            logging::warn(format_args!(
+                "{}: replaced before the failure; the original is kept at {}",
+                self.path.display(),
+                backup.display()
+            ));
+        }
+        let path = self.path.clone();
+        if let Err(e) = self.restore(keep_mtime) {
+            logging::debug(format_args!(
+                "{}: attributes not restored: {}",
+                path.display(),
+                e
+            ));
+        }
+    }
+
+    /// Removes the backup again, for an input that wasn't replaced.
+    pub fn discard(self) {
+        if let Some(backup) = &self.backup {
+            let _ = fs::remove_file(backup);
+        }
+    }
+}
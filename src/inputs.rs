@@ -0,0 +1,259 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// Expands glob patterns in `patterns` into the matching file paths.
+///
+/// Patterns that name an existing file, or contain no wildcards, are passed
+/// through unchanged. This gives shells without globbing (cmd, PowerShell)
+/// the same behavior as a Unix shell. Patterns without any match are kept
+/// as-is so that they surface as a per-file error instead of vanishing, and
+/// so are URLs, whose `?` starts a query.
+pub fn expand_inputs(patterns: &[PathBuf]) -> Vec<PathBuf> {
+    let mut inputs = Vec::new();
+    for pattern in patterns {
+        let glob = pattern
+            .to_str()
+            .filter(|p| is_glob(p) && !crate::is_url(pattern));
+        let Some(glob) = glob.filter(|_| !pattern.exists()) else {
+            inputs.push(pattern.clone());
+            continue;
+        };
+        let mut matches = expand_glob(glob);
+        if matches.is_empty() {
+            inputs.push(pattern.clone());
+        } else {
+            matches.sort();
+            inputs.extend(matches);
+        }
+    }
+    inputs
+}
+
+/// Reads input paths from the file at `path`, or from standard input for
+/// `-`. The list holds one path per line, or is NUL-separated when it
+/// contains a NUL, as printed by `find -print0`. Empty entries are skipped.
+pub fn read_file_list(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut contents = Vec::new();
+    if path == Path::new("-") {
+        io::stdin().lock().read_to_end(&mut contents)?;
+    } else {
+        contents = fs::read(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    }
+    let separator = if contents.contains(&0) { 0 } else { b'\n' };
+    Ok(contents
+        .split(|&byte| byte == separator)
+        .map(|entry| match separator {
+            b'\n' => entry.strip_suffix(b"\r").unwrap_or(entry),
+            _ => entry,
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(OsString::from_vec(bytes.to_vec()))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(OsString::from(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    // Backslashes separate directories on Windows only; elsewhere they are
+    // part of file names or escape a wildcard.
+    let normalized = if cfg!(windows) {
+        pattern.replace('\\', "/")
+    } else {
+        pattern.to_string()
+    };
+    let (root, rest) = split_root(&normalized);
+
+    let mut candidates = vec![root];
+    for component in rest.split('/').filter(|c| !c.is_empty()) {
+        let mut next = Vec::new();
+        for base in &candidates {
+            if !is_glob(component) {
+                next.push(base.join(component));
+                continue;
+            }
+            let dir = if base.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                base.as_path()
+            };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let file_name = entry.file_name();
+                let Some(name) = file_name.to_str() else {
+                    continue;
+                };
+                if name.starts_with('.') && !component.starts_with('.') {
+                    continue;
+                }
+                if matches_pattern(component, name) {
+                    next.push(base.join(&file_name));
+                }
+            }
+        }
+        candidates = next;
+    }
+
+    candidates.into_iter().filter(|c| c.is_file()).collect()
+}
+
+/// Splits the root off a pattern with `/` separators: `/`, a drive letter
+/// or, on Windows, the `//server/share/` of a UNC path. The `//?/` prefix of
+/// long paths is dropped.
+fn split_root(pattern: &str) -> (PathBuf, &str) {
+    if let Some(unc) = pattern.strip_prefix("//").filter(|_| cfg!(windows)) {
+        if let Some(local) = unc.strip_prefix("?/").filter(|p| !p.starts_with("UNC/")) {
+            return split_root(local);
+        }
+        let unc = unc.strip_prefix("?/UNC/").unwrap_or(unc);
+        let mut parts = unc.splitn(3, '/');
+        if let (Some(server), Some(share)) = (parts.next(), parts.next()) {
+            let root = PathBuf::from(format!(r"\\{}\{}\", server, share));
+            return (root, parts.next().unwrap_or_default());
+        }
+    }
+    match pattern.strip_prefix('/') {
+        Some(rest) => (PathBuf::from("/"), rest),
+        None => match pattern.split_once(":/") {
+            Some((drive, rest)) if drive.len() == 1 => {
+                (PathBuf::from(format!("{}:/", drive)), rest)
+            }
+            _ => (PathBuf::new(), pattern),
+        },
+    }
+}
+
+/// Matches a single path component against `*`, `?` and `[...]` wildcards,
+/// ignoring case on Windows like its file systems do. A backslash makes the
+/// character after it literal, on platforms where it isn't a separator.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = if cfg!(windows) {
+        (pattern.to_lowercase(), name.to_lowercase())
+    } else {
+        (pattern.to_string(), name.to_string())
+    };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // The pattern position after the last `*` and the name position it
+    // would resume from, tried again with one more character on a mismatch.
+    let mut star = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, n));
+        } else if let Some(len) = match_char(&pattern[p..], name[n]) {
+            p += len;
+            n += 1;
+        } else if let Some((after, from)) = star {
+            p = after;
+            n = from + 1;
+            star = Some((after, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The length of the `?`, `[...]` or literal at the start of `pattern` when
+/// it matches `c`.
+fn match_char(pattern: &[char], c: char) -> Option<usize> {
+    match pattern.first()? {
+        '\\' if pattern.len() > 1 => (pattern[1] == c).then_some(2),
+        '*' => None,
+        '?' => Some(1),
+        '[' => match pattern.iter().position(|&c| c == ']') {
+            Some(end) if end > 1 => {
+                let class = &pattern[1..end];
+                let (negated, class) = match class.first() {
+                    Some('!') | Some('^') => (true, &class[1..]),
+                    _ => (false, class),
+                };
+                let mut found = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        found |= class[i] <= c && c <= class[i + 2];
+                        i += 3;
+                    } else {
+                        found |= class[i] == c;
+                        i += 1;
+                    }
+                }
+                (found != negated).then_some(end + 1)
+            }
+            _ => (c == '[').then_some(1),
+        },
+        &p => (p == c).then_some(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches_pattern("*.wav", "song.wav"));
+        assert!(!matches_pattern("*.wav", "song.wav.bak"));
+        assert!(matches_pattern("a*b*c", "aXbYbZc"));
+        assert!(matches_pattern("track?[0-9].*", "track 2.flac"));
+        assert!(!matches_pattern("[!a-c]*", "beat.mp3"));
+        assert!(matches_pattern("[x", "[x"));
+        assert!(matches_pattern("**", ""));
+    }
+
+    #[test]
+    fn many_stars_stay_linear() {
+        let name = "a".repeat(100);
+        assert!(!matches_pattern(&format!("{}b", "*a".repeat(20)), &name));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escaped_wildcards_match_themselves() {
+        let dir =
+            std::env::temp_dir().join(format!("ffmpeg-normalize-glob-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["take*1.wav", "take 1.wav", r"back\slash.wav"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let expand = |pattern: &str| {
+            let mut paths = expand_glob(&format!("{}/{}", dir.display(), pattern));
+            paths.sort();
+            paths
+        };
+        assert_eq!(expand(r"take\*1.*"), [dir.join("take*1.wav")]);
+        assert_eq!(
+            expand("take*1.*"),
+            [dir.join("take 1.wav"), dir.join("take*1.wav")]
+        );
+        assert_eq!(expand(r"back\\*.wav"), [dir.join(r"back\slash.wav")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn case_follows_the_platform() {
+        assert_eq!(matches_pattern("*.WAV", "song.wav"), cfg!(windows));
+    }
+}
@@ -0,0 +1,202 @@
+//! Ctrl+C (SIGINT) and SIGTERM handling.
+//!
+//! The handler only records the interruption, and the running ffmpeg
+//! processes are then asked to stop: from a watcher thread on Unix, where a
+//! signal handler must not lock, and from the handler itself on Windows,
+//! which runs it on a thread of its own. The functions waiting on those
+//! processes then see them fail, return
+//! [`Error::Interrupted`](crate::Error::Interrupted) and clean up on the way
+//! out.
+
+use crate::task;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Process ids of running children.
+static CHILDREN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Installs the handler for Ctrl+C and termination requests.
+pub fn install_handler() {
+    platform::install();
+}
+
+/// Whether the user asked the process to stop.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Keeps a child's process id registered so an interruption reaches it,
+/// until dropped.
+pub(crate) struct ChildGuard {
+    pid: u32,
+}
+
+impl ChildGuard {
+    /// Also registers the child with the cancellation token of the task
+    /// running on this thread, if any. A child started after the
+    /// interruption is stopped right away.
+    pub(crate) fn register(pid: u32) -> Self {
+        children().push(pid);
+        task::register_child(pid);
+        if is_interrupted() {
+            platform::terminate(pid);
+        }
+        Self { pid }
+    }
+}
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        let mut children = children();
+        if let Some(index) = children.iter().position(|&pid| pid == self.pid) {
+            children.swap_remove(index);
+        }
+        drop(children);
+        task::unregister_child(self.pid);
+    }
+}
+
+fn children() -> std::sync::MutexGuard<'static, Vec<u32>> {
+    CHILDREN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Asks the process `pid` to stop.
+pub(crate) fn terminate(pid: u32) {
+    platform::terminate(pid);
+}
+
+/// Asks every running child to stop. Not for signal handlers, as it locks.
+fn terminate_children() {
+    let pids = children().clone();
+    for pid in pids {
+        platform::terminate(pid);
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::{
+        ffi::c_void,
+        io,
+        sync::atomic::{AtomicI32, Ordering},
+        thread,
+    };
+
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+
+    /// Write end of the pipe waking the watcher thread, -1 before
+    /// installation.
+    static WAKE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+        fn kill(pid: i32, signum: i32) -> i32;
+        fn pipe(fds: *mut i32) -> i32;
+        fn read(fd: i32, buf: *mut c_void, count: usize) -> isize;
+        fn write(fd: i32, buf: *const c_void, count: usize) -> isize;
+    }
+
+    extern "C" fn handle(_signum: i32) {
+        super::INTERRUPTED.store(true, Ordering::SeqCst);
+        let fd = WAKE.load(Ordering::SeqCst);
+        if fd >= 0 {
+            // SAFETY: write(2) is async-signal-safe and reads the one byte
+            // passed.
+            unsafe {
+                write(fd, [0u8].as_ptr().cast(), 1);
+            }
+        }
+    }
+
+    pub(super) fn install() {
+        let mut fds = [-1; 2];
+        // SAFETY: pipe(2) writes two descriptors into `fds`.
+        if unsafe { pipe(fds.as_mut_ptr()) } == 0 {
+            let [wait, wake] = fds;
+            let watcher =
+                thread::Builder::new()
+                    .name("interrupt".to_string())
+                    .spawn(move || loop {
+                        let mut byte = 0u8;
+                        // SAFETY: reads at most one byte into `byte`.
+                        match unsafe { read(wait, (&mut byte as *mut u8).cast(), 1) } {
+                            1 => super::terminate_children(),
+                            // Interrupted by the very signal it waits for.
+                            -1 if io::Error::last_os_error().kind()
+                                == io::ErrorKind::Interrupted => {}
+                            _ => break,
+                        }
+                    });
+            if watcher.is_ok() {
+                WAKE.store(wake, Ordering::SeqCst);
+            }
+        }
+        // SAFETY: `handle` only touches atomics and calls write(2), both of
+        // which are async-signal-safe.
+        unsafe {
+            signal(SIGINT, handle);
+            signal(SIGTERM, handle);
+        }
+    }
+
+    pub(super) fn terminate(pid: u32) {
+        // SAFETY: kill(2) has no memory safety requirements.
+        unsafe {
+            kill(pid as i32, SIGTERM);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+
+    const PROCESS_TERMINATE: u32 = 0x0001;
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn TerminateProcess(process: *mut c_void, exit_code: u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    extern "system" fn handle(_event: u32) -> i32 {
+        super::INTERRUPTED.store(true, std::sync::atomic::Ordering::SeqCst);
+        super::terminate_children();
+        1
+    }
+
+    pub(super) fn install() {
+        // SAFETY: registers a handler, which Windows runs on a thread of its
+        // own, so it may lock.
+        unsafe {
+            SetConsoleCtrlHandler(handle, 1);
+        }
+    }
+
+    /// Windows has no termination request a console program can handle
+    /// for a single process, so the process is ended right away; its
+    /// output is an unfinished temporary file either way.
+    pub(super) fn terminate(pid: u32) {
+        // SAFETY: the handle is checked before use and closed afterwards.
+        unsafe {
+            let process = OpenProcess(PROCESS_TERMINATE, 0, pid);
+            if !process.is_null() {
+                TerminateProcess(process, 1);
+                CloseHandle(process);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    pub(super) fn install() {}
+
+    pub(super) fn terminate(_pid: u32) {}
+}
@@ -0,0 +1,221 @@
+//! Two-pass loudness normalization built on ffmpeg's `loudnorm` filter.
+//!
+//! The first pass measures an input with [`analyze`], the second pass applies
+//! the filter returned by [`build_filter`] (or runs it directly with
+//! [`normalize`]). [`analyze_async`] and [`normalize_async`] return futures
+//! instead, which can be cancelled and report progress through a
+//! [`TaskContext`].
+//!
+//! [`run`] carries out a whole [`RunConfig`] the way the command line does:
+//! batches, albums, `--in-place`, `--watch` and the printed results.
+
+mod album;
+pub mod analysis;
+mod analyzer;
+mod audiobook;
+mod batch;
+mod cache;
+mod commands;
+mod compare;
+mod compliance;
+mod concat;
+mod config;
+mod cue;
+mod dashboard;
+mod doctor;
+mod error;
+mod events;
+mod ffmpeg;
+mod filter;
+mod history;
+mod hook;
+mod in_place;
+mod inputs;
+pub mod interrupt;
+pub mod logging;
+mod loudness;
+mod manifest;
+#[cfg(feature = "native")]
+mod native;
+mod normalizer;
+mod notify;
+mod options;
+mod playlist;
+mod plot;
+mod presets;
+mod print;
+mod probe;
+mod process;
+mod progress;
+mod prompt;
+mod provision;
+#[cfg(feature = "python")]
+mod python;
+mod remote;
+mod report;
+mod run;
+mod selftest;
+pub mod serve;
+mod shell;
+mod state;
+mod stats;
+mod stdin;
+mod stems;
+mod tagging;
+mod task;
+mod template;
+pub mod throttle;
+mod timeline;
+mod traversal;
+mod verify;
+mod watch;
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+pub use album::{Album, AlbumTrack, GroupReference, ReferenceTrack};
+pub use analyzer::LoudnessAnalyzer;
+pub use audiobook::{Audiobook, AudiobookMode, ChapterResult};
+pub use cache::AnalysisCache;
+pub use compliance::{Compliance, Criterion, SpecProfile, SPEC_PROFILES};
+pub use concat::ConcatBuffer;
+pub use config::{ConfigFile, ConfigValue, CONFIG_FILE_NAME};
+pub use cue::{CueSheet, CueTrack};
+pub use doctor::{Doctor, DoctorCheck};
+pub use error::Error;
+pub use filter::{FilterScript, FilterSettings};
+pub use history::LoudnessHistory;
+pub use hook::PostHook;
+pub use inputs::{expand_inputs, read_file_list};
+pub use loudness::{Loudness, NormalizationType, OutputStats};
+pub use manifest::{Job, Manifest};
+pub use normalizer::Normalizer;
+pub use notify::Notifier;
+pub use options::{
+    layout_channels, layout_has_center, parse_in_range, Backend, Compressor, Dialnorm, Downmix,
+    Dynaudnorm, EncodeOptions, Engine, Limiter, Mode, Options, PeakMode, Resampler, Sampling,
+    SilenceTrim, Speechnorm, Strategy, StreamFormat, TagFormat, INTEGRATED_LOUDNESS_RANGE,
+    LOUDNESS_RANGE_RANGE, OFFSET_RANGE, RMS_RANGE, TRUE_PEAK_RANGE,
+};
+pub use playlist::{Playlist, PlaylistEntry};
+pub use plot::LoudnessPlot;
+pub use presets::{Preset, PRESETS};
+pub use probe::{AudioStreamInfo, Chapter, MediaInfo};
+pub use progress::{MultiProgress, ProgressSpinner};
+pub use prompt::Confirmation;
+pub use provision::FfmpegDownload;
+pub use remote::{is_url, url_file_name, RemoteDownload};
+pub use run::{
+    run, FilterTarget, OutputFormat, PrintValue, ProgressFormat, RunConfig, NOOP_EXIT_CODE,
+    STDIN_PATH, STDOUT_PATH,
+};
+pub use selftest::{SelfTest, SelfTestCheck};
+pub use shell::Shell;
+pub use stats::{ImportedStats, StatsExport};
+pub use stdin::StdinBuffer;
+pub use stems::Stems;
+pub use tagging::{GainTags, Tagger, MARKER_TAG};
+pub use task::{CancellationToken, ProgressEvent, Task, TaskContext};
+pub use template::{OutputTemplate, DEFAULT_OUTPUT_TEMPLATE, DEFAULT_VARIANT_TEMPLATE};
+pub use timeline::{Timeline, TimelinePoint};
+pub use traversal::{walk_audio_files, DEFAULT_EXTENSIONS};
+pub use verify::{Verification, DEFAULT_TOLERANCE};
+pub use watch::DirectoryWatcher;
+
+/// Runs the loudnorm measurement pass over `input_path`.
+pub fn analyze(input_path: &Path, options: &Options) -> io::Result<Loudness> {
+    LoudnessAnalyzer::measure(input_path, options)
+}
+
+/// Measures momentary and short-term loudness over the duration of
+/// `input_path`.
+pub fn timeline(input_path: &Path, options: &Options) -> io::Result<Timeline> {
+    Timeline::measure(input_path, options)
+}
+
+/// Builds the second-pass loudnorm filter for previously measured values.
+pub fn build_filter(loudness: &Loudness, options: &Options) -> String {
+    FilterSettings::construct(options, Some(loudness))
+}
+
+/// Measures every audio stream of `input_path` separately.
+pub fn analyze_all_streams(input_path: &Path, options: &Options) -> io::Result<Vec<Loudness>> {
+    LoudnessAnalyzer::measure_all_streams(input_path, options)
+}
+
+/// Builds a filtergraph normalizing each audio stream with its own
+/// measurements, labelling the outputs `[a0]`, `[a1]`, ...
+pub fn build_stream_filters(streams: &[Loudness], options: &Options) -> String {
+    FilterSettings::construct_streams(options, streams)
+}
+
+/// Measures `input_path` and writes the normalized result to `output_path`.
+pub fn normalize(input_path: &Path, output_path: &Path, options: &Options) -> io::Result<Loudness> {
+    Normalizer::normalize(input_path, output_path, options)
+}
+
+/// Measures `input_path` and writes ReplayGain (and, for Opus, R128) tags
+/// instead of re-encoding, as chosen by `options.tag_format`. Tags are
+/// written into `output_path` when given, otherwise into the input file
+/// itself.
+pub fn tag(
+    input_path: &Path,
+    output_path: Option<&Path>,
+    options: &Options,
+) -> io::Result<(Loudness, GainTags)> {
+    let info = MediaInfo::probe(input_path, options)?;
+    let opus = Tagger::is_opus(&info, options);
+    Tagger::check_format(opus, options)?;
+    let loudness = LoudnessAnalyzer::measure_probed(input_path, options, &info)?;
+    let tags = GainTags::compute(&loudness, options, opus).ok_or(Error::Silent)?;
+    let metadata = tags.to_metadata(options.tag_format);
+    Tagger::write(input_path, output_path, &metadata, options)?;
+    Ok((loudness, tags))
+}
+
+/// Normalizes every audio stream of `input_path` independently, copying the
+/// video, and writes the result to `output_path`.
+pub fn normalize_all_streams(
+    input_path: &Path,
+    output_path: &Path,
+    options: &Options,
+) -> io::Result<Vec<Loudness>> {
+    Normalizer::normalize_all_streams(input_path, output_path, options)
+}
+
+/// [`analyze`] as a [`Task`] that can be awaited instead of blocking the
+/// calling thread.
+pub fn analyze_async(
+    input_path: PathBuf,
+    options: Options,
+    context: TaskContext,
+) -> Task<Loudness> {
+    Task::spawn(context, move || analyze(&input_path, &options))
+}
+
+/// [`normalize`] as a [`Task`] that can be awaited instead of blocking the
+/// calling thread.
+pub fn normalize_async(
+    input_path: PathBuf,
+    output_path: PathBuf,
+    options: Options,
+    context: TaskContext,
+) -> Task<Loudness> {
+    Task::spawn(context, move || {
+        normalize(&input_path, &output_path, &options)
+    })
+}
+
+/// Measures `input_path` with the ebur128 filter and checks it against the
+/// limits of `spec`.
+pub fn check(input_path: &Path, options: &Options, spec: &SpecProfile) -> io::Result<Compliance> {
+    Compliance::check(input_path, options, spec)
+}
+
+/// Measures a normalized `output_path` and checks it against the targets in
+/// `options`, allowing `tolerance` LU around the integrated loudness target.
+pub fn verify(output_path: &Path, options: &Options, tolerance: f64) -> io::Result<Verification> {
+    Verification::check(output_path, options, tolerance)
+}
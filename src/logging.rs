@@ -0,0 +1,67 @@
+//! Diagnostics on stderr, filtered by a process-wide level.
+
+use crate::MultiProgress;
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Severity of a log message, from least to most verbose.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    #[default]
+    Warn,
+    Info,
+    /// ffmpeg command lines, timings and full stderr of failed runs.
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+/// Shows messages up to and including `level` from now on.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether messages at `level` are shown.
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Prints `message` if `level` is enabled.
+pub fn log(level: Level, message: fmt::Arguments) {
+    if enabled(level) {
+        MultiProgress::suspend(|| eprintln!("{}", message));
+    }
+}
+
+/// Logs at [`Level::Warn`].
+pub fn warn(message: fmt::Arguments) {
+    log(Level::Warn, message);
+}
+
+/// Logs at [`Level::Info`].
+pub fn info(message: fmt::Arguments) {
+    log(Level::Info, message);
+}
+
+/// Logs at [`Level::Debug`].
+pub fn debug(message: fmt::Arguments) {
+    log(Level::Debug, message);
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(format!("unknown log level '{}'", s)),
+        }
+    }
+}
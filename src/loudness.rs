@@ -0,0 +1,290 @@
+use crate::{Compressor, Error, FilterSettings, Options};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, io, str::FromStr};
+
+/// Quietest integrated loudness loudnorm measures; its absolute gate drops
+/// everything below.
+const SILENCE_LUFS: f64 = -70.0;
+
+/// Measurements reported by the loudnorm filter's first pass.
+///
+/// loudnorm prints every value as a string, `-inf` for silence included.
+/// Values are parsed into numbers and serialized back in the same notation
+/// with two decimals.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS.
+    #[serde(with = "loudnorm_value")]
+    pub input_i: f64,
+    /// True peak in dBTP.
+    #[serde(with = "loudnorm_value")]
+    pub input_tp: f64,
+    /// Loudness range in LU.
+    #[serde(with = "loudnorm_value")]
+    pub input_lra: f64,
+    /// Relative gating threshold in LUFS.
+    #[serde(with = "loudnorm_value")]
+    pub input_thresh: f64,
+    /// Gain in LU loudnorm suggests for the second pass `offset`.
+    #[serde(with = "loudnorm_value")]
+    pub target_offset: f64,
+    /// Integrated loudness of loudnorm's output, when it reported one.
+    #[serde(
+        default,
+        with = "optional_loudnorm_value",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub output_i: Option<f64>,
+    /// True peak of loudnorm's output.
+    #[serde(
+        default,
+        with = "optional_loudnorm_value",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub output_tp: Option<f64>,
+    /// Loudness range of loudnorm's output.
+    #[serde(
+        default,
+        with = "optional_loudnorm_value",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub output_lra: Option<f64>,
+    /// Relative gating threshold of loudnorm's output.
+    #[serde(
+        default,
+        with = "optional_loudnorm_value",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub output_thresh: Option<f64>,
+    /// Whether loudnorm normalized linearly or dynamically.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalization_type: Option<NormalizationType>,
+    /// RMS level in dBFS, measured by astats alongside loudnorm in RMS mode.
+    #[serde(
+        default,
+        with = "optional_loudnorm_value",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub input_rms: Option<f64>,
+    /// Sample peak in dBFS, measured by astats alongside loudnorm.
+    #[serde(
+        default,
+        with = "optional_loudnorm_value",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sample_peak: Option<f64>,
+    /// Samples of the input at full scale, which were most likely clipped
+    /// before it reached us.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipped_samples: Option<u64>,
+    /// Sample rate of the measured stream in Hz, as ffprobe reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+    /// Dialogue level in dB the measured AC-3 or E-AC-3 stream declares.
+    /// Decoders honoring it play the stream `31 + dialnorm` dB quieter
+    /// than measured here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dialnorm: Option<i32>,
+    /// The compressor `--enforce-lra` put ahead of loudnorm; the other
+    /// values were measured through it, and the second pass applies it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressor: Option<Compressor>,
+    /// Whether only sampled chunks were measured, with `--fast-analysis`,
+    /// so the values are estimates for the whole input.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub approximate: bool,
+    /// Integrated loudness in LUFS of the dialogue alone, with
+    /// `--dialogue-gated`; the gain targets it instead of `input_i`.
+    #[serde(
+        default,
+        with = "optional_loudnorm_value",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub dialogue_i: Option<f64>,
+}
+
+/// What loudnorm reported for its output at the end of the second pass.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct OutputStats {
+    /// Integrated loudness in LUFS.
+    pub output_i: f64,
+    /// True peak in dBTP.
+    pub output_tp: f64,
+    /// Loudness range in LU.
+    pub output_lra: f64,
+    /// Relative gating threshold in LUFS, when loudnorm reported one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_thresh: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalization_type: Option<NormalizationType>,
+}
+
+/// How loudnorm applied its gain.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NormalizationType {
+    Linear,
+    Dynamic,
+}
+
+impl Loudness {
+    /// Measurements without loudnorm's output values, e.g. from another
+    /// measuring backend.
+    pub fn new(input_i: f64, input_tp: f64, input_lra: f64, input_thresh: f64) -> Self {
+        Self {
+            input_i,
+            input_tp,
+            input_lra,
+            input_thresh,
+            target_offset: 0.0,
+            output_i: None,
+            output_tp: None,
+            output_lra: None,
+            output_thresh: None,
+            normalization_type: None,
+            input_rms: None,
+            sample_peak: None,
+            clipped_samples: None,
+            sample_rate: None,
+            dialnorm: None,
+            compressor: None,
+            approximate: false,
+            dialogue_i: None,
+        }
+    }
+
+    /// The output values of a report loudnorm printed for a second pass.
+    pub fn output_stats(&self) -> Option<OutputStats> {
+        Some(OutputStats {
+            output_i: self.output_i?,
+            output_tp: self.output_tp?,
+            output_lra: self.output_lra?,
+            output_thresh: self.output_thresh,
+            normalization_type: self.normalization_type,
+        })
+    }
+
+    /// Whether the input was silent, which loudnorm reports as `-inf` or as
+    /// a loudness below its absolute gate. No gain can normalize it.
+    pub fn is_silent(&self) -> bool {
+        !self.input_i.is_finite() || self.input_i < SILENCE_LUFS
+    }
+
+    /// Whether the input is within `tolerance` dB of the target of
+    /// `options.mode` with its true peak under the ceiling, so normalizing
+    /// it would change next to nothing.
+    pub fn is_at_target(&self, options: &Options, tolerance: f64) -> bool {
+        FilterSettings::gain_db(options, self).is_some_and(|gain| gain.abs() <= tolerance)
+            && self.input_tp <= options.true_peak
+    }
+
+    /// Fails with [`Error::Silent`] for a silent input, unless
+    /// `options.pass_silent` asks to let it through unchanged.
+    pub fn ensure_audible(&self, options: &Options) -> io::Result<()> {
+        if self.is_silent() && !options.pass_silent {
+            return Err(Error::Silent.into());
+        }
+        Ok(())
+    }
+}
+
+/// Formats a value the way loudnorm prints it.
+pub(crate) fn format_loudnorm_value(value: f64) -> String {
+    if value.is_finite() {
+        format!("{:.2}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+impl FromStr for Loudness {
+    type Err = serde_json::Error;
+
+    /// Parses the JSON summary loudnorm prints with `print_format=json`.
+    fn from_str(json: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(json)
+    }
+}
+
+impl fmt::Display for Loudness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "I={} LUFS TP={} dBTP LRA={} LU threshold={} LUFS",
+            format_loudnorm_value(self.input_i),
+            format_loudnorm_value(self.input_tp),
+            format_loudnorm_value(self.input_lra),
+            format_loudnorm_value(self.input_thresh)
+        )
+    }
+}
+
+impl fmt::Display for NormalizationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NormalizationType::Linear => "linear",
+            NormalizationType::Dynamic => "dynamic",
+        })
+    }
+}
+
+/// Serde for a loudnorm value: a string such as `"-27.61"` or `"-inf"`, or
+/// a plain number.
+mod loudnorm_value {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_loudnorm_value(*value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+
+    struct ValueVisitor;
+
+    impl de::Visitor<'_> for ValueVisitor {
+        type Value = f64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a number or a numeric string")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<f64, E> {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<f64, E> {
+            Ok(value)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+    }
+}
+
+/// [`loudnorm_value`] for values loudnorm doesn't always report.
+mod optional_loudnorm_value {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => loudnorm_value::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<f64>, D::Error> {
+        loudnorm_value::deserialize(deserializer).map(Some)
+    }
+}
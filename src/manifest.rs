@@ -0,0 +1,139 @@
+//! Jobs read from a `--manifest` CSV: one input per row, with optional
+//! per-input overrides of the targets, the output and the preset.
+
+use crate::{
+    parse_in_range, Preset, INTEGRATED_LOUDNESS_RANGE, LOUDNESS_RANGE_RANGE, TRUE_PEAK_RANGE,
+};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The overrides of one manifest row. Empty cells leave the settings of
+/// the command line in place.
+#[derive(Debug, Clone, Default)]
+pub struct Job {
+    pub output: Option<PathBuf>,
+    /// Targets replacing the global ones, before the explicit columns.
+    pub preset: Option<Preset>,
+    pub integrated_loudness: Option<f64>,
+    pub true_peak: Option<f64>,
+    pub loudness_range: Option<f64>,
+}
+
+/// The rows of a manifest, in order.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    inputs: Vec<PathBuf>,
+    jobs: HashMap<PathBuf, Job>,
+}
+
+impl Manifest {
+    /// Reads the CSV at `path`. The header names the columns: `input`, and
+    /// any of `output`, `preset`, `i`, `tp` and `lra`.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let invalid = |line: usize, message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), line, message),
+            )
+        };
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty());
+        let Some((_, header)) = lines.next() else {
+            return Ok(Self::default());
+        };
+        let columns: Vec<String> = split_row(header)
+            .into_iter()
+            .map(|column| column.trim().to_lowercase())
+            .collect();
+        if let Some(column) = columns
+            .iter()
+            .find(|c| !["input", "output", "preset", "i", "tp", "lra"].contains(&c.as_str()))
+        {
+            return Err(invalid(1, format!("unknown column '{}'", column)));
+        }
+        if !columns.iter().any(|c| c == "input") {
+            return Err(invalid(1, "no input column".to_string()));
+        }
+
+        let mut manifest = Self::default();
+        for (line, row) in lines {
+            let mut input = None;
+            let mut job = Job::default();
+            for (column, value) in columns.iter().zip(split_row(row)) {
+                let value = value.trim();
+                if value.is_empty() {
+                    continue;
+                }
+                let number = |range, unit| {
+                    parse_in_range(value, range, unit)
+                        .map(Some)
+                        .map_err(|e| invalid(line, format!("{}: {}", column, e)))
+                };
+                match column.as_str() {
+                    "input" => input = Some(PathBuf::from(value)),
+                    "output" => job.output = Some(PathBuf::from(value)),
+                    "preset" => {
+                        job.preset =
+                            Some(Preset::find(value).ok_or_else(|| {
+                                invalid(line, format!("unknown preset '{}'", value))
+                            })?)
+                    }
+                    "i" => job.integrated_loudness = number(INTEGRATED_LOUDNESS_RANGE, "LUFS")?,
+                    "tp" => job.true_peak = number(TRUE_PEAK_RANGE, "dBTP")?,
+                    _ => job.loudness_range = number(LOUDNESS_RANGE_RANGE, "LU")?,
+                }
+            }
+            let input = input.ok_or_else(|| invalid(line, "no input".to_string()))?;
+            if manifest.jobs.insert(input.clone(), job).is_some() {
+                return Err(invalid(
+                    line,
+                    format!("{} is listed twice", input.display()),
+                ));
+            }
+            manifest.inputs.push(input);
+        }
+        Ok(manifest)
+    }
+
+    /// The inputs of all rows, in order.
+    pub fn inputs(&self) -> &[PathBuf] {
+        &self.inputs
+    }
+
+    /// Whether every row names its output.
+    pub fn names_outputs(&self) -> bool {
+        !self.jobs.is_empty() && self.jobs.values().all(|job| job.output.is_some())
+    }
+
+    /// The overrides of the row of `input_path`.
+    pub fn job(&self, input_path: &Path) -> Option<&Job> {
+        self.jobs.get(input_path)
+    }
+}
+
+/// The cells of one CSV row, unquoting `"..."` cells with `""` for a quote.
+fn split_row(row: &str) -> Vec<String> {
+    let mut cells = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        let cell = cells.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => cells.push(String::new()),
+            _ => cell.push(c),
+        }
+    }
+    cells
+}
@@ -0,0 +1,655 @@
+//! EBU R128 measurement in pure Rust, used by `--backend native` instead of
+//! the ffmpeg loudnorm pass, and to join the chunks of `--analysis-jobs`.
+//! Only WAV input is decoded; chunks are decoded to WAV by ffmpeg.
+
+use crate::{
+    ffmpeg::{self, parse_timestamp},
+    interrupt::{self, ChildGuard},
+    logging, Error, Loudness, Options, Shell, TaskContext,
+};
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+    process::Stdio,
+    thread,
+};
+
+/// Loudness below which blocks are ignored entirely, in LUFS.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Seconds decoded ahead of every chunk but the first and then dropped, so
+/// the K-weighting filters have settled where the chunk starts. A whole
+/// number of 100ms segments.
+const PREROLL: f64 = 1.0;
+
+/// Measures `input_path` like the loudnorm first pass would.
+pub(crate) fn measure(input_path: &Path, options: &Options) -> io::Result<Loudness> {
+    if options.audio_stream.is_some_and(|index| index > 0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "WAV files have a single audio stream",
+        ));
+    }
+    let mut reader = WavReader::open(input_path)?;
+    let mut meter = Meter::new(
+        reader.sample_rate,
+        reader.channels,
+        options.dual_mono && reader.channels == 1,
+    );
+    let frames_at = |timestamp: &Option<String>| {
+        timestamp
+            .as_deref()
+            .and_then(parse_timestamp)
+            .map(|seconds| (seconds.max(0.0) * reader.sample_rate as f64) as u64)
+    };
+    let skip = frames_at(&options.start).unwrap_or(0);
+    let limit = frames_at(&options.duration).unwrap_or(u64::MAX);
+    let mut frame = vec![0.0; reader.channels];
+    let mut position = 0u64;
+    while position < skip.saturating_add(limit) && reader.read_frame(&mut frame)? {
+        if position >= skip {
+            meter.push(&frame);
+        }
+        position += 1;
+    }
+    // WAV carries no dialnorm; the sample rate is what ffprobe would report.
+    Ok(Loudness {
+        sample_rate: Some(reader.sample_rate),
+        ..meter.finish()
+    })
+}
+
+/// Measures `duration` seconds of `input_path` from `start` in `jobs`
+/// chunks decoded by concurrent ffmpeg processes, and gates the 100ms
+/// segments of all of them together, as if measured in one pass.
+pub(crate) fn measure_chunked(
+    input_path: &Path,
+    options: &Options,
+    start: f64,
+    duration: f64,
+    jobs: usize,
+) -> io::Result<Loudness> {
+    // Whole seconds keep the chunk boundaries on the 100ms segment grid.
+    let chunk = (duration / jobs as f64).ceil().max(1.0);
+    let offsets: Vec<f64> = (0..jobs)
+        .map(|index| index as f64 * chunk)
+        .take_while(|&offset| offset < duration)
+        .collect();
+    // The chunk processes belong to the task measuring, so its token
+    // cancels them too.
+    let context = TaskContext::current();
+    let meters: Vec<io::Result<Meter>> = thread::scope(|scope| {
+        let handles: Vec<_> = offsets
+            .iter()
+            .map(|&offset| {
+                let context = context.clone();
+                scope.spawn(move || {
+                    context.run(|| {
+                        measure_chunk(
+                            input_path,
+                            options,
+                            start + offset,
+                            chunk.min(duration - offset),
+                        )
+                    })
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(io::Error::other("a chunk measurement panicked")))
+            })
+            .collect()
+    });
+    let mut segments = Vec::new();
+    let mut peak: f64 = 0.0;
+    for meter in meters {
+        let meter = meter?;
+        segments.extend(meter.segments);
+        peak = peak.max(meter.peak);
+    }
+    Ok(summarize(&segments, peak))
+}
+
+/// Decodes `len` seconds of `input_path` from `start` with ffmpeg, after
+/// the preroll, and meters them.
+fn measure_chunk(input_path: &Path, options: &Options, start: f64, len: f64) -> io::Result<Meter> {
+    let preroll = PREROLL.min(start);
+    let mut command = ffmpeg::ffmpeg_command(options)?;
+    command
+        .args(ffmpeg::protocol_args(input_path))
+        .args(["-nostdin", "-hide_banner", "-loglevel", "error", "-ss"])
+        .arg(format!("{:.3}", start - preroll))
+        .arg("-i")
+        .arg(ffmpeg::path_arg(input_path))
+        .arg("-t")
+        .arg(format!("{:.3}", len + preroll))
+        .arg("-vn");
+    if let Some(stream) = options.stream_specifier() {
+        command.args(["-map", &stream]);
+    }
+    let conversion = options.aformat_prefix();
+    if let Some(filter) = conversion.strip_suffix(',') {
+        command.args(["-af", filter]);
+    }
+    command
+        .args(["-c:a", "pcm_f32le", "-f", "wav", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    logging::debug(format_args!(
+        "$ {}",
+        ffmpeg::command_line(&command, Shell::default())
+    ));
+    let mut child = command.spawn()?;
+    let _guard = ChildGuard::register(child.id());
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let metered = (|| {
+        let mut reader = WavReader::new(BufReader::new(stdout), u64::MAX)?;
+        let mut meter = Meter::new(
+            reader.sample_rate,
+            reader.channels,
+            options.dual_mono && reader.channels == 1,
+        );
+        let mut frame = vec![0.0; reader.channels];
+        while reader.read_frame(&mut frame)? {
+            meter.push(&frame);
+        }
+        let preroll_segments = ((preroll * 10.0).round() as usize).min(meter.segments.len());
+        meter.segments.drain(..preroll_segments);
+        Ok(meter)
+    })();
+    let output = child.wait_with_output()?;
+    if interrupt::is_interrupted() {
+        return Err(Error::Interrupted.into());
+    }
+    if !output.status.success() {
+        return Err(Error::process_failed("ffmpeg", &output).into());
+    }
+    metered
+}
+
+/// Streams interleaved samples out of a PCM or IEEE float WAV file.
+struct WavReader<R> {
+    reader: R,
+    channels: usize,
+    sample_rate: u32,
+    format: SampleFormat,
+    remaining: u64,
+    buffer: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+enum SampleFormat {
+    Int(u16),
+    Float(u16),
+}
+
+impl WavReader<BufReader<File>> {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        WavReader::new(BufReader::new(file), file_len)
+    }
+}
+
+impl<R: Read> WavReader<R> {
+    /// Reads the header of a WAV stream of `len` bytes, or of unknown length
+    /// for `u64::MAX`.
+    fn new(mut reader: R, len: u64) -> io::Result<Self> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut position = 12u64;
+        let mut header = [0; 12];
+        reader.read_exact(&mut header).map_err(|_| {
+            invalid("The native backend only reads WAV files; use --backend ffmpeg")
+        })?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(invalid(
+                "The native backend only reads WAV files; use --backend ffmpeg",
+            ));
+        }
+
+        let mut format = None;
+        loop {
+            let mut chunk = [0; 8];
+            reader
+                .read_exact(&mut chunk)
+                .map_err(|_| invalid("WAV file has no data chunk"))?;
+            let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+            position += 8;
+            match &chunk[0..4] {
+                b"fmt " => {
+                    let mut fmt = vec![0; size as usize];
+                    reader.read_exact(&mut fmt)?;
+                    if fmt.len() < 16 {
+                        return Err(invalid("WAV fmt chunk is too short"));
+                    }
+                    let field = |at: usize| u16::from_le_bytes([fmt[at], fmt[at + 1]]);
+                    let mut tag = field(0);
+                    if tag == 0xFFFE && fmt.len() >= 26 {
+                        tag = field(24);
+                    }
+                    let channels = field(2) as usize;
+                    let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                    let bits = field(14);
+                    let sample_format = match (tag, bits) {
+                        (1, 8 | 16 | 24 | 32) => SampleFormat::Int(bits),
+                        (3, 32 | 64) => SampleFormat::Float(bits),
+                        _ => {
+                            return Err(invalid(&format!(
+                                "Unsupported WAV sample format {} with {} bits",
+                                tag, bits
+                            )))
+                        }
+                    };
+                    if channels == 0 || sample_rate == 0 {
+                        return Err(invalid("WAV file has no channels or sample rate"));
+                    }
+                    format = Some((channels, sample_rate, sample_format));
+                    position += size;
+                    if size % 2 == 1 {
+                        skip(&mut reader, 1)?;
+                        position += 1;
+                    }
+                }
+                b"data" => {
+                    let (channels, sample_rate, format) =
+                        format.ok_or_else(|| invalid("WAV data chunk precedes fmt chunk"))?;
+                    // Streams written without a known length leave the size
+                    // at its maximum; read to the end of the file instead.
+                    let remaining = if size == u64::from(u32::MAX) {
+                        len.saturating_sub(position)
+                    } else {
+                        size.min(len.saturating_sub(position))
+                    };
+                    return Ok(Self {
+                        reader,
+                        channels,
+                        sample_rate,
+                        format,
+                        remaining,
+                        buffer: Vec::new(),
+                    });
+                }
+                _ => {
+                    skip(&mut reader, size + size % 2)?;
+                    position += size + size % 2;
+                }
+            }
+        }
+    }
+
+    /// Reads the next frame into `frame`, returning `false` at the end.
+    fn read_frame(&mut self, frame: &mut [f64]) -> io::Result<bool> {
+        let width = match self.format {
+            SampleFormat::Int(bits) | SampleFormat::Float(bits) => bits as usize / 8,
+        };
+        let frame_len = width * self.channels;
+        if self.remaining < frame_len as u64 {
+            return Ok(false);
+        }
+        self.buffer.resize(frame_len, 0);
+        match self.reader.read_exact(&mut self.buffer) {
+            // The end of a stream of unknown length.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            result => result?,
+        }
+        self.remaining -= frame_len as u64;
+        for (sample, bytes) in frame.iter_mut().zip(self.buffer.chunks_exact(width)) {
+            *sample = match (self.format, bytes) {
+                (SampleFormat::Int(8), [b]) => (*b as f64 - 128.0) / 128.0,
+                (SampleFormat::Int(16), [a, b]) => i16::from_le_bytes([*a, *b]) as f64 / 32768.0,
+                (SampleFormat::Int(24), [a, b, c]) => {
+                    (i32::from_le_bytes([0, *a, *b, *c]) >> 8) as f64 / 8388608.0
+                }
+                (SampleFormat::Int(32), [a, b, c, d]) => {
+                    i32::from_le_bytes([*a, *b, *c, *d]) as f64 / 2147483648.0
+                }
+                (SampleFormat::Float(32), [a, b, c, d]) => {
+                    f32::from_le_bytes([*a, *b, *c, *d]) as f64
+                }
+                (SampleFormat::Float(64), bytes) => {
+                    f64::from_le_bytes(bytes.try_into().expect("eight bytes per sample"))
+                }
+                _ => unreachable!("sample width matches the format"),
+            };
+        }
+        Ok(true)
+    }
+}
+
+/// Reads past `len` bytes of `reader`.
+fn skip(reader: &mut impl Read, len: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(len), &mut io::sink())?;
+    if skipped < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Direct form I biquad.
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// The two K-weighting stages of ITU-R BS.1770 for `sample_rate`.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        x: [0.0; 2],
+        y: [0.0; 2],
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        x: [0.0; 2],
+        y: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+/// Gated loudness, loudness range and true peak meter.
+struct Meter {
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    /// Samples per 100ms segment.
+    segment_len: usize,
+    segment_energy: f64,
+    segment_fill: usize,
+    /// Mean weighted energy of every complete 100ms segment.
+    segments: Vec<f64>,
+    oversampler: Oversampler,
+    peak: f64,
+}
+
+impl Meter {
+    fn new(sample_rate: u32, channels: usize, dual_mono: bool) -> Self {
+        // BS.1770 channel weights for L, R, C, LFE, Ls, Rs; the LFE is not
+        // measured and surround channels count 1.41 times.
+        let weights = (0..channels)
+            .map(|channel| match (channels, channel) {
+                (1, _) if dual_mono => 2.0,
+                (6, 3) => 0.0,
+                (6, 4 | 5) => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+        Self {
+            filters: vec![k_weighting(sample_rate); channels],
+            weights,
+            segment_len: (sample_rate as usize / 10).max(1),
+            segment_energy: 0.0,
+            segment_fill: 0,
+            segments: Vec::new(),
+            oversampler: Oversampler::new(sample_rate, channels),
+            peak: 0.0,
+        }
+    }
+
+    fn push(&mut self, frame: &[f64]) {
+        for ((sample, filters), weight) in frame.iter().zip(&mut self.filters).zip(&self.weights) {
+            let [shelf, high_pass] = filters;
+            let filtered = high_pass.process(shelf.process(*sample));
+            self.segment_energy += weight * filtered * filtered;
+        }
+        self.peak = self.peak.max(self.oversampler.push(frame));
+        self.segment_fill += 1;
+        if self.segment_fill == self.segment_len {
+            self.segments
+                .push(self.segment_energy / self.segment_len as f64);
+            self.segment_energy = 0.0;
+            self.segment_fill = 0;
+        }
+    }
+
+    fn finish(self) -> Loudness {
+        summarize(&self.segments, self.peak)
+    }
+}
+
+/// Mean energy of every window of `len` of the 100ms `segments`, advancing
+/// one segment at a time.
+fn blocks(segments: &[f64], len: usize) -> Vec<f64> {
+    segments
+        .windows(len)
+        .map(|window| window.iter().sum::<f64>() / len as f64)
+        .collect()
+}
+
+/// The measurement of consecutive 100ms `segments` whose highest absolute
+/// interpolated sample was `peak`.
+fn summarize(segments: &[f64], peak: f64) -> Loudness {
+    let true_peak = if peak > 0.0 {
+        20.0 * peak.log10()
+    } else {
+        f64::NEG_INFINITY
+    };
+
+    // Integrated loudness over 400ms blocks with a -10 LU relative gate.
+    // Without a block above the absolute gate, report silence the way
+    // loudnorm does, which `Loudness::is_silent` recognizes.
+    let Some((integrated, threshold)) = gated(&blocks(segments, 4), -10.0) else {
+        return Loudness::new(f64::NEG_INFINITY, true_peak, 0.0, ABSOLUTE_GATE);
+    };
+
+    // Loudness range over 3s blocks with a -20 LU relative gate.
+    let short_term = blocks(segments, 30);
+    let Some((_, lra_threshold)) = gated(&short_term, -20.0) else {
+        return Loudness::new(integrated, true_peak, 0.0, threshold);
+    };
+    let mut levels: Vec<f64> = short_term
+        .iter()
+        .map(|&energy| loudness_of(energy))
+        .filter(|&level| level > ABSOLUTE_GATE && level > lra_threshold)
+        .collect();
+    levels.sort_by(f64::total_cmp);
+    let percentile = |p: f64| levels[((levels.len() - 1) as f64 * p).round() as usize];
+    let range = percentile(0.95) - percentile(0.10);
+
+    Loudness::new(integrated, true_peak, range, threshold)
+}
+
+fn loudness_of(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Gates `blocks` absolutely and then `relative` LU below their mean,
+/// returning the gated loudness and the relative threshold, or nothing when
+/// no block is above the absolute gate.
+fn gated(blocks: &[f64], relative: f64) -> Option<(f64, f64)> {
+    let mean = |energies: &mut dyn Iterator<Item = f64>| {
+        let (sum, count) = energies.fold((0.0, 0usize), |(sum, count), e| (sum + e, count + 1));
+        (count > 0).then(|| loudness_of(sum / count as f64))
+    };
+    let threshold = mean(
+        &mut blocks
+            .iter()
+            .copied()
+            .filter(|&e| loudness_of(e) > ABSOLUTE_GATE),
+    )? + relative;
+    // The relative gate is below the mean, so the loudest block passes it.
+    let loudness = mean(&mut blocks.iter().copied().filter(|&e| {
+        let level = loudness_of(e);
+        level > ABSOLUTE_GATE && level > threshold
+    }))?;
+    Some((loudness, threshold.max(ABSOLUTE_GATE)))
+}
+
+/// Interpolates between samples to find inter-sample (true) peaks.
+struct Oversampler {
+    factor: usize,
+    /// Windowed-sinc coefficients, one row per interpolated phase.
+    phases: Vec<Vec<f64>>,
+    history: Vec<Vec<f64>>,
+}
+
+impl Oversampler {
+    const TAPS: usize = 12;
+
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        let factor = match sample_rate {
+            0..=95_999 => 4,
+            96_000..=191_999 => 2,
+            _ => 1,
+        };
+        let half = Self::TAPS as f64 / 2.0;
+        let phases = (0..factor)
+            .map(|phase| {
+                (0..Self::TAPS)
+                    .map(|tap| {
+                        let t = tap as f64 - half + 1.0 - phase as f64 / factor as f64;
+                        let sinc = if t == 0.0 {
+                            1.0
+                        } else {
+                            (PI * t).sin() / (PI * t)
+                        };
+                        let window = 0.5 + 0.5 * (PI * t / half).cos();
+                        sinc * window
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            factor,
+            phases,
+            history: vec![vec![0.0; Self::TAPS]; channels],
+        }
+    }
+
+    /// Adds a frame and returns the highest absolute interpolated value.
+    fn push(&mut self, frame: &[f64]) -> f64 {
+        let mut peak: f64 = 0.0;
+        for (sample, history) in frame.iter().zip(&mut self.history) {
+            history.rotate_left(1);
+            history[Self::TAPS - 1] = *sample;
+            peak = peak.max(sample.abs());
+            if self.factor == 1 {
+                continue;
+            }
+            for phase in &self.phases[1..] {
+                let value: f64 = phase.iter().zip(history.iter()).map(|(c, x)| c * x).sum();
+                peak = peak.max(value.abs());
+            }
+        }
+        peak
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    /// Meters a stereo 1 kHz sine played at each `(dbfs, seconds)` in turn,
+    /// the reference signals of EBU Tech 3341 and 3342.
+    fn measure_sine(parts: &[(f64, f64)]) -> Loudness {
+        let mut meter = Meter::new(SAMPLE_RATE, 2, false);
+        let mut n = 0u64;
+        for &(dbfs, seconds) in parts {
+            let amplitude = 10f64.powf(dbfs / 20.0);
+            for _ in 0..(seconds * SAMPLE_RATE as f64) as u64 {
+                let sample = amplitude * (2.0 * PI * 1000.0 * n as f64 / SAMPLE_RATE as f64).sin();
+                meter.push(&[sample, sample]);
+                n += 1;
+            }
+        }
+        meter.finish()
+    }
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn tech_3341_integrated_loudness() {
+        assert_near(measure_sine(&[(-23.0, 20.0)]).input_i, -23.0, 0.1);
+        assert_near(measure_sine(&[(-33.0, 20.0)]).input_i, -33.0, 0.1);
+        let gated = measure_sine(&[(-36.0, 10.0), (-23.0, 60.0), (-36.0, 10.0)]);
+        assert_near(gated.input_i, -23.0, 0.1);
+        let gated = measure_sine(&[
+            (-72.0, 10.0),
+            (-36.0, 10.0),
+            (-23.0, 60.0),
+            (-36.0, 10.0),
+            (-72.0, 10.0),
+        ]);
+        assert_near(gated.input_i, -23.0, 0.1);
+    }
+
+    #[test]
+    fn tech_3342_loudness_range() {
+        assert_near(
+            measure_sine(&[(-20.0, 20.0), (-30.0, 20.0)]).input_lra,
+            10.0,
+            1.0,
+        );
+        assert_near(
+            measure_sine(&[(-20.0, 20.0), (-15.0, 20.0)]).input_lra,
+            5.0,
+            1.0,
+        );
+    }
+
+    #[test]
+    fn sine_true_peak() {
+        assert_near(measure_sine(&[(-6.0, 5.0)]).input_tp, -6.0, 0.2);
+    }
+
+    #[test]
+    fn silence_is_reported_like_loudnorm() {
+        let loudness = measure_sine(&[(f64::NEG_INFINITY, 5.0)]);
+        assert!(loudness.is_silent());
+        assert_eq!(loudness.input_tp, f64::NEG_INFINITY);
+        assert_eq!(loudness.input_lra, 0.0);
+        assert_eq!(loudness.input_thresh, ABSOLUTE_GATE);
+        assert!(measure_sine(&[(-80.0, 5.0)]).is_silent());
+    }
+}
@@ -0,0 +1,480 @@
+use crate::{
+    ffmpeg,
+    filter::{FilterScript, FILTER_SCRIPT_THRESHOLD},
+    logging,
+    tagging::temp_path_for,
+    AudioStreamInfo, FilterSettings, Loudness, LoudnessAnalyzer, MediaInfo, Options, OutputStats,
+    ProgressSpinner, Shell, STDOUT_PATH,
+};
+use std::{ffi::OsStr, fs, io, path::Path, process::Command as ProcessCommand};
+
+/// Output extensions whose containers can hold embedded cover art.
+const COVER_ART_EXTENSIONS: &[&str] = &["mp3", "flac", "m4a", "m4b", "mp4", "mov", "mkv", "mka"];
+
+/// Sample depth of an input, as far as a lossless output can keep it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Depth {
+    Integer(u32),
+    Float(u32),
+}
+
+/// Runs both passes and writes the normalized output.
+pub struct Normalizer;
+
+impl Normalizer {
+    /// Measures `input_path`, then encodes it to `output_path` with the
+    /// second-pass filter. Returns the first-pass measurements.
+    pub fn normalize(
+        input_path: &Path,
+        output_path: &Path,
+        options: &Options,
+    ) -> io::Result<Loudness> {
+        let loudness = LoudnessAnalyzer::measure(input_path, options)?;
+        loudness.ensure_audible(options)?;
+        let filter_settings = FilterSettings::construct(options, Some(&loudness));
+        Self::encode(input_path, output_path, &filter_settings, options)?;
+        Ok(loudness)
+    }
+
+    /// Measures each audio stream of `input_path` and encodes them all to
+    /// `output_path`, each with its own loudnorm filter. Video is copied.
+    pub fn normalize_all_streams(
+        input_path: &Path,
+        output_path: &Path,
+        options: &Options,
+    ) -> io::Result<Vec<Loudness>> {
+        let streams = LoudnessAnalyzer::measure_all_streams(input_path, options)?;
+        for loudness in &streams {
+            loudness.ensure_audible(options)?;
+        }
+        let filter_complex = FilterSettings::construct_streams(options, &streams);
+        let script = Self::script_for(&filter_complex)?;
+        let temp_path = temp_path_for(output_path, "partial");
+        let command = Self::streams_command(
+            input_path,
+            &temp_path,
+            &filter_complex,
+            streams.len(),
+            script.as_ref().map(FilterScript::path),
+            options,
+        )?;
+        Self::run(command, &temp_path, output_path, options)?;
+        Ok(streams)
+    }
+
+    /// Encodes `input_path` to `output_path` through `filter_settings`, or
+    /// to standard output in `options.encoding.stream_format` when
+    /// `output_path` is `-`. Returns what loudnorm reported for the output,
+    /// when the filter has a loudnorm stage.
+    pub fn encode(
+        input_path: &Path,
+        output_path: &Path,
+        filter_settings: &str,
+        options: &Options,
+    ) -> io::Result<Option<OutputStats>> {
+        let filter_settings = FilterSettings::with_print_format(filter_settings);
+        let script = Self::script_for(&filter_settings)?;
+        let script_path = script.as_ref().map(FilterScript::path);
+        let stderr = if output_path == Path::new(STDOUT_PATH) {
+            let command = Self::encode_command(
+                input_path,
+                output_path,
+                &filter_settings,
+                script_path,
+                options,
+            )?;
+            let spinner = ProgressSpinner::labeled("Encoding");
+            let stderr = ffmpeg::run_to_stdout(options, ffmpeg::ffmpeg_args(&command));
+            spinner.stop();
+            stderr?
+        } else {
+            let temp_path = temp_path_for(output_path, "partial");
+            let command = Self::encode_command(
+                input_path,
+                &temp_path,
+                &filter_settings,
+                script_path,
+                options,
+            )?;
+            Self::run(command, &temp_path, output_path, options)?
+        };
+        let stats = LoudnessAnalyzer::extract_json(&stderr)
+            .parse::<Loudness>()
+            .ok()
+            .and_then(|report| report.output_stats());
+        Ok(stats)
+    }
+
+    /// The ffmpeg command line that [`Normalizer::encode`] would run, quoted
+    /// for `shell`, for running it later or elsewhere. With `filter_script`,
+    /// the command reads the filter from that file instead.
+    pub fn command_line(
+        input_path: &Path,
+        output_path: &Path,
+        filter_settings: &str,
+        filter_script: Option<&Path>,
+        options: &Options,
+        shell: Shell,
+    ) -> io::Result<String> {
+        Self::encode_command(
+            input_path,
+            output_path,
+            filter_settings,
+            filter_script,
+            options,
+        )
+        .map(|command| ffmpeg::command_line(&command, shell))
+    }
+
+    /// The ffmpeg command line that normalizes every stream in `streams`, as
+    /// run by [`Normalizer::normalize_all_streams`], quoted for `shell`.
+    pub fn streams_command_line(
+        input_path: &Path,
+        output_path: &Path,
+        streams: &[Loudness],
+        filter_script: Option<&Path>,
+        options: &Options,
+        shell: Shell,
+    ) -> io::Result<String> {
+        let filter_complex = FilterSettings::construct_streams(options, streams);
+        Self::streams_command(
+            input_path,
+            output_path,
+            &filter_complex,
+            streams.len(),
+            filter_script,
+            options,
+        )
+        .map(|command| ffmpeg::command_line(&command, shell))
+    }
+
+    /// A temporary script holding `graph` when it is too long to pass on the
+    /// command line comfortably.
+    fn script_for(graph: &str) -> io::Result<Option<FilterScript>> {
+        (graph.len() > FILTER_SCRIPT_THRESHOLD)
+            .then(|| FilterScript::temporary(graph))
+            .transpose()
+    }
+
+    fn encode_command(
+        input_path: &Path,
+        output_path: &Path,
+        filter_settings: &str,
+        filter_script: Option<&Path>,
+        options: &Options,
+    ) -> io::Result<ProcessCommand> {
+        let segment = if options.cut {
+            options.segment_args()
+        } else {
+            Vec::new()
+        };
+        let (input, output) = (ffmpeg::path_arg(input_path), ffmpeg::path_arg(output_path));
+        let mut args: Vec<&OsStr> = segment.iter().map(OsStr::new).collect();
+        args.extend(ffmpeg::protocol_args(input_path).iter().map(OsStr::new));
+        args.extend([
+            "-i".as_ref(),
+            &*input,
+            "-hide_banner".as_ref(),
+            "-y".as_ref(),
+        ]);
+        if options.encoding.map_all {
+            return Self::map_all_command(
+                input_path,
+                output_path,
+                filter_settings,
+                filter_script,
+                &args,
+                options,
+            );
+        }
+        let pictures: Vec<String> = Self::cover_art(input_path, output_path, options)
+            .into_iter()
+            .map(|index| format!("0:{}", index))
+            .collect();
+        if options.encoding.copy_video {
+            args.extend(["-copyts", "-map", "0:v?"].map(OsStr::new));
+        }
+        for picture in &pictures {
+            args.extend(["-map", picture.as_str()].map(OsStr::new));
+        }
+        let stream = if options.encoding.copy_video || !pictures.is_empty() {
+            // Mapping video means the audio has to be mapped explicitly too,
+            // or ffmpeg would drop it.
+            args.extend(["-c:v", "copy"].map(OsStr::new));
+            Some(
+                options
+                    .stream_specifier()
+                    .unwrap_or_else(|| "0:a:0".to_string()),
+            )
+        } else {
+            options.stream_specifier()
+        };
+        if let Some(stream) = &stream {
+            args.extend(["-map", stream.as_str()].map(OsStr::new));
+        }
+        match filter_script {
+            Some(script) => args.extend(["-filter_script:a".as_ref(), script.as_os_str()]),
+            None => args.extend(["-af", filter_settings].map(OsStr::new)),
+        }
+        let mut encoding = options.encoding.to_args();
+        encoding.extend(options.dialnorm_args("a"));
+        args.extend(encoding.iter().map(OsStr::new));
+        let depth = Self::bit_depth_args(input_path, output_path, options);
+        args.extend(depth.iter().map(OsStr::new));
+        args.push(&output);
+
+        let mut command = ffmpeg::ffmpeg_command(options)?;
+        command.args(args);
+        Ok(command)
+    }
+
+    /// [`Self::encode_command`] with `--map-all`: every stream is mapped in
+    /// its order, and all but the selected audio stream are stream-copied.
+    /// `input_args` are the arguments up to the output options.
+    fn map_all_command(
+        input_path: &Path,
+        output_path: &Path,
+        filter_settings: &str,
+        filter_script: Option<&Path>,
+        input_args: &[&OsStr],
+        options: &Options,
+    ) -> io::Result<ProcessCommand> {
+        let mut args = input_args.to_vec();
+        let info = MediaInfo::probe(input_path, options)?;
+        let selected = options.audio_stream.unwrap_or(0);
+        info.audio_stream(Some(selected))?;
+        let specifier = format!("a:{}", selected);
+        args.extend(["-copyts", "-map", "0"].map(OsStr::new));
+        let filter_option = match filter_script {
+            Some(_) => format!("-filter_script:{}", specifier),
+            None => format!("-filter:{}", specifier),
+        };
+        args.push(filter_option.as_ref());
+        args.push(filter_script.map_or(OsStr::new(filter_settings), Path::as_os_str));
+        let mut encoding = options.encoding.to_args_for(&specifier);
+        encoding.extend(options.dialnorm_args(&specifier));
+        args.extend(encoding.iter().map(OsStr::new));
+        let depth = Self::bit_depth_args(input_path, output_path, options);
+        args.extend(depth.iter().map(OsStr::new));
+        // Later codec options win, so these copies override any `-c:a`
+        // among the encoding arguments for the other audio streams.
+        let copies: Vec<String> = (0..info.audio_streams.len())
+            .filter(|&index| index != selected)
+            .map(|index| format!("-c:a:{}", index))
+            .collect();
+        for copy in &copies {
+            args.extend([copy.as_str(), "copy"].map(OsStr::new));
+        }
+        args.extend(
+            [
+                "-c:v", "copy", "-c:s", "copy", "-c:d", "copy", "-c:t", "copy",
+            ]
+            .map(OsStr::new),
+        );
+        let output = ffmpeg::path_arg(output_path);
+        args.push(&output);
+
+        let mut command = ffmpeg::ffmpeg_command(options)?;
+        command.args(args);
+        Ok(command)
+    }
+
+    /// Encoder arguments keeping the bit depth of `input_path` in a lossless
+    /// `output_path`, where the encoder would otherwise pick its default
+    /// format for loudnorm's floating point output: 16-bit for WAV, and
+    /// 32-bit rather than 24-bit for FLAC.
+    fn bit_depth_args(input_path: &Path, output_path: &Path, options: &Options) -> Vec<String> {
+        let encoding = &options.encoding;
+        if !encoding.keep_bit_depth || encoding.codec.is_some() || encoding.sample_fmt.is_some() {
+            return Vec::new();
+        }
+        let extension = output_path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+        let Some(extension) =
+            extension.filter(|ext| ["flac", "wav", "w64", "aif", "aiff"].contains(&ext.as_str()))
+        else {
+            return Vec::new();
+        };
+        let info = match MediaInfo::probe(input_path, options) {
+            Ok(info) => info,
+            Err(e) => {
+                logging::debug(format_args!(
+                    "{}: not keeping the bit depth: {}",
+                    input_path.display(),
+                    e
+                ));
+                return Vec::new();
+            }
+        };
+        let Some(depth) = info
+            .audio_stream(options.audio_stream)
+            .ok()
+            .and_then(Self::depth_of)
+        else {
+            return Vec::new();
+        };
+        let args: &[&str] = match (extension.as_str(), depth) {
+            ("flac", Depth::Integer(bits)) if bits <= 16 => &["-sample_fmt", "s16"],
+            ("flac", Depth::Integer(24)) => &["-sample_fmt", "s32", "-bits_per_raw_sample", "24"],
+            ("flac", Depth::Integer(_)) => &["-sample_fmt", "s32"],
+            // FLAC has no floating point samples; the encoder's choice stands.
+            ("flac", Depth::Float(_)) => &[],
+            ("wav" | "w64", depth) => match depth {
+                Depth::Integer(bits) if bits <= 16 => &["-c:a", "pcm_s16le"],
+                Depth::Integer(24) => &["-c:a", "pcm_s24le"],
+                Depth::Integer(_) => &["-c:a", "pcm_s32le"],
+                Depth::Float(64) => &["-c:a", "pcm_f64le"],
+                Depth::Float(_) => &["-c:a", "pcm_f32le"],
+            },
+            (_, depth) => match depth {
+                Depth::Integer(bits) if bits <= 16 => &["-c:a", "pcm_s16be"],
+                Depth::Integer(24) => &["-c:a", "pcm_s24be"],
+                Depth::Integer(_) => &["-c:a", "pcm_s32be"],
+                Depth::Float(64) => &["-c:a", "pcm_f64be"],
+                Depth::Float(_) => &["-c:a", "pcm_f32be"],
+            },
+        };
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// The sample depth of a lossless or PCM `stream`. Lossy codecs decode
+    /// to floating point without any depth worth keeping, so they have none.
+    fn depth_of(stream: &AudioStreamInfo) -> Option<Depth> {
+        let codec = stream.codec_name.as_deref()?;
+        let lossless = ["flac", "alac", "wavpack", "tta", "ape", "mlp", "truehd"];
+        if let Some(format) = codec.strip_prefix("pcm_") {
+            let bits = format
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+                .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+                .parse()
+                .ok()?;
+            return Some(if format.starts_with('f') {
+                Depth::Float(bits)
+            } else {
+                Depth::Integer(bits)
+            });
+        }
+        if !lossless.contains(&codec) {
+            return None;
+        }
+        let sample_fmt = stream.sample_fmt.as_deref()?.trim_end_matches('p');
+        match sample_fmt {
+            "flt" => Some(Depth::Float(32)),
+            "dbl" => Some(Depth::Float(64)),
+            "u8" | "s16" => Some(Depth::Integer(16)),
+            "s32" | "s64" => Some(Depth::Integer(stream.bits_per_sample.unwrap_or(32))),
+            _ => None,
+        }
+    }
+
+    /// Indices of the cover art streams of `input_path` to carry over into
+    /// `output_path`. Empty when the video is copied anyway, metadata is
+    /// stripped or the output container can't hold pictures.
+    fn cover_art(input_path: &Path, output_path: &Path, options: &Options) -> Vec<usize> {
+        let holds_pictures = output_path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|ext| {
+                COVER_ART_EXTENSIONS
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            });
+        if options.encoding.copy_video || options.encoding.strip_metadata || !holds_pictures {
+            return Vec::new();
+        }
+        match MediaInfo::probe(input_path, options) {
+            Ok(info) => info.attached_pics,
+            Err(e) => {
+                logging::debug(format_args!(
+                    "{}: not copying cover art: {}",
+                    input_path.display(),
+                    e
+                ));
+                Vec::new()
+            }
+        }
+    }
+
+    fn streams_command(
+        input_path: &Path,
+        output_path: &Path,
+        filter_complex: &str,
+        stream_count: usize,
+        filter_script: Option<&Path>,
+        options: &Options,
+    ) -> io::Result<ProcessCommand> {
+        let (input, output) = (ffmpeg::path_arg(input_path), ffmpeg::path_arg(output_path));
+        let mut args: Vec<&OsStr> = ffmpeg::protocol_args(input_path)
+            .iter()
+            .map(OsStr::new)
+            .collect();
+        args.extend([
+            "-i".as_ref(),
+            &*input,
+            "-hide_banner".as_ref(),
+            "-y".as_ref(),
+        ]);
+        match filter_script {
+            Some(script) => args.extend(["-filter_complex_script".as_ref(), script.as_os_str()]),
+            None => args.extend(["-filter_complex", filter_complex].map(OsStr::new)),
+        }
+        args.extend(["-map", "0:v?", "-c:v", "copy"].map(OsStr::new));
+        let labels: Vec<_> = (0..stream_count).map(|i| format!("[a{}]", i)).collect();
+        for label in &labels {
+            args.extend(["-map", label.as_str()].map(OsStr::new));
+        }
+        if options.encoding.map_all {
+            args.extend(
+                [
+                    "-map", "0:s?", "-c:s", "copy", "-map", "0:d?", "-c:d", "copy", "-map", "0:t?",
+                    "-c:t", "copy",
+                ]
+                .map(OsStr::new),
+            );
+        }
+        let mut encoding = options.encoding.to_args();
+        encoding.extend(options.dialnorm_args("a"));
+        args.extend(encoding.iter().map(OsStr::new));
+        args.push(&output);
+
+        let mut command = ffmpeg::ffmpeg_command(options)?;
+        command.args(args);
+        Ok(command)
+    }
+
+    /// Runs the second pass writing to `temp_path`, then renames it to
+    /// `output_path`. The partly written file is removed if ffmpeg fails or
+    /// is interrupted, so `output_path` never holds a truncated output.
+    /// Returns ffmpeg's stderr.
+    fn run(
+        command: ProcessCommand,
+        temp_path: &Path,
+        output_path: &Path,
+        options: &Options,
+    ) -> io::Result<String> {
+        logging::info(format_args!("writing {}", output_path.display()));
+        let spinner = ProgressSpinner::labeled("Encoding");
+        // ffmpeg reports the input's duration on stderr, which gives the
+        // percentage, unless only a segment of it is written.
+        let duration = options
+            .cut
+            .then(|| options.segment_duration(None))
+            .flatten();
+        let output = ffmpeg::run_with_progress(
+            options,
+            ffmpeg::ffmpeg_args(&command),
+            duration,
+            &spinner,
+            |_| false,
+        );
+        spinner.stop();
+
+        let result = output.and_then(|stderr| fs::rename(temp_path, output_path).map(|()| stderr));
+        if result.is_err() {
+            let _ = fs::remove_file(temp_path);
+        }
+        result
+    }
+}
@@ -0,0 +1,119 @@
+//! JSON events POSTed to the `--notify-url` as inputs and the run complete.
+
+use crate::{logging, report::ReportRow, Error};
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    process::{Command as ProcessCommand, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+/// Seconds a POST may take before it is given up on.
+const REQUEST_TIMEOUT_SECS: u32 = 10;
+
+#[derive(Serialize)]
+struct FileDone<'a> {
+    event: &'static str,
+    #[serde(flatten)]
+    row: &'a ReportRow,
+}
+
+#[derive(Serialize)]
+struct BatchDone {
+    event: &'static str,
+    succeeded: usize,
+    failed: usize,
+    /// Wall-clock time of the whole run in seconds.
+    elapsed: f64,
+    interrupted: bool,
+}
+
+/// Sends the events of one run to a URL with `curl`, counting the inputs
+/// for the final summary. Failed requests are logged and otherwise ignored,
+/// so an unreachable endpoint never fails the normalization.
+pub struct Notifier {
+    url: String,
+    started: Instant,
+    succeeded: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl Notifier {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            started: Instant::now(),
+            succeeded: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Posts the `file_done` event for the input of `row`.
+    pub fn file_done(&self, row: &ReportRow) {
+        let counter = match row.status.as_str() {
+            "ok" | "already normalized" => &self.succeeded,
+            _ => &self.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.post(&FileDone {
+            event: "file_done",
+            row,
+        });
+    }
+
+    /// Posts the `batch_done` event with the counts of the run.
+    pub fn batch_done(&self, interrupted: bool) {
+        self.post(&BatchDone {
+            event: "batch_done",
+            succeeded: self.succeeded.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed().as_secs_f64(),
+            interrupted,
+        });
+    }
+
+    fn post(&self, event: &impl Serialize) {
+        if let Err(e) = serde_json::to_vec(event)
+            .map_err(io::Error::from)
+            .and_then(|body| self.send(&body))
+        {
+            logging::warn(format_args!("{}: {}", self.url, e));
+        }
+    }
+
+    fn send(&self, body: &[u8]) -> io::Result<()> {
+        let mut process = ProcessCommand::new("curl")
+            .args(["-fsS", "-X", "POST"])
+            .args(["--max-time", &REQUEST_TIMEOUT_SECS.to_string()])
+            .args([
+                "-H",
+                "Content-Type: application/json",
+                "--data-binary",
+                "@-",
+            ])
+            .arg(&self.url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => Error::BinaryNotFound(
+                    "curl is needed for --notify-url but was not found".to_string(),
+                )
+                .into(),
+                _ => e,
+            })?;
+        if let Some(mut stdin) = process.stdin.take() {
+            stdin.write_all(body)?;
+        }
+        let output = process.wait_with_output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "notification failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,899 @@
+use crate::{ffmpeg::parse_timestamp, Loudness};
+use serde::{Deserialize, Serialize};
+use std::{ops::RangeInclusive, path::PathBuf, str::FromStr, time::Duration};
+
+/// Integrated loudness targets loudnorm accepts, in LUFS.
+pub const INTEGRATED_LOUDNESS_RANGE: RangeInclusive<f64> = -70.0..=-5.0;
+/// Loudness range targets loudnorm accepts, in LU.
+pub const LOUDNESS_RANGE_RANGE: RangeInclusive<f64> = 1.0..=50.0;
+/// True peak ceilings loudnorm accepts, in dBTP.
+pub const TRUE_PEAK_RANGE: RangeInclusive<f64> = -9.0..=0.0;
+/// Gain offsets loudnorm accepts, in LU.
+pub const OFFSET_RANGE: RangeInclusive<f64> = -99.0..=99.0;
+/// RMS level targets accepted for [`Mode::Rms`], in dBFS.
+pub const RMS_RANGE: RangeInclusive<f64> = -80.0..=0.0;
+
+/// Parses a number in `range`, such as one of the target ranges above, or
+/// fails with a message naming the range in `unit`.
+pub fn parse_in_range(value: &str, range: RangeInclusive<f64>, unit: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(number) if range.contains(&number) => Ok(number),
+        _ => Err(format!(
+            "expected a number from {} to {} {}",
+            range.start(),
+            range.end(),
+            unit
+        )
+        .trim_end()
+        .to_string()),
+    }
+}
+
+/// Loudness targets and filter settings shared by both passes.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Integrated loudness target in LUFS.
+    pub integrated_loudness: f64,
+    /// Loudness range target in LU.
+    pub loudness_range: f64,
+    /// Maximum true peak in dBTP.
+    pub true_peak: f64,
+    /// Channel layout to convert to before measuring and normalizing, e.g.
+    /// `mono` or `stereo`. Together with the sample rate and format in
+    /// `encoding`, it makes up the `aformat` stage of the filter.
+    pub channel_layout: Option<String>,
+    /// Matrix folding a multichannel source down, ahead of the `aformat`
+    /// stage. `None` leaves the downmix to ffmpeg's defaults.
+    pub downmix: Option<Downmix>,
+    /// Treat mono input as dual-mono so it is measured like a stereo
+    /// playback of the same signal.
+    pub dual_mono: bool,
+    /// Gain offset in LU applied in the second pass instead of the measured
+    /// `target_offset`.
+    pub offset: Option<f64>,
+    /// Measurements to use instead of running the first pass, e.g. from a
+    /// previous run or another tool.
+    pub measured: Option<Loudness>,
+    /// Pass silent inputs through without gain instead of failing with
+    /// [`crate::Error::Silent`].
+    pub pass_silent: bool,
+    /// What the gain is computed from.
+    pub mode: Mode,
+    /// Which peak `true_peak` caps.
+    pub peak_mode: PeakMode,
+    /// RMS level target in dBFS for [`Mode::Rms`].
+    pub target_rms: f64,
+    /// Filter that does the normalizing. Engines other than loudnorm need
+    /// no measurement; their filter comes from
+    /// [`crate::FilterSettings::construct_engine`].
+    pub engine: Engine,
+    /// How the second pass chooses between linear and dynamic normalization.
+    pub strategy: Strategy,
+    /// Limiter appended after loudnorm in the second pass, catching the
+    /// inter-sample overs lossy encoding can add.
+    pub limiter: Option<Limiter>,
+    /// Index of the audio stream to measure and normalize, counted among the
+    /// input's audio streams. `None` lets ffmpeg pick the default stream.
+    pub audio_stream: Option<usize>,
+    /// Location of the ffmpeg binary, or of the directory containing it.
+    /// Falls back to `FFMPEG_PATH`, `FFMPEG_BINARY` and then `PATH`.
+    pub ffmpeg_path: Option<PathBuf>,
+    /// Directory for cached first-pass measurements. Caching is disabled when
+    /// `None`.
+    pub cache_dir: Option<PathBuf>,
+    /// SQLite database recording measurements, consulted like the cache
+    /// when set. See [`crate::LoudnessHistory`].
+    pub history_db: Option<PathBuf>,
+    /// Encoder settings for the second pass.
+    pub encoding: EncodeOptions,
+    /// What measures the first pass.
+    pub backend: Backend,
+    /// Position to start measuring at, in seconds or `[HH:]MM:SS[.m]`.
+    pub start: Option<String>,
+    /// Length of the measured segment, in the same format as `start`.
+    pub duration: Option<String>,
+    /// Cut the output to the `start`/`duration` segment too, instead of only
+    /// measuring it.
+    pub cut: bool,
+    /// Which gain tags tagging writes.
+    pub tag_format: TagFormat,
+    /// Trim silence off the start and end in both passes.
+    pub trim_silence: Option<SilenceTrim>,
+    /// Measure long inputs from sampled chunks only.
+    pub fast_analysis: Option<Sampling>,
+    /// Measure long inputs in this many chunks decoded concurrently, joined
+    /// by the native meter.
+    pub analysis_jobs: usize,
+    /// Compress the dynamics ahead of loudnorm in both passes.
+    pub compressor: Option<Compressor>,
+    /// Filters spliced in ahead of loudnorm in both passes, e.g.
+    /// `highpass=f=60`.
+    pub pre_filter: Option<String>,
+    /// Filters appended to the second pass, e.g. `aresample=48000`.
+    pub post_filter: Option<String>,
+    /// Derive a [`Compressor`] from the measurement when the loudness range
+    /// is well above its target, and measure again through it.
+    pub enforce_lra: bool,
+    /// Measure the dialogue, through [`crate::FilterSettings::dialogue_selection`],
+    /// in addition to the program, and derive the second-pass gain from
+    /// the dialogue loudness.
+    pub dialogue_gated: bool,
+    /// Kill an ffmpeg pass whose reported position hasn't advanced for this
+    /// long, failing with [`crate::Error::Stalled`].
+    pub timeout: Option<Duration>,
+    /// Niceness ffmpeg runs with, through `nice`.
+    pub nice: Option<i32>,
+    /// Run ffmpeg in the idle I/O scheduling class, through Linux's
+    /// `ionice`.
+    pub idle_io: bool,
+}
+
+/// Encoder settings for the normalized output. Unset fields keep ffmpeg's
+/// defaults for the output container.
+#[derive(Debug, Clone, Default)]
+pub struct EncodeOptions {
+    /// Audio codec, e.g. `aac`, `libopus` or `flac`.
+    pub codec: Option<String>,
+    /// Audio bitrate, e.g. `192k`.
+    pub bitrate: Option<String>,
+    /// Output sample rate in Hz.
+    pub sample_rate: Option<u32>,
+    /// Output sample format, e.g. `s16` or `s32`.
+    pub sample_fmt: Option<String>,
+    /// Resampler for sample rate conversions, including the one from
+    /// loudnorm's 192kHz back to the output rate. `None` keeps ffmpeg's
+    /// defaults.
+    pub resampler: Option<Resampler>,
+    /// Stream-copy the video of the input alongside the normalized audio,
+    /// keeping its timestamps.
+    pub copy_video: bool,
+    /// Carry every stream of the input over, stream-copying subtitles,
+    /// data, attachments, video and the other audio streams, so that only
+    /// the normalized audio stream is re-encoded.
+    pub map_all: bool,
+    /// Drop tags, chapters and cover art instead of carrying them over
+    /// from the input.
+    pub strip_metadata: bool,
+    /// Tags set on the output as key and value, on top of those carried
+    /// over.
+    pub metadata: Vec<(String, String)>,
+    /// Resample loudnorm's 192kHz output back to the sample rate of the
+    /// input, unless `sample_rate` sets one.
+    pub keep_sample_rate: bool,
+    /// Encode lossless outputs with the bit depth of the input, e.g. 24-bit
+    /// FLAC as 24-bit, unless `codec` or `sample_fmt` is set.
+    pub keep_bit_depth: bool,
+    /// Write byte-identical outputs for identical inputs and settings: no
+    /// library versions, random stream serials or timestamps.
+    pub reproducible: bool,
+    /// Dialogue level declared in AC-3 and E-AC-3 outputs.
+    pub dialnorm: Option<Dialnorm>,
+    /// Container of an output written to standard output, which has no
+    /// extension to pick one by.
+    pub stream_format: Option<StreamFormat>,
+}
+
+impl EncodeOptions {
+    /// The ffmpeg output arguments selecting these settings.
+    pub fn to_args(&self) -> Vec<String> {
+        self.to_args_for("a")
+    }
+
+    /// [`Self::to_args`] with the codec and bitrate applied to the output
+    /// streams of `specifier` only, e.g. `a:1`.
+    pub fn to_args_for(&self, specifier: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(codec) = &self.codec {
+            args.extend([format!("-c:{}", specifier), codec.clone()]);
+        }
+        if let Some(bitrate) = &self.bitrate {
+            args.extend([format!("-b:{}", specifier), bitrate.clone()]);
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            args.extend(["-ar".to_string(), sample_rate.to_string()]);
+        }
+        if let Some(sample_fmt) = &self.sample_fmt {
+            args.extend(["-sample_fmt".to_string(), sample_fmt.clone()]);
+        }
+        if let Some(resampler) = &self.resampler {
+            for (name, value) in resampler.options() {
+                args.extend([format!("-{}", name), value]);
+            }
+        }
+        let source = if self.strip_metadata { "-1" } else { "0" };
+        for option in ["-map_metadata", "-map_chapters"] {
+            args.extend([option.to_string(), source.to_string()]);
+        }
+        for (key, value) in &self.metadata {
+            args.extend(["-metadata".to_string(), format!("{}={}", key, value)]);
+        }
+        args.extend(self.bitexact_args());
+        if let Some(format) = self.stream_format {
+            args.extend(format.args().iter().map(|arg| arg.to_string()));
+        }
+        args
+    }
+
+    /// Output arguments making the muxer and encoders bitexact when
+    /// `reproducible` is set. ffmpeg then writes `Lavf`/`Lavc` without
+    /// versions as the encoder tags, fixed stream serials and zero creation
+    /// times; a `creation_time` tag carried over from the input is dropped.
+    pub fn bitexact_args(&self) -> Vec<String> {
+        if !self.reproducible {
+            return Vec::new();
+        }
+        [
+            "-fflags",
+            "+bitexact",
+            "-flags:a",
+            "+bitexact",
+            "-metadata",
+            "creation_time=",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+}
+
+/// What an output written to standard output is encoded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    Wav,
+    Flac,
+    /// Headerless 16-bit little-endian PCM.
+    S16le,
+}
+
+impl FromStr for StreamFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wav" => Ok(StreamFormat::Wav),
+            "flac" => Ok(StreamFormat::Flac),
+            "s16le" => Ok(StreamFormat::S16le),
+            _ => Err(format!(
+                "unknown output format '{}'; expected wav, flac or s16le",
+                s
+            )),
+        }
+    }
+}
+
+impl StreamFormat {
+    /// The ffmpeg output options selecting this format.
+    pub fn args(self) -> &'static [&'static str] {
+        match self {
+            StreamFormat::Wav => &["-f", "wav"],
+            StreamFormat::Flac => &["-f", "flac"],
+            StreamFormat::S16le => &["-f", "s16le", "-c:a", "pcm_s16le"],
+        }
+    }
+}
+
+/// The dialogue level an AC-3 or E-AC-3 output declares, which decoders
+/// use to bring programmes to a common level of -31 dB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialnorm {
+    /// The integrated loudness target, rounded, so that the output declares
+    /// the level it was normalized to.
+    Target,
+    /// A level in dB from -31 to -1.
+    Value(i32),
+}
+
+impl FromStr for Dialnorm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "target" {
+            return Ok(Dialnorm::Target);
+        }
+        match s.parse::<i32>() {
+            Ok(value) if (-31..=-1).contains(&value) => Ok(Dialnorm::Value(value)),
+            _ => Err(format!(
+                "invalid dialnorm '{}'; expected target or a level from -31 to -1 dB",
+                s
+            )),
+        }
+    }
+}
+
+impl Dialnorm {
+    /// The level in dB, for outputs normalized to `target` LUFS.
+    pub fn value(self, target: f64) -> i32 {
+        match self {
+            Dialnorm::Target => (target.round() as i32).clamp(-31, -1),
+            Dialnorm::Value(value) => value,
+        }
+    }
+}
+
+/// libswresample settings for resampling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resampler {
+    /// `swr` or `soxr`.
+    pub engine: String,
+    /// Precision in bits for `soxr`, from 15 to 33.
+    pub precision: Option<u32>,
+}
+
+impl Resampler {
+    /// The libswresample options selecting these settings, as name and
+    /// value pairs.
+    pub fn options(&self) -> Vec<(&'static str, String)> {
+        let mut options = vec![("resampler", self.engine.clone())];
+        if let Some(precision) = self.precision {
+            options.push(("precision", precision.to_string()));
+        }
+        options
+    }
+}
+
+/// How a multichannel source is folded down, e.g. 5.1 to stereo.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Downmix {
+    /// ITU-R BS.775 Lo/Ro: centre and surrounds at -3 dB, LFE dropped.
+    LoRo,
+    /// Matrix-encoded Lt/Rt, which Dolby Pro Logic decoders expand to
+    /// surround again.
+    LtRt,
+    /// Lo/Ro with the centre, where dialogue sits, at full level, the
+    /// surrounds at -6 dB and the LFE at -10 dB.
+    DialogueBoost,
+    /// The arguments of a `pan` filter, e.g.
+    /// `stereo|FL=FL+0.7*FC+0.5*LFE|FR=FR+0.7*FC+0.5*LFE`.
+    Pan(String),
+}
+
+impl FromStr for Downmix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lo-ro" => Ok(Downmix::LoRo),
+            "lt-rt" => Ok(Downmix::LtRt),
+            "dialogue-boost" => Ok(Downmix::DialogueBoost),
+            _ if s.contains('|') => Ok(Downmix::Pan(s.to_string())),
+            _ => Err(format!(
+                "unknown downmix '{}'; expected lo-ro, lt-rt, dialogue-boost or a pan matrix such as 'stereo|FL=FL+0.7*FC|FR=FR+0.7*FC'",
+                s
+            )),
+        }
+    }
+}
+
+impl Downmix {
+    /// The filter doing the downmix, followed by a comma. The presets set
+    /// the mix levels of an `aresample` stage, which does the downmix to
+    /// the layout the following `aformat` asks for, whatever the layout of
+    /// the source.
+    pub fn filter(&self) -> String {
+        match self {
+            Downmix::LoRo => "aresample=clev=0.707:slev=0.707:lfe_mix_level=0,".to_string(),
+            Downmix::LtRt => "aresample=matrix_encoding=dolby,".to_string(),
+            Downmix::DialogueBoost => {
+                "aresample=clev=1.0:slev=0.5:lfe_mix_level=0.316,".to_string()
+            }
+            Downmix::Pan(matrix) => format!("pan={},", matrix),
+        }
+    }
+
+    /// The layout a `pan` matrix produces, named before its first `|`.
+    pub fn pan_layout(&self) -> Option<&str> {
+        match self {
+            Downmix::Pan(matrix) => matrix.split('|').next(),
+            _ => None,
+        }
+    }
+}
+
+/// How many channels the layout `name` has, for the layouts
+/// `--channel-layout` offers.
+pub fn layout_channels(name: &str) -> Option<u32> {
+    match name {
+        "mono" => Some(1),
+        "stereo" => Some(2),
+        "2.1" => Some(3),
+        "quad" => Some(4),
+        "5.0" => Some(5),
+        "5.1" => Some(6),
+        "7.1" => Some(8),
+        _ => None,
+    }
+}
+
+/// Whether the layout `name` has a front center channel, which carries the
+/// dialogue of film and TV mixes. Explicit channel lists like `FL+FR+FC`
+/// are searched, and counted layouts like `6 channels` have none.
+pub fn layout_has_center(name: &str) -> bool {
+    if name.contains('+') {
+        return name.split('+').any(|channel| channel == "FC");
+    }
+    !name.ends_with(" channels")
+        && !matches!(
+            name,
+            "stereo" | "2.1" | "2.2" | "quad" | "quad(side)" | "cube" | "downmix" | "binaural"
+        )
+}
+
+impl Options {
+    /// The `-dialnorm` encoder option for the output streams of
+    /// `specifier`, e.g. `a`, when the encoding declares one.
+    pub fn dialnorm_args(&self, specifier: &str) -> Vec<String> {
+        self.encoding
+            .dialnorm
+            .map(|dialnorm| {
+                vec![
+                    format!("-dialnorm:{}", specifier),
+                    dialnorm.value(self.integrated_loudness).to_string(),
+                ]
+            })
+            .unwrap_or_default()
+    }
+
+    /// The layout the audio is converted to before measuring: the
+    /// configured one, or the one a `pan` downmix produces.
+    pub fn target_layout(&self) -> Option<&str> {
+        self.channel_layout
+            .as_deref()
+            .or_else(|| self.downmix.as_ref()?.pan_layout())
+    }
+
+    /// The `aformat=...,` stage converting to the configured channel layout,
+    /// sample rate and sample format, or nothing when none is set. A
+    /// [`Downmix`] comes first, and with a resampler, an `aresample` stage
+    /// does the rate conversion before the `aformat`. The `pre_filter`
+    /// follows, then silence trimming, so it sees the channels that loudnorm
+    /// gets, and then the compressor.
+    pub fn aformat_prefix(&self) -> String {
+        let downmix = self
+            .downmix
+            .as_ref()
+            .map(Downmix::filter)
+            .unwrap_or_default();
+        let resample = match (self.encoding.sample_rate, &self.encoding.resampler) {
+            (Some(sample_rate), Some(resampler)) => {
+                let options: Vec<String> = resampler
+                    .options()
+                    .into_iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                format!("aresample={}:{},", sample_rate, options.join(":"))
+            }
+            _ => String::new(),
+        };
+        let mut params = Vec::new();
+        if let Some(sample_fmt) = &self.encoding.sample_fmt {
+            params.push(format!("sample_fmts={}", sample_fmt));
+        }
+        if let Some(sample_rate) = self.encoding.sample_rate {
+            params.push(format!("sample_rates={}", sample_rate));
+        }
+        if let Some(channel_layout) = &self.channel_layout {
+            params.push(format!("channel_layouts={}", channel_layout));
+        }
+        let mut pre_chain = self
+            .pre_filter
+            .as_ref()
+            .map(|filter| format!("{},", filter))
+            .unwrap_or_default();
+        if let Some(trim_silence) = self.trim_silence {
+            pre_chain.push_str(&trim_silence.filter());
+            pre_chain.push(',');
+        }
+        if let Some(compressor) = &self.compressor {
+            pre_chain.push_str(&compressor.filter());
+            pre_chain.push(',');
+        }
+        if params.is_empty() {
+            format!("{}{}{}", downmix, resample, pre_chain)
+        } else {
+            format!(
+                "{}{}aformat={},{}",
+                downmix,
+                resample,
+                params.join(":"),
+                pre_chain
+            )
+        }
+    }
+
+    /// The `-map` specifier selecting the configured audio stream.
+    pub fn stream_specifier(&self) -> Option<String> {
+        self.audio_stream.map(|index| format!("0:a:{}", index))
+    }
+
+    /// Input arguments limiting measurement to the `start`/`duration`
+    /// segment. They go before `-i`.
+    pub fn segment_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(start) = &self.start {
+            args.extend(["-ss".to_string(), start.clone()]);
+        }
+        if let Some(duration) = &self.duration {
+            args.extend(["-t".to_string(), duration.clone()]);
+        }
+        args
+    }
+
+    /// The measured length in seconds for an input lasting `total` seconds,
+    /// taking `start` and `duration` into account.
+    pub fn segment_duration(&self, total: Option<f64>) -> Option<f64> {
+        let start = self
+            .start
+            .as_deref()
+            .and_then(parse_timestamp)
+            .unwrap_or(0.0);
+        let limit = self.duration.as_deref().and_then(parse_timestamp);
+        match (total, limit) {
+            (Some(total), Some(limit)) => Some(limit.min(total - start).max(0.0)),
+            (Some(total), None) => Some((total - start).max(0.0)),
+            (None, limit) => limit,
+        }
+    }
+}
+
+/// Settings of the `silenceremove` stages added with `--trim-silence`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SilenceTrim {
+    /// Level in dBFS below which audio counts as silence.
+    pub threshold: f64,
+    /// Seconds that audio has to stay above the threshold to end the
+    /// silence, so that a click doesn't.
+    pub min_duration: f64,
+}
+
+impl Default for SilenceTrim {
+    fn default() -> Self {
+        Self {
+            threshold: -60.0,
+            min_duration: 0.1,
+        }
+    }
+}
+
+impl SilenceTrim {
+    /// The `silenceremove` stages trimming the start and, between two
+    /// `areverse` stages, the end. Reversing buffers the whole stream.
+    pub fn filter(&self) -> String {
+        let trim = format!(
+            "silenceremove=start_periods=1:start_duration={:?}:start_threshold={:?}dB:detection=peak",
+            self.min_duration, self.threshold
+        );
+        format!("{trim},areverse,{trim},areverse")
+    }
+}
+
+/// Chunks measured with `--fast-analysis`: the first `chunk` seconds of
+/// every `interval`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    pub chunk: f64,
+    pub interval: f64,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            chunk: 30.0,
+            interval: 300.0,
+        }
+    }
+}
+
+impl Sampling {
+    /// Whether an input of `duration` seconds is long enough for sampling
+    /// to leave out much, taking at least two chunks.
+    pub fn applies_to(&self, duration: f64) -> bool {
+        duration >= 2.0 * self.interval && self.chunk < self.interval
+    }
+
+    /// The `aselect` stage keeping the chunks, with timestamps made
+    /// contiguous again so loudnorm sees one stream. The input is still
+    /// decoded in full; only the measuring is saved.
+    pub fn filter(&self) -> String {
+        format!(
+            "aselect='lt(mod(t,{:?}),{:?})',asetpts=N/SR/TB",
+            self.interval, self.chunk
+        )
+    }
+
+    /// Seconds of audio left of `duration` seconds.
+    pub fn sampled_duration(&self, duration: f64) -> f64 {
+        (duration / self.interval).floor() * self.chunk + (duration % self.interval).min(self.chunk)
+    }
+}
+
+/// Settings of an `acompressor` stage narrowing the loudness range ahead
+/// of loudnorm, derived by `--enforce-lra`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Compressor {
+    /// Level in dBFS above which the gain is reduced.
+    pub threshold: f64,
+    pub ratio: f64,
+    /// Attack time in milliseconds.
+    pub attack: f64,
+    /// Release time in milliseconds.
+    pub release: f64,
+}
+
+impl Compressor {
+    /// Slow enough to follow the loudness of passages rather than
+    /// transients, which loudnorm's limiter handles.
+    const ATTACK_MS: f64 = 50.0;
+    const RELEASE_MS: f64 = 1000.0;
+
+    /// A compressor bringing the measured loudness range of `loudness` down
+    /// to the target of `options`: levels above the middle of the target
+    /// range around the integrated loudness are reduced by the ratio of the
+    /// ranges.
+    pub fn for_range(loudness: &Loudness, options: &Options) -> Self {
+        Self {
+            threshold: (loudness.input_i - options.loudness_range / 2.0).clamp(-60.0, 0.0),
+            ratio: (loudness.input_lra / options.loudness_range).clamp(1.5, 20.0),
+            attack: Self::ATTACK_MS,
+            release: Self::RELEASE_MS,
+        }
+    }
+
+    /// The `acompressor` stage, leaving the make-up gain to loudnorm.
+    pub fn filter(&self) -> String {
+        format!(
+            "acompressor=threshold={:.6}:ratio={:.2}:attack={}:release={}:knee=6",
+            10f64.powf(self.threshold / 20.0),
+            self.ratio,
+            self.attack,
+            self.release
+        )
+    }
+}
+
+/// Settings of the `alimiter` appended with `--limiter`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limiter {
+    /// Attack time in milliseconds.
+    pub attack: f64,
+    /// Release time in milliseconds.
+    pub release: f64,
+    /// Ceiling in dBTP. `None` uses the true peak target.
+    pub ceiling: Option<f64>,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self {
+            attack: 5.0,
+            release: 50.0,
+            ceiling: None,
+        }
+    }
+}
+
+/// Measurement the normalization targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// EBU R128 integrated loudness, normalized by loudnorm.
+    #[default]
+    Ebu,
+    /// Plain gain bringing the true peak to the true peak target, without
+    /// loudnorm's resampling and gating.
+    Peak,
+    /// Plain gain bringing the RMS level to `target_rms`, for pipelines
+    /// specified in RMS rather than LUFS.
+    Rms,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ebu" => Ok(Mode::Ebu),
+            "peak" => Ok(Mode::Peak),
+            "rms" => Ok(Mode::Rms),
+            _ => Err(format!("unknown mode '{}'", s)),
+        }
+    }
+}
+
+/// The peak measured and held under the ceiling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PeakMode {
+    /// The true peak between samples, as EBU R128 specifies it.
+    #[default]
+    True,
+    /// The highest sample, for specs defined by sample peak. The first pass
+    /// measures with ebur128 at the input's rate instead of loudnorm at
+    /// 192 kHz, which is considerably faster.
+    Sample,
+}
+
+impl FromStr for PeakMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "true" => Ok(PeakMode::True),
+            "sample" => Ok(PeakMode::Sample),
+            _ => Err(format!("unknown peak mode '{}'", s)),
+        }
+    }
+}
+
+/// The ffmpeg filter that normalizes the audio.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Engine {
+    /// Two-pass EBU R128 normalization with loudnorm.
+    #[default]
+    Loudnorm,
+    /// Single-pass dynamic normalization with dynaudnorm.
+    Dynaudnorm(Dynaudnorm),
+    /// Single-pass speech normalization with speechnorm.
+    Speechnorm(Speechnorm),
+}
+
+/// Settings of the dynaudnorm filter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dynaudnorm {
+    /// Frame length in milliseconds.
+    pub frame_len: u32,
+    /// Size of the gaussian smoothing window in frames. Must be odd.
+    pub gauss_size: u32,
+    /// Target peak value, from 0 to 1.
+    pub peak: f64,
+    /// Maximum gain factor.
+    pub max_gain: f64,
+}
+
+impl Default for Dynaudnorm {
+    fn default() -> Self {
+        Self {
+            frame_len: 500,
+            gauss_size: 31,
+            peak: 0.95,
+            max_gain: 10.0,
+        }
+    }
+}
+
+/// Settings of the speechnorm filter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Speechnorm {
+    /// Target peak value, from 0 to 1.
+    pub peak: f64,
+    /// Maximum expansion factor per half-cycle.
+    pub expansion: f64,
+    /// Maximum compression factor per half-cycle.
+    pub compression: f64,
+}
+
+impl Default for Speechnorm {
+    fn default() -> Self {
+        Self {
+            peak: 0.95,
+            expansion: 2.0,
+            compression: 2.0,
+        }
+    }
+}
+
+/// Second-pass normalization mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Always request linear normalization. loudnorm silently falls back to
+    /// dynamic mode when the measurements don't allow it.
+    Linear,
+    /// Always normalize dynamically.
+    Dynamic,
+    /// Use linear normalization when the measurements allow it, dynamic
+    /// normalization otherwise.
+    #[default]
+    Auto,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Strategy::Linear),
+            "dynamic" => Ok(Strategy::Dynamic),
+            "auto" => Ok(Strategy::Auto),
+            _ => Err(format!("unknown strategy '{}'", s)),
+        }
+    }
+}
+
+/// Implementation of the measurement pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Run ffmpeg's loudnorm filter.
+    #[default]
+    Ffmpeg,
+    /// Measure in-process without ffmpeg. Requires the `native` feature and
+    /// only reads WAV files.
+    Native,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ffmpeg" => Ok(Backend::Ffmpeg),
+            "native" => Ok(Backend::Native),
+            _ => Err(format!("unknown backend '{}'", s)),
+        }
+    }
+}
+
+/// Gain tags written when tagging instead of re-encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TagFormat {
+    /// ReplayGain tags, plus `R128_TRACK_GAIN`/`R128_ALBUM_GAIN` for Opus.
+    #[default]
+    Auto,
+    /// ReplayGain tags only, also for Opus.
+    ReplayGain,
+    /// Only the Opus `R128_TRACK_GAIN`/`R128_ALBUM_GAIN` tags, which
+    /// players apply on top of the header's output gain. Inputs other than
+    /// Opus are refused.
+    R128,
+}
+
+impl FromStr for TagFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(TagFormat::Auto),
+            "replaygain" => Ok(TagFormat::ReplayGain),
+            "r128" => Ok(TagFormat::R128),
+            _ => Err(format!("unknown tag format '{}'", s)),
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            integrated_loudness: -23.0,
+            loudness_range: 7.0,
+            true_peak: -2.0,
+            channel_layout: None,
+            downmix: None,
+            pre_filter: None,
+            post_filter: None,
+            dual_mono: false,
+            offset: None,
+            measured: None,
+            pass_silent: false,
+            mode: Mode::default(),
+            peak_mode: PeakMode::default(),
+            target_rms: -20.0,
+            engine: Engine::default(),
+            strategy: Strategy::default(),
+            limiter: None,
+            audio_stream: None,
+            ffmpeg_path: None,
+            cache_dir: None,
+            history_db: None,
+            encoding: EncodeOptions::default(),
+            backend: Backend::default(),
+            start: None,
+            duration: None,
+            cut: false,
+            tag_format: TagFormat::default(),
+            trim_silence: None,
+            fast_analysis: None,
+            analysis_jobs: 1,
+            compressor: None,
+            enforce_lra: false,
+            dialogue_gated: false,
+            timeout: None,
+            nice: None,
+            idle_io: false,
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! M3U/M3U8 playlists, read as inputs and written for the outputs.
+
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A track of a playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub path: PathBuf,
+    /// The `#EXTINF` line describing the track, without the directive.
+    pub info: Option<String>,
+}
+
+/// The tracks of an M3U or M3U8 playlist, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Playlist {
+    pub entries: Vec<PlaylistEntry>,
+}
+
+impl Playlist {
+    /// Whether `path` names a playlist, judged by its extension.
+    pub fn is_playlist(path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+    }
+
+    /// Reads the playlist at `path`. Relative entries are resolved against
+    /// the directory of the playlist; URLs are kept as they are.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let contents = fs::read(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        // Plain .m3u files are often Latin-1; such lines are best effort.
+        let contents = String::from_utf8_lossy(&contents);
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut entries = Vec::new();
+        let mut info = None;
+        for line in contents.lines() {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+                info = Some(extinf.to_string());
+            } else if !line.is_empty() && !line.starts_with('#') {
+                entries.push(PlaylistEntry {
+                    path: resolve(base, line),
+                    info: info.take(),
+                });
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// Writes the playlist to `path` as extended M3U, with entries below
+    /// the playlist's directory written relative to it.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut contents = String::from("#EXTM3U\n");
+        for entry in &self.entries {
+            if let Some(info) = &entry.info {
+                let _ = writeln!(contents, "#EXTINF:{}", info);
+            }
+            let entry_path = match entry.path.strip_prefix(base) {
+                Ok(relative) if !base.as_os_str().is_empty() => relative,
+                _ => entry.path.as_path(),
+            };
+            let _ = writeln!(contents, "{}", entry_path.display());
+        }
+        fs::write(path, contents)
+    }
+}
+
+fn resolve(base: &Path, entry: &str) -> PathBuf {
+    if let Some(file_path) = entry.strip_prefix("file://") {
+        return PathBuf::from(percent_decode(file_path));
+    }
+    if entry.contains("://") {
+        return PathBuf::from(entry);
+    }
+    let entry_path = if cfg!(windows) {
+        PathBuf::from(entry)
+    } else {
+        PathBuf::from(entry.replace('\\', "/"))
+    };
+    if entry_path.is_absolute() {
+        entry_path
+    } else {
+        base.join(entry_path)
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
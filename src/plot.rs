@@ -0,0 +1,165 @@
+use crate::{Options, Timeline, TimelinePoint};
+use std::{fmt::Write as _, fs, io, path::Path};
+
+const WIDTH: f64 = 960.0;
+const HEIGHT: f64 = 400.0;
+const MARGIN_LEFT: f64 = 56.0;
+const MARGIN_RIGHT: f64 = 16.0;
+const MARGIN_TOP: f64 = 24.0;
+const MARGIN_BOTTOM: f64 = 40.0;
+/// Lowest level drawn, in LUFS. Quieter readings are clamped to it.
+const FLOOR: f64 = -60.0;
+
+/// Picks the value of one series out of a timeline point.
+type Reading = fn(&TimelinePoint) -> Option<f64>;
+
+/// Draws a timeline as an SVG loudness-over-time chart.
+pub struct LoudnessPlot;
+
+impl LoudnessPlot {
+    /// Writes the chart for `timeline` to `path`. Only SVG output is
+    /// supported.
+    pub fn write(timeline: &Timeline, path: &Path, options: &Options) -> io::Result<()> {
+        let svg = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+        if !svg {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{}: plots can only be written as .svg", path.display()),
+            ));
+        }
+        fs::write(path, Self::render(timeline, options))
+    }
+
+    /// Renders momentary, short-term and integrated loudness, the per-frame
+    /// true peak, and the target and true peak ceiling from `options`.
+    pub fn render(timeline: &Timeline, options: &Options) -> String {
+        let duration = timeline
+            .points
+            .last()
+            .map_or(1.0, |point| point.time)
+            .max(f64::EPSILON);
+        let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+        let plot_height = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+        let x = |time: f64| MARGIN_LEFT + time / duration * plot_width;
+        let y = |level: f64| MARGIN_TOP + level.clamp(FLOOR, 0.0) / FLOOR * plot_height;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">"#,
+            w = WIDTH,
+            h = HEIGHT
+        );
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+
+        for level in (FLOOR as i32..=0).step_by(10) {
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{x1}" x2="{x2}" y1="{y:.1}" y2="{y:.1}" stroke="#ddd"/><text x="{tx}" y="{ty:.1}" text-anchor="end">{level}</text>"##,
+                x1 = MARGIN_LEFT,
+                x2 = WIDTH - MARGIN_RIGHT,
+                y = y(level as f64),
+                tx = MARGIN_LEFT - 6.0,
+                ty = y(level as f64) + 4.0,
+                level = level
+            );
+        }
+        for step in 0..=10 {
+            let time = duration * step as f64 / 10.0;
+            let _ = writeln!(
+                svg,
+                r#"<text x="{x:.1}" y="{y}" text-anchor="middle">{label}</text>"#,
+                x = x(time),
+                y = HEIGHT - MARGIN_BOTTOM + 16.0,
+                label = if duration < 60.0 {
+                    format!("{:.1}s", time)
+                } else {
+                    format_time(time)
+                }
+            );
+        }
+        let _ = writeln!(
+            svg,
+            r#"<text x="{x}" y="{y}" text-anchor="middle">time</text><text x="14" y="{ly}" transform="rotate(-90 14 {ly})" text-anchor="middle">LUFS / dBTP</text>"#,
+            x = MARGIN_LEFT + plot_width / 2.0,
+            y = HEIGHT - 6.0,
+            ly = MARGIN_TOP + plot_height / 2.0
+        );
+
+        let series: [(&str, &str, Reading); 4] = [
+            ("momentary", "#9ecae1", |p| Some(p.momentary)),
+            ("short-term", "#3182bd", |p| Some(p.short_term)),
+            ("integrated", "#e6550d", |p| Some(p.integrated)),
+            ("true peak", "#31a354", |p| p.true_peak),
+        ];
+        for (index, (name, color, value)) in series.iter().enumerate() {
+            let points: Vec<String> = timeline
+                .points
+                .iter()
+                .filter_map(|point| {
+                    value(point).map(|level| format!("{:.1},{:.1}", x(point.time), y(level)))
+                })
+                .collect();
+            if !points.is_empty() {
+                let _ = writeln!(
+                    svg,
+                    r#"<polyline fill="none" stroke="{}" stroke-width="1.2" points="{}"/>"#,
+                    color,
+                    points.join(" ")
+                );
+            }
+            legend(&mut svg, index, name, color, "");
+        }
+
+        let references = [
+            (options.integrated_loudness, "target", "#e6550d"),
+            (options.true_peak, "true peak ceiling", "#de2d26"),
+        ];
+        for (offset, &(level, name, color)) in references.iter().enumerate() {
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{x1}" x2="{x2}" y1="{y:.1}" y2="{y:.1}" stroke="{color}" stroke-dasharray="6 4"/>"#,
+                x1 = MARGIN_LEFT,
+                x2 = WIDTH - MARGIN_RIGHT,
+                y = y(level),
+                color = color
+            );
+            legend(
+                &mut svg,
+                series.len() + offset,
+                name,
+                color,
+                r#" stroke-dasharray="6 4""#,
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+fn legend(svg: &mut String, index: usize, name: &str, color: &str, style: &str) {
+    let x = MARGIN_LEFT + 8.0 + index as f64 * 130.0;
+    let _ = writeln!(
+        svg,
+        r#"<line x1="{x}" x2="{x2}" y1="12" y2="12" stroke="{color}" stroke-width="2"{style}/><text x="{tx}" y="16">{name}</text>"#,
+        x = x,
+        x2 = x + 18.0,
+        color = color,
+        style = style,
+        tx = x + 22.0,
+        name = name
+    );
+}
+
+/// Formats seconds as `m:ss`, or `h:mm:ss` for long inputs.
+fn format_time(seconds: f64) -> String {
+    let total = seconds.round() as u64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
@@ -0,0 +1,71 @@
+/// Loudness targets for a common delivery specification.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub description: &'static str,
+    pub integrated_loudness: f64,
+    pub loudness_range: f64,
+    pub true_peak: f64,
+}
+
+/// Built-in presets, selectable with `--preset`.
+pub const PRESETS: [Preset; 5] = [
+    Preset {
+        name: "ebu-r128",
+        aliases: &[],
+        description: "EBU R128 broadcast, -23 LUFS",
+        integrated_loudness: -23.0,
+        loudness_range: 7.0,
+        true_peak: -1.0,
+    },
+    Preset {
+        name: "streaming",
+        aliases: &["spotify"],
+        description: "Music streaming services, -14 LUFS",
+        integrated_loudness: -14.0,
+        loudness_range: 11.0,
+        true_peak: -1.0,
+    },
+    Preset {
+        name: "youtube",
+        aliases: &[],
+        description: "YouTube, -14 LUFS and -1 dBTP",
+        integrated_loudness: -14.0,
+        loudness_range: 11.0,
+        true_peak: -1.0,
+    },
+    Preset {
+        name: "podcast",
+        aliases: &[],
+        description: "Podcasts and spoken word, -16 LUFS",
+        integrated_loudness: -16.0,
+        loudness_range: 11.0,
+        true_peak: -1.5,
+    },
+    Preset {
+        name: "broadcast-atsc",
+        aliases: &["atsc"],
+        description: "ATSC A/85 broadcast, -24 LKFS",
+        integrated_loudness: -24.0,
+        loudness_range: 7.0,
+        true_peak: -2.0,
+    },
+];
+
+impl Preset {
+    /// Looks up a built-in preset by name or alias.
+    pub fn find(name: &str) -> Option<Preset> {
+        PRESETS.into_iter().find(|p| {
+            p.name.eq_ignore_ascii_case(name)
+                || p.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// All names accepted by [`Preset::find`].
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        PRESETS
+            .iter()
+            .flat_map(|p| std::iter::once(p.name).chain(p.aliases.iter().copied()))
+    }
+}
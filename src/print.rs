@@ -0,0 +1,374 @@
+//! Printing the [`FileResult`] of each input as `--format`, `--print` and
+//! `--target` ask, and running the `--post-hook` for its outputs.
+
+use crate::{
+    hook::HookValues,
+    process::{FileResult, StreamResult},
+    Audiobook, ChapterResult, FilterSettings, FilterTarget, Loudness, NormalizationType, Options,
+    OutputFormat, PeakMode, PrintValue, ProgressFormat, RunConfig, Verification, STDOUT_PATH,
+};
+use std::{
+    ffi::OsStr,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Prints `result`, then fails if its output didn't pass `--verify`.
+pub(crate) fn report_result(
+    config: &RunConfig,
+    result: &FileResult,
+    batch: bool,
+) -> io::Result<()> {
+    print_result(config, result, batch)?;
+    let variants = result.variants.iter().map(|v| &v.verification);
+    std::iter::once(&result.verification)
+        .chain(variants)
+        .flatten()
+        .try_for_each(Verification::ensure_passed)?;
+    if config.verify_tolerance.is_some() {
+        Audiobook::ensure_passed(&result.chapters)?;
+    }
+    run_post_hook(config, result)
+}
+
+pub(crate) fn print_result(config: &RunConfig, result: &FileResult, batch: bool) -> io::Result<()> {
+    if config.progress_format == ProgressFormat::Jsonl || config.tui {
+        // The result went out with the file_done event or is on the
+        // dashboard.
+        return Ok(());
+    }
+    if result.output.as_deref() == Some(Path::new(STDOUT_PATH)) && result.command.is_none() {
+        // Standard output carries the audio.
+        return Ok(());
+    }
+    let text = match (config.format, &result.tags, &result.filter) {
+        (OutputFormat::Json, _, _) => {
+            println!("{}", serde_json::to_string(result)?);
+            return Ok(());
+        }
+        (OutputFormat::Text, _, _) if result.command.is_some() => {
+            println!("{}", result.command.as_deref().unwrap_or_default());
+            return Ok(());
+        }
+        (OutputFormat::Text, None, _) if config.print == PrintValue::Summary => {
+            let Some(loudness) = &result.loudness else {
+                return Ok(());
+            };
+            if batch {
+                println!("{}:", result.input.display());
+            }
+            println!("{}", format_summary(loudness));
+            return Ok(());
+        }
+        (OutputFormat::Text, _, _) if config.report => {
+            println!("{}", result.input.display());
+            if let Some(loudness) = &result.loudness {
+                println!("{}", format_report(loudness, &config.options));
+            }
+            if !result.streams.is_empty() {
+                println!("{}", format_stream_table(&result.streams, &config.options));
+            }
+            return Ok(());
+        }
+        (OutputFormat::Text, Some(tags), _) => tags
+            .to_metadata(config.options.tag_format)
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join(" "),
+        (OutputFormat::Text, None, _) if !result.chapters.is_empty() => {
+            if batch {
+                println!("{}:", result.input.display());
+            }
+            if let Some(verification) = &result.verification {
+                println!("{}", verification);
+            }
+            println!("{}", format_chapter_table(&result.chapters));
+            return Ok(());
+        }
+        (OutputFormat::Text, None, _) if result.verification.is_some() => result
+            .verification
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        (OutputFormat::Text, None, _) if result.output.is_some() => return Ok(()),
+        (OutputFormat::Text, None, _) if !result.variants.is_empty() => {
+            let verified: Vec<String> = result
+                .variants
+                .iter()
+                .filter_map(|v| Some(format!("{}: {}", v.variant, v.verification.as_ref()?)))
+                .collect();
+            if verified.is_empty() {
+                return Ok(());
+            }
+            verified.join("; ")
+        }
+        (OutputFormat::Text, None, Some(_)) if config.print == PrintValue::Gain => {
+            format!("{:.2}", result.gain_db.unwrap_or(0.0))
+        }
+        (OutputFormat::Text, None, Some(filter)) => {
+            let filter = match config.print {
+                PrintValue::Volume => {
+                    FilterSettings::construct_gain(&config.options, result.gain_db.unwrap_or(0.0))
+                }
+                _ => filter.clone(),
+            };
+            let filter = match config.target {
+                FilterTarget::Ffmpeg => filter,
+                FilterTarget::Mpv => FilterSettings::to_mpv_option(&filter),
+            };
+            match config.escape {
+                Some(shell) => shell.quote(OsStr::new(&filter)),
+                None => filter,
+            }
+        }
+        (OutputFormat::Text, None, None) => return Ok(()),
+    };
+    if config.print0 {
+        let mut record = Vec::new();
+        if batch {
+            record.extend_from_slice(result.input.as_os_str().as_encoded_bytes());
+            record.push(0);
+        }
+        record.extend_from_slice(text.as_bytes());
+        record.push(0);
+        return io::stdout().lock().write_all(&record);
+    }
+    if batch {
+        println!("{}: {}", result.input.display(), text);
+    } else {
+        println!("{}", text);
+    }
+    Ok(())
+}
+
+/// Runs the `--post-hook` for each output written for `result`.
+pub(crate) fn run_post_hook(config: &RunConfig, result: &FileResult) -> io::Result<()> {
+    let Some(hook) = &config.post_hook else {
+        return Ok(());
+    };
+    let loudness = result.loudness.as_ref();
+    if let Some(output) = &result.output {
+        hook.run(&HookValues {
+            input: &result.input,
+            output,
+            loudness,
+            gain_db: result.album.map(|album| album.gain_db).or(result.gain_db),
+            target_i: config.options.integrated_loudness,
+        })?;
+    }
+    for variant in &result.variants {
+        let options = config
+            .variants
+            .iter()
+            .find(|(name, _)| *name == variant.variant)
+            .map(|(_, preset)| config.variant_options(preset))
+            .unwrap_or_else(|| config.options.clone());
+        hook.run(&HookValues {
+            input: &result.input,
+            output: &variant.output,
+            loudness,
+            gain_db: loudness.and_then(|loudness| FilterSettings::gain_db(&options, loudness)),
+            target_i: options.integrated_loudness,
+        })?;
+    }
+    Ok(())
+}
+
+/// `loudness` laid out like loudnorm's `print_format=summary`, with the
+/// output values it predicts for the second pass where known.
+fn format_summary(loudness: &Loudness) -> String {
+    let row = |label: &str, value: f64, unit: &str| format!("{:<20}{:+6.1} {}", label, value, unit);
+    let mut lines = vec![
+        row("Input Integrated:", loudness.input_i, "LUFS"),
+        row("Input True Peak:", loudness.input_tp, "dBTP"),
+        format!("{:<20}{:6.1} LU", "Input LRA:", loudness.input_lra),
+        row("Input Threshold:", loudness.input_thresh, "LUFS"),
+    ];
+    if let (Some(output_i), Some(output_tp), Some(output_lra)) =
+        (loudness.output_i, loudness.output_tp, loudness.output_lra)
+    {
+        lines.extend([
+            String::new(),
+            row("Output Integrated:", output_i, "LUFS"),
+            row("Output True Peak:", output_tp, "dBTP"),
+            format!("{:<20}{:6.1} LU", "Output LRA:", output_lra),
+        ]);
+    }
+    lines.push(String::new());
+    if let Some(normalization_type) = loudness.normalization_type {
+        let name = match normalization_type {
+            NormalizationType::Linear => "Linear",
+            NormalizationType::Dynamic => "Dynamic",
+        };
+        lines.push(format!("{:<20} {}", "Normalization Type:", name));
+    }
+    lines.push(row("Target Offset:", loudness.target_offset, "LU"));
+    lines.join("\n")
+}
+
+/// A table of the streams measured with `--all-audio-streams`, one row per
+/// stream with its language, layout, measurements and compliance.
+fn format_stream_table(streams: &[StreamResult], options: &Options) -> String {
+    let mut lines = vec![format!(
+        "  {:>6}  {:<8}  {:<16}  {:>12}  {:>11}  {:>8}  Target {:.1} LUFS / {:.1} dBTP",
+        "Stream",
+        "Language",
+        "Layout",
+        "Integrated",
+        "True peak",
+        "LRA",
+        options.integrated_loudness,
+        options.true_peak
+    )];
+    lines.extend(streams.iter().map(|stream| {
+        let loudness = &stream.loudness;
+        format!(
+            "  {:>6}  {:<8}  {:<16}  {:>7.2} LUFS  {:>6.2} dBTP  {:>5.2} LU  {}",
+            stream.audio_stream,
+            stream.language.as_deref().unwrap_or("-"),
+            stream.channel_layout.as_deref().unwrap_or("-"),
+            loudness.input_i,
+            loudness.input_tp,
+            loudness.input_lra,
+            if stream.compliant { "pass" } else { "FAIL" }
+        )
+    }));
+    lines.join("\n")
+}
+
+/// One line per chapter of `--audiobook`: where it starts, its loudness in
+/// the input and the gain it got, and its loudness in the output when it
+/// was verified.
+fn format_chapter_table(chapters: &[ChapterResult]) -> String {
+    let mut lines = vec![format!(
+        "  {:>3}  {:>8}  {:<24}  {:>12}  {:>9}  {:>12}  {:>11}",
+        "#", "Start", "Title", "Input", "Gain", "Output", "True peak"
+    )];
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    lines.extend(chapters.iter().enumerate().map(|(index, chapter)| {
+        let seconds = chapter.start as u64;
+        let title: String = chapter
+            .title
+            .as_deref()
+            .unwrap_or("-")
+            .chars()
+            .take(24)
+            .collect();
+        let verification = chapter.verification.as_ref();
+        let row = format!(
+            "  {:>3}  {:>2}:{:02}:{:02}  {:<24}  {:>12}  {:>9}  {:>12}  {:>11}  {}",
+            index + 1,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            title,
+            or_dash(
+                chapter
+                    .loudness
+                    .as_ref()
+                    .map(|l| format!("{:.2} LUFS", l.input_i))
+            ),
+            or_dash(chapter.gain_db.map(|gain| format!("{:+.2} dB", gain))),
+            or_dash(verification.map(|v| format!("{:.2} LUFS", v.integrated_loudness))),
+            or_dash(verification.map(|v| format!("{:.2} dBTP", v.true_peak))),
+            verification.map_or("", |v| if v.passed { "pass" } else { "FAIL" })
+        );
+        row.trim_end().to_string()
+    }));
+    lines.join("\n")
+}
+
+/// Formats the `analyze` report for one measurement, comparing it with the
+/// targets in `options`.
+fn format_report(loudness: &Loudness, options: &Options) -> String {
+    let compare = |measured: f64, target: f64, unit: &str, noun: &str| {
+        if measured.is_finite() {
+            let difference = measured - target;
+            let direction = if difference < 0.0 { "below" } else { "above" };
+            let difference_unit = match unit {
+                "LUFS" => "LU",
+                "dBTP" | "dBFS" => "dB",
+                unit => unit,
+            };
+            format!(
+                "{:.2} {} {} the {:.1} {} {}",
+                difference.abs(),
+                difference_unit,
+                direction,
+                target,
+                unit,
+                noun
+            )
+        } else {
+            format!("{} {:.1} {}", noun, target, unit)
+        }
+    };
+    let mut lines = vec![
+        format!(
+            "  Integrated loudness: {:>7.2} LUFS ({})",
+            loudness.input_i,
+            compare(
+                loudness.input_i,
+                options.integrated_loudness,
+                "LUFS",
+                "target"
+            )
+        ),
+        match options.peak_mode {
+            PeakMode::True => format!(
+                "  True peak:           {:>7.2} dBTP ({})",
+                loudness.input_tp,
+                compare(loudness.input_tp, options.true_peak, "dBTP", "ceiling")
+            ),
+            PeakMode::Sample => format!(
+                "  Sample peak:         {:>7.2} dBFS ({})",
+                loudness.input_tp,
+                compare(loudness.input_tp, options.true_peak, "dBFS", "ceiling")
+            ),
+        },
+        format!(
+            "  Loudness range:      {:>7.2} LU   ({})",
+            loudness.input_lra,
+            compare(loudness.input_lra, options.loudness_range, "LU", "target")
+        ),
+        format!("  Gating threshold:    {:>7.2} LUFS", loudness.input_thresh),
+    ];
+    if let Some(dialogue) = loudness.dialogue_i {
+        lines.push(format!(
+            "  Dialogue loudness:   {:>7.2} LUFS ({})",
+            dialogue,
+            compare(dialogue, options.integrated_loudness, "LUFS", "target")
+        ));
+    }
+    if let Some(rms) = loudness.input_rms {
+        lines.push(format!(
+            "  RMS level:           {:>7.2} dBFS ({})",
+            rms,
+            compare(rms, options.target_rms, "dBFS", "target")
+        ));
+    }
+    match (options.peak_mode, loudness.sample_peak) {
+        (PeakMode::True, Some(peak)) => lines.push(format!(
+            "  Sample peak:         {:>7.2} dBFS ({} clipped samples)",
+            peak,
+            loudness.clipped_samples.unwrap_or(0)
+        )),
+        (PeakMode::Sample, Some(_)) => lines.push(format!(
+            "  Clipped samples:     {:>7}",
+            loudness.clipped_samples.unwrap_or(0)
+        )),
+        (_, None) => {}
+    }
+    if let Some(dialnorm) = loudness.dialnorm {
+        lines.push(format!(
+            "  Dialnorm:            {:>7} dB   (decoders play it {} dB quieter)",
+            dialnorm,
+            31 + dialnorm
+        ));
+    }
+    if loudness.approximate {
+        lines.push("  (approximate: measured from sampled chunks)".to_string());
+    }
+    lines.join("\n")
+}
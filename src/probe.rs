@@ -0,0 +1,258 @@
+use crate::{ffmpeg, Error, Options};
+use serde::Deserialize;
+use std::{collections::BTreeMap, io, path::Path, process::Stdio};
+
+/// Stream and container details gathered with ffprobe before analysis.
+#[derive(Debug, Clone, Default)]
+pub struct MediaInfo {
+    /// Container duration in seconds, when known.
+    pub duration: Option<f64>,
+    /// Container format name as reported by ffprobe, e.g. `mov,mp4,m4a`.
+    pub format_name: Option<String>,
+    /// The audio streams, in the order `-map 0:a:N` addresses them.
+    pub audio_streams: Vec<AudioStreamInfo>,
+    /// Codec types of every stream in the container (`video`, `subtitle`, ...).
+    pub stream_types: Vec<String>,
+    /// Absolute indices of embedded cover art streams.
+    pub attached_pics: Vec<usize>,
+    /// Container tags merged with those of the audio streams, which is
+    /// where Ogg keeps them, with upper-case keys.
+    pub tags: BTreeMap<String, String>,
+    /// The chapters of the container, in order.
+    pub chapters: Vec<Chapter>,
+}
+
+/// A chapter of the container, e.g. of an M4B audiobook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    /// Start in seconds.
+    pub start: f64,
+    /// End in seconds.
+    pub end: f64,
+    pub title: Option<String>,
+}
+
+impl Chapter {
+    /// Length in seconds.
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// Details of a single audio stream.
+#[derive(Debug, Clone, Default)]
+pub struct AudioStreamInfo {
+    /// Absolute stream index within the container.
+    pub index: usize,
+    pub codec_name: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub sample_fmt: Option<String>,
+    pub bits_per_sample: Option<u32>,
+    pub channels: Option<u32>,
+    pub channel_layout: Option<String>,
+    pub language: Option<String>,
+    /// Stream duration in seconds, when the container reports it.
+    pub duration: Option<f64>,
+    /// Dialogue level in dB an AC-3 or E-AC-3 stream declares, from -31
+    /// to -1, when ffprobe reports it as a tag or side data.
+    pub dialnorm: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+    #[serde(default)]
+    chapters: Vec<ProbeChapter>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    index: usize,
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    sample_rate: Option<String>,
+    sample_fmt: Option<String>,
+    bits_per_raw_sample: Option<String>,
+    channels: Option<u32>,
+    channel_layout: Option<String>,
+    duration: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+    #[serde(default)]
+    disposition: BTreeMap<String, i64>,
+    #[serde(default)]
+    side_data_list: Vec<BTreeMap<String, serde_json::Value>>,
+}
+
+impl ProbeStream {
+    /// The dialnorm of the stream, from a `dialnorm` tag or side data entry.
+    fn dialnorm(&self) -> Option<i32> {
+        let tag = self
+            .tags
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("dialnorm"))
+            .and_then(|(_, value)| value.trim().parse().ok());
+        let side_data = || {
+            self.side_data_list
+                .iter()
+                .find_map(|side_data| match side_data.get("dialnorm")? {
+                    serde_json::Value::Number(n) => n.as_i64(),
+                    serde_json::Value::String(s) => s.trim().parse().ok(),
+                    _ => None,
+                })
+                .and_then(|value| i32::try_from(value).ok())
+        };
+        tag.or_else(side_data)
+            .filter(|value| (-31..=-1).contains(value))
+    }
+}
+
+#[derive(Deserialize)]
+struct ProbeChapter {
+    start_time: Option<String>,
+    end_time: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+    format_name: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+impl MediaInfo {
+    /// Runs ffprobe on `input_path`.
+    pub fn probe(input_path: &Path, options: &Options) -> io::Result<Self> {
+        let output = ffmpeg::output(
+            ffmpeg::ffprobe_command(options)?
+                .args([
+                    "-v",
+                    "error",
+                    "-print_format",
+                    "json",
+                    "-show_format",
+                    "-show_streams",
+                    "-show_chapters",
+                ])
+                .args(ffmpeg::protocol_args(input_path))
+                .arg(ffmpeg::path_arg(input_path))
+                .stdin(Stdio::null()),
+        )?;
+
+        if !output.status.success() {
+            return Err(Error::process_failed("ffprobe", &output).into());
+        }
+
+        let parsed: ProbeOutput =
+            serde_json::from_slice(&output.stdout).map_err(|e| Error::InvalidOutput {
+                message: format!("Failed to parse ffprobe output: {}", e),
+                text: String::from_utf8_lossy(&output.stdout).into_owned(),
+            })?;
+        Ok(Self::from_probe_output(parsed))
+    }
+
+    fn from_probe_output(parsed: ProbeOutput) -> Self {
+        let parse_f64 = |value: Option<&String>| value.and_then(|v| v.parse::<f64>().ok());
+        let stream_types = parsed
+            .streams
+            .iter()
+            .map(|s| s.codec_type.clone().unwrap_or_default())
+            .collect();
+        let attached_pics = parsed
+            .streams
+            .iter()
+            .filter(|s| s.disposition.get("attached_pic") == Some(&1))
+            .map(|s| s.index)
+            .collect();
+        let audio_streams = parsed
+            .streams
+            .iter()
+            .filter(|s| s.codec_type.as_deref() == Some("audio"))
+            .map(|s| AudioStreamInfo {
+                index: s.index,
+                codec_name: s.codec_name.clone(),
+                sample_rate: s.sample_rate.as_ref().and_then(|r| r.parse().ok()),
+                sample_fmt: s.sample_fmt.clone(),
+                bits_per_sample: s.bits_per_raw_sample.as_ref().and_then(|b| b.parse().ok()),
+                channels: s.channels,
+                channel_layout: s.channel_layout.clone(),
+                language: s.tags.get("language").cloned(),
+                duration: parse_f64(s.duration.as_ref()),
+                dialnorm: s.dialnorm(),
+            })
+            .collect();
+        let format = parsed.format.as_ref();
+        let tags = parsed
+            .streams
+            .iter()
+            .filter(|s| s.codec_type.as_deref() == Some("audio"))
+            .flat_map(|s| &s.tags)
+            .chain(format.into_iter().flat_map(|f| &f.tags))
+            .map(|(key, value)| (key.to_uppercase(), value.clone()))
+            .collect();
+        let chapters = parsed
+            .chapters
+            .iter()
+            .filter_map(|c| {
+                Some(Chapter {
+                    start: parse_f64(c.start_time.as_ref())?,
+                    end: parse_f64(c.end_time.as_ref())?,
+                    title: c
+                        .tags
+                        .iter()
+                        .find(|(key, _)| key.eq_ignore_ascii_case("title"))
+                        .map(|(_, title)| title.clone()),
+                })
+            })
+            .filter(|c| c.end > c.start)
+            .collect();
+        Self {
+            duration: parse_f64(format.and_then(|f| f.duration.as_ref())),
+            format_name: format.and_then(|f| f.format_name.clone()),
+            audio_streams,
+            stream_types,
+            attached_pics,
+            tags,
+            chapters,
+        }
+    }
+
+    /// Returns the audio stream selected by `audio_stream`, or the first one,
+    /// failing with a descriptive error when there is no such stream.
+    pub fn audio_stream(&self, audio_stream: Option<usize>) -> io::Result<&AudioStreamInfo> {
+        if self.audio_streams.is_empty() {
+            let found = if self.stream_types.is_empty() {
+                "no streams at all".to_string()
+            } else {
+                format!("only {} streams", self.stream_types.join(", "))
+            };
+            return Err(Error::NoAudioStream(format!(
+                "Input contains no audio stream ({})",
+                found
+            ))
+            .into());
+        }
+        let index = audio_stream.unwrap_or(0);
+        self.audio_streams.get(index).ok_or_else(|| {
+            Error::NoAudioStream(format!(
+                "Audio stream {} does not exist; the input has {} audio stream(s)",
+                index,
+                self.audio_streams.len()
+            ))
+            .into()
+        })
+    }
+
+    /// Duration of the selected audio stream, falling back to the container's.
+    pub fn duration_of(&self, audio_stream: Option<usize>) -> Option<f64> {
+        self.audio_streams
+            .get(audio_stream.unwrap_or(0))
+            .and_then(|s| s.duration)
+            .or(self.duration)
+    }
+}
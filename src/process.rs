@@ -0,0 +1,752 @@
+//! Normalizing one input the way a [`RunConfig`] asks: measuring it,
+//! writing its output, variants, streams, chapters or tags, and the
+//! [`FileResult`] printed for it.
+
+use crate::{
+    analysis, in_place, logging, Audiobook, AudiobookMode, ChapterResult, FilterScript,
+    FilterSettings, GainTags, Loudness, LoudnessPlot, MediaInfo, Mode, Normalizer, Options,
+    OutputStats, PrintValue, RemoteDownload, RunConfig, StdinBuffer, Strategy, Tagger,
+    Verification, DEFAULT_TOLERANCE, MARKER_TAG, STDIN_PATH,
+};
+use serde::{Serialize, Serializer};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Outcome of processing one input, printed as text or JSON.
+#[derive(Serialize)]
+pub(crate) struct FileResult {
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) input: PathBuf,
+    #[serde(
+        serialize_with = "serialize_optional_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) output: Option<PathBuf>,
+    #[serde(flatten)]
+    pub(crate) loudness: Option<Loudness>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) streams: Vec<StreamResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tags: Option<GainTags>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) filter: Option<String>,
+    /// Gain in dB reaching the target from the measurements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) gain_db: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) album: Option<AlbumSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) verification: Option<Verification>,
+    /// What loudnorm reported for the output of the second pass.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) second_pass: Option<OutputStats>,
+    /// The input was within the tolerance of the target already.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) already_normalized: bool,
+    /// Encoding was declined with `--interactive`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) declined: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) variants: Vec<VariantResult>,
+    /// The chapters of an audiobook with `--audiobook`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) chapters: Vec<ChapterResult>,
+}
+
+/// One output written with `--targets`.
+#[derive(Serialize)]
+pub(crate) struct VariantResult {
+    pub(crate) variant: String,
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) output: PathBuf,
+    pub(crate) filter: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) second_pass: Option<OutputStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) verification: Option<Verification>,
+}
+
+/// Measurement of one audio stream with `--all-audio-streams`.
+#[derive(Serialize)]
+pub(crate) struct StreamResult {
+    /// Index among the audio streams, as taken by `--audio-stream`.
+    pub(crate) audio_stream: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) channel_layout: Option<String>,
+    #[serde(flatten)]
+    pub(crate) loudness: Loudness,
+    /// The stream was within the tolerance of the target, with its true
+    /// peak under the ceiling.
+    pub(crate) compliant: bool,
+}
+
+/// Album-level values repeated on every track result in album mode.
+#[derive(Serialize, Clone, Copy)]
+pub(crate) struct AlbumSummary {
+    pub(crate) integrated_loudness: f64,
+    pub(crate) true_peak: f64,
+    pub(crate) gain_db: f64,
+    /// Loudness of the track the gain was chosen for with
+    /// `--group-relative`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reference_loudness: Option<f64>,
+}
+
+impl FileResult {
+    pub(crate) fn new(input_path: &Path, output_path: Option<PathBuf>) -> Self {
+        Self {
+            input: input_path.to_path_buf(),
+            output: output_path,
+            loudness: None,
+            streams: Vec::new(),
+            tags: None,
+            filter: None,
+            gain_db: None,
+            album: None,
+            command: None,
+            verification: None,
+            second_pass: None,
+            already_normalized: false,
+            declined: false,
+            variants: Vec::new(),
+            chapters: Vec::new(),
+        }
+    }
+}
+
+pub(crate) fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+fn serialize_optional_path<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Whether the encode of `input_path` may go ahead: always, unless
+/// `--interactive` asks and the answer is no. `proposal` says what would be
+/// applied.
+fn confirm(
+    config: &RunConfig,
+    input_path: &Path,
+    loudness: &Loudness,
+    proposal: &str,
+) -> io::Result<bool> {
+    let Some(confirmation) = &config.interactive else {
+        return Ok(true);
+    };
+    let gain = FilterSettings::gain_db(&config.options, loudness)
+        .map(|gain| format!(", gain {:+.2} dB", gain))
+        .unwrap_or_default();
+    let details = format!(
+        "I={:.2} LUFS, TP={:.2} dBTP, LRA={:.2} LU{}\n{}",
+        loudness.input_i, loudness.input_tp, loudness.input_lra, gain, proposal
+    );
+    let confirmed = confirmation.ask(input_path, &details)?;
+    if !confirmed {
+        logging::info(format_args!("{}: declined; skipping", input_path.display()));
+    }
+    Ok(confirmed)
+}
+
+/// Processes one input, reading it from standard input when it is `-` and
+/// downloading it first with `--download-inputs` when it is a URL.
+pub(crate) fn process(config: &RunConfig, input_path: &Path) -> io::Result<FileResult> {
+    let job_config;
+    let config = match config
+        .manifest
+        .as_ref()
+        .and_then(|manifest| manifest.job(input_path))
+    {
+        Some(job) => {
+            job_config = config.for_job(job);
+            &job_config
+        }
+        None => config,
+    };
+    let imported_config;
+    let config = match config
+        .imported_stats
+        .as_ref()
+        .and_then(|stats| stats.measurements(input_path, config.options.audio_stream))
+    {
+        Some(loudness) => {
+            logging::info(format_args!(
+                "{}: using the imported measurements",
+                input_path.display()
+            ));
+            imported_config = RunConfig {
+                options: Options {
+                    measured: Some(loudness),
+                    ..config.options.clone()
+                },
+                ..config.clone()
+            };
+            &imported_config
+        }
+        None => config,
+    };
+    let result = if config.in_place {
+        process_in_place(config, input_path)?
+    } else if config.download_inputs && crate::is_url(input_path) {
+        let _download = RemoteDownload::fetch(input_path)?;
+        process_file(config, input_path)?
+    } else if input_path != Path::new(STDIN_PATH) {
+        process_file(config, input_path)?
+    } else {
+        let buffer = StdinBuffer::read(config.input_format.as_deref())?;
+        let mut result = process_file(config, buffer.path())?;
+        result.input = input_path.to_path_buf();
+        result
+    };
+    if let (Some(path), Some(loudness)) = (&config.save_analysis_path, &result.loudness) {
+        analysis::save(path, input_path, loudness)?;
+    }
+    Ok(result)
+}
+
+/// Normalizes `input_path` over itself for `--in-place`, carrying its
+/// attributes over to the output, or removing the backup again when it
+/// wasn't replaced after all.
+fn process_in_place(config: &RunConfig, input_path: &Path) -> io::Result<FileResult> {
+    if input_path == Path::new(STDIN_PATH) || crate::is_url(input_path) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--in-place needs a local file",
+        ));
+    }
+    let original = in_place::Original::prepare(input_path, config.backup_suffix.as_deref())?;
+    let result = process_file(config, input_path);
+    // The backup goes only with an input left as it was; once replaced,
+    // it may be the last copy of the original even if a later step failed.
+    match &result {
+        _ if !original.replaced() => original.discard(),
+        Ok(_) => original.restore(config.keep_mtime)?,
+        Err(_) => original.keep(config.keep_mtime),
+    }
+    result
+}
+
+pub(crate) fn process_file(config: &RunConfig, input_path: &Path) -> io::Result<FileResult> {
+    if config.timeline_path.is_some() || config.plot_path.is_some() {
+        let timeline = crate::timeline(input_path, &config.options)?;
+        if let Some(timeline_path) = &config.timeline_path {
+            timeline.write(timeline_path)?;
+        }
+        if let Some(plot_path) = &config.plot_path {
+            LoudnessPlot::write(&timeline, plot_path, &config.options)?;
+        }
+    }
+    if config.tag_only {
+        return process_tags(config, input_path);
+    }
+    if config.all_audio_streams {
+        return process_all_streams(config, input_path);
+    }
+    if let Some(mode) = config.audiobook {
+        return process_audiobook(config, input_path, mode);
+    }
+
+    if let Some(filter) = FilterSettings::construct_engine(&config.options) {
+        return process_engine(config, input_path, filter);
+    }
+    if !config.variants.is_empty() {
+        return process_variants(config, input_path);
+    }
+
+    let output_path = config.output_for(input_path)?;
+    let mut second_pass = None;
+    let loudness = match &output_path {
+        Some(_) if config.print_command => crate::analyze(input_path, &config.options)?,
+        Some(path) => {
+            let loudness = crate::analyze(input_path, &config.options)?;
+            if is_within_skip_tolerance(config, &loudness) {
+                logging::info(format_args!(
+                    "{}: within tolerance of the target; skipping the second pass",
+                    input_path.display()
+                ));
+                let mut result = FileResult::new(input_path, None);
+                result.gain_db = FilterSettings::gain_db(&config.options, &loudness);
+                result.loudness = Some(loudness);
+                return Ok(result);
+            }
+            loudness.ensure_audible(&config.options)?;
+            let filter = crate::build_filter(&loudness, &config.options);
+            if !confirm(
+                config,
+                input_path,
+                &loudness,
+                &format!("filter: {}", filter),
+            )? {
+                let mut result = FileResult::new(input_path, None);
+                result.gain_db = FilterSettings::gain_db(&config.options, &loudness);
+                result.loudness = Some(loudness);
+                result.declined = true;
+                return Ok(result);
+            }
+            second_pass = Normalizer::encode(
+                input_path,
+                path,
+                &filter,
+                &marked(config, &config.options, Some(&loudness)),
+            )?;
+            loudness
+        }
+        None => crate::analyze(input_path, &config.options)?,
+    };
+    if !config.report {
+        loudness.ensure_audible(&config.options)?;
+    }
+    warn_if_not_linear(config, input_path, &loudness);
+    let mut filter = crate::build_filter(&loudness, &config.options);
+    if let Some(script_path) = &config.filter_script_path {
+        FilterScript::write(script_path, &filter)?;
+    }
+    let mut result = FileResult::new(input_path, output_path);
+    if let (Some(tolerance), Some(output_path)) = (config.verify_tolerance, &result.output) {
+        let mut verification = crate::verify(output_path, &config.options, tolerance)?;
+        if verification.true_peak > verification.ceiling {
+            (filter, verification, second_pass) = retry_overshoot(
+                config,
+                input_path,
+                output_path,
+                &loudness,
+                &filter,
+                verification,
+            )?;
+        }
+        result.verification = Some(verification);
+    }
+    let command_output = match &result.output {
+        Some(output_path) if config.print_command || config.print == PrintValue::All => {
+            Some(output_path.as_path())
+        }
+        // A placeholder for the command of a run that only measures.
+        None if config.print == PrintValue::All => Some(Path::new("OUTPUT")),
+        _ => None,
+    };
+    if let Some(output_path) = command_output {
+        result.command = Some(Normalizer::command_line(
+            input_path,
+            output_path,
+            &filter,
+            config.filter_script_path.as_deref(),
+            &config.options,
+            config.shell,
+        )?);
+    }
+    if let Some(stats) = &second_pass {
+        logging::info(format_args!(
+            "{}: loudnorm reports {:.2} LUFS, {:.2} dBTP, {:.2} LU LRA for the output",
+            input_path.display(),
+            stats.output_i,
+            stats.output_tp,
+            stats.output_lra
+        ));
+    }
+    result.second_pass = second_pass;
+    result.filter = Some(filter);
+    result.gain_db = FilterSettings::gain_db(&config.options, &loudness);
+    result.loudness = Some(loudness);
+    Ok(result)
+}
+
+/// Normalizes the audiobook `input_path` with `--audiobook`, chapter by
+/// chapter or as a whole, and verifies the chapters of the output: always
+/// in book mode, where that is the point, and with `--verify` otherwise.
+fn process_audiobook(
+    config: &RunConfig,
+    input_path: &Path,
+    mode: AudiobookMode,
+) -> io::Result<FileResult> {
+    let book = Audiobook::probe(input_path, &config.options)?;
+    let (mut result, tolerance) = match mode {
+        AudiobookMode::Chapters => (
+            process_chapters(config, input_path, &book)?,
+            config.verify_tolerance,
+        ),
+        AudiobookMode::Book => {
+            let whole = RunConfig {
+                audiobook: None,
+                ..config.clone()
+            };
+            let mut result = process_file(&whole, input_path)?;
+            result.chapters = book.chapters.iter().map(ChapterResult::new).collect();
+            (
+                result,
+                Some(config.verify_tolerance.unwrap_or(DEFAULT_TOLERANCE)),
+            )
+        }
+    };
+    if let (Some(tolerance), Some(output_path)) = (tolerance, &result.output) {
+        if !config.print_command {
+            let verifications = book.verify(output_path, &config.options, tolerance)?;
+            for (chapter, verification) in result.chapters.iter_mut().zip(verifications) {
+                chapter.verification = verification;
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Measures each chapter of `input_path` and writes one output applying
+/// the gain of every chapter to it.
+fn process_chapters(
+    config: &RunConfig,
+    input_path: &Path,
+    book: &Audiobook,
+) -> io::Result<FileResult> {
+    let (filter, chapters) = book.chapter_filter(input_path, &config.options)?;
+    if let Some(script_path) = &config.filter_script_path {
+        FilterScript::write(script_path, &filter)?;
+    }
+    let mut result = FileResult::new(input_path, config.output_for(input_path)?);
+    if let Some(output_path) = &result.output {
+        if config.print_command {
+            result.command = Some(Normalizer::command_line(
+                input_path,
+                output_path,
+                &filter,
+                config.filter_script_path.as_deref(),
+                &config.options,
+                config.shell,
+            )?);
+        } else {
+            Normalizer::encode(
+                input_path,
+                output_path,
+                &filter,
+                &marked(config, &config.options, None),
+            )?;
+        }
+    }
+    result.chapters = chapters;
+    result.filter = Some(filter);
+    Ok(result)
+}
+
+/// Measures `input_path` once and writes an output normalized to each of
+/// the `--targets` presets from that measurement.
+fn process_variants(config: &RunConfig, input_path: &Path) -> io::Result<FileResult> {
+    let loudness = crate::analyze(input_path, &config.options)?;
+    loudness.ensure_audible(&config.options)?;
+    let mut result = FileResult::new(input_path, None);
+    let names: Vec<&str> = config
+        .variants
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if !confirm(
+        config,
+        input_path,
+        &loudness,
+        &format!("targets: {}", names.join(", ")),
+    )? {
+        result.loudness = Some(loudness);
+        result.declined = true;
+        return Ok(result);
+    }
+    for (name, preset) in &config.variants {
+        let options = config.variant_options(preset);
+        // loudnorm's suggested offset only holds for the targets it was
+        // measured against, as with cached measurements.
+        let same_targets = (
+            options.integrated_loudness,
+            options.loudness_range,
+            options.true_peak,
+        ) == (
+            config.options.integrated_loudness,
+            config.options.loudness_range,
+            config.options.true_peak,
+        );
+        let measured = Loudness {
+            target_offset: if same_targets {
+                loudness.target_offset
+            } else {
+                0.0
+            },
+            ..loudness.clone()
+        };
+        let output_path = config.variant_output_for(input_path, name, &options)?;
+        let filter = crate::build_filter(&measured, &options);
+        let second_pass = Normalizer::encode(
+            input_path,
+            &output_path,
+            &filter,
+            &marked(config, &options, Some(&loudness)),
+        )?;
+        let verification = config
+            .verify_tolerance
+            .map(|tolerance| crate::verify(&output_path, &options, tolerance))
+            .transpose()?;
+        logging::info(format_args!(
+            "{}: wrote the {} variant to {}",
+            input_path.display(),
+            name,
+            output_path.display()
+        ));
+        result.variants.push(VariantResult {
+            variant: name.clone(),
+            output: output_path,
+            filter,
+            second_pass,
+            verification,
+        });
+    }
+    result.gain_db = FilterSettings::gain_db(&config.options, &loudness);
+    result.loudness = Some(loudness);
+    Ok(result)
+}
+
+/// Applies a single-pass engine `filter`, which needs no measurement.
+fn process_engine(config: &RunConfig, input_path: &Path, filter: String) -> io::Result<FileResult> {
+    if let Some(script_path) = &config.filter_script_path {
+        FilterScript::write(script_path, &filter)?;
+    }
+    let mut result = FileResult::new(input_path, config.output_for(input_path)?);
+    if let Some(output_path) = &result.output {
+        if config.print_command {
+            result.command = Some(Normalizer::command_line(
+                input_path,
+                output_path,
+                &filter,
+                config.filter_script_path.as_deref(),
+                &config.options,
+                config.shell,
+            )?);
+        } else {
+            Normalizer::encode(
+                input_path,
+                output_path,
+                &filter,
+                &marked(config, &config.options, None),
+            )?;
+        }
+    }
+    result.filter = Some(filter);
+    Ok(result)
+}
+
+/// Encodes `input_path` again after its output overshot the true peak
+/// ceiling: first with dynamic normalization if linear was used, then with a
+/// limiter appended unless one is configured already. Returns the last
+/// filter, its verification and what loudnorm reported for it.
+fn retry_overshoot(
+    config: &RunConfig,
+    input_path: &Path,
+    output_path: &Path,
+    loudness: &Loudness,
+    filter: &str,
+    mut verification: Verification,
+) -> io::Result<(String, Verification, Option<OutputStats>)> {
+    let dynamic = Options {
+        strategy: Strategy::Dynamic,
+        ..config.options.clone()
+    };
+    let mut retries = Vec::new();
+    if FilterSettings::use_linear(&config.options, loudness) {
+        retries.push((
+            "dynamic normalization",
+            FilterSettings::construct(&dynamic, Some(loudness)),
+        ));
+    }
+    if config.options.limiter.is_none() {
+        retries.push((
+            "a limiter",
+            FilterSettings::construct_limited(&dynamic, loudness),
+        ));
+    }
+
+    let mut filter = filter.to_string();
+    let mut second_pass = None;
+    for (remedy, retry_filter) in retries {
+        logging::warn(format_args!(
+            "{}: true peak {:.2} dBTP overshoots the {:.1} dBTP ceiling; retrying with {}",
+            input_path.display(),
+            verification.true_peak,
+            verification.ceiling,
+            remedy
+        ));
+        second_pass = Normalizer::encode(
+            input_path,
+            output_path,
+            &retry_filter,
+            &marked(config, &config.options, Some(loudness)),
+        )?;
+        filter = retry_filter;
+        verification = crate::verify(output_path, &config.options, verification.tolerance)?;
+        if verification.true_peak <= verification.ceiling {
+            break;
+        }
+    }
+    Ok((filter, verification, second_pass))
+}
+
+fn process_all_streams(config: &RunConfig, input_path: &Path) -> io::Result<FileResult> {
+    let output_path = config.output_for(input_path)?;
+    let streams = match &output_path {
+        Some(output_path) if !config.print_command => crate::normalize_all_streams(
+            input_path,
+            output_path,
+            &marked(config, &config.options, None),
+        )?,
+        _ => crate::analyze_all_streams(input_path, &config.options)?,
+    };
+    for loudness in &streams {
+        if !config.report {
+            loudness.ensure_audible(&config.options)?;
+        }
+        warn_if_not_linear(config, input_path, loudness);
+    }
+    let filter = crate::build_stream_filters(&streams, &config.options);
+    if let Some(script_path) = &config.filter_script_path {
+        FilterScript::write(script_path, &filter)?;
+    }
+    let mut result = FileResult::new(input_path, output_path);
+    if config.print_command {
+        if let Some(output_path) = &result.output {
+            result.command = Some(Normalizer::streams_command_line(
+                input_path,
+                output_path,
+                &streams,
+                config.filter_script_path.as_deref(),
+                &config.options,
+                config.shell,
+            )?);
+        }
+    }
+    result.filter = Some(filter);
+    let info = MediaInfo::probe(input_path, &config.options)?;
+    let tolerance = config.verify_tolerance.unwrap_or(DEFAULT_TOLERANCE);
+    result.streams = streams
+        .into_iter()
+        .zip(info.audio_streams)
+        .enumerate()
+        .map(|(audio_stream, (loudness, stream))| StreamResult {
+            audio_stream,
+            language: stream.language,
+            channel_layout: stream
+                .channel_layout
+                .or_else(|| stream.channels.map(|n| format!("{} channels", n))),
+            compliant: loudness.is_at_target(&config.options, tolerance),
+            loudness,
+        })
+        .collect();
+    Ok(result)
+}
+
+fn process_tags(config: &RunConfig, input_path: &Path) -> io::Result<FileResult> {
+    let output_path = config.output_for(input_path)?;
+    if output_path.is_none() && crate::is_url(input_path) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "URL inputs can't be tagged in place; pass --output, --output-template or --output-dir",
+        ));
+    }
+    let (loudness, tags) = crate::tag(input_path, output_path.as_deref(), &config.options)?;
+    let mut result = FileResult::new(input_path, output_path);
+    result.loudness = Some(loudness);
+    result.tags = Some(tags);
+    Ok(result)
+}
+
+pub(crate) fn process_cue_track(
+    image: &Path,
+    output_path: &Path,
+    options: &Options,
+    album: Option<&(Loudness, AlbumSummary)>,
+) -> io::Result<FileResult> {
+    let mut result = FileResult::new(image, Some(output_path.to_path_buf()));
+    if let Some(filter) = FilterSettings::construct_engine(options) {
+        Normalizer::encode(image, output_path, &filter, options)?;
+        result.filter = Some(filter);
+    } else if let Some((loudness, summary)) = album {
+        let filter = FilterSettings::construct_gain(options, summary.gain_db);
+        Normalizer::encode(image, output_path, &filter, options)?;
+        result.filter = Some(filter);
+        result.loudness = Some(loudness.clone());
+        result.album = Some(*summary);
+    } else {
+        let loudness = crate::normalize(image, output_path, options)?;
+        result.filter = Some(crate::build_filter(&loudness, options));
+        result.gain_db = FilterSettings::gain_db(options, &loudness);
+        result.loudness = Some(loudness);
+    }
+    Ok(result)
+}
+
+/// `options` with the [`MARKER_TAG`] of an output normalized from an input
+/// measured as `loudness` among the metadata, unless `--no-marker` is given.
+pub(crate) fn marked(
+    config: &RunConfig,
+    options: &Options,
+    loudness: Option<&Loudness>,
+) -> Options {
+    let mut options = options.clone();
+    if config.marker {
+        let marker = Tagger::marker(&options, loudness);
+        options
+            .encoding
+            .metadata
+            .push((MARKER_TAG.to_string(), marker));
+    }
+    options
+}
+
+/// Whether `--skip-within` lets the second pass be skipped for `loudness`.
+fn is_within_skip_tolerance(config: &RunConfig, loudness: &Loudness) -> bool {
+    config
+        .skip_within
+        .is_some_and(|tolerance| loudness.is_at_target(&config.options, tolerance))
+}
+
+/// Whether `input_path` carries a marker tag of an earlier run with the
+/// mode and target configured now.
+pub(crate) fn is_normalized(config: &RunConfig, input_path: &Path) -> bool {
+    input_path != Path::new(STDIN_PATH)
+        && MediaInfo::probe(input_path, &config.options)
+            .is_ok_and(|info| Tagger::is_normalized(&info, &config.options))
+}
+
+/// Whether `input_path` carries gain tags, for `--skip-tagged`. Inputs that
+/// can't be probed are processed, so their error is reported.
+pub(crate) fn is_tagged(config: &RunConfig, input_path: &Path) -> bool {
+    input_path != Path::new(STDIN_PATH)
+        && MediaInfo::probe(input_path, &config.options).is_ok_and(|info| Tagger::is_tagged(&info))
+}
+
+fn warn_if_not_linear(config: &RunConfig, input_path: &Path, loudness: &Loudness) {
+    if config.report
+        || config.options.mode != Mode::Ebu
+        || config.print != PrintValue::Filter
+        || loudness.dialogue_i.is_some()
+    {
+        return;
+    }
+    if let Some(reason) = FilterSettings::linear_obstacle(&config.options, loudness) {
+        match config.options.strategy {
+            Strategy::Auto => logging::warn(format_args!(
+                "{}: {}; using dynamic normalization",
+                input_path.display(),
+                reason
+            )),
+            Strategy::Linear => logging::warn(format_args!(
+                "{}: {}; loudnorm will fall back to dynamic normalization",
+                input_path.display(),
+                reason
+            )),
+            Strategy::Dynamic => {}
+        }
+    }
+}
@@ -0,0 +1,449 @@
+use crate::{
+    logging::{self, Level},
+    task::{self, ProgressEvent, TaskContext},
+};
+use core::time::Duration;
+use std::{
+    cell::{Cell, RefCell},
+    env,
+    fmt::Write as _,
+    io::{self, IsTerminal, Write as _},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Lines of [`MultiProgress`] bars currently drawn at the bottom of stderr.
+static DRAWN_LINES: Mutex<usize> = Mutex::new(0);
+
+thread_local! {
+    /// Whether this thread is inside [`MultiProgress::suspend`].
+    static SUSPENDED: Cell<bool> = const { Cell::new(false) };
+    /// The file of [`ProgressSpinner::for_file`] running on this thread.
+    static FILE: RefCell<Option<Arc<FileStatus>>> = const { RefCell::new(None) };
+    /// The stage of [`ProgressSpinner::in_stage`] running on this thread.
+    static STAGE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Width of the bars drawn by [`MultiProgress`], in characters.
+const BAR_WIDTH: usize = 20;
+/// File names longer than this are shortened from the front.
+const NAME_WIDTH: usize = 32;
+
+/// Progress is printed as a plain line whenever it crosses another step of
+/// this fraction...
+const LINE_STEP: f64 = 0.25;
+/// ...or this much time has passed since the last line.
+const LINE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Terminal spinner shown on stderr while ffmpeg runs. When stderr is not a
+/// terminal, e.g. in CI logs, progress is printed as occasional plain lines
+/// instead.
+pub struct ProgressSpinner {
+    label: String,
+    file: Option<Arc<FileStatus>>,
+    finished: Arc<AtomicBool>,
+    status: Arc<Mutex<String>>,
+    handle: Option<JoinHandle<()>>,
+    lines: Option<Mutex<LineState>>,
+}
+
+/// The file a spinner's pass belongs to, shown before its stage.
+struct FileStatus {
+    name: String,
+    /// The file's number and the number of files, in a batch.
+    position: Option<(usize, usize)>,
+    started: Instant,
+}
+
+impl FileStatus {
+    /// `[12/308] name`, or just the name outside a batch.
+    fn prefix(&self) -> String {
+        match self.position {
+            Some((number, total)) => format!("[{}/{}] {}", number, total, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// When the last plain progress line was printed.
+struct LineState {
+    next_fraction: f64,
+    printed: Instant,
+}
+
+impl ProgressSpinner {
+    /// Globally enables or disables the spinner and progress lines, e.g.
+    /// while several files are processed concurrently and their spinners
+    /// would overwrite each other.
+    pub fn set_enabled(enabled: bool) {
+        ENABLED.store(enabled, Ordering::Release);
+    }
+
+    /// Starts a spinner labelled `Processing`.
+    pub fn start() -> Self {
+        Self::labeled("Processing")
+    }
+
+    /// Runs `job`, which processes the file `name`, showing the file, its
+    /// `position` as number and total in a batch, and the time spent on it
+    /// on the spinners started meanwhile on this thread.
+    pub fn for_file<T>(name: &str, position: Option<(usize, usize)>, job: impl FnOnce() -> T) -> T {
+        let file = Arc::new(FileStatus {
+            name: shorten(name, NAME_WIDTH),
+            position,
+            started: Instant::now(),
+        });
+        task::with_local(&FILE, file, job)
+    }
+
+    /// Runs `job` with the spinners started meanwhile on this thread
+    /// labelled `stage`, e.g. `Verifying` for a measurement of an output.
+    pub fn in_stage<T>(stage: &str, job: impl FnOnce() -> T) -> T {
+        task::with_local(&STAGE, stage.to_string(), job)
+    }
+
+    /// Starts a spinner naming the stage that is running, e.g. `Measuring`
+    /// or `Encoding`, after the file of [`Self::for_file`] if there is one.
+    pub fn labeled(label: &str) -> Self {
+        let label = STAGE
+            .with(|stage| stage.borrow().clone())
+            .unwrap_or_else(|| label.to_string());
+        let file = FILE.with(|file| file.borrow().clone());
+        const PROGRESS_CHARS: [&str; 12] =
+            ["⠂", "⠃", "⠁", "⠉", "⠈", "⠘", "⠐", "⠰", "⠠", "⠤", "⠄", "⠆"];
+        let finished = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(Mutex::new(String::new()));
+        let enabled = ENABLED.load(Ordering::Acquire);
+        let terminal = is_terminal();
+        // NO_COLOR asks for no escape sequences; lines are then cleared by
+        // overwriting them with spaces.
+        let escapes = env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+        let handle = (enabled && terminal).then(|| {
+            let stop_signal = Arc::clone(&finished);
+            let status = Arc::clone(&status);
+            let label = label.clone();
+            let file = file.clone();
+            thread::spawn(move || {
+                let mut width: usize = 0;
+                for pc in PROGRESS_CHARS.iter().cycle() {
+                    if stop_signal.load(Ordering::Acquire) {
+                        break;
+                    };
+                    let status = status.lock().map(|s| s.clone()).unwrap_or_default();
+                    let line = match &file {
+                        Some(file) => format!(
+                            "{} {} {} {} {}",
+                            file.prefix(),
+                            label,
+                            pc,
+                            status,
+                            format_elapsed(file.started.elapsed())
+                        ),
+                        None => format!("{} {} {}", label, pc, status),
+                    };
+                    if escapes {
+                        eprint!("\r\x1b[2K{}", line);
+                    } else {
+                        let length = line.chars().count();
+                        eprint!("\r{}{:pad$}", line, "", pad = width.saturating_sub(length));
+                        width = length;
+                    }
+                    thread::sleep(Duration::from_millis(250));
+                }
+                if escapes {
+                    eprint!("\r\x1b[2K");
+                } else {
+                    eprint!("\r{:width$}\r", "");
+                }
+            })
+        });
+        let lines = (enabled && !terminal && logging::enabled(Level::Warn)).then(|| {
+            Mutex::new(LineState {
+                next_fraction: LINE_STEP,
+                printed: Instant::now(),
+            })
+        });
+        Self {
+            label,
+            file,
+            finished,
+            status,
+            handle,
+            lines,
+        }
+    }
+
+    /// Reports how far the current pass has come, given as a fraction in
+    /// `0.0..=1.0`, the estimated time remaining and how many times faster
+    /// than realtime ffmpeg is going.
+    pub fn set_progress(&self, fraction: f64, eta: Option<Duration>, speed: Option<f64>) {
+        task::report_progress(|| ProgressEvent {
+            stage: self.label.clone(),
+            fraction,
+            eta,
+            speed,
+        });
+        let mut status = format!("{:5.1}%", (fraction * 100.0).clamp(0.0, 100.0));
+        if let Some(eta) = eta {
+            status.push_str(&format!(" ETA {}", format_clock(eta)));
+        }
+        if let Some(speed) = speed {
+            status.push_str(&format!(" {:.1}x", speed));
+        }
+        if let Some(Ok(mut lines)) = self.lines.as_ref().map(Mutex::lock) {
+            if fraction >= lines.next_fraction || lines.printed.elapsed() >= LINE_INTERVAL {
+                match &self.file {
+                    Some(file) => {
+                        eprintln!("{}: {}: {}", file.prefix(), self.label, status.trim_start())
+                    }
+                    None => eprintln!("{}: {}", self.label, status.trim_start()),
+                }
+                lines.next_fraction = ((fraction / LINE_STEP).floor() + 1.0) * LINE_STEP;
+                lines.printed = Instant::now();
+            }
+        }
+        if let Ok(mut current) = self.status.lock() {
+            *current = status;
+        }
+    }
+
+    pub fn stop(self) {}
+}
+
+impl Drop for ProgressSpinner {
+    /// Stops the spinner and clears its line, also when a pass is abandoned
+    /// early.
+    fn drop(&mut self) {
+        self.finished.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// `duration` as `HH:MM:SS`.
+fn format_clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Time spent on a file, e.g. `(00:01:23)`.
+fn format_elapsed(elapsed: Duration) -> String {
+    format!("({})", format_clock(elapsed))
+}
+
+/// Whether stderr is a terminal that can redraw lines.
+fn is_terminal() -> bool {
+    io::stderr().is_terminal() && env::var("TERM").map_or(true, |t| t != "dumb")
+}
+
+/// Progress of files processed concurrently: a bar per file in flight and
+/// an overall bar, redrawn at the bottom of stderr. Other output goes
+/// through [`MultiProgress::suspend`] so the bars don't overwrite it.
+pub struct MultiProgress {
+    bars: Arc<Mutex<Bars>>,
+    finished: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Bars {
+    /// The files in flight; freed slots are reused so bars keep their place.
+    slots: Vec<Option<Slot>>,
+    done: usize,
+    total: usize,
+}
+
+struct Slot {
+    name: String,
+    stage: String,
+    fraction: f64,
+}
+
+impl MultiProgress {
+    /// Starts drawing bars for `total` files, or returns `None` when stderr
+    /// can't show them: it isn't a terminal, `NO_COLOR` rules out the
+    /// cursor movements, or progress is disabled.
+    pub fn start(total: usize) -> Option<Self> {
+        let escapes = env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+        if !ENABLED.load(Ordering::Acquire) || !is_terminal() || !escapes {
+            return None;
+        }
+        let bars = Arc::new(Mutex::new(Bars {
+            total,
+            ..Bars::default()
+        }));
+        let finished = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let bars = Arc::clone(&bars);
+            let stop_signal = Arc::clone(&finished);
+            thread::spawn(move || {
+                let mut previous = Vec::new();
+                while !stop_signal.load(Ordering::Acquire) {
+                    if let (Ok(mut drawn), Ok(bars)) = (DRAWN_LINES.lock(), bars.lock()) {
+                        let lines = bars.lines();
+                        // Unchanged bars that are still on screen stay as
+                        // they are.
+                        if lines != previous || *drawn != lines.len() {
+                            *drawn = redraw(*drawn, &lines);
+                            previous = lines;
+                        }
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                if let Ok(mut drawn) = DRAWN_LINES.lock() {
+                    *drawn = redraw(*drawn, &[]);
+                }
+            })
+        };
+        Some(Self {
+            bars,
+            finished,
+            handle: Some(handle),
+        })
+    }
+
+    /// Runs `job` for the file `name` with a bar of its own, fed by the
+    /// progress ffmpeg reports. The overall bar advances when it returns.
+    pub fn track<T>(&self, name: &str, job: impl FnOnce() -> T) -> T {
+        let index = self.bars.lock().map_or(usize::MAX, |mut bars| {
+            let slot = Some(Slot {
+                name: name.to_string(),
+                stage: String::new(),
+                fraction: 0.0,
+            });
+            match bars.slots.iter().position(Option::is_none) {
+                Some(index) => {
+                    bars.slots[index] = slot;
+                    index
+                }
+                None => {
+                    bars.slots.push(slot);
+                    bars.slots.len() - 1
+                }
+            }
+        });
+        let bars = Arc::clone(&self.bars);
+        let outcome = TaskContext::new()
+            .on_progress(move |event| {
+                if let Ok(mut bars) = bars.lock() {
+                    if let Some(Some(slot)) = bars.slots.get_mut(index) {
+                        slot.stage = event.stage;
+                        slot.fraction = event.fraction.clamp(0.0, 1.0);
+                    }
+                }
+            })
+            .run(job);
+        if let Ok(mut bars) = self.bars.lock() {
+            if let Some(slot) = bars.slots.get_mut(index) {
+                *slot = None;
+            }
+            bars.done += 1;
+        }
+        outcome
+    }
+
+    /// Clears the bars while `print` writes to the terminal; they are drawn
+    /// again below its output. Calls nest, and without bars `print` simply
+    /// runs.
+    pub fn suspend<T>(print: impl FnOnce() -> T) -> T {
+        if SUSPENDED.get() {
+            return print();
+        }
+        let Ok(mut drawn) = DRAWN_LINES.lock() else {
+            return print();
+        };
+        *drawn = redraw(*drawn, &[]);
+        SUSPENDED.set(true);
+        struct Resume;
+        impl Drop for Resume {
+            fn drop(&mut self) {
+                SUSPENDED.set(false);
+            }
+        }
+        let _resume = Resume;
+        print()
+    }
+}
+
+impl Drop for MultiProgress {
+    /// Stops drawing and clears the bars.
+    fn drop(&mut self) {
+        self.finished.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Bars {
+    fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .slots
+            .iter()
+            .flatten()
+            .map(|slot| {
+                let name = shorten(&slot.name, NAME_WIDTH);
+                format!(
+                    "{:<width$} {} {:5.1}% {}",
+                    name,
+                    bar(slot.fraction),
+                    slot.fraction * 100.0,
+                    slot.stage,
+                    width = NAME_WIDTH
+                )
+            })
+            .collect();
+        let overall = if self.total == 0 {
+            1.0
+        } else {
+            self.done as f64 / self.total as f64
+        };
+        lines.push(format!(
+            "{:<width$} {} {}/{} files",
+            "Overall",
+            bar(overall),
+            self.done,
+            self.total,
+            width = NAME_WIDTH
+        ));
+        lines
+    }
+}
+
+fn bar(fraction: f64) -> String {
+    let filled = ((fraction * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled))
+}
+
+/// `name` cut to its last `width` characters, so the file name stays.
+fn shorten(name: &str, width: usize) -> String {
+    let length = name.chars().count();
+    if length <= width {
+        return name.to_string();
+    }
+    let tail: String = name.chars().skip(length - width + 1).collect();
+    format!("…{}", tail)
+}
+
+/// Replaces the `drawn` lines at the bottom of stderr with `lines`,
+/// returning how many are drawn now.
+fn redraw(drawn: usize, lines: &[String]) -> usize {
+    let mut output = String::new();
+    if drawn > 0 {
+        let _ = write!(output, "\x1b[{}A\r\x1b[J", drawn);
+    }
+    for line in lines {
+        let _ = writeln!(output, "{}", line);
+    }
+    let mut stderr = io::stderr().lock();
+    let _ = stderr.write_all(output.as_bytes());
+    let _ = stderr.flush();
+    lines.len()
+}
@@ -0,0 +1,63 @@
+//! The question asked with `--interactive` before each input is encoded.
+
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Asks on stderr whether to encode each input, reading the answers from
+/// standard input. "all" stops asking, "quit" declines the rest, as does the
+/// end of standard input.
+#[derive(Default)]
+pub struct Confirmation {
+    all: AtomicBool,
+    quit: AtomicBool,
+}
+
+impl Confirmation {
+    /// Whether `input_path` should be encoded, after showing `details` of
+    /// its measurement and the proposed filter.
+    pub fn ask(&self, input_path: &Path, details: &str) -> io::Result<bool> {
+        if self.all.load(Ordering::Relaxed) {
+            return Ok(true);
+        }
+        if self.quit_requested() {
+            return Ok(false);
+        }
+        let mut stderr = io::stderr().lock();
+        writeln!(stderr, "{}", input_path.display())?;
+        for line in details.lines() {
+            writeln!(stderr, "  {}", line)?;
+        }
+        let mut stdin = io::stdin().lock();
+        loop {
+            write!(stderr, "Normalize? [y]es/[n]o/[a]ll/[q]uit: ")?;
+            stderr.flush()?;
+            let mut answer = String::new();
+            if stdin.read_line(&mut answer)? == 0 {
+                writeln!(stderr)?;
+                self.quit.store(true, Ordering::Relaxed);
+                return Ok(false);
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                "a" | "all" => {
+                    self.all.store(true, Ordering::Relaxed);
+                    return Ok(true);
+                }
+                "q" | "quit" => {
+                    self.quit.store(true, Ordering::Relaxed);
+                    return Ok(false);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the remaining inputs were declined with "quit".
+    pub fn quit_requested(&self) -> bool {
+        self.quit.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,381 @@
+//! Static ffmpeg builds downloaded on demand for machines without one.
+//!
+//! The builds are pinned to dated releases of BtbN's FFmpeg-Builds, with
+//! the SHA-256 of each archive recorded in [`PINNED_ARCHIVES`], and a
+//! download is only unpacked when it is that very archive. The archives
+//! are fetched with `curl` and unpacked with `tar`, both of which ship with
+//! current Linux and Windows 10+ installations.
+
+use crate::{ffmpeg, logging, AnalysisCache, Error, Options};
+use std::{
+    env,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Command as ProcessCommand, Stdio},
+};
+
+/// Where the releases of the downloaded builds are published.
+const RELEASES_URL: &str = "https://github.com/BtbN/FFmpeg-Builds/releases/download";
+
+/// File in the cache directory holding the SHA-256 of the archive its
+/// build came from, so a build pinned since is replaced.
+const BUILD_FILE: &str = "build";
+
+/// A build archive trusted for download on one platform.
+struct PinnedArchive {
+    os: &'static str,
+    arch: &'static str,
+    /// A dated release tag such as `autobuild-2024-11-30-13-06`, whose
+    /// assets, unlike those of `latest`, are not replaced.
+    release: &'static str,
+    name: &'static str,
+    /// SHA-256 of the archive in hex.
+    sha256: &'static str,
+}
+
+/// The archives `--auto-download-ffmpeg` may fetch. An entry is added with
+/// the checksum of an archive that has been downloaded and vetted, copied
+/// from the release's `checksums.sha256`; platforms without one are
+/// refused rather than fetched unverified.
+const PINNED_ARCHIVES: &[PinnedArchive] = &[];
+
+/// Downloads and keeps a static ffmpeg and ffprobe in a cache directory.
+pub struct FfmpegDownload;
+
+impl FfmpegDownload {
+    /// `ffmpeg` below the per-user cache directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        AnalysisCache::default_dir().map(|dir| dir.join("ffmpeg"))
+    }
+
+    /// Whether ffmpeg can't be found for `options` by any of the usual
+    /// means, so it would have to be downloaded. An explicit
+    /// `options.ffmpeg_path` is never replaced.
+    pub fn is_needed(options: &Options) -> bool {
+        options.ffmpeg_path.is_none() && ffmpeg::ffmpeg_command(options).is_err()
+    }
+
+    /// Makes sure `dir` holds ffmpeg and ffprobe of the pinned build,
+    /// downloading it for this platform when it doesn't yet. Returns the
+    /// path of ffmpeg.
+    pub fn ensure(dir: &Path) -> io::Result<PathBuf> {
+        let [ffmpeg, ffprobe] = ["ffmpeg", "ffprobe"].map(|name| dir.join(executable(name)));
+        let build_file = dir.join(BUILD_FILE);
+        let pinned = pinned_archive().ok_or_else(|| {
+            Error::BinaryNotFound(format!(
+                "no pinned static ffmpeg build is known for {}-{}; install ffmpeg instead",
+                env::consts::OS,
+                env::consts::ARCH
+            ))
+        })?;
+        if ffmpeg.is_file()
+            && ffprobe.is_file()
+            && fs::read_to_string(&build_file).is_ok_and(|build| build.trim() == pinned.sha256)
+        {
+            return Ok(ffmpeg);
+        }
+        fs::create_dir_all(dir)?;
+        let staging = dir.join(".download");
+        let _ = fs::remove_dir_all(&staging);
+        fs::create_dir_all(&staging)?;
+        let result = (|| {
+            let archive = staging.join(pinned.name);
+            let url = format!("{}/{}/{}", RELEASES_URL, pinned.release, pinned.name);
+            logging::warn(format_args!("downloading ffmpeg from {}", url));
+            download(&url, &archive)?;
+            let actual = sha256_file(&archive)?;
+            if !actual.eq_ignore_ascii_case(pinned.sha256) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}: SHA-256 {} doesn't match the pinned {}",
+                        pinned.name, actual, pinned.sha256
+                    ),
+                ));
+            }
+            run(ProcessCommand::new("tar")
+                .arg("-xf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(&staging))
+        })();
+        let result = result.and_then(|()| {
+            for target in [&ffmpeg, &ffprobe] {
+                let name = target.file_name().unwrap_or_default();
+                let found = find_file(&staging, name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!(
+                            "{} missing from the downloaded archive",
+                            name.to_string_lossy()
+                        ),
+                    )
+                })?;
+                fs::rename(found, target)?;
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(target, fs::Permissions::from_mode(0o755))?;
+                }
+            }
+            fs::write(&build_file, pinned.sha256)?;
+            Ok(ffmpeg)
+        });
+        let _ = fs::remove_dir_all(&staging);
+        result
+    }
+}
+
+/// The pinned archive holding ffmpeg and ffprobe for this platform.
+fn pinned_archive() -> Option<&'static PinnedArchive> {
+    PINNED_ARCHIVES
+        .iter()
+        .find(|archive| archive.os == env::consts::OS && archive.arch == env::consts::ARCH)
+}
+
+fn download(url: &str, path: &Path) -> io::Result<()> {
+    run(ProcessCommand::new("curl")
+        .args(["-fsSL", "--proto", "=https", "--retry", "3", "-o"])
+        .arg(path)
+        .arg(url))
+}
+
+/// The SHA-256 of the file at `path`, in hex.
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut sha = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        sha.update(&buffer[..read]);
+    }
+    Ok(sha
+        .finish()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// SHA-256 as specified in FIPS 180-4.
+struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    filled: usize,
+    length: u64,
+}
+
+impl Sha256 {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+
+    fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            block: [0; 64],
+            filled: 0,
+            length: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let take = (64 - self.filled).min(data.len());
+            self.block[self.filled..self.filled + take].copy_from_slice(&data[..take]);
+            self.filled += take;
+            data = &data[take..];
+            if self.filled == 64 {
+                self.compress();
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; 32] {
+        let bits = self.length.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.filled != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+        let mut digest = [0; 32];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (i, chunk) in self.block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (k, w) in Self::K.iter().zip(w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(*k)
+                .wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+fn executable(name: &str) -> String {
+    format!("{}{}", name, env::consts::EXE_SUFFIX)
+}
+
+fn run(command: &mut ProcessCommand) -> io::Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::BinaryNotFound(format!(
+                "{} is needed to download ffmpeg but was not found",
+                program
+            ))
+            .into(),
+            _ => e,
+        })?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// The first file named `name` below `dir`.
+fn find_file(dir: &Path, name: &OsStr) -> Option<PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(dir).ok()?.flatten().collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            if let Some(found) = find_file(&path, name) {
+                return Some(found);
+            }
+        } else if entry.file_name() == name {
+            return Some(path);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha256(data: &[u8]) -> String {
+        let mut sha = Sha256::new();
+        sha.update(data);
+        sha.finish()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    #[test]
+    fn sha256_matches_the_fips_examples() {
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            sha256(&[b'a'; 1_000_000]),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn pinned_archives_name_a_dated_release_and_a_full_checksum() {
+        for (index, archive) in PINNED_ARCHIVES.iter().enumerate() {
+            assert!(
+                archive.release.starts_with("autobuild-"),
+                "{}",
+                archive.name
+            );
+            assert!(
+                archive.sha256.len() == 64 && archive.sha256.chars().all(|c| c.is_ascii_hexdigit()),
+                "{}",
+                archive.name
+            );
+            assert!(
+                PINNED_ARCHIVES[..index]
+                    .iter()
+                    .all(|other| (other.os, other.arch) != (archive.os, archive.arch)),
+                "{}",
+                archive.name
+            );
+        }
+    }
+
+    #[test]
+    fn unpinned_platforms_are_refused_before_downloading() {
+        if pinned_archive().is_some() {
+            return;
+        }
+        let dir =
+            env::temp_dir().join(format!("ffmpeg-normalize-provision-{}", std::process::id()));
+        let e = FfmpegDownload::ensure(&dir).unwrap_err();
+        assert!(
+            e.to_string().contains("no pinned static ffmpeg build"),
+            "{}",
+            e
+        );
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,612 @@
+//! A CPython extension module, built with the `python` feature, exposing
+//! [`analyze`], [`normalize`] and [`build_filter`] to Python:
+//!
+//! ```text
+//! cargo rustc --release --lib --features python --crate-type cdylib
+//! cp target/release/libffmpeg_normalize.so ffmpeg_normalize.so
+//! ```
+//!
+//! On macOS the library also needs `-C link-arg=-undefined -C
+//! link-arg=dynamic_lookup`, and on Windows it is named `.pyd`.
+//!
+//! ```python
+//! import ffmpeg_normalize
+//! measured = ffmpeg_normalize.analyze("in.flac", integrated_loudness=-16)
+//! ffmpeg_normalize.normalize("in.flac", "out.flac", preset="podcast")
+//! ffmpeg_normalize.build_filter(measured, true_peak=-1.5)
+//! ```
+//!
+//! Keyword arguments are named after the long command line options, with
+//! underscores for dashes. Measurements are dicts with the fields of
+//! [`Loudness`]; failures raise `ValueError` for invalid arguments and
+//! `RuntimeError` otherwise.
+//!
+//! The module is written against the limited API of CPython 3 directly,
+//! converting arguments and results through Python's `json` module, and
+//! releases the GIL while ffmpeg runs. The layouts of the structures it
+//! shares with the interpreter are asserted below, and the tests compare
+//! them with the installed `Python.h` and import the built module.
+
+use crate::{
+    analyze, build_filter, normalize, Loudness, Mode, Options, PeakMode, Preset, ProgressSpinner,
+    Strategy, INTEGRATED_LOUDNESS_RANGE, LOUDNESS_RANGE_RANGE, OFFSET_RANGE, RMS_RANGE,
+    TRUE_PEAK_RANGE,
+};
+use serde_json::{Map, Value};
+use std::{
+    cell::UnsafeCell,
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    mem::{offset_of, size_of},
+    ops::RangeInclusive,
+    path::PathBuf,
+    ptr,
+    str::FromStr,
+};
+
+#[repr(C)]
+struct PyObject {
+    ob_refcnt: isize,
+    ob_type: *mut c_void,
+}
+
+type PyCFunctionWithKeywords =
+    unsafe extern "C" fn(*mut PyObject, *mut PyObject, *mut PyObject) -> *mut PyObject;
+
+#[repr(C)]
+struct PyMethodDef {
+    ml_name: *const c_char,
+    ml_meth: Option<PyCFunctionWithKeywords>,
+    ml_flags: c_int,
+    ml_doc: *const c_char,
+}
+
+#[repr(C)]
+struct PyModuleDefBase {
+    ob_base: PyObject,
+    m_init: Option<unsafe extern "C" fn() -> *mut PyObject>,
+    m_index: isize,
+    m_copy: *mut PyObject,
+}
+
+#[repr(C)]
+struct PyModuleDef {
+    m_base: PyModuleDefBase,
+    m_name: *const c_char,
+    m_doc: *const c_char,
+    m_size: isize,
+    m_methods: *mut PyMethodDef,
+    m_slots: *mut c_void,
+    m_traverse: *mut c_void,
+    m_clear: *mut c_void,
+    m_free: *mut c_void,
+}
+
+// A mismatch with the interpreter's layouts would corrupt memory rather
+// than fail the import, so they are checked in pointer-sized words.
+const _: () = {
+    const WORD: usize = size_of::<usize>();
+    assert!(size_of::<PyObject>() == 2 * WORD);
+    assert!(size_of::<PyMethodDef>() == 4 * WORD);
+    assert!(size_of::<PyModuleDefBase>() == 5 * WORD);
+    assert!(size_of::<PyModuleDef>() == 13 * WORD);
+    assert!(offset_of!(PyModuleDef, m_methods) == 8 * WORD);
+    assert!(offset_of!(PyModuleDef, m_free) == 12 * WORD);
+};
+
+const METH_VARARGS: c_int = 0x0001;
+const METH_KEYWORDS: c_int = 0x0002;
+const PYTHON_API_VERSION: c_int = 1013;
+
+extern "C" {
+    static mut PyExc_RuntimeError: *mut PyObject;
+    static mut PyExc_TypeError: *mut PyObject;
+    static mut PyExc_ValueError: *mut PyObject;
+
+    fn PyModule_Create2(module: *mut PyModuleDef, api_version: c_int) -> *mut PyObject;
+    fn PyImport_ImportModule(name: *const c_char) -> *mut PyObject;
+    fn PyObject_GetAttrString(object: *mut PyObject, name: *const c_char) -> *mut PyObject;
+    fn PyObject_Call(
+        callable: *mut PyObject,
+        args: *mut PyObject,
+        kwargs: *mut PyObject,
+    ) -> *mut PyObject;
+    fn PyTuple_Pack(n: isize, ...) -> *mut PyObject;
+    fn PyDict_New() -> *mut PyObject;
+    fn PyDict_SetItemString(dict: *mut PyObject, key: *const c_char, value: *mut PyObject)
+        -> c_int;
+    fn PyUnicode_FromStringAndSize(text: *const c_char, len: isize) -> *mut PyObject;
+    fn PyUnicode_AsUTF8String(text: *mut PyObject) -> *mut PyObject;
+    fn PyBytes_AsString(bytes: *mut PyObject) -> *mut c_char;
+    fn PyBytes_Size(bytes: *mut PyObject) -> isize;
+    fn PyErr_SetString(kind: *mut PyObject, message: *const c_char);
+    fn PyEval_SaveThread() -> *mut c_void;
+    fn PyEval_RestoreThread(state: *mut c_void);
+    fn Py_DecRef(object: *mut PyObject);
+}
+
+/// A static handed to the interpreter, which may write to it.
+struct PyStatic<T>(UnsafeCell<T>);
+
+// SAFETY: the interpreter only touches these with the GIL held.
+unsafe impl<T> Sync for PyStatic<T> {}
+
+static METHODS: PyStatic<[PyMethodDef; 4]> = PyStatic(UnsafeCell::new([
+    PyMethodDef {
+        ml_name: c"analyze".as_ptr(),
+        ml_meth: Some(py_analyze),
+        ml_flags: METH_VARARGS | METH_KEYWORDS,
+        ml_doc: c"analyze(input, **options) -> dict\n\nRuns the loudnorm measurement pass over input."
+            .as_ptr(),
+    },
+    PyMethodDef {
+        ml_name: c"normalize".as_ptr(),
+        ml_meth: Some(py_normalize),
+        ml_flags: METH_VARARGS | METH_KEYWORDS,
+        ml_doc: c"normalize(input, output, **options) -> dict\n\nMeasures input and writes the normalized result to output, returning the measurements."
+            .as_ptr(),
+    },
+    PyMethodDef {
+        ml_name: c"build_filter".as_ptr(),
+        ml_meth: Some(py_build_filter),
+        ml_flags: METH_VARARGS | METH_KEYWORDS,
+        ml_doc: c"build_filter(measurements, **options) -> str\n\nBuilds the second-pass filter for measurements returned by analyze."
+            .as_ptr(),
+    },
+    PyMethodDef {
+        ml_name: ptr::null(),
+        ml_meth: None,
+        ml_flags: 0,
+        ml_doc: ptr::null(),
+    },
+]));
+
+static MODULE: PyStatic<PyModuleDef> = PyStatic(UnsafeCell::new(PyModuleDef {
+    m_base: PyModuleDefBase {
+        ob_base: PyObject {
+            ob_refcnt: 1,
+            ob_type: ptr::null_mut(),
+        },
+        m_init: None,
+        m_index: 0,
+        m_copy: ptr::null_mut(),
+    },
+    m_name: c"ffmpeg_normalize".as_ptr(),
+    m_doc: c"Two-pass loudness normalization built on ffmpeg's loudnorm filter.".as_ptr(),
+    m_size: -1,
+    m_methods: METHODS.0.get().cast(),
+    m_slots: ptr::null_mut(),
+    m_traverse: ptr::null_mut(),
+    m_clear: ptr::null_mut(),
+    m_free: ptr::null_mut(),
+}));
+
+/// Entry point Python calls on `import ffmpeg_normalize`.
+///
+/// # Safety
+///
+/// Called by the interpreter, with the GIL held.
+#[no_mangle]
+pub unsafe extern "C" fn PyInit_ffmpeg_normalize() -> *mut c_void {
+    // Progress belongs to the calling program, not to lines on stderr.
+    ProgressSpinner::set_enabled(false);
+    PyModule_Create2(MODULE.0.get(), PYTHON_API_VERSION).cast()
+}
+
+/// Why a call failed, raised as the matching Python exception.
+enum PyError {
+    Type(String),
+    Value(String),
+    Runtime(String),
+}
+
+impl From<std::io::Error> for PyError {
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::InvalidInput {
+            PyError::Value(e.to_string())
+        } else {
+            PyError::Runtime(e.to_string())
+        }
+    }
+}
+
+/// A new reference, released when dropped.
+struct Owned(*mut PyObject);
+
+impl Owned {
+    /// Takes `object`, failing with the exception Python already set when
+    /// it is null.
+    fn new(object: *mut PyObject) -> Result<Self, ()> {
+        if object.is_null() {
+            Err(())
+        } else {
+            Ok(Self(object))
+        }
+    }
+
+    /// Hands the reference over to the caller.
+    fn into_raw(self) -> *mut PyObject {
+        let object = self.0;
+        std::mem::forget(self);
+        object
+    }
+}
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        // SAFETY: `self.0` is a reference we own, released with the GIL held.
+        unsafe { Py_DecRef(self.0) }
+    }
+}
+
+/// Calls `json.<function>(argument, **kwargs)`.
+unsafe fn call_json(
+    function: &CStr,
+    argument: *mut PyObject,
+    kwargs: *mut PyObject,
+) -> Result<Owned, ()> {
+    let json = Owned::new(PyImport_ImportModule(c"json".as_ptr()))?;
+    let function = Owned::new(PyObject_GetAttrString(json.0, function.as_ptr()))?;
+    let args = Owned::new(PyTuple_Pack(1, argument))?;
+    Owned::new(PyObject_Call(function.0, args.0, kwargs))
+}
+
+/// `object` converted to JSON, with path-like objects as strings.
+unsafe fn to_json(object: *mut PyObject) -> Result<Value, ()> {
+    let os = Owned::new(PyImport_ImportModule(c"os".as_ptr()))?;
+    let fspath = Owned::new(PyObject_GetAttrString(os.0, c"fspath".as_ptr()))?;
+    let kwargs = Owned::new(PyDict_New())?;
+    if PyDict_SetItemString(kwargs.0, c"default".as_ptr(), fspath.0) != 0 {
+        return Err(());
+    }
+    let text = call_json(c"dumps", object, kwargs.0)?;
+    let bytes = Owned::new(PyUnicode_AsUTF8String(text.0))?;
+    let data = PyBytes_AsString(bytes.0);
+    if data.is_null() {
+        return Err(());
+    }
+    let data = std::slice::from_raw_parts(data.cast::<u8>(), PyBytes_Size(bytes.0) as usize);
+    serde_json::from_slice(data).map_err(|e| raise(PyError::Runtime(e.to_string())))
+}
+
+/// `value` converted to a Python object.
+unsafe fn from_json(value: &Value) -> Result<Owned, ()> {
+    let text = value.to_string();
+    let text = Owned::new(PyUnicode_FromStringAndSize(
+        text.as_ptr().cast(),
+        text.len() as isize,
+    ))?;
+    call_json(c"loads", text.0, ptr::null_mut())
+}
+
+/// Sets `error` as the pending Python exception.
+unsafe fn raise(error: PyError) {
+    let (kind, message) = match error {
+        PyError::Type(message) => (PyExc_TypeError, message),
+        PyError::Value(message) => (PyExc_ValueError, message),
+        PyError::Runtime(message) => (PyExc_RuntimeError, message),
+    };
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    PyErr_SetString(kind, message.as_ptr());
+}
+
+/// Runs a module function: converts the positional and keyword arguments,
+/// runs `function` on them without the GIL, and converts its result.
+unsafe fn call(
+    args: *mut PyObject,
+    kwargs: *mut PyObject,
+    function: impl FnOnce(Vec<Value>, Map<String, Value>) -> Result<Value, PyError>,
+) -> *mut PyObject {
+    let converted = (|| {
+        let Value::Array(args) = to_json(args)? else {
+            return Err(());
+        };
+        let kwargs = match kwargs.is_null() {
+            true => Map::new(),
+            false => match to_json(kwargs)? {
+                Value::Object(kwargs) => kwargs,
+                _ => return Err(()),
+            },
+        };
+        Ok((args, kwargs))
+    })();
+    let Ok((args, kwargs)) = converted else {
+        return ptr::null_mut();
+    };
+    let state = PyEval_SaveThread();
+    let result = function(args, kwargs);
+    PyEval_RestoreThread(state);
+    match result {
+        Ok(value) => match from_json(&value) {
+            Ok(object) => object.into_raw(),
+            Err(()) => ptr::null_mut(),
+        },
+        Err(error) => {
+            raise(error);
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe extern "C" fn py_analyze(
+    _module: *mut PyObject,
+    args: *mut PyObject,
+    kwargs: *mut PyObject,
+) -> *mut PyObject {
+    call(args, kwargs, |args, kwargs| {
+        let [input] = positional(args, ["input"])?;
+        let loudness = analyze(&path(input, "input")?, &options(kwargs)?)?;
+        Ok(serde_json::to_value(loudness).unwrap_or_default())
+    })
+}
+
+unsafe extern "C" fn py_normalize(
+    _module: *mut PyObject,
+    args: *mut PyObject,
+    kwargs: *mut PyObject,
+) -> *mut PyObject {
+    call(args, kwargs, |args, kwargs| {
+        let [input, output] = positional(args, ["input", "output"])?;
+        let loudness = normalize(
+            &path(input, "input")?,
+            &path(output, "output")?,
+            &options(kwargs)?,
+        )?;
+        Ok(serde_json::to_value(loudness).unwrap_or_default())
+    })
+}
+
+unsafe extern "C" fn py_build_filter(
+    _module: *mut PyObject,
+    args: *mut PyObject,
+    kwargs: *mut PyObject,
+) -> *mut PyObject {
+    call(args, kwargs, |args, kwargs| {
+        let [measurements] = positional(args, ["measurements"])?;
+        let loudness: Loudness = serde_json::from_value(measurements)
+            .map_err(|e| PyError::Value(format!("measurements: {}", e)))?;
+        Ok(Value::from(build_filter(&loudness, &options(kwargs)?)))
+    })
+}
+
+/// The positional arguments `names`, exactly.
+fn positional<const N: usize>(args: Vec<Value>, names: [&str; N]) -> Result<[Value; N], PyError> {
+    let count = args.len();
+    args.try_into().map_err(|_| {
+        PyError::Type(format!(
+            "expected {} positional argument{} ({}), got {}",
+            N,
+            if N == 1 { "" } else { "s" },
+            names.join(", "),
+            count
+        ))
+    })
+}
+
+fn path(value: Value, name: &str) -> Result<PathBuf, PyError> {
+    match value {
+        Value::String(path) => Ok(PathBuf::from(path)),
+        _ => Err(PyError::Type(format!("{} must be a path", name))),
+    }
+}
+
+/// [`Options`] from keyword arguments named like the command line options.
+fn options(kwargs: Map<String, Value>) -> Result<Options, PyError> {
+    let mut options = Options::default();
+    if let Some(name) = kwargs.get("preset") {
+        let name = string(name, "preset")?;
+        let preset = Preset::find(&name)
+            .ok_or_else(|| PyError::Value(format!("unknown preset '{}'", name)))?;
+        options.integrated_loudness = preset.integrated_loudness;
+        options.loudness_range = preset.loudness_range;
+        options.true_peak = preset.true_peak;
+    }
+    for (key, value) in &kwargs {
+        match key.as_str() {
+            "preset" => {}
+            "integrated_loudness" => {
+                options.integrated_loudness = number(value, key, INTEGRATED_LOUDNESS_RANGE)?
+            }
+            "loudness_range" => options.loudness_range = number(value, key, LOUDNESS_RANGE_RANGE)?,
+            "true_peak" => options.true_peak = number(value, key, TRUE_PEAK_RANGE)?,
+            "offset" => options.offset = Some(number(value, key, OFFSET_RANGE)?),
+            "target_rms" => options.target_rms = number(value, key, RMS_RANGE)?,
+            "dual_mono" => options.dual_mono = flag(value, key)?,
+            "pass_silent" => options.pass_silent = flag(value, key)?,
+            "copy_video" => options.encoding.copy_video = flag(value, key)?,
+            "map_all" => options.encoding.map_all = flag(value, key)?,
+            "audio_stream" => options.audio_stream = Some(integer(value, key)? as usize),
+            "sample_rate" => {
+                options.encoding.sample_rate = Some(
+                    u32::try_from(integer(value, key)?)
+                        .map_err(|_| PyError::Value(format!("{}: out of range", key)))?,
+                )
+            }
+            "codec" => options.encoding.codec = Some(string(value, key)?),
+            "bitrate" => options.encoding.bitrate = Some(string(value, key)?),
+            "sample_fmt" => options.encoding.sample_fmt = Some(string(value, key)?),
+            "channel_layout" => options.channel_layout = Some(string(value, key)?),
+            "pre_filter" => options.pre_filter = Some(string(value, key)?),
+            "post_filter" => options.post_filter = Some(string(value, key)?),
+            "start" => options.start = Some(string(value, key)?),
+            "duration" => options.duration = Some(string(value, key)?),
+            "ffmpeg_path" => options.ffmpeg_path = Some(PathBuf::from(string(value, key)?)),
+            "mode" => options.mode = parse::<Mode>(value, key)?,
+            "strategy" => options.strategy = parse::<Strategy>(value, key)?,
+            "peak_mode" => options.peak_mode = parse::<PeakMode>(value, key)?,
+            _ => {
+                return Err(PyError::Type(format!(
+                    "unexpected keyword argument '{}'",
+                    key
+                )))
+            }
+        }
+    }
+    Ok(options)
+}
+
+fn number(value: &Value, key: &str, range: RangeInclusive<f64>) -> Result<f64, PyError> {
+    let number = value
+        .as_f64()
+        .ok_or_else(|| PyError::Type(format!("{} must be a number", key)))?;
+    if !range.contains(&number) {
+        return Err(PyError::Value(format!(
+            "{}: {} is outside {} to {}",
+            key,
+            number,
+            range.start(),
+            range.end()
+        )));
+    }
+    Ok(number)
+}
+
+fn integer(value: &Value, key: &str) -> Result<u64, PyError> {
+    value
+        .as_u64()
+        .ok_or_else(|| PyError::Type(format!("{} must be a non-negative integer", key)))
+}
+
+fn flag(value: &Value, key: &str) -> Result<bool, PyError> {
+    value
+        .as_bool()
+        .ok_or_else(|| PyError::Type(format!("{} must be a bool", key)))
+}
+
+fn string(value: &Value, key: &str) -> Result<String, PyError> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| PyError::Type(format!("{} must be a str", key)))
+}
+
+fn parse<T: FromStr<Err = String>>(value: &Value, key: &str) -> Result<T, PyError> {
+    string(value, key)?
+        .parse()
+        .map_err(|e| PyError::Value(format!("{}: {}", key, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process::Command};
+
+    /// Runs `program` with `args`, failing the test unless it succeeds.
+    fn run(program: &str, args: &[&str]) -> String {
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .unwrap_or_else(|e| panic!("{}: {}", program, e));
+        assert!(
+            output.status.success(),
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    #[test]
+    fn layouts_match_the_installed_headers() {
+        let dir = env::temp_dir().join(format!("ffmpeg-normalize-python-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("layout.c");
+        fs::write(
+            &source,
+            r#"#define Py_LIMITED_API 0x03070000
+#include <Python.h>
+#include <stddef.h>
+#include <stdio.h>
+int main(void) {
+    printf("%zu %zu %zu %zu %zu %zu %d %d %d\n", sizeof(PyObject), sizeof(PyMethodDef),
+           sizeof(PyModuleDef_Base), sizeof(PyModuleDef), offsetof(PyModuleDef, m_methods),
+           offsetof(PyModuleDef, m_free), METH_VARARGS, METH_KEYWORDS, PYTHON_API_VERSION);
+    return 0;
+}
+"#,
+        )
+        .unwrap();
+        let includes = run("python3-config", &["--includes"]);
+        let binary = dir.join("layout");
+        let mut args: Vec<&str> = includes.split_whitespace().collect();
+        args.extend([source.to_str().unwrap(), "-o", binary.to_str().unwrap()]);
+        run("cc", &args);
+        let printed = run(binary.to_str().unwrap(), &[]);
+        let _ = fs::remove_dir_all(&dir);
+        let expected = format!(
+            "{} {} {} {} {} {} {} {} {}",
+            size_of::<PyObject>(),
+            size_of::<PyMethodDef>(),
+            size_of::<PyModuleDefBase>(),
+            size_of::<PyModuleDef>(),
+            offset_of!(PyModuleDef, m_methods),
+            offset_of!(PyModuleDef, m_free),
+            METH_VARARGS,
+            METH_KEYWORDS,
+            PYTHON_API_VERSION
+        );
+        assert_eq!(printed.trim(), expected);
+    }
+
+    #[test]
+    fn imports_into_the_interpreter() {
+        // A target directory of its own, as the one of the running tests is
+        // locked by cargo.
+        let root = env!("CARGO_MANIFEST_DIR");
+        let target = format!("{}/target/python-test", root);
+        let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+        run(
+            &cargo,
+            &[
+                "rustc",
+                "--quiet",
+                "--lib",
+                "--features",
+                "python",
+                "--crate-type",
+                "cdylib",
+                "--manifest-path",
+                &format!("{}/Cargo.toml", root),
+                "--target-dir",
+                &target,
+            ],
+        );
+        let dir = format!("{}/module", target);
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(
+            format!("{}/debug/libffmpeg_normalize.so", target),
+            format!("{}/ffmpeg_normalize.so", dir),
+        )
+        .unwrap();
+        let script = r#"
+import sys
+sys.path.insert(0, sys.argv[1])
+import ffmpeg_normalize as fn
+measured = {"input_i": -30.0, "input_tp": -10.0, "input_lra": 5.0, "input_thresh": -40.0,
+            "target_offset": 0.5}
+print(fn.build_filter(measured, integrated_loudness=-16, true_peak=-1.5).split(":")[0])
+for call, error in [
+    (lambda: fn.build_filter(measured, loudness=1), TypeError),
+    (lambda: fn.build_filter(measured, true_peak=5), ValueError),
+    (lambda: fn.build_filter(), TypeError),
+    (lambda: fn.analyze("in.flac", preset="nonesuch"), ValueError),
+]:
+    try:
+        call()
+    except error as e:
+        print(type(e).__name__)
+print(fn.analyze.__doc__.splitlines()[0])
+"#;
+        let printed = run("python3", &["-c", script, &dir]);
+        assert_eq!(
+            printed.lines().collect::<Vec<_>>(),
+            [
+                "loudnorm=I=-16.0",
+                "TypeError",
+                "ValueError",
+                "TypeError",
+                "ValueError",
+                "analyze(input, **options) -> dict",
+            ]
+        );
+    }
+}
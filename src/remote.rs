@@ -0,0 +1,120 @@
+//! Inputs given as `http://` or `https://` URLs, such as presigned S3 links.
+//!
+//! ffmpeg reads URLs itself, so by default every pass fetches the resource
+//! again. A [`RemoteDownload`] fetches it once with `curl` instead; while it
+//! lives, ffmpeg and ffprobe are handed the downloaded copy in place of the
+//! URL.
+
+use crate::{logging, Error};
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::{self, Command as ProcessCommand, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Protocols ffmpeg may use for URL inputs: plain and TLS HTTP, and what
+/// HLS playlists of encrypted segments need. `file` is left out so a remote
+/// playlist can't make ffmpeg read local files.
+pub(crate) const PROTOCOL_WHITELIST: &str = "http,https,tcp,tls,crypto";
+
+/// The downloaded copies of URL inputs, by URL.
+static DOWNLOADS: Mutex<Option<HashMap<PathBuf, PathBuf>>> = Mutex::new(None);
+
+/// Numbers the download directories of one process.
+static NEXT_DOWNLOAD: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether `path` is an `http://` or `https://` URL rather than a file.
+pub fn is_url(path: &Path) -> bool {
+    let Some(text) = path.to_str() else {
+        return false;
+    };
+    ["http://", "https://"].iter().any(|scheme| {
+        text.get(..scheme.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    })
+}
+
+/// The last segment of the path of the URL `url`, without query or
+/// fragment, e.g. `track.flac` for a presigned S3 link to it.
+pub fn url_file_name(url: &Path) -> Option<&str> {
+    let text = url.to_str()?;
+    let (_, rest) = text.split_once("://")?;
+    let path = rest.split(['?', '#']).next()?;
+    let (_, name) = path.split_once('/')?;
+    name.rsplit('/').next().filter(|name| !name.is_empty())
+}
+
+/// The downloaded copy of `url`, while its [`RemoteDownload`] lives.
+pub(crate) fn local_copy(url: &Path) -> Option<PathBuf> {
+    let downloads = DOWNLOADS.lock().ok()?;
+    downloads.as_ref()?.get(url).cloned()
+}
+
+/// A URL input fetched to a temporary file, which is removed again when
+/// this is dropped.
+pub struct RemoteDownload {
+    url: PathBuf,
+    dir: PathBuf,
+}
+
+impl RemoteDownload {
+    /// Downloads `url`, failing with curl's message when the resource is
+    /// unreachable or the server answers with an error status.
+    pub fn fetch(url: &Path) -> io::Result<Self> {
+        let dir = env::temp_dir().join(format!(
+            "ffmpeg-loudnorm-helper-{}-download{}",
+            process::id(),
+            NEXT_DOWNLOAD.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        let download = Self {
+            url: url.to_path_buf(),
+            dir,
+        };
+        // Keeping the file name keeps the extension ffmpeg may need to
+        // recognize the format.
+        let path = download.dir.join(url_file_name(url).unwrap_or("download"));
+        logging::info(format_args!("{}: downloading", url.display()));
+        let output = ProcessCommand::new("curl")
+            .args(["-fsSL", "--retry", "3", "-o"])
+            .arg(&path)
+            .arg(url)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => Error::BinaryNotFound(
+                    "curl is needed for --download-inputs but was not found".to_string(),
+                )
+                .into(),
+                _ => e,
+            })?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "download failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        if let Ok(mut downloads) = DOWNLOADS.lock() {
+            downloads
+                .get_or_insert_with(HashMap::new)
+                .insert(download.url.clone(), path);
+        }
+        Ok(download)
+    }
+}
+
+impl Drop for RemoteDownload {
+    fn drop(&mut self) {
+        if let Ok(mut downloads) = DOWNLOADS.lock() {
+            if let Some(downloads) = downloads.as_mut() {
+                downloads.remove(&self.url);
+            }
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
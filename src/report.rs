@@ -0,0 +1,103 @@
+//! Per-input summary of a batch run, written with `--report`.
+
+use serde::Serialize;
+use std::{fmt::Write as _, fs, io, path::Path, sync::Mutex};
+
+/// One input of the batch and what became of it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReportRow {
+    pub input: String,
+    pub output: Option<String>,
+    /// Integrated loudness of the input in LUFS.
+    pub input_i: Option<f64>,
+    /// True peak of the input in dBTP.
+    pub input_tp: Option<f64>,
+    /// Loudness range of the input in LU.
+    pub input_lra: Option<f64>,
+    /// Gain in dB brought to the integrated loudness.
+    pub gain_db: Option<f64>,
+    /// Integrated loudness of the output, as verified or else as loudnorm
+    /// reported it for the second pass.
+    pub output_i: Option<f64>,
+    /// True peak of the output, likewise.
+    pub output_tp: Option<f64>,
+    /// Loudness range of the output as loudnorm reported it.
+    pub output_lra: Option<f64>,
+    /// `linear` or `dynamic`, as loudnorm applied the second pass.
+    pub normalization_type: Option<String>,
+    /// `ok`, `already normalized` when the input was at the target already,
+    /// `failed verification` or the error that stopped the input.
+    pub status: String,
+    /// Wall-clock time spent on the input in seconds.
+    pub elapsed: f64,
+}
+
+/// Rows collected from the workers, kept in input order.
+#[derive(Default)]
+pub struct BatchReport {
+    rows: Mutex<Vec<(usize, ReportRow)>>,
+}
+
+impl BatchReport {
+    /// Records the row for the input at `index`, replacing an earlier one
+    /// when the input was retried.
+    pub fn add(&self, index: usize, row: ReportRow) {
+        if let Ok(mut rows) = self.rows.lock() {
+            rows.retain(|(other, _)| *other != index);
+            rows.push((index, row));
+        }
+    }
+
+    /// Writes the report to `path`, as JSON when the extension is `.json`
+    /// and as CSV otherwise.
+    pub fn write(self, path: &Path) -> io::Result<()> {
+        let mut rows = self.rows.into_inner().unwrap_or_default();
+        rows.sort_by_key(|(index, _)| *index);
+        let rows: Vec<ReportRow> = rows.into_iter().map(|(_, row)| row).collect();
+        let json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let contents = if json {
+            serde_json::to_string_pretty(&rows)?
+        } else {
+            to_csv(&rows)
+        };
+        fs::write(path, contents)
+    }
+}
+
+fn to_csv(rows: &[ReportRow]) -> String {
+    let number = |value: Option<f64>| value.map_or_else(String::new, |v| format!("{:.2}", v));
+    let mut csv = String::from(
+        "input,output,input_i,input_tp,input_lra,gain_db,output_i,output_tp,output_lra,\
+         normalization_type,status,elapsed\n",
+    );
+    for row in rows {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{},{},{},{:.2}",
+            csv_field(&row.input),
+            csv_field(row.output.as_deref().unwrap_or_default()),
+            number(row.input_i),
+            number(row.input_tp),
+            number(row.input_lra),
+            number(row.gain_db),
+            number(row.output_i),
+            number(row.output_tp),
+            number(row.output_lra),
+            row.normalization_type.as_deref().unwrap_or_default(),
+            csv_field(&row.status),
+            row.elapsed
+        );
+    }
+    csv
+}
+
+/// Quotes `value` when it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
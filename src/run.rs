@@ -0,0 +1,461 @@
+//! What one run of the command line does, as a [`RunConfig`], and [`run`]
+//! carrying it out.
+
+use crate::{
+    batch::{self, Failures},
+    commands, is_url, serve, walk_audio_files, watch, AudiobookMode, Confirmation, CueSheet,
+    FfmpegDownload, GroupReference, ImportedStats, Job, LoudnessHistory, Manifest, Notifier,
+    Options, OutputTemplate, Playlist, PostHook, Preset, ProgressSpinner, Shell, SpecProfile,
+    StatsExport, DEFAULT_EXTENSIONS, DEFAULT_OUTPUT_TEMPLATE, DEFAULT_VARIANT_TEMPLATE,
+};
+use std::{
+    borrow::Cow,
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
+
+/// Input path that stands for standard input.
+pub const STDIN_PATH: &str = "-";
+
+/// Output path that stands for standard output.
+pub const STDOUT_PATH: &str = "-";
+
+/// Exit code with `--noop-exit-code` when every input was already at its
+/// target.
+pub const NOOP_EXIT_CODE: u8 = 9;
+
+/// How the result of each input is printed.
+#[derive(Clone, Copy)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Program whose syntax the printed filter uses.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FilterTarget {
+    Ffmpeg,
+    Mpv,
+}
+
+/// How progress is reported.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ProgressFormat {
+    /// A spinner on a terminal, occasional lines otherwise.
+    Text,
+    /// JSON events on stdout, which then carries nothing else.
+    Jsonl,
+}
+
+/// What is printed for each input in place of the second-pass filter.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PrintValue {
+    Filter,
+    /// The gain in dB reaching the target.
+    Gain,
+    /// A `volume=XdB` filter applying that gain.
+    Volume,
+    /// The measurements in the aligned columns of loudnorm's
+    /// `print_format=summary`.
+    Summary,
+    /// With `--format json`, the measurements, filter, gain and the full
+    /// second-pass command in one object.
+    All,
+}
+
+/// Everything a run is asked to do: the inputs, where their outputs go,
+/// how they are processed and what is printed for them.
+#[derive(Clone)]
+pub struct RunConfig {
+    pub input_paths: Vec<PathBuf>,
+    pub watch_dir: Option<PathBuf>,
+    pub input_format: Option<String>,
+    /// Fetch URL inputs once instead of streaming them in every pass.
+    pub download_inputs: bool,
+    pub output_path: Option<PathBuf>,
+    pub output_template: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    pub output_ext: Option<String>,
+    /// Replace each input with its output (`--in-place`).
+    pub in_place: bool,
+    /// Keep the replaced input under its name with this appended.
+    pub backup_suffix: Option<String>,
+    /// Give in-place outputs the modification time of their input.
+    pub keep_mtime: bool,
+    /// Presets of `--targets`, under the names given, each normalized to
+    /// from one measurement.
+    pub variants: Vec<(String, Preset)>,
+    /// Per-input overrides of the `--manifest`.
+    pub manifest: Option<Arc<Manifest>>,
+    /// Join the inputs into one program.
+    pub concat: bool,
+    /// Apply the gain of the first input to all of them.
+    pub stems: bool,
+    pub timeline_path: Option<PathBuf>,
+    /// File the measurements of the input are saved to.
+    pub save_analysis_path: Option<PathBuf>,
+    /// Measurements of inputs from a stats file of the Python
+    /// ffmpeg-normalize.
+    pub imported_stats: Option<Arc<ImportedStats>>,
+    /// Stats file in the format of the Python ffmpeg-normalize written for
+    /// the inputs.
+    pub stats_export: Option<Arc<StatsExport>>,
+    pub filter_script_path: Option<PathBuf>,
+    pub report_path: Option<PathBuf>,
+    /// Write an M3U playlist of the outputs here.
+    pub output_playlist: Option<PathBuf>,
+    /// Run after each written output.
+    pub post_hook: Option<PostHook>,
+    /// Receives an event per input and one for the whole run.
+    pub notifier: Option<Arc<Notifier>>,
+    pub state_path: Option<PathBuf>,
+    /// The `--db` history, opened in `main`.
+    pub history: Option<LoudnessHistory>,
+    pub resume: bool,
+    /// Overwrite existing outputs.
+    pub force: bool,
+    /// Skip inputs that already carry gain tags.
+    pub skip_tagged: bool,
+    /// Skip inputs whose marker tag records the configured target.
+    pub skip_normalized: bool,
+    /// Leave a marker tag in every output.
+    pub marker: bool,
+    /// Leave inputs whose output already exists alone.
+    pub skip_existing: bool,
+    pub plot_path: Option<PathBuf>,
+    pub recursive: bool,
+    pub include_ext: Vec<String>,
+    pub jobs: usize,
+    /// Hold back new inputs while the load average is above this.
+    pub max_load: Option<f64>,
+    pub all_audio_streams: bool,
+    pub tag_only: bool,
+    pub album: bool,
+    /// Bring this track of the album to the target instead of the album
+    /// loudness.
+    pub group_reference: Option<GroupReference>,
+    /// Print the second-pass command instead of running it.
+    pub print_command: bool,
+    /// Shell the printed command is quoted for.
+    pub shell: Shell,
+    /// Show the spinner, or progress lines when stderr is not a terminal.
+    pub progress: bool,
+    /// Download a static ffmpeg when none is installed.
+    pub auto_download_ffmpeg: bool,
+    pub progress_format: ProgressFormat,
+    /// Re-measure outputs, accepting this deviation in LU from the target.
+    pub verify_tolerance: Option<f64>,
+    /// Print a loudness report instead of a filter (`analyze`).
+    pub report: bool,
+    /// Print the loudness of two inputs side by side (`compare`).
+    pub compare: bool,
+    /// Check inputs against this delivery specification (`check`).
+    pub check: Option<SpecProfile>,
+    /// Check the ffmpeg build against generated signals (`selftest`).
+    pub selftest: bool,
+    /// Check the ffmpeg installation and the writable directories
+    /// (`doctor`).
+    pub doctor: bool,
+    /// Take jobs over HTTP on this endpoint (`serve`).
+    pub serve: Option<serve::Endpoint>,
+    /// Exit with [`NOOP_EXIT_CODE`] when no input needed normalizing.
+    pub noop_exit_code: bool,
+    /// Stop starting new inputs once one has failed.
+    pub fail_fast: bool,
+    /// Ask before encoding each input.
+    pub interactive: Option<Arc<Confirmation>>,
+    /// Show the batch on a full-screen dashboard.
+    pub tui: bool,
+    /// Leave inputs within this many LU of the target unencoded.
+    pub skip_within: Option<f64>,
+    /// Split the single input into the tracks of this sheet.
+    pub cue: Option<CueSheet>,
+    /// Normalize inputs as audiobooks, by chapter or as a whole.
+    pub audiobook: Option<AudiobookMode>,
+    pub format: OutputFormat,
+    /// End each printed value, and the path before it in batches, with a NUL
+    /// instead of a newline.
+    pub print0: bool,
+    /// Shell the printed filter is quoted for, as one word.
+    pub escape: Option<Shell>,
+    pub target: FilterTarget,
+    pub print: PrintValue,
+    pub options: Options,
+}
+impl RunConfig {
+    /// A run normalizing with `options` and otherwise the defaults of the
+    /// command line, without any inputs yet.
+    pub fn new(options: Options) -> Self {
+        Self {
+            input_paths: Vec::new(),
+            watch_dir: None,
+            input_format: None,
+            download_inputs: false,
+            output_path: None,
+            output_template: None,
+            output_dir: None,
+            output_ext: None,
+            in_place: false,
+            backup_suffix: None,
+            keep_mtime: false,
+            variants: Vec::new(),
+            manifest: None,
+            concat: false,
+            stems: false,
+            timeline_path: None,
+            save_analysis_path: None,
+            imported_stats: None,
+            stats_export: None,
+            filter_script_path: None,
+            report_path: None,
+            output_playlist: None,
+            post_hook: None,
+            notifier: None,
+            state_path: None,
+            history: None,
+            resume: false,
+            force: false,
+            skip_tagged: false,
+            skip_normalized: false,
+            marker: true,
+            skip_existing: false,
+            plot_path: None,
+            recursive: false,
+            include_ext: DEFAULT_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            jobs: 1,
+            max_load: None,
+            all_audio_streams: false,
+            tag_only: false,
+            album: false,
+            group_reference: None,
+            print_command: false,
+            shell: Shell::default(),
+            progress: true,
+            auto_download_ffmpeg: false,
+            progress_format: ProgressFormat::Text,
+            verify_tolerance: None,
+            report: false,
+            compare: false,
+            check: None,
+            selftest: false,
+            doctor: false,
+            serve: None,
+            noop_exit_code: false,
+            fail_fast: false,
+            interactive: None,
+            tui: false,
+            skip_within: None,
+            cue: None,
+            audiobook: None,
+            format: OutputFormat::Text,
+            print0: false,
+            escape: None,
+            target: FilterTarget::Ffmpeg,
+            print: PrintValue::Filter,
+            options,
+        }
+    }
+
+    /// The second-pass output path for `input_path`, or `None` when only
+    /// analyzing.
+    pub fn output_for(&self, input_path: &Path) -> io::Result<Option<PathBuf>> {
+        let job = self
+            .manifest
+            .as_ref()
+            .and_then(|manifest| manifest.job(input_path));
+        if let Some(output_path) = job.and_then(|job| job.output.as_ref()) {
+            return Ok(Some(output_path.clone()));
+        }
+        if self.in_place {
+            return Ok(Some(input_path.to_path_buf()));
+        }
+        if let Some(output_path) = &self.output_path {
+            return Ok(Some(output_path.clone()));
+        }
+        if self.output_template.is_none() && self.output_dir.is_none() {
+            return Ok(None);
+        }
+        self.render_output(input_path, None).map(Some)
+    }
+
+    /// This configuration with the targets of the `--manifest` row `job`:
+    /// those of its preset, replaced in turn by the ones given explicitly.
+    pub fn for_job(&self, job: &Job) -> Self {
+        let mut config = self.clone();
+        let options = &mut config.options;
+        if let Some(preset) = &job.preset {
+            options.integrated_loudness = preset.integrated_loudness;
+            options.loudness_range = preset.loudness_range;
+            options.true_peak = preset.true_peak;
+        }
+        options.integrated_loudness = job
+            .integrated_loudness
+            .unwrap_or(options.integrated_loudness);
+        options.loudness_range = job.loudness_range.unwrap_or(options.loudness_range);
+        options.true_peak = job.true_peak.unwrap_or(options.true_peak);
+        config
+    }
+
+    /// The options of the `--targets` variant `preset`.
+    pub(crate) fn variant_options(&self, preset: &Preset) -> Options {
+        Options {
+            integrated_loudness: preset.integrated_loudness,
+            loudness_range: preset.loudness_range,
+            true_peak: preset.true_peak,
+            ..self.options.clone()
+        }
+    }
+
+    /// Every output written for `input_path`: one per `--targets` variant,
+    /// or the single one of [`Self::output_for`].
+    pub fn outputs_for(&self, input_path: &Path) -> io::Result<Vec<PathBuf>> {
+        if self.variants.is_empty() {
+            return Ok(self.output_for(input_path)?.into_iter().collect());
+        }
+        self.variants
+            .iter()
+            .map(|(name, preset)| {
+                self.variant_output_for(input_path, name, &self.variant_options(preset))
+            })
+            .collect()
+    }
+
+    /// The output of `input_path` for the `--targets` variant `name`, whose
+    /// targets are in `options`.
+    pub(crate) fn variant_output_for(
+        &self,
+        input_path: &Path,
+        name: &str,
+        options: &Options,
+    ) -> io::Result<PathBuf> {
+        self.render_output(input_path, Some((name, options)))
+    }
+
+    fn render_output(
+        &self,
+        input_path: &Path,
+        variant: Option<(&str, &Options)>,
+    ) -> io::Result<PathBuf> {
+        let default_template = match variant {
+            Some(_) => DEFAULT_VARIANT_TEMPLATE,
+            None => DEFAULT_OUTPUT_TEMPLATE,
+        };
+        let template = self.output_template.as_deref().unwrap_or(default_template);
+        let template = match &self.output_ext {
+            Some(ext) => Cow::Owned(template.replace("{ext}", ext)),
+            None => Cow::Borrowed(template),
+        };
+        let output_dir = self.output_dir.as_deref();
+        let output_path = match variant {
+            Some((name, options)) => {
+                OutputTemplate::render_variant(&template, input_path, output_dir, options, name)?
+            }
+            None => OutputTemplate::render(&template, input_path, output_dir, &self.options)?,
+        };
+        if output_path == input_path {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "Output path {} would overwrite the input",
+                    output_path.display()
+                ),
+            ));
+        }
+        if let Some(parent) = output_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(output_path)
+    }
+
+    /// Resolves the inputs to process, reading the tracks of playlists and
+    /// descending into directories when `--recursive` is set.
+    pub fn collect_inputs(&self) -> Vec<io::Result<PathBuf>> {
+        self.input_paths
+            .iter()
+            .flat_map(|input_path| {
+                if self.recursive && input_path.is_dir() {
+                    walk_audio_files(input_path, &self.include_ext)
+                } else if Playlist::is_playlist(input_path) && !is_url(input_path) {
+                    match Playlist::read(input_path) {
+                        Ok(playlist) => playlist
+                            .entries
+                            .into_iter()
+                            .map(|entry| Ok(entry.path))
+                            .collect(),
+                        Err(e) => vec![Err(e)],
+                    }
+                } else {
+                    vec![Ok(input_path.clone())]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Carries out `config`: one of the subcommands, `--watch`, `--cue`,
+/// `--concat` or `--stems`, or else the batch of its inputs. Returns the
+/// exit code of the run.
+pub fn run(mut config: RunConfig) -> ExitCode {
+    if config.auto_download_ffmpeg && FfmpegDownload::is_needed(&config.options) {
+        let downloaded = FfmpegDownload::default_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory found"))
+            .and_then(|dir| FfmpegDownload::ensure(&dir));
+        match downloaded {
+            Ok(ffmpeg_path) => config.options.ffmpeg_path = Some(ffmpeg_path),
+            Err(e) => {
+                eprintln!("Could not download ffmpeg: {}", e);
+                let failures = Failures::default();
+                failures.record(Some(&e));
+                return failures.exit_code();
+            }
+        }
+    }
+    if let Some(path) = &config.options.history_db {
+        match LoudnessHistory::open(path) {
+            Ok(history) => config.history = Some(history),
+            Err(e) => {
+                eprintln!("{}", e);
+                let failures = Failures::default();
+                failures.record(Some(&e));
+                return failures.exit_code();
+            }
+        }
+    }
+    if !config.progress || config.progress_format == ProgressFormat::Jsonl {
+        ProgressSpinner::set_enabled(false);
+    }
+    if config.selftest {
+        return commands::selftest(&config);
+    }
+    if config.doctor {
+        return commands::doctor(&config);
+    }
+    if let Some(endpoint) = &config.serve {
+        return commands::serve(&config, endpoint);
+    }
+    if let Some(dir) = &config.watch_dir {
+        return watch::watch(&config, dir);
+    }
+    if config.compare {
+        return commands::compare(&config);
+    }
+    if let Some(sheet) = &config.cue {
+        return batch::split_cue(&config, sheet);
+    }
+    let inputs = config.collect_inputs();
+    if let Some(spec) = &config.check {
+        return commands::check(&config, spec, inputs);
+    }
+    if config.concat {
+        return batch::concat(&config, inputs);
+    }
+    if config.stems {
+        return batch::stems(&config, inputs);
+    }
+    batch::run(&config, inputs)
+}
@@ -0,0 +1,155 @@
+//! Checks of the ffmpeg build and of the parsing of its output against
+//! generated signals of known loudness, run by `selftest`.
+//!
+//! The signals follow EBU Tech 3341: a 1 kHz stereo sine measures as many
+//! LUFS as its peak level in dBFS.
+
+use crate::{ffmpeg, Error, Options, Verification, DEFAULT_TOLERANCE};
+use serde::Serialize;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+};
+
+/// Length of the generated signals in seconds, well above the 3 seconds
+/// loudnorm needs.
+const SIGNAL_SECONDS: u32 = 20;
+
+/// Peak level of the test sine in dBFS, and so its integrated loudness.
+const SINE_LEVEL: f64 = -20.0;
+
+/// How far the measured integrated loudness may be off, in LU.
+const LOUDNESS_TOLERANCE: f64 = 0.2;
+
+/// How far the measured true peak may be off, in dB.
+const PEAK_TOLERANCE: f64 = 0.5;
+
+/// Outcome of one check of [`SelfTest::run`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestCheck {
+    pub name: String,
+    pub passed: bool,
+    /// What was measured, or why the check couldn't run.
+    pub detail: String,
+}
+
+/// Measures and normalizes generated signals and compares the results with
+/// their known loudness.
+pub struct SelfTest;
+
+impl SelfTest {
+    /// Runs every check with the ffmpeg selected by `options`. The targets
+    /// are the defaults, so the expectations don't depend on other settings.
+    /// Fails only when the signals can't be generated at all.
+    pub fn run(options: &Options) -> io::Result<Vec<SelfTestCheck>> {
+        let options = Options {
+            ffmpeg_path: options.ffmpeg_path.clone(),
+            ..Options::default()
+        };
+        let dir = env::temp_dir().join(format!("ffmpeg-normalize-selftest-{}", process::id()));
+        fs::create_dir_all(&dir)?;
+        let checks = Self::run_in(&dir, &options);
+        let _ = fs::remove_dir_all(&dir);
+        checks
+    }
+
+    fn run_in(dir: &Path, options: &Options) -> io::Result<Vec<SelfTestCheck>> {
+        let amplitude = 10f64.powf(SINE_LEVEL / 20.0);
+        let sine = generate(
+            dir,
+            "sine",
+            &format!(
+                "aevalsrc={a}*sin(2*PI*1000*t)|{a}*sin(2*PI*1000*t):s=48000:d={d}",
+                a = amplitude,
+                d = SIGNAL_SECONDS
+            ),
+            options,
+        )?;
+        let silence = generate(
+            dir,
+            "silence",
+            &format!("anullsrc=r=48000:cl=stereo:d={}", SIGNAL_SECONDS),
+            options,
+        )?;
+
+        let mut checks = Vec::new();
+        match crate::analyze(&sine, options) {
+            Ok(loudness) => {
+                checks.push(expect(
+                    "integrated loudness",
+                    loudness.input_i,
+                    SINE_LEVEL,
+                    LOUDNESS_TOLERANCE,
+                    "LUFS",
+                ));
+                checks.push(expect(
+                    "true peak",
+                    loudness.input_tp,
+                    SINE_LEVEL,
+                    PEAK_TOLERANCE,
+                    "dBTP",
+                ));
+                checks.push(expect("loudness range", loudness.input_lra, 0.0, 1.0, "LU"));
+            }
+            Err(e) => checks.push(failed("measurement", &e)),
+        }
+
+        let output = dir.join("normalized.wav");
+        let normalized = crate::normalize(&sine, &output, options)
+            .and_then(|_| Verification::check(&output, options, DEFAULT_TOLERANCE));
+        checks.push(match normalized {
+            Ok(verification) => SelfTestCheck {
+                name: "normalization".to_string(),
+                passed: verification.passed,
+                detail: verification.to_string(),
+            },
+            Err(e) => failed("normalization", &e),
+        });
+
+        checks.push(match crate::analyze(&silence, options) {
+            Ok(loudness) => SelfTestCheck {
+                name: "silence detection".to_string(),
+                passed: loudness.is_silent(),
+                detail: format!("I={:.2} LUFS", loudness.input_i),
+            },
+            Err(e) => failed("silence detection", &e),
+        });
+        Ok(checks)
+    }
+}
+
+/// Renders the lavfi `source` into `<name>.wav` in `dir`.
+fn generate(dir: &Path, name: &str, source: &str, options: &Options) -> io::Result<PathBuf> {
+    let path = dir.join(format!("{}.wav", name));
+    let output = ffmpeg::output(
+        ffmpeg::ffmpeg_command(options)?
+            .args(["-hide_banner", "-y", "-f", "lavfi", "-i", source])
+            .args(["-c:a", "pcm_s24le"])
+            .arg(ffmpeg::path_arg(&path))
+            .stdin(Stdio::null()),
+    )?;
+    if !output.status.success() {
+        return Err(Error::process_failed("ffmpeg", &output).into());
+    }
+    Ok(path)
+}
+
+fn expect(name: &str, measured: f64, expected: f64, tolerance: f64, unit: &str) -> SelfTestCheck {
+    SelfTestCheck {
+        name: name.to_string(),
+        passed: (measured - expected).abs() <= tolerance,
+        detail: format!(
+            "{:.2} {} (expected {:.1} ±{:.1})",
+            measured, unit, expected, tolerance
+        ),
+    }
+}
+
+fn failed(name: &str, error: &io::Error) -> SelfTestCheck {
+    SelfTestCheck {
+        name: name.to_string(),
+        passed: false,
+        detail: error.to_string(),
+    }
+}
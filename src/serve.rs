@@ -0,0 +1,838 @@
+//! The `serve` daemon: normalization jobs submitted over HTTP on a Unix
+//! socket or a loopback TCP port, queued and run by a pool of workers.
+//!
+//! The API is JSON over HTTP/1.1, one request per connection:
+//!
+//! - `POST /jobs` queues a job, `{"input": ...}` with any of `output`,
+//!   `preset`, `i`, `tp` and `lra` as in a `--manifest` row
+//! - `GET /jobs` lists the jobs, `GET /jobs/ID` shows one with its progress
+//!   and, once done, its result
+//! - `DELETE /jobs/ID` cancels a queued or running job
+//! - `GET /status` counts the jobs by state
+//!
+//! Every request needs the token printed at startup, as
+//! `Authorization: Bearer TOKEN`, so neither web pages nor other users can
+//! queue jobs, and requests naming another host, as rebound DNS names do,
+//! are refused. Jobs read local files only and write their outputs below
+//! the output directory.
+
+use crate::{
+    interrupt, is_url, logging, parse_in_range, CancellationToken, Job, Preset, TaskContext,
+    INTEGRATED_LOUDNESS_RANGE, LOUDNESS_RANGE_RANGE, TRUE_PEAK_RANGE,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How often the accept loop looks for interrupts between connections.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest request body accepted, far more than any job needs.
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+/// Longest request or header line accepted.
+const MAX_LINE_LEN: usize = 8 * 1024;
+
+/// Most header lines accepted in a request.
+const MAX_HEADERS: usize = 64;
+
+/// Connections answered at once; further ones are turned away until one
+/// of them is done.
+const MAX_CONNECTIONS: usize = 32;
+
+/// Finished jobs kept for status requests; older ones are forgotten.
+const FINISHED_JOBS_KEPT: usize = 1000;
+
+/// Jobs waiting for a worker; further submissions are turned away until
+/// some of them have started.
+const MAX_PENDING_JOBS: usize = 1000;
+
+/// Where `serve` listens.
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    /// A Unix socket at this path, `--socket`.
+    Socket(PathBuf),
+    /// A TCP address, `--listen`, which must be a loopback one.
+    Tcp(String),
+}
+
+/// A job as submitted, with the columns of a manifest row.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Submission {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    preset: Option<String>,
+    i: Option<f64>,
+    tp: Option<f64>,
+    lra: Option<f64>,
+}
+
+impl Submission {
+    /// The overrides of the submission, checked like those of a manifest.
+    /// Inputs must be local files, and outputs relative paths below the
+    /// output directory.
+    fn job(&self) -> Result<Job, String> {
+        if is_url(&self.input) || self.input == Path::new("-") {
+            return Err("input: only local files are accepted".to_string());
+        }
+        if let Some(output) = &self.output {
+            if !output
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                return Err(
+                    "output: must be a relative path below the output directory".to_string()
+                );
+            }
+        }
+        let number = |value: Option<f64>, range, unit, name| {
+            value
+                .map(|value| parse_in_range(&value.to_string(), range, unit))
+                .transpose()
+                .map_err(|e| format!("{}: {}", name, e))
+        };
+        Ok(Job {
+            output: self.output.clone(),
+            preset: self
+                .preset
+                .as_deref()
+                .map(|name| Preset::find(name).ok_or(format!("unknown preset '{}'", name)))
+                .transpose()?,
+            integrated_loudness: number(self.i, INTEGRATED_LOUDNESS_RANGE, "LUFS", "i")?,
+            true_peak: number(self.tp, TRUE_PEAK_RANGE, "dBTP", "tp")?,
+            loudness_range: number(self.lra, LOUDNESS_RANGE_RANGE, "LU", "lra")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum State {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl State {
+    fn name(self) -> &'static str {
+        match self {
+            State::Queued => "queued",
+            State::Running => "running",
+            State::Done => "done",
+            State::Failed => "failed",
+            State::Cancelled => "cancelled",
+        }
+    }
+
+    fn is_finished(self) -> bool {
+        matches!(self, State::Done | State::Failed | State::Cancelled)
+    }
+}
+
+/// A job and how far it has come.
+#[derive(Serialize)]
+struct JobStatus {
+    id: u64,
+    input: String,
+    state: State,
+    /// The running pass, e.g. `Measuring` or `Encoding`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percent: Option<f64>,
+    /// What the run printed with `--format json`, for a finished job.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip)]
+    input_path: PathBuf,
+    #[serde(skip)]
+    job: Job,
+    #[serde(skip)]
+    cancel: CancellationToken,
+}
+
+#[derive(Default)]
+struct Queue {
+    next_id: u64,
+    jobs: BTreeMap<u64, JobStatus>,
+    pending: VecDeque<u64>,
+    /// No more jobs are taken once the daemon stops.
+    closed: bool,
+}
+
+impl Queue {
+    /// Forgets the oldest finished jobs beyond [`FINISHED_JOBS_KEPT`].
+    fn prune(&mut self) {
+        let finished: Vec<u64> = self
+            .jobs
+            .values()
+            .filter(|status| status.state.is_finished())
+            .map(|status| status.id)
+            .collect();
+        for id in finished
+            .iter()
+            .take(finished.len().saturating_sub(FINISHED_JOBS_KEPT))
+        {
+            self.jobs.remove(id);
+        }
+    }
+}
+
+/// The job queue shared by the connections and the workers.
+pub struct Server {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+    workers: usize,
+    /// The bearer token every request must carry.
+    token: String,
+    /// Connections being answered.
+    connections: AtomicUsize,
+}
+
+impl Server {
+    /// A server with a new random token.
+    pub fn new(workers: usize) -> io::Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            queue: Mutex::default(),
+            ready: Condvar::new(),
+            workers: workers.max(1),
+            token: new_token()?,
+            connections: AtomicUsize::new(0),
+        }))
+    }
+
+    /// The token clients authenticate with.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Serves `listener` until the daemon is interrupted, running each job
+    /// with `process`, which returns the result shown for the job.
+    pub fn run(
+        self: &Arc<Self>,
+        listener: &Listener,
+        process: impl Fn(&Path, &Job) -> io::Result<Value> + Sync,
+    ) {
+        thread::scope(|scope| {
+            for _ in 0..self.workers {
+                scope.spawn(|| self.work(&process));
+            }
+            while !interrupt::is_interrupted() {
+                match listener.accept() {
+                    Ok(Some(mut connection)) => {
+                        if self.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                            self.connections.fetch_sub(1, Ordering::SeqCst);
+                            let _ = connection.set_timeout(POLL_INTERVAL).and_then(|_| {
+                                write_response(
+                                    &mut connection,
+                                    503,
+                                    &json!({ "error": "too many connections" }),
+                                )
+                            });
+                            continue;
+                        }
+                        let server = Arc::clone(self);
+                        thread::spawn(move || {
+                            if let Err(e) = server.handle(connection) {
+                                logging::debug(format_args!("serve: {}", e));
+                            }
+                            server.connections.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    }
+                    Ok(None) => thread::sleep(POLL_INTERVAL),
+                    Err(e) => logging::warn(format_args!("serve: {}", e)),
+                }
+            }
+            let mut queue = self.lock();
+            queue.closed = true;
+            for id in std::mem::take(&mut queue.pending) {
+                if let Some(status) = queue.jobs.get_mut(&id) {
+                    status.state = State::Cancelled;
+                }
+            }
+            drop(queue);
+            self.ready.notify_all();
+        });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs queued jobs until the queue is closed.
+    fn work(self: &Arc<Self>, process: &(impl Fn(&Path, &Job) -> io::Result<Value> + Sync)) {
+        loop {
+            let (id, input_path, job, cancel) = {
+                let mut queue = self.lock();
+                let id = loop {
+                    if queue.closed {
+                        return;
+                    }
+                    match queue.pending.pop_front() {
+                        Some(id) => break id,
+                        None => queue = self.ready.wait(queue).unwrap_or_else(|e| e.into_inner()),
+                    }
+                };
+                let Some(status) = queue.jobs.get_mut(&id) else {
+                    continue;
+                };
+                status.state = State::Running;
+                (
+                    id,
+                    status.input_path.clone(),
+                    status.job.clone(),
+                    status.cancel.clone(),
+                )
+            };
+            logging::info(format_args!(
+                "serve: job {} started: {}",
+                id,
+                input_path.display()
+            ));
+            let server = Arc::clone(self);
+            let outcome = TaskContext::new()
+                .cancel_with(&cancel)
+                .on_progress(move |event| {
+                    if let Some(status) = server.lock().jobs.get_mut(&id) {
+                        status.stage = Some(event.stage);
+                        status.percent = Some((event.fraction * 100.0).clamp(0.0, 100.0));
+                    }
+                })
+                .run(|| process(&input_path, &job));
+            let mut queue = self.lock();
+            if let Some(status) = queue.jobs.get_mut(&id) {
+                status.stage = None;
+                status.percent = None;
+                match outcome {
+                    _ if cancel.is_cancelled() => status.state = State::Cancelled,
+                    Ok(result) => {
+                        status.state = State::Done;
+                        status.result = Some(result);
+                    }
+                    Err(e) => {
+                        status.state = State::Failed;
+                        status.error = Some(e.to_string());
+                    }
+                }
+                logging::info(format_args!(
+                    "serve: job {} {}: {}{}",
+                    id,
+                    status.state.name(),
+                    input_path.display(),
+                    status
+                        .error
+                        .as_ref()
+                        .map(|e| format!(": {}", e))
+                        .unwrap_or_default()
+                ));
+            }
+            queue.prune();
+        }
+    }
+
+    /// Answers the request on `connection`.
+    fn handle(&self, mut connection: Box<dyn Connection>) -> io::Result<()> {
+        connection.set_timeout(REQUEST_TIMEOUT)?;
+        let (status, body) = match read_request(&mut connection) {
+            Ok(request) => match self.refuse(&request) {
+                Some(refusal) => refusal,
+                None => self.respond(&request.method, &request.path, &request.body),
+            },
+            Err(e) => (400, json!({ "error": e.to_string() })),
+        };
+        write_response(&mut connection, status, &body)
+    }
+
+    /// The answer refusing `request`, unless it names a loopback host, comes
+    /// from no web page, carries the token and, with a body, is JSON.
+    fn refuse(&self, request: &Request) -> Option<(u16, Value)> {
+        let error = |status, message: &str| Some((status, json!({ "error": message })));
+        if request
+            .header("host")
+            .is_some_and(|host| !is_loopback_host(host))
+        {
+            return error(403, "requests must name a loopback host");
+        }
+        if request.header("origin").is_some_and(|origin| {
+            origin
+                .split_once("://")
+                .is_none_or(|(_, host)| !is_loopback_host(host))
+        }) {
+            return error(403, "cross-origin requests are refused");
+        }
+        let token = request
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        if !token.is_some_and(|token| same_token(token, &self.token)) {
+            return error(401, "missing or wrong token");
+        }
+        let media_type = request
+            .header("content-type")
+            .and_then(|value| value.split(';').next())
+            .map(str::trim);
+        if !request.body.is_empty()
+            && !media_type
+                .is_some_and(|media_type| media_type.eq_ignore_ascii_case("application/json"))
+        {
+            return error(415, "the body must be application/json");
+        }
+        None
+    }
+
+    /// The status code and body answering `method` on `path`.
+    fn respond(&self, method: &str, path: &str, body: &[u8]) -> (u16, Value) {
+        let error = |status, message: &str| (status, json!({ "error": message }));
+        let path = path.split('?').next().unwrap_or(path).trim_end_matches('/');
+        let segments: Vec<&str> = path.split('/').skip(1).collect();
+        match (method, segments.as_slice()) {
+            ("GET", ["status"]) => {
+                let queue = self.lock();
+                let count = |state| {
+                    queue
+                        .jobs
+                        .values()
+                        .filter(|status| status.state == state)
+                        .count()
+                };
+                (
+                    200,
+                    json!({
+                        "workers": self.workers,
+                        "queued": count(State::Queued),
+                        "running": count(State::Running),
+                        "done": count(State::Done),
+                        "failed": count(State::Failed),
+                        "cancelled": count(State::Cancelled),
+                    }),
+                )
+            }
+            ("GET", ["jobs"]) => {
+                let queue = self.lock();
+                let jobs: Vec<&JobStatus> = queue.jobs.values().collect();
+                (200, json!(jobs))
+            }
+            ("POST", ["jobs"]) => match self.submit(body) {
+                Ok(status) => (201, status),
+                Err((status, message)) => error(status, &message),
+            },
+            ("GET" | "DELETE", ["jobs", id]) => {
+                let Ok(id) = id.parse::<u64>() else {
+                    return error(404, "no such job");
+                };
+                let mut queue = self.lock();
+                let Some(status) = queue.jobs.get_mut(&id) else {
+                    return error(404, "no such job");
+                };
+                if method == "DELETE" {
+                    match status.state {
+                        State::Queued => status.state = State::Cancelled,
+                        State::Running => status.cancel.cancel(),
+                        _ => return error(409, "the job has finished"),
+                    }
+                    queue.pending.retain(|&pending| pending != id);
+                }
+                (200, json!(queue.jobs.get(&id)))
+            }
+            (_, ["jobs"] | ["jobs", _] | ["status"]) => error(405, "method not allowed"),
+            _ => error(404, "not found"),
+        }
+    }
+
+    /// Queues the job described by `body`, returning its status, or the
+    /// HTTP status and message it is refused with.
+    fn submit(&self, body: &[u8]) -> Result<Value, (u16, String)> {
+        let submission: Submission =
+            serde_json::from_slice(body).map_err(|e| (400, format!("invalid job: {}", e)))?;
+        let job = submission.job().map_err(|message| (400, message))?;
+        let mut queue = self.lock();
+        if queue.closed {
+            return Err((503, "the server is shutting down".to_string()));
+        }
+        if queue.pending.len() >= MAX_PENDING_JOBS {
+            return Err((503, "the queue is full; try again later".to_string()));
+        }
+        queue.next_id += 1;
+        let id = queue.next_id;
+        let status = JobStatus {
+            id,
+            input: submission.input.to_string_lossy().into_owned(),
+            state: State::Queued,
+            stage: None,
+            percent: None,
+            result: None,
+            error: None,
+            input_path: submission.input,
+            job,
+            cancel: CancellationToken::new(),
+        };
+        let value = json!(status);
+        queue.jobs.insert(id, status);
+        queue.pending.push_back(id);
+        drop(queue);
+        self.ready.notify_one();
+        Ok(value)
+    }
+}
+
+/// An HTTP request as far as the API looks at it.
+struct Request {
+    method: String,
+    path: String,
+    /// Header names in lowercase, with their values.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads the request line, headers and body of an HTTP request, within
+/// the limits on their size.
+fn read_request(connection: &mut impl Read) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut reader = BufReader::new(connection);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if read_line(&mut reader, &mut line)? == 0 {
+            return Err(invalid("incomplete headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .map(|(_, value)| value.parse::<usize>())
+        .transpose()
+        .map_err(|_| invalid("invalid Content-Length"))?
+        .unwrap_or(0);
+    if content_length > MAX_BODY_LEN {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+/// Reads a line of at most [`MAX_LINE_LEN`] bytes into `line`, returning
+/// its length.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let read = reader.take(MAX_LINE_LEN as u64 + 1).read_line(line)?;
+    if read > MAX_LINE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request line or header too long",
+        ));
+    }
+    Ok(read)
+}
+
+fn write_response(connection: &mut impl Write, status: u16, body: &Value) -> io::Result<()> {
+    let body = serde_json::to_vec(body)?;
+    let authenticate = if status == 401 {
+        "WWW-Authenticate: Bearer\r\n"
+    } else {
+        ""
+    };
+    write!(
+        connection,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        status,
+        reason(status),
+        body.len(),
+        authenticate
+    )?;
+    connection.write_all(&body)?;
+    connection.flush()
+}
+
+/// Whether the `Host` header value, or the host of an `Origin`, `host`
+/// names this machine by a loopback address or `localhost`.
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(rest),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// 128 random bits in hex from the random number generator of the
+/// operating system.
+fn new_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    random_bytes(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(unix)]
+fn random_bytes(buffer: &mut [u8]) -> io::Result<()> {
+    std::fs::File::open("/dev/urandom")?.read_exact(buffer)
+}
+
+#[cfg(windows)]
+fn random_bytes(buffer: &mut [u8]) -> io::Result<()> {
+    use std::ffi::c_void;
+
+    const BCRYPT_USE_SYSTEM_PREFERRED_RNG: u32 = 0x0000_0002;
+
+    #[link(name = "bcrypt")]
+    extern "system" {
+        fn BCryptGenRandom(algorithm: *mut c_void, buffer: *mut u8, len: u32, flags: u32) -> i32;
+    }
+
+    // SAFETY: fills `buffer`, whose length is passed along, with the
+    // system's preferred generator.
+    let status = unsafe {
+        BCryptGenRandom(
+            std::ptr::null_mut(),
+            buffer.as_mut_ptr(),
+            buffer.len() as u32,
+            BCRYPT_USE_SYSTEM_PREFERRED_RNG,
+        )
+    };
+    if status < 0 {
+        return Err(io::Error::other(format!(
+            "BCryptGenRandom failed with {:#x}",
+            status
+        )));
+    }
+    Ok(())
+}
+
+/// Compares tokens in time independent of where they differ.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |differ, (a, b)| differ | (a ^ b))
+            == 0
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        415 => "Unsupported Media Type",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// An accepted connection of either kind of listener.
+pub trait Connection: Read + Write + Send {
+    fn set_timeout(&self, timeout: Duration) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.set_nonblocking(false)?;
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+}
+
+#[cfg(unix)]
+impl Connection for std::os::unix::net::UnixStream {
+    fn set_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.set_nonblocking(false)?;
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+}
+
+/// A non-blocking listener, so the daemon notices interrupts between
+/// connections.
+pub enum Listener {
+    Tcp(TcpListener),
+    /// The socket file is removed again when the listener is dropped.
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener, PathBuf),
+}
+
+impl Listener {
+    pub fn bind(endpoint: &Endpoint) -> io::Result<Self> {
+        let listener = match endpoint {
+            Endpoint::Tcp(address) => {
+                let addresses: Vec<_> = address.to_socket_addrs()?.collect();
+                // Jobs name arbitrary paths, so the API stays on this
+                // machine; anything further needs a proxy in front.
+                if let Some(address) = addresses.iter().find(|a| !a.ip().is_loopback()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("--listen {}: only loopback addresses are served", address),
+                    ));
+                }
+                let listener = TcpListener::bind(addresses.as_slice()).map_err(|e| {
+                    io::Error::new(e.kind(), format!("--listen {}: {}", address, e))
+                })?;
+                logging::info(format_args!(
+                    "serve: listening on {}",
+                    listener.local_addr()?
+                ));
+                Listener::Tcp(listener)
+            }
+            #[cfg(unix)]
+            Endpoint::Socket(path) => {
+                use std::os::unix::net::{UnixListener, UnixStream};
+                if path.exists() {
+                    if UnixStream::connect(path).is_ok() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AddrInUse,
+                            format!("{}: another server is listening", path.display()),
+                        ));
+                    }
+                    // Left behind by a server that didn't shut down.
+                    std::fs::remove_file(path)?;
+                }
+                // Only the owner may connect, from the moment the socket
+                // exists; workers aren't running yet to mind the umask.
+                let previous = umask::set(0o177);
+                let bound = UnixListener::bind(path);
+                umask::set(previous);
+                let listener = bound
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+                logging::info(format_args!("serve: listening on {}", path.display()));
+                Listener::Unix(listener, path.clone())
+            }
+            #[cfg(not(unix))]
+            Endpoint::Socket(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "--socket needs Unix sockets; use --listen",
+                ))
+            }
+        };
+        match &listener {
+            Listener::Tcp(listener) => listener.set_nonblocking(true)?,
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => listener.set_nonblocking(true)?,
+        }
+        Ok(listener)
+    }
+
+    /// The next connection, or `None` when none is waiting.
+    fn accept(&self) -> io::Result<Option<Box<dyn Connection>>> {
+        let accepted = match self {
+            Listener::Tcp(listener) => listener
+                .accept()
+                .map(|(stream, _)| Box::new(stream) as Box<dyn Connection>),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => listener
+                .accept()
+                .map(|(stream, _)| Box::new(stream) as Box<dyn Connection>),
+        };
+        match accepted {
+            Ok(connection) => Ok(Some(connection)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Listener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod umask {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    type Mode = u32;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    type Mode = u16;
+
+    extern "C" {
+        fn umask(mask: Mode) -> Mode;
+    }
+
+    /// Sets the file mode creation mask of the process, returning the
+    /// previous one.
+    pub(super) fn set(mask: u32) -> u32 {
+        // SAFETY: umask(2) has no memory safety requirements and can't fail.
+        unsafe { umask(mask as Mode) as u32 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_hex() {
+        let (a, b) = (new_token().unwrap(), new_token().unwrap());
+        assert_eq!(a.len(), 32);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn submissions_beyond_the_pending_limit_are_refused() {
+        let server = Server::new(1).unwrap();
+        let body = br#"{"input": "/music/track.flac"}"#;
+        for _ in 0..MAX_PENDING_JOBS {
+            assert_eq!(server.respond("POST", "/jobs", body).0, 201);
+        }
+        let (status, value) = server.respond("POST", "/jobs", body);
+        assert_eq!(status, 503);
+        assert_eq!(value["error"], "the queue is full; try again later");
+    }
+}
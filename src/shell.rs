@@ -0,0 +1,191 @@
+//! Quoting of printed command lines for the shell they will be pasted into.
+
+use std::{ffi::OsStr, str::FromStr};
+
+/// Shell syntax for command lines printed with `--print-command`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    /// sh, bash, zsh and other POSIX shells.
+    Posix,
+    /// Windows `cmd.exe`.
+    Cmd,
+    /// Windows PowerShell and PowerShell 7.
+    PowerShell,
+    /// The fish shell.
+    Fish,
+}
+
+impl Default for Shell {
+    /// `cmd` on Windows, POSIX elsewhere.
+    fn default() -> Self {
+        if cfg!(windows) {
+            Shell::Cmd
+        } else {
+            Shell::Posix
+        }
+    }
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "posix" | "sh" => Ok(Shell::Posix),
+            "cmd" => Ok(Shell::Cmd),
+            "powershell" | "pwsh" => Ok(Shell::PowerShell),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("unknown shell '{}'", s)),
+        }
+    }
+}
+
+impl Shell {
+    /// `program` and `args` as one command line for this shell.
+    pub fn command_line<'a>(
+        self,
+        program: &OsStr,
+        args: impl IntoIterator<Item = &'a OsStr>,
+    ) -> String {
+        let program_word = self.quote(program);
+        // PowerShell only runs a quoted program through the call operator.
+        let program_word = if self == Shell::PowerShell && program_word.starts_with('\'') {
+            format!("& {}", program_word)
+        } else {
+            program_word
+        };
+        std::iter::once(program_word)
+            .chain(args.into_iter().map(|arg| self.quote(arg)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Quotes `arg` as a single word, leaving plain words unquoted.
+    ///
+    /// On Unix, bytes that aren't UTF-8 are spelled out as escapes for POSIX
+    /// shells and fish. Cmd and PowerShell have no such escapes, so an
+    /// argument that isn't valid Unicode is printed with replacement
+    /// characters and won't match what runs.
+    pub fn quote(self, arg: &OsStr) -> String {
+        if let Some(arg) = arg.to_str() {
+            return self.quote_str(arg);
+        }
+        #[cfg(unix)]
+        if matches!(self, Shell::Posix | Shell::Fish) {
+            use std::os::unix::ffi::OsStrExt;
+            return self.quote_bytes(arg.as_bytes());
+        }
+        self.quote_str(&arg.to_string_lossy())
+    }
+
+    /// Quotes the UTF-8 runs of `arg` as usual and joins them into one word
+    /// with escapes for the other bytes: `$'\xff'`, which bash, zsh and
+    /// POSIX.1-2024 shells read, or fish's `\Xff`.
+    #[cfg(unix)]
+    fn quote_bytes(self, arg: &[u8]) -> String {
+        let mut quoted = String::new();
+        for chunk in arg.utf8_chunks() {
+            if !chunk.valid().is_empty() {
+                quoted.push_str(&self.quote_str(chunk.valid()));
+            }
+            if chunk.invalid().is_empty() {
+                continue;
+            }
+            let escapes: String = chunk
+                .invalid()
+                .iter()
+                .map(|byte| match self {
+                    Shell::Fish => format!("\\X{:02x}", byte),
+                    _ => format!("\\x{:02x}", byte),
+                })
+                .collect();
+            match self {
+                Shell::Fish => quoted.push_str(&escapes),
+                _ => quoted.push_str(&format!("$'{}'", escapes)),
+            }
+        }
+        quoted
+    }
+
+    fn quote_str(self, arg: &str) -> String {
+        let safe = match self {
+            Shell::Posix => "_@%+=:,./-",
+            Shell::Cmd => "_@+=:,./-\\",
+            Shell::PowerShell => "_+=:./-\\",
+            Shell::Fish => "_@+=:,./-",
+        };
+        if !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || safe.contains(c))
+        {
+            return arg.to_string();
+        }
+        match self {
+            Shell::Posix => format!("'{}'", arg.replace('\'', "'\\''")),
+            Shell::Cmd => quote_for_crt(arg),
+            Shell::PowerShell => format!("'{}'", arg.replace('\'', "''")),
+            // Inside single quotes fish still reads backslash escapes.
+            Shell::Fish => format!("'{}'", arg.replace('\\', "\\\\").replace('\'', "\\'")),
+        }
+    }
+}
+
+/// Quotes `arg` the way `CommandLineToArgvW` and the C runtime parse it back:
+/// backslashes only need doubling before a quote. `%` can't be escaped
+/// inside quotes in cmd, so the quotes are closed around it, and the
+/// backslashes before the closing quote doubled like those before any other.
+fn quote_for_crt(arg: &str) -> String {
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            '%' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+                quoted.push_str("\"^");
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+        if c == '%' {
+            quoted.push('"');
+        }
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn bytes_that_are_not_utf8_are_escaped() {
+        use std::os::unix::ffi::OsStrExt;
+        let arg = OsStr::from_bytes(b"caf\xe9 1.wav");
+        assert_eq!(Shell::Posix.quote(arg), r"caf$'\xe9'' 1.wav'");
+        assert_eq!(Shell::Fish.quote(arg), r"caf\Xe9' 1.wav'");
+    }
+
+    #[test]
+    fn cmd_closes_quotes_around_percent() {
+        let quote = |arg: &str| Shell::Cmd.quote(OsStr::new(arg));
+        assert_eq!(quote("a b%c"), r#""a b"^%"c""#);
+        assert_eq!(quote(r"C:\dir\%x"), r#""C:\dir\\"^%"x""#);
+        assert_eq!(quote(r"a \%"), r#""a \\"^%"""#);
+        assert_eq!(quote(r#"a \"b"#), r#""a \\\"b""#);
+    }
+}
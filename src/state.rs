@@ -0,0 +1,102 @@
+//! Progress of a batch run kept on disk with `--state`, so `--resume` can
+//! skip inputs that already succeeded.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct StateFile {
+    inputs: BTreeMap<String, Entry>,
+}
+
+/// Per-input outcomes of a run, rewritten after every input.
+pub struct RunState {
+    path: PathBuf,
+    file: Mutex<StateFile>,
+}
+
+impl RunState {
+    /// Starts a state file at `path`. With `resume`, the outcomes already
+    /// recorded there are kept; otherwise the run starts from scratch.
+    pub fn open(path: &Path, resume: bool) -> io::Result<Self> {
+        let file = match fs::read(path) {
+            Ok(contents) if resume => serde_json::from_slice(&contents).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })?,
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => StateFile::default(),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Whether `input_path` completed successfully in an earlier run.
+    pub fn is_done(&self, input_path: &Path) -> bool {
+        self.file.lock().is_ok_and(|file| {
+            file.inputs
+                .get(&key(input_path))
+                .is_some_and(|entry| entry.status == Status::Done)
+        })
+    }
+
+    /// Records the outcome for `input_path`, `error` being `None` on
+    /// success, and saves the state.
+    pub fn record(&self, input_path: &Path, error: Option<&str>) -> io::Result<()> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("state file lock poisoned"))?;
+        file.inputs.insert(
+            key(input_path),
+            Entry {
+                status: if error.is_none() {
+                    Status::Done
+                } else {
+                    Status::Failed
+                },
+                error: error.map(str::to_string),
+            },
+        );
+        // Written aside and renamed so an interruption never leaves a
+        // truncated state file behind.
+        let mut temp_name = OsString::from(self.path.as_os_str());
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+        fs::write(&temp_path, serde_json::to_vec_pretty(&*file)?)?;
+        fs::rename(&temp_path, &self.path)
+    }
+}
+
+/// The state key for `input_path`: its canonical form when it exists, so
+/// that the same file matches however it was named on the command line.
+fn key(input_path: &Path) -> String {
+    fs::canonicalize(input_path)
+        .unwrap_or_else(|_| input_path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,248 @@
+//! Stats files of the Python ffmpeg-normalize, the JSON its `--print-stats`
+//! prints, read with `--import-stats` and written with `--export-stats`, so
+//! measurements carry over between the two tools.
+
+use crate::{logging, Loudness, NormalizationType, OutputStats};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// The loudnorm values of an `ebu_pass1` or `ebu_pass2` object. Python
+/// writes them as numbers and silence as a bare `-Infinity`, which isn't
+/// JSON; the export writes `null` instead, which both tools read as `-inf`.
+#[derive(Serialize)]
+struct EbuStats {
+    input_i: f64,
+    input_tp: f64,
+    input_lra: f64,
+    input_thresh: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_i: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_tp: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_lra: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_thresh: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normalization_type: Option<NormalizationType>,
+    target_offset: f64,
+}
+
+/// One audio stream of one input, as an element of the stats array.
+#[derive(Serialize)]
+pub struct StreamStats {
+    input_file: String,
+    output_file: Option<String>,
+    /// Index of the stream among all streams of the input.
+    stream_id: usize,
+    ebu_pass1: EbuStats,
+    /// What loudnorm reported at the end of the second pass.
+    ebu_pass2: Option<EbuStats>,
+    /// RMS level in dB, which Python takes from volumedetect.
+    mean: Option<f64>,
+    /// Sample peak in dB.
+    max: Option<f64>,
+}
+
+impl StreamStats {
+    pub fn new(
+        input_path: &Path,
+        output_path: Option<&Path>,
+        stream_id: usize,
+        loudness: &Loudness,
+        second_pass: Option<&OutputStats>,
+    ) -> Self {
+        let ebu_pass1 = EbuStats {
+            input_i: loudness.input_i,
+            input_tp: loudness.input_tp,
+            input_lra: loudness.input_lra,
+            input_thresh: loudness.input_thresh,
+            output_i: loudness.output_i,
+            output_tp: loudness.output_tp,
+            output_lra: loudness.output_lra,
+            output_thresh: loudness.output_thresh,
+            normalization_type: loudness.normalization_type,
+            target_offset: loudness.target_offset,
+        };
+        let ebu_pass2 = second_pass.map(|stats| EbuStats {
+            output_i: Some(stats.output_i),
+            output_tp: Some(stats.output_tp),
+            output_lra: Some(stats.output_lra),
+            output_thresh: stats.output_thresh,
+            normalization_type: stats.normalization_type,
+            ..ebu_pass1
+        });
+        Self {
+            input_file: input_path.to_string_lossy().into_owned(),
+            output_file: output_path.map(|path| path.to_string_lossy().into_owned()),
+            stream_id,
+            ebu_pass1,
+            ebu_pass2,
+            mean: loudness.input_rms,
+            max: loudness.sample_peak,
+        }
+    }
+}
+
+/// The `--export-stats` file, written again after every input so it is
+/// complete whenever the run stops.
+pub struct StatsExport {
+    path: PathBuf,
+    streams: Mutex<Vec<StreamStats>>,
+}
+
+impl StatsExport {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            streams: Mutex::default(),
+        }
+    }
+
+    /// Adds the streams of one input and writes the file.
+    pub fn record(&self, streams: Vec<StreamStats>) -> io::Result<()> {
+        let mut all = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        all.extend(streams);
+        fs::write(&self.path, serde_json::to_string_pretty(&*all)? + "\n")
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", self.path.display(), e)))
+    }
+}
+
+/// The measurements of an `--import-stats` file, in the order of the file.
+pub struct ImportedStats {
+    streams: Vec<(PathBuf, Loudness)>,
+}
+
+impl ImportedStats {
+    /// Reads the stats at `path`: an array as `--print-stats` prints it, or
+    /// a single object. Streams measured only in Python's peak or RMS
+    /// modes have no loudnorm values and are left out.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        };
+        let contents = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let contents = quote_infinities(&contents);
+        let entries = match serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()))? {
+            Value::Array(entries) => entries,
+            entry @ Value::Object(_) => vec![entry],
+            _ => return Err(invalid("expected an array of stats".to_string())),
+        };
+        let mut streams = Vec::new();
+        let mut without_ebu = 0;
+        for (index, entry) in entries.into_iter().enumerate() {
+            let Some(input) = entry.get("input_file").and_then(Value::as_str) else {
+                return Err(invalid(format!("entry {} has no input_file", index + 1)));
+            };
+            let Some(Value::Object(pass1)) = entry.get("ebu_pass1") else {
+                without_ebu += 1;
+                continue;
+            };
+            // Values Python couldn't measure are null; loudnorm would have
+            // reported them as -inf.
+            let pass1: serde_json::Map<String, Value> = pass1
+                .iter()
+                .filter(|(key, value)| !(value.is_null() && *key == "normalization_type"))
+                .map(|(key, value)| match value {
+                    Value::Null => (key.clone(), Value::from("-inf")),
+                    value => (key.clone(), value.clone()),
+                })
+                .collect();
+            let mut loudness: Loudness = serde_json::from_value(Value::Object(pass1))
+                .map_err(|e| invalid(format!("entry {}: {}", index + 1, e)))?;
+            loudness.input_rms = entry.get("mean").and_then(Value::as_f64);
+            loudness.sample_peak = entry.get("max").and_then(Value::as_f64);
+            streams.push((PathBuf::from(input), loudness));
+        }
+        if without_ebu > 0 {
+            logging::warn(format_args!(
+                "{}: {} stream(s) without EBU measurements ignored",
+                path.display(),
+                without_ebu
+            ));
+        }
+        if streams.is_empty() {
+            return Err(invalid("no EBU measurements".to_string()));
+        }
+        Ok(Self { streams })
+    }
+
+    /// The measurements of the `audio_stream`th stream listed for
+    /// `input_path`, the first by default. Paths are compared as given and
+    /// otherwise resolved, since the file may come from another working
+    /// directory.
+    pub fn measurements(&self, input_path: &Path, audio_stream: Option<usize>) -> Option<Loudness> {
+        let canonical = fs::canonicalize(input_path).ok();
+        let matches = |path: &Path| {
+            path == input_path
+                || canonical.as_ref().is_some_and(|canonical| {
+                    fs::canonicalize(path).ok().as_ref() == Some(canonical)
+                })
+        };
+        self.streams
+            .iter()
+            .filter(|(path, _)| matches(path))
+            .nth(audio_stream.unwrap_or(0))
+            .map(|(_, loudness)| loudness.clone())
+    }
+}
+
+/// Turns the bare `Infinity` and `-Infinity` Python's json module writes for
+/// infinite values into the strings loudnorm uses, leaving strings alone so
+/// paths containing the words survive.
+fn quote_infinities(json: &str) -> String {
+    let mut quoted = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut after_separator = false;
+    let mut rest = json;
+    while let Some(c) = rest.chars().next() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if let Some((token, value)) = [("-Infinity", "\"-inf\""), ("Infinity", "\"inf\"")]
+            .into_iter()
+            .find(|(token, _)| after_separator && rest.starts_with(token))
+        {
+            quoted.push_str(value);
+            rest = &rest[token.len()..];
+            after_separator = false;
+            continue;
+        } else if !c.is_whitespace() {
+            after_separator = matches!(c, ':' | '[' | ',');
+            in_string = c == '"';
+        }
+        quoted.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_infinities_only_touches_values() {
+        assert_eq!(
+            quote_infinities(
+                r#"{"input_file": "Infinity -Infinity \"Infinity\".wav", "input_i": -Infinity, "x": [Infinity, 1]}"#
+            ),
+            r#"{"input_file": "Infinity -Infinity \"Infinity\".wav", "input_i": "-inf", "x": ["inf", 1]}"#
+        );
+    }
+}
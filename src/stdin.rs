@@ -0,0 +1,47 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Audio read from standard input into a temporary file, so that both passes
+/// can read it. The file is removed when the buffer is dropped.
+pub struct StdinBuffer {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl StdinBuffer {
+    /// Copies all of standard input to a temporary file. `format` becomes
+    /// its extension, which is how ffmpeg picks the demuxer for inputs it
+    /// can't recognize by content.
+    pub fn read(format: Option<&str>) -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("ffmpeg-loudnorm-helper-{}", process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = match format {
+            Some(format) => dir.join(format!("stdin.{}", format)),
+            None => dir.join("stdin"),
+        };
+        let buffer = Self { dir, path };
+        let copied = io::copy(&mut io::stdin().lock(), &mut File::create(&buffer.path)?)?;
+        if copied == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "No data on standard input",
+            ));
+        }
+        Ok(buffer)
+    }
+
+    /// Location of the buffered audio.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for StdinBuffer {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
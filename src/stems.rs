@@ -0,0 +1,74 @@
+//! Stems normalized by the gain of their mix with `--stems`, so they still
+//! sum to the normalized mix.
+
+use crate::{logging, Error, FilterSettings, Loudness, LoudnessAnalyzer, Normalizer, Options};
+use std::{io, path::Path};
+
+/// The measured mix and the plain gain applied to it and to every stem.
+#[derive(Debug, Clone)]
+pub struct Stems {
+    /// Measurements of the mix.
+    pub loudness: Loudness,
+    /// Gain in dB bringing the mix to its target.
+    pub gain_db: f64,
+    /// The volume filter applying `gain_db`.
+    pub filter: String,
+}
+
+impl Stems {
+    /// `options` without silence trimming, pre-filters, compression and
+    /// limiting, which would act on each stem differently.
+    pub fn options(options: &Options) -> Options {
+        Options {
+            pre_filter: None,
+            trim_silence: None,
+            enforce_lra: false,
+            compressor: None,
+            limiter: None,
+            ..options.clone()
+        }
+    }
+
+    /// Measures the mix at `mix_path` with the [`Stems::options`] of
+    /// `options`. The gain is kept even when it lifts the mix past the true
+    /// peak ceiling, as limiting the stems would change their sum.
+    pub fn measure(mix_path: &Path, options: &Options) -> io::Result<Self> {
+        let options = Self::options(options);
+        let loudness = LoudnessAnalyzer::measure(mix_path, &options)?;
+        if loudness.is_silent() {
+            return Err(Error::Silent.into());
+        }
+        let gain_db = FilterSettings::gain_db(&options, &loudness).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no level to normalize by in this mode",
+            )
+        })?;
+        if loudness.input_tp + gain_db > options.true_peak {
+            logging::warn(format_args!(
+                "{}: the mix peaks at {:.2} dBTP after {:+.2} dB, above the {:.1} dBTP ceiling; the gain is applied anyway to keep the stems summing to it",
+                mix_path.display(),
+                loudness.input_tp + gain_db,
+                gain_db,
+                options.true_peak
+            ));
+        }
+        Ok(Self {
+            filter: FilterSettings::construct_gain(&options, gain_db),
+            loudness,
+            gain_db,
+        })
+    }
+
+    /// Applies the gain to the mix or a stem at `input_path` and writes the
+    /// result to `output_path`, encoded with `options`.
+    pub fn encode(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        options: &Options,
+    ) -> io::Result<()> {
+        Normalizer::encode(input_path, output_path, &self.filter, options)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{ffmpeg, Error, Loudness, MediaInfo, Mode, Options, TagFormat};
+use serde::Serialize;
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Loudness of the reference Opus playback level defined by RFC 7845.
+const OPUS_REFERENCE_LUFS: f64 = -23.0;
+
+/// The tag a second pass leaves in its output, holding `key=value` pairs
+/// separated by `;`: the mode and target, the other targets, the measured
+/// integrated loudness of the input and the date.
+pub const MARKER_TAG: &str = "LOUDNORM_HELPER";
+
+/// How far in LU or dB a recorded target may be from the configured one
+/// and still count as the same; the marker rounds to 0.1.
+const MARKER_TARGET_TOLERANCE: f64 = 0.05;
+
+/// ReplayGain/R128 gain tags derived from a measurement.
+#[derive(Debug, Clone, Serialize)]
+pub struct GainTags {
+    /// Gain in dB that brings the track to the integrated loudness target.
+    pub track_gain_db: f64,
+    /// Measured true peak as a linear amplitude, where 1.0 is full scale.
+    pub track_peak: f64,
+    /// Opus `R128_TRACK_GAIN`, a Q7.8 fixed-point gain relative to -23 LUFS.
+    /// Only set for Opus streams, unless [`TagFormat::ReplayGain`] is asked
+    /// for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r128_track_gain: Option<i32>,
+    /// Gain in dB that brings the whole album to the target.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album_gain_db: Option<f64>,
+    /// Highest true peak of the album as a linear amplitude.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album_peak: Option<f64>,
+    /// Opus `R128_ALBUM_GAIN` in Q7.8, relative to -23 LUFS.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r128_album_gain: Option<i32>,
+}
+
+impl GainTags {
+    /// Computes the tags for `loudness` against the targets in `options`.
+    /// Returns `None` when the measurement is not finite, e.g. for silence.
+    pub fn compute(loudness: &Loudness, options: &Options, opus: bool) -> Option<Self> {
+        let finite = |value: f64| Some(value).filter(|v| v.is_finite());
+        let input_i = finite(loudness.input_i)?;
+        let input_tp = finite(loudness.input_tp)?;
+        let target_i = options.integrated_loudness;
+        Some(Self {
+            track_gain_db: target_i - input_i,
+            track_peak: 10f64.powf(input_tp / 20.0),
+            r128_track_gain: (opus && options.tag_format != TagFormat::ReplayGain)
+                .then(|| q7_8(OPUS_REFERENCE_LUFS - input_i)),
+            album_gain_db: None,
+            album_peak: None,
+            r128_album_gain: None,
+        })
+    }
+
+    /// Adds album gain and peak, given the album gain in dB, the album's true
+    /// peak in dBTP and its integrated loudness in LUFS.
+    pub fn with_album(mut self, gain_db: f64, true_peak: f64, integrated_loudness: f64) -> Self {
+        self.album_gain_db = Some(gain_db);
+        self.album_peak = Some(10f64.powf(true_peak / 20.0));
+        self.r128_album_gain = self
+            .r128_track_gain
+            .map(|_| q7_8(OPUS_REFERENCE_LUFS - integrated_loudness));
+        self
+    }
+
+    /// The tags of `format` as `KEY=value` pairs, in the notation players
+    /// expect.
+    pub fn to_metadata(&self, format: TagFormat) -> Vec<(String, String)> {
+        if format == TagFormat::R128 {
+            return [
+                ("R128_TRACK_GAIN", self.r128_track_gain),
+                ("R128_ALBUM_GAIN", self.r128_album_gain),
+            ]
+            .into_iter()
+            .filter_map(|(key, gain)| Some((key.to_string(), gain?.to_string())))
+            .collect();
+        }
+        let mut metadata = vec![
+            (
+                "REPLAYGAIN_TRACK_GAIN".to_string(),
+                format!("{:.2} dB", self.track_gain_db),
+            ),
+            (
+                "REPLAYGAIN_TRACK_PEAK".to_string(),
+                format!("{:.6}", self.track_peak),
+            ),
+        ];
+        if let Some(gain) = self.r128_track_gain {
+            metadata.push(("R128_TRACK_GAIN".to_string(), gain.to_string()));
+        }
+        if let Some(gain) = self.album_gain_db {
+            metadata.push((
+                "REPLAYGAIN_ALBUM_GAIN".to_string(),
+                format!("{:.2} dB", gain),
+            ));
+        }
+        if let Some(peak) = self.album_peak {
+            metadata.push(("REPLAYGAIN_ALBUM_PEAK".to_string(), format!("{:.6}", peak)));
+        }
+        if let Some(gain) = self.r128_album_gain {
+            metadata.push(("R128_ALBUM_GAIN".to_string(), gain.to_string()));
+        }
+        metadata
+    }
+}
+
+/// Converts a gain in dB to the Q7.8 representation used by Opus tags.
+pub(crate) fn q7_8(gain_db: f64) -> i32 {
+    (gain_db * 256.0)
+        .round()
+        .clamp(i16::MIN as f64, i16::MAX as f64) as i32
+}
+
+/// Writes gain tags by remuxing with stream copy, leaving the audio itself
+/// untouched.
+pub struct Tagger;
+
+impl Tagger {
+    /// Whether the selected audio stream of `info` is Opus.
+    pub fn is_opus(info: &MediaInfo, options: &Options) -> bool {
+        info.audio_stream(options.audio_stream)
+            .ok()
+            .and_then(|s| s.codec_name.as_deref())
+            == Some("opus")
+    }
+
+    /// Fails unless the tags of `options.tag_format` can be written to a
+    /// stream that is Opus or not, as given by `opus`.
+    pub fn check_format(opus: bool, options: &Options) -> io::Result<()> {
+        if options.tag_format == TagFormat::R128 && !opus {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "R128 gain tags are only defined for Opus",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether `info` carries ReplayGain or R128 track gain tags, e.g. from
+    /// an earlier run.
+    pub fn is_tagged(info: &MediaInfo) -> bool {
+        ["REPLAYGAIN_TRACK_GAIN", "R128_TRACK_GAIN"]
+            .iter()
+            .any(|key| info.tags.contains_key(*key))
+    }
+
+    /// The value of [`MARKER_TAG`] for an output normalized with `options`,
+    /// from an input measured as `loudness` where there was a measurement.
+    /// Reproducible outputs go without the date.
+    pub fn marker(options: &Options, loudness: Option<&Loudness>) -> String {
+        let (mode, target) = marker_target(options);
+        let mut fields = vec![
+            format!("mode={}", mode),
+            format!("target={:.1}", target),
+            format!("tp={:.1}", options.true_peak),
+            format!("lra={:.1}", options.loudness_range),
+        ];
+        if let Some(loudness) = loudness.filter(|l| l.input_i.is_finite()) {
+            fields.push(format!("input_i={:.2}", loudness.input_i));
+        }
+        if !options.encoding.reproducible {
+            fields.push(format!("date={}", utc_date(SystemTime::now())));
+        }
+        fields.join(";")
+    }
+
+    /// Whether `info` carries a [`MARKER_TAG`] of a run with the mode and
+    /// target of `options`, so normalizing it again would change nothing.
+    pub fn is_normalized(info: &MediaInfo, options: &Options) -> bool {
+        let Some(marker) = info.tags.get(MARKER_TAG) else {
+            return false;
+        };
+        let field = |name: &str| {
+            marker.split(';').find_map(|pair| {
+                pair.split_once('=')
+                    .filter(|(key, _)| key.trim() == name)
+                    .map(|(_, value)| value.trim())
+            })
+        };
+        let (mode, target) = marker_target(options);
+        field("mode") == Some(mode)
+            && field("target")
+                .and_then(|value| value.parse::<f64>().ok())
+                .is_some_and(|recorded| (recorded - target).abs() < MARKER_TARGET_TOLERANCE)
+    }
+
+    /// Writes `metadata` into a copy of `input_path` at `output_path`, or
+    /// back into `input_path` itself when no output is given.
+    pub fn write(
+        input_path: &Path,
+        output_path: Option<&Path>,
+        metadata: &[(String, String)],
+        options: &Options,
+    ) -> io::Result<()> {
+        let destination = output_path.unwrap_or(input_path);
+        let temp_path = temp_path_for(destination, "tagging");
+
+        let mut args: Vec<OsString> = ffmpeg::protocol_args(input_path)
+            .iter()
+            .map(OsString::from)
+            .collect();
+        args.extend(["-i".into(), ffmpeg::path_arg(input_path).into()]);
+        args.extend(
+            [
+                "-hide_banner",
+                "-y",
+                "-map",
+                "0",
+                "-map_metadata",
+                "0",
+                "-c",
+                "copy",
+            ]
+            .map(OsString::from),
+        );
+        for (key, value) in metadata {
+            args.push("-metadata".into());
+            args.push(format!("{}={}", key, value).into());
+        }
+        args.extend(
+            options
+                .encoding
+                .bitexact_args()
+                .into_iter()
+                .map(OsString::from),
+        );
+        args.push(ffmpeg::path_arg(&temp_path).into());
+
+        let output = ffmpeg::output(
+            ffmpeg::ffmpeg_command(options)?
+                .args(&args)
+                .stdin(Stdio::null()),
+        )
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })?;
+        if !output.status.success() {
+            let _ = fs::remove_file(&temp_path);
+            return Err(Error::process_failed("ffmpeg", &output).into());
+        }
+        fs::rename(&temp_path, destination).inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })
+    }
+}
+
+/// The name of the mode of `options` and the level it targets.
+fn marker_target(options: &Options) -> (&'static str, f64) {
+    match options.mode {
+        Mode::Ebu => ("ebu", options.integrated_loudness),
+        Mode::Peak => ("peak", options.true_peak),
+        Mode::Rms => ("rms", options.target_rms),
+    }
+}
+
+/// `time` as a UTC date, `2024-05-31`.
+fn utc_date(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 86_400) as i64;
+    // Civil date from days since 1970-01-01, after Howard Hinnant's
+    // `civil_from_days`.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// A hidden sibling of `path` marked with `label` that keeps its extension,
+/// so ffmpeg picks the same container format. Being in the same directory,
+/// it can be renamed over `path` atomically.
+pub(crate) fn temp_path_for(path: &Path, label: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_stem().unwrap_or(OsStr::new("output")));
+    name.push(".");
+    name.push(label);
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
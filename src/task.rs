@@ -0,0 +1,320 @@
+//! Futures for running analysis and normalization off the calling thread.
+//!
+//! A [`Task`] is queued to a pool of worker threads shared by all tasks and
+//! completes once its job is done, so it can be awaited from any executor
+//! without blocking it, and any number of tasks costs no more threads than
+//! the pool has. A [`CancellationToken`] stops the ffmpeg processes of the
+//! tasks it was given to, and progress callbacks receive the same updates
+//! the terminal spinner shows.
+
+use crate::{interrupt, Error};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    io,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    thread::{self, LocalKey},
+    time::Duration,
+};
+
+/// Stops the ffmpeg processes of every task it was passed to: with SIGTERM
+/// on Unix, and on Windows by ending them. Elsewhere running passes are
+/// left to finish.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<TokenState>,
+}
+
+#[derive(Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    children: Mutex<Vec<u32>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the tasks: running ffmpeg processes are terminated, and the
+    /// tasks complete with [`Error::Interrupted`]. Tasks still queued
+    /// complete without starting.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        if let Ok(children) = self.inner.children.lock() {
+            for &pid in children.iter() {
+                interrupt::terminate(pid);
+            }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// How far an ffmpeg pass of a task has come.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressEvent {
+    /// The running stage, e.g. `Measuring` or `Encoding`.
+    pub stage: String,
+    /// Fraction of the input processed, from 0 to 1.
+    pub fraction: f64,
+    /// Estimated time until the pass finishes.
+    pub eta: Option<Duration>,
+    /// How many times faster than realtime ffmpeg is going.
+    pub speed: Option<f64>,
+}
+
+/// Cancellation and progress reporting for a task.
+#[derive(Clone, Default)]
+pub struct TaskContext {
+    cancel: CancellationToken,
+    on_progress: Option<Arc<dyn Fn(ProgressEvent) + Send + Sync>>,
+}
+
+impl TaskContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets `token` cancel the task.
+    pub fn cancel_with(mut self, token: &CancellationToken) -> Self {
+        self.cancel = token.clone();
+        self
+    }
+
+    /// Calls `callback` from the task's thread whenever ffmpeg reports
+    /// progress.
+    pub fn on_progress(mut self, callback: impl Fn(ProgressEvent) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Runs `job` on the calling thread within this context, for callers
+    /// that manage their own threads.
+    pub fn run<T>(self, job: impl FnOnce() -> T) -> T {
+        with_local(&CURRENT, self, job)
+    }
+
+    /// The context of the task running on this thread, for the threads it
+    /// starts to [`run`](Self::run) their part of the job in.
+    pub(crate) fn current() -> Self {
+        CURRENT.with(|current| current.borrow().clone().unwrap_or_default())
+    }
+}
+
+/// Runs `job` with the thread-local `key` set to `value`, restoring the
+/// previous value afterwards, on unwinding too.
+pub(crate) fn with_local<V: 'static, T>(
+    key: &'static LocalKey<RefCell<Option<V>>>,
+    value: V,
+    job: impl FnOnce() -> T,
+) -> T {
+    struct Restore<V: 'static> {
+        key: &'static LocalKey<RefCell<Option<V>>>,
+        previous: Option<V>,
+    }
+    impl<V> Drop for Restore<V> {
+        fn drop(&mut self) {
+            let previous = self.previous.take();
+            self.key.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+    let previous = key.with(|current| current.replace(Some(value)));
+    let _restore = Restore { key, previous };
+    job()
+}
+
+thread_local! {
+    /// The context of the task running on this thread, if any.
+    static CURRENT: RefCell<Option<TaskContext>> = const { RefCell::new(None) };
+}
+
+/// Adds a child process to the cancellation token of the current task,
+/// terminating it right away if the task is already cancelled.
+pub(crate) fn register_child(pid: u32) {
+    CURRENT.with(|current| {
+        if let Some(context) = current.borrow().as_ref() {
+            if let Ok(mut children) = context.cancel.inner.children.lock() {
+                children.push(pid);
+            }
+            if context.cancel.is_cancelled() {
+                interrupt::terminate(pid);
+            }
+        }
+    });
+}
+
+/// Removes a finished child process from the current task's token.
+pub(crate) fn unregister_child(pid: u32) {
+    CURRENT.with(|current| {
+        if let Some(context) = current.borrow().as_ref() {
+            if let Ok(mut children) = context.cancel.inner.children.lock() {
+                children.retain(|&child| child != pid);
+            }
+        }
+    });
+}
+
+/// Hands a progress update to the current task's callback.
+pub(crate) fn report_progress(event: impl FnOnce() -> ProgressEvent) {
+    CURRENT.with(|current| {
+        if let Some(callback) = current
+            .borrow()
+            .as_ref()
+            .and_then(|c| c.on_progress.as_ref())
+        {
+            callback(event());
+        }
+    });
+}
+
+/// Tasks waiting for a worker, and the workers of the pool.
+struct Pool {
+    queue: Mutex<VecDeque<Box<dyn FnOnce() + Send>>>,
+    ready: Condvar,
+    /// Workers started so far; they wait for tasks once started.
+    workers: AtomicUsize,
+    /// Workers waiting for a task.
+    idle: AtomicUsize,
+}
+
+static POOL: Pool = Pool {
+    queue: Mutex::new(VecDeque::new()),
+    ready: Condvar::new(),
+    workers: AtomicUsize::new(0),
+    idle: AtomicUsize::new(0),
+};
+
+/// Most workers the pool starts, 0 for the available parallelism.
+static MAX_WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+impl Pool {
+    /// Queues `job`, starting another worker if none is idle and the pool
+    /// isn't full yet.
+    fn submit(&'static self, job: Box<dyn FnOnce() + Send>) {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        queue.push_back(job);
+        let limit = match MAX_WORKERS.load(Ordering::SeqCst) {
+            0 => thread::available_parallelism().map_or(4, |n| n.get()),
+            limit => limit,
+        };
+        if self.idle.load(Ordering::SeqCst) < queue.len()
+            && self.workers.load(Ordering::SeqCst) < limit
+        {
+            self.workers.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(|| self.work());
+        }
+        drop(queue);
+        self.ready.notify_one();
+    }
+
+    fn work(&self) {
+        loop {
+            let job = {
+                let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+                loop {
+                    if let Some(job) = queue.pop_front() {
+                        break job;
+                    }
+                    self.idle.fetch_add(1, Ordering::SeqCst);
+                    queue = self.ready.wait(queue).unwrap_or_else(|e| e.into_inner());
+                    self.idle.fetch_sub(1, Ordering::SeqCst);
+                }
+            };
+            job();
+        }
+    }
+}
+
+/// A job queued to the shared worker pool, completing with the job's
+/// result. Dropping the task detaches it; use a [`CancellationToken`] to
+/// stop it.
+pub struct Task<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+struct Shared<T> {
+    result: Option<io::Result<T>>,
+    waker: Option<Waker>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    /// Queues `job` to run within `context` on a worker of the pool.
+    pub fn spawn(
+        context: TaskContext,
+        job: impl FnOnce() -> io::Result<T> + Send + 'static,
+    ) -> Self {
+        let shared = Arc::new(Mutex::new(Shared {
+            result: None,
+            waker: None,
+        }));
+        let done = Arc::clone(&shared);
+        POOL.submit(Box::new(move || {
+            let token = context.cancel.clone();
+            let result = if token.is_cancelled() {
+                Err(Error::Interrupted.into())
+            } else {
+                panic::catch_unwind(AssertUnwindSafe(|| context.run(job)))
+                    .unwrap_or_else(|_| Err(io::Error::other("task panicked")))
+                    .map_err(|e| {
+                        if token.is_cancelled() {
+                            Error::Interrupted.into()
+                        } else {
+                            e
+                        }
+                    })
+            };
+            if let Ok(mut shared) = done.lock() {
+                shared.result = Some(result);
+                if let Some(waker) = shared.waker.take() {
+                    waker.wake();
+                }
+            }
+        }));
+        Self { shared }
+    }
+}
+
+impl Task<()> {
+    /// Limits the pool to `workers` threads running tasks at once, the
+    /// available parallelism by default. Tasks beyond that wait in the
+    /// queue; workers already started keep running.
+    pub fn set_max_workers(workers: usize) {
+        MAX_WORKERS.store(workers.max(1), Ordering::SeqCst);
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = io::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Ok(mut shared) = self.shared.lock() else {
+            return Poll::Ready(Err(io::Error::other("task state poisoned")));
+        };
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{remote, Options};
+use std::{
+    ffi::{OsStr, OsString},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Template used with `--output-dir` when no `--output-template` is given.
+pub const DEFAULT_OUTPUT_TEMPLATE: &str = "{stem}.{ext}";
+
+/// Default template when several target variants are written.
+pub const DEFAULT_VARIANT_TEMPLATE: &str = "{stem}.{variant}.{ext}";
+
+/// Expands output path templates for batch runs.
+///
+/// Supported placeholders:
+/// - `{dir}`: directory of the input file
+/// - `{stem}`: input file name without extension
+/// - `{ext}`: input file extension
+/// - `{lufs}`: integrated loudness target, e.g. `-16`
+/// - `{variant}`: name of the target variant, with [`Self::render_variant`]
+pub struct OutputTemplate;
+
+impl OutputTemplate {
+    /// Renders `template` for `input_path`. Relative results are placed in
+    /// `output_dir` when given.
+    pub fn render(
+        template: &str,
+        input_path: &Path,
+        output_dir: Option<&Path>,
+        options: &Options,
+    ) -> io::Result<PathBuf> {
+        Self::render_with(template, input_path, output_dir, options, None)
+    }
+
+    /// Renders `template` for the output of `input_path` normalized to the
+    /// targets of `options`, which are those of the variant named `variant`.
+    pub fn render_variant(
+        template: &str,
+        input_path: &Path,
+        output_dir: Option<&Path>,
+        options: &Options,
+        variant: &str,
+    ) -> io::Result<PathBuf> {
+        Self::render_with(template, input_path, output_dir, options, Some(variant))
+    }
+
+    fn render_with(
+        template: &str,
+        input_path: &Path,
+        output_dir: Option<&Path>,
+        options: &Options,
+        variant: Option<&str>,
+    ) -> io::Result<PathBuf> {
+        // URLs are named after the file at the end of their path, and have
+        // no directory to put the output in.
+        let (input_path, dir) = match remote::is_url(input_path) {
+            true => (
+                Path::new(remote::url_file_name(input_path).unwrap_or("download")),
+                OsStr::new("."),
+            ),
+            false => (
+                input_path,
+                input_path
+                    .parent()
+                    .map(Path::as_os_str)
+                    .filter(|p| !p.is_empty())
+                    .unwrap_or(OsStr::new(".")),
+            ),
+        };
+        let stem = input_path.file_stem().unwrap_or_default();
+        let ext = input_path.extension().unwrap_or_default();
+        let lufs = options.integrated_loudness.to_string();
+
+        let mut rendered = OsString::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            rendered.push(&rest[..start]);
+            let end = rest[start..].find('}').ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unclosed placeholder in output template '{}'", template),
+                )
+            })? + start;
+            rendered.push(match &rest[start + 1..end] {
+                "dir" => dir,
+                "stem" => stem,
+                "ext" => ext,
+                "lufs" => OsStr::new(&lufs),
+                "variant" => OsStr::new(variant.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "The {variant} placeholder needs target variants",
+                    )
+                })?),
+                other => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown placeholder '{{{}}}' in output template", other),
+                    ))
+                }
+            });
+            rest = &rest[end + 1..];
+        }
+        rendered.push(rest);
+
+        let rendered = PathBuf::from(rendered);
+        Ok(match output_dir {
+            Some(dir) if rendered.is_relative() => dir.join(rendered),
+            _ => rendered,
+        })
+    }
+}
@@ -0,0 +1,50 @@
+//! Holding back new inputs of a batch while the machine is busy, with
+//! `--max-load`.
+
+use crate::{interrupt, logging};
+use std::{fs, process::Command, sync::Mutex, thread, time::Duration};
+
+/// How often a waiting worker looks at the load again.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Serializes the waiting workers, so that only one of them polls and
+/// reports the wait.
+static WAITING: Mutex<()> = Mutex::new(());
+
+/// The one-minute load average, from `/proc/loadavg` on Linux or
+/// `sysctl vm.loadavg` on the BSDs and macOS, or `None` when neither is
+/// available.
+pub fn load_average() -> Option<f64> {
+    let text = fs::read_to_string("/proc/loadavg").ok().or_else(|| {
+        let output = Command::new("sysctl")
+            .args(["-n", "vm.loadavg"])
+            .output()
+            .ok()?;
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    })?;
+    // sysctl wraps the values in braces: `{ 1.23 1.10 1.00 }`.
+    text.split_whitespace()
+        .find(|word| *word != "{")?
+        .parse()
+        .ok()
+}
+
+/// Waits until the load average is at most `max_load` or the run is
+/// interrupted. Doesn't wait when the load can't be read.
+pub fn wait_for_load(max_load: f64) {
+    let _waiting = WAITING.lock();
+    let mut announced = false;
+    while let Some(load) = load_average().filter(|&load| load > max_load) {
+        if interrupt::is_interrupted() {
+            return;
+        }
+        if !announced {
+            logging::info(format_args!(
+                "load average {:.2} is above --max-load {}; waiting",
+                load, max_load
+            ));
+            announced = true;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
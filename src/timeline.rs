@@ -0,0 +1,163 @@
+use crate::{ffmpeg, FilterSettings, MediaInfo, Options, ProgressSpinner};
+use serde::Serialize;
+use std::{ffi::OsStr, fmt::Write as _, fs, io, path::Path};
+
+/// Loudness readings at one point in time, as logged by the ebur128 filter.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TimelinePoint {
+    /// Seconds from the start of the input.
+    pub time: f64,
+    /// Momentary loudness over the last 400 ms, in LUFS.
+    pub momentary: f64,
+    /// Short-term loudness over the last 3 s, in LUFS.
+    pub short_term: f64,
+    /// Integrated loudness up to this point, in LUFS.
+    pub integrated: f64,
+    /// Highest true peak of any channel in this frame, in dBFS.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub true_peak: Option<f64>,
+}
+
+/// Momentary and short-term loudness over the duration of an input.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Timeline {
+    pub points: Vec<TimelinePoint>,
+    /// Integrated loudness of the whole input in LUFS, from the summary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrated: Option<f64>,
+    /// Loudness range of the whole input in LU, from the summary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loudness_range: Option<f64>,
+}
+
+impl Timeline {
+    /// Runs the ebur128 filter over `input_path` and collects its per-frame
+    /// readings.
+    pub fn measure(input_path: &Path, options: &Options) -> io::Result<Self> {
+        let info = MediaInfo::probe(input_path, options)?;
+        info.audio_stream(options.audio_stream)?;
+
+        let filter_settings = FilterSettings::construct_ebur128(options);
+        let segment = options.segment_args();
+        let mut args: Vec<&OsStr> = segment.iter().map(OsStr::new).collect();
+        args.extend(ffmpeg::protocol_args(input_path).iter().map(OsStr::new));
+        let input = ffmpeg::path_arg(input_path);
+        args.extend([
+            "-i".as_ref(),
+            &*input,
+            "-hide_banner".as_ref(),
+            "-vn".as_ref(),
+        ]);
+        let stream = options.stream_specifier();
+        if let Some(stream) = &stream {
+            args.extend(["-map", stream.as_str()].map(OsStr::new));
+        }
+        args.extend(["-af", &filter_settings, "-f", "null", "-"].map(OsStr::new));
+
+        let spinner = ProgressSpinner::labeled("Measuring timeline");
+        let output = ffmpeg::run_with_progress(
+            options,
+            args,
+            options.segment_duration(info.duration_of(options.audio_stream)),
+            &spinner,
+            |line| line.contains("TARGET:"),
+        );
+        spinner.stop();
+        Ok(Self::parse(&output?))
+    }
+
+    /// Parses the per-frame lines of the ebur128 filter's log, e.g.
+    /// `t: 0.5  TARGET:-23 LUFS  M: -24.9 S:-120.7  I: -24.9 LUFS ...`,
+    /// and the summary following them.
+    pub fn parse(output: &str) -> Self {
+        let summary = output.rfind("Summary:").map(|at| &output[at..]);
+        let (frames, summary) = match summary {
+            Some(summary) => (&output[..output.len() - summary.len()], summary),
+            None => (output, ""),
+        };
+        let points = frames
+            .lines()
+            .filter_map(|line| {
+                let line = &line[line.find("t:")?..];
+                Some(TimelinePoint {
+                    time: value_after(line, "t:")?,
+                    momentary: value_after(line, "M:")?,
+                    short_term: value_after(line, "S:")?,
+                    integrated: value_after(line, "I:")?,
+                    true_peak: line.find("FTPK:").and_then(|at| {
+                        line[at + "FTPK:".len()..]
+                            .split_whitespace()
+                            .map_while(|value| value.parse::<f64>().ok())
+                            .reduce(f64::max)
+                    }),
+                })
+            })
+            .collect();
+        Self {
+            points,
+            integrated: value_after(summary, "I:"),
+            loudness_range: value_after(summary, "LRA:"),
+        }
+    }
+
+    /// The highest momentary loudness in LUFS.
+    pub fn max_momentary(&self) -> Option<f64> {
+        self.points
+            .iter()
+            .map(|point| point.momentary)
+            .reduce(f64::max)
+    }
+
+    /// The highest short-term loudness in LUFS.
+    pub fn max_short_term(&self) -> Option<f64> {
+        self.points
+            .iter()
+            .map(|point| point.short_term)
+            .reduce(f64::max)
+    }
+
+    /// The highest true peak of any frame in dBTP.
+    pub fn max_true_peak(&self) -> Option<f64> {
+        self.points
+            .iter()
+            .filter_map(|point| point.true_peak)
+            .reduce(f64::max)
+    }
+
+    /// Writes the timeline to `path`, as JSON when the extension is `.json`
+    /// and as CSV otherwise.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let contents = if json {
+            serde_json::to_string_pretty(&self.points)?
+        } else {
+            self.to_csv()
+        };
+        fs::write(path, contents)
+    }
+
+    /// The timeline as CSV with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("time,momentary,short_term,integrated,true_peak\n");
+        for point in &self.points {
+            let true_peak = point
+                .true_peak
+                .map_or_else(String::new, |tp| tp.to_string());
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{}",
+                point.time, point.momentary, point.short_term, point.integrated, true_peak
+            );
+        }
+        csv
+    }
+}
+
+/// Parses the number following `key`, allowing whitespace in between.
+fn value_after(line: &str, key: &str) -> Option<f64> {
+    let rest = line[line.find(key)? + key.len()..].trim_start();
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
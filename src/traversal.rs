@@ -0,0 +1,58 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// File extensions treated as audio when walking a directory tree.
+pub const DEFAULT_EXTENSIONS: [&str; 11] = [
+    "aac", "aif", "aiff", "alac", "flac", "m4a", "mp3", "ogg", "opus", "wav", "wma",
+];
+
+/// Recursively collects the files below `root` whose extension is listed in
+/// `extensions` (compared case-insensitively).
+///
+/// Hidden entries and symlinked directories are skipped. Directories that
+/// cannot be read are reported as errors alongside the collected files so a
+/// single unreadable folder doesn't abort the whole walk.
+pub fn walk_audio_files(root: &Path, extensions: &[String]) -> Vec<io::Result<PathBuf>> {
+    let mut results = Vec::new();
+    walk(root, extensions, &mut results);
+    results
+}
+
+fn walk(dir: &Path, extensions: &[String], results: &mut Vec<io::Result<PathBuf>>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            results.push(Err(io::Error::new(
+                e.kind(),
+                format!("{}: {}", dir.display(), e),
+            )));
+            return;
+        }
+    };
+
+    let mut paths: Vec<_> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .collect();
+    paths.sort_by_key(|entry| entry.file_name());
+
+    for entry in paths {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            walk(&path, extensions, results);
+        } else if has_audio_extension(&path, extensions) {
+            results.push(Ok(path));
+        }
+    }
+}
+
+fn has_audio_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext)))
+}
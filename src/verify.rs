@@ -0,0 +1,85 @@
+use crate::{Error, Loudness, LoudnessAnalyzer, Options, ProgressSpinner};
+use serde::Serialize;
+use std::{fmt, io, path::Path};
+
+/// Tolerance in LU around the integrated loudness target used by default.
+pub const DEFAULT_TOLERANCE: f64 = 0.5;
+
+/// Outcome of re-measuring a normalized file against the targets.
+#[derive(Debug, Clone, Serialize)]
+pub struct Verification {
+    /// Integrated loudness of the output in LUFS.
+    pub integrated_loudness: f64,
+    /// True peak of the output in dBTP.
+    pub true_peak: f64,
+    /// Integrated loudness target in LUFS.
+    pub target: f64,
+    /// Accepted deviation from `target` in LU.
+    pub tolerance: f64,
+    /// True peak ceiling in dBTP.
+    pub ceiling: f64,
+    /// Whether the output is within tolerance and under the ceiling.
+    pub passed: bool,
+}
+
+impl Verification {
+    /// Measures `output_path` and compares it with the targets in `options`.
+    /// The whole file is measured, bypassing the cache, supplied
+    /// measurements and any segment or stream selection meant for the
+    /// input.
+    pub fn check(output_path: &Path, options: &Options, tolerance: f64) -> io::Result<Self> {
+        let options = Options {
+            audio_stream: None,
+            start: None,
+            duration: None,
+            cache_dir: None,
+            history_db: None,
+            measured: None,
+            compressor: None,
+            enforce_lra: false,
+            ..options.clone()
+        };
+        let loudness = ProgressSpinner::in_stage("Verifying", || {
+            LoudnessAnalyzer::measure(output_path, &options)
+        })?;
+        Ok(Self::compare(&loudness, &options, tolerance))
+    }
+
+    /// Compares measurements of an output with the targets in `options`.
+    pub fn compare(loudness: &Loudness, options: &Options, tolerance: f64) -> Self {
+        let passed = (loudness.input_i - options.integrated_loudness).abs() <= tolerance
+            && loudness.input_tp <= options.true_peak;
+        Self {
+            integrated_loudness: loudness.input_i,
+            true_peak: loudness.input_tp,
+            target: options.integrated_loudness,
+            tolerance,
+            ceiling: options.true_peak,
+            passed,
+        }
+    }
+
+    /// Fails with [`Error::NotCompliant`] when verification didn't pass.
+    pub fn ensure_passed(&self) -> io::Result<()> {
+        if self.passed {
+            Ok(())
+        } else {
+            Err(Error::NotCompliant(self.to_string()).into())
+        }
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: I={:.2} LUFS (target {:.1} ±{:.1} LU), TP={:.2} dBTP (ceiling {:.1} dBTP)",
+            if self.passed { "PASS" } else { "FAIL" },
+            self.integrated_loudness,
+            self.target,
+            self.tolerance,
+            self.true_peak,
+            self.ceiling
+        )
+    }
+}
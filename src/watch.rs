@@ -0,0 +1,139 @@
+use crate::{
+    interrupt, print::report_result, process::process, walk_audio_files, Error, RunConfig,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// How often `--watch` rescans its directory.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Size and modification time of a file at one scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileState {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Polls a directory tree for audio files that have finished being written.
+///
+/// A file is reported once it looks the same on two consecutive scans, so
+/// recordings still being copied into the folder are left alone. Files that
+/// change after being reported are reported again.
+pub struct DirectoryWatcher {
+    dir: PathBuf,
+    extensions: Vec<String>,
+    excluded: Vec<PathBuf>,
+    pending: HashMap<PathBuf, FileState>,
+    reported: HashMap<PathBuf, FileState>,
+}
+
+impl DirectoryWatcher {
+    /// Watches `dir` and its subdirectories for files with one of
+    /// `extensions`.
+    pub fn new(dir: &Path, extensions: &[String]) -> Self {
+        Self {
+            dir: fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()),
+            extensions: extensions.to_vec(),
+            excluded: Vec::new(),
+            pending: HashMap::new(),
+            reported: HashMap::new(),
+        }
+    }
+
+    /// Ignores everything below `dir`, e.g. an output directory inside the
+    /// watched one.
+    pub fn exclude(mut self, dir: &Path) -> Self {
+        self.excluded
+            .push(fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()));
+        self
+    }
+
+    /// Scans the directory once and returns the files that became stable
+    /// since the previous scan.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut current = HashMap::new();
+        for path in walk_audio_files(&self.dir, &self.extensions)
+            .into_iter()
+            .flatten()
+            .filter(|path| !self.excluded.iter().any(|dir| path.starts_with(dir)))
+        {
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let state = FileState {
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+            };
+            current.insert(path, state);
+        }
+
+        let mut stable = Vec::new();
+        for (path, state) in &current {
+            if self.reported.get(path) == Some(state) {
+                continue;
+            }
+            if state.len > 0 && self.pending.get(path) == Some(state) {
+                self.reported.insert(path.clone(), *state);
+                stable.push(path.clone());
+            }
+        }
+        self.reported.retain(|path, _| current.contains_key(path));
+        self.pending = current;
+        stable.sort();
+        stable
+    }
+}
+
+/// Normalizes files dropped into `dir` until the process is stopped.
+pub(crate) fn watch(config: &RunConfig, dir: &Path) -> ExitCode {
+    if config.output_dir.is_none() && config.output_template.is_none() {
+        eprintln!("--watch needs --output-dir or --output-template");
+        return ExitCode::from(2);
+    }
+    if !dir.is_dir() {
+        eprintln!("{}: not a directory", dir.display());
+        return ExitCode::from(2);
+    }
+    let mut watcher = DirectoryWatcher::new(dir, &config.include_ext);
+    if let Some(output_dir) = &config.output_dir {
+        if let Err(e) = fs::create_dir_all(output_dir) {
+            eprintln!("{}: {}", output_dir.display(), e);
+            return ExitCode::FAILURE;
+        }
+        watcher = watcher.exclude(output_dir);
+    }
+
+    while !interrupt::is_interrupted() {
+        for input_path in watcher.poll() {
+            if is_up_to_date(config, &input_path) {
+                continue;
+            }
+            if let Err(e) =
+                process(config, &input_path).and_then(|result| report_result(config, &result, true))
+            {
+                eprintln!("{}: {}", input_path.display(), e);
+            }
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+    ExitCode::from(Error::Interrupted.exit_code())
+}
+
+/// Whether the output for `input_path` already exists and is newer than it,
+/// so restarting `--watch` doesn't redo finished files.
+fn is_up_to_date(config: &RunConfig, input_path: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match config.output_for(input_path) {
+        Ok(Some(output_path)) => match (modified(input_path), modified(&output_path)) {
+            (Some(input), Some(output)) => output >= input,
+            _ => false,
+        },
+        _ => false,
+    }
+}